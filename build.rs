@@ -0,0 +1,69 @@
+use std::{
+    path::Path,
+    process::Command,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// generated by `sqlx migrate build-script`
+fn main() {
+    // trigger recompilation when a new migration is added
+    println!("cargo:rerun-if-changed=migrations");
+
+    build_info();
+
+    // protox compiles the proto files itself, so building doesn't need protoc
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto");
+        let descriptors = protox::compile(["proto/rinha.proto"], ["proto"])
+            .expect("can't compile proto/rinha.proto");
+        tonic_build::configure()
+            .compile_fds(descriptors)
+            .expect("can't generate the grpc code");
+    }
+}
+
+// what `GET /version` answers. GIT_SHA wins over asking git, for the builds
+// made without the repository's history
+fn build_info() {
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+
+    let sha = std::env::var("GIT_SHA")
+        .ok()
+        .or_else(|| output("git", &["rev-parse", "HEAD"]))
+        .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=RINHA_GIT_SHA={}", sha);
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc = output(&rustc, &["--version"]).unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=RINHA_RUSTC_VERSION={}", rustc);
+
+    let built_at = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    println!("cargo:rustc-env=RINHA_BUILT_AT={}", built_at);
+
+    let mut features: Vec<String> = std::env::vars()
+        .filter_map(|(name, _)| {
+            let feature = name.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_ascii_lowercase().replace('_', "-"))
+        })
+        .collect();
+    features.sort();
+    println!("cargo:rustc-env=RINHA_FEATURES={}", features.join(","));
+}
+
+fn output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let output = String::from_utf8(output.stdout).ok()?;
+
+    Some(output.trim().to_string())
+}
@@ -0,0 +1,7 @@
+CREATE TABLE idempotency_keys (
+  wallet_id INT REFERENCES wallets(id) NOT NULL,
+  key VARCHAR(255) NOT NULL,
+  response JSONB,
+  inserted_at TIMESTAMP with time zone DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (wallet_id, key)
+);
@@ -0,0 +1,9 @@
+-- keys are stored as the hex sha256 of the key, never in the clear:
+--   INSERT INTO api_keys (key_hash, scope, description)
+--   VALUES (encode(sha256('the-key'), 'hex'), 'write', 'load tests');
+CREATE TABLE api_keys (
+  key_hash CHAR(64) PRIMARY KEY,
+  scope VARCHAR(5) NOT NULL CHECK (scope IN ('read', 'write')),
+  description VARCHAR(255),
+  inserted_at TIMESTAMP with time zone DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,26 @@
+-- announces every committed transaction on the `transactions` channel, so
+-- the live streams of all the instances see the writes of the others. the
+-- payload is the json of `events::TransactionEvent`
+CREATE FUNCTION notify_transaction() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('transactions', json_build_object(
+    'cliente_id', NEW.wallet_id,
+    'saldo', COALESCE(wallets.balance, 0),
+    'limite', COALESCE(wallets.credit_limit, 0),
+    'transacao', json_build_object(
+      'valor', NEW.value,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'realizada_em', NEW.inserted_at
+    )
+  )::text)
+  FROM wallets
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_notify
+AFTER INSERT ON transactions
+FOR EACH ROW EXECUTE FUNCTION notify_transaction();
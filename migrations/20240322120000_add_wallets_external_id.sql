@@ -0,0 +1,6 @@
+-- the id the client is known by in some other system
+ALTER TABLE wallets ADD COLUMN external_id VARCHAR(255) UNIQUE;
+
+-- the first wallets were inserted with explicit ids, so the serial would
+-- hand those out again
+SELECT setval('wallets_id_seq', (SELECT MAX(id) FROM wallets));
@@ -0,0 +1,13 @@
+-- append-only record of the changes made to a wallet outside of its
+-- transactions. the values are json so each action can keep its own shape
+CREATE TABLE audit_log (
+  id BIGSERIAL PRIMARY KEY,
+  wallet_id INT REFERENCES wallets(id) NOT NULL,
+  action VARCHAR(64) NOT NULL,
+  old_value JSONB,
+  new_value JSONB,
+  actor VARCHAR(255) NOT NULL,
+  inserted_at TIMESTAMP with time zone DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX audit_log_wallet_id_index ON audit_log (wallet_id);
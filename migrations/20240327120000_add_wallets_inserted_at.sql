@@ -0,0 +1,2 @@
+-- wallets that predate the column get the time of the migration
+ALTER TABLE wallets ADD COLUMN inserted_at TIMESTAMP with time zone DEFAULT CURRENT_TIMESTAMP;
@@ -0,0 +1,6 @@
+-- the two rows of a transfer, the debit on one wallet and the credit on the
+-- other, share the id of the transfer
+ALTER TABLE transactions ADD COLUMN transfer_id UUID;
+
+CREATE INDEX transactions_transfer_id_index ON transactions (transfer_id)
+WHERE transfer_id IS NOT NULL;
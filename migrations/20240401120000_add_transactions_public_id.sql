@@ -0,0 +1,5 @@
+-- the id transactions are exposed under. unlike the serial it's not
+-- guessable and doesn't give away how many transactions there are
+ALTER TABLE transactions ADD COLUMN public_id UUID NOT NULL DEFAULT gen_random_uuid();
+
+CREATE UNIQUE INDEX transactions_public_id_index ON transactions (public_id);
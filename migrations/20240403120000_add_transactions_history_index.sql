@@ -0,0 +1,4 @@
+-- matches the keyset pagination of `GET /clientes/:id/transacoes`, which
+-- walks a wallet's rows in `(inserted_at, id)` order
+CREATE INDEX transactions_wallet_id_inserted_at_id_index
+ON transactions (wallet_id, inserted_at, id);
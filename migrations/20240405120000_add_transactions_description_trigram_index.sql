@@ -0,0 +1,6 @@
+-- lets the `ILIKE '%...%'` of the history search use an index instead of
+-- reading every row of the wallet
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX transactions_description_trigram_index
+ON transactions USING gin (description gin_trgm_ops);
@@ -0,0 +1,22 @@
+-- announces the id of every wallet a write touched on the `wallet_changed`
+-- channel, so each instance can drop what it cached for it. `*` when the
+-- table is truncated
+CREATE FUNCTION notify_wallet_changed() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'TRUNCATE' THEN
+    PERFORM pg_notify('wallet_changed', '*');
+  ELSE
+    PERFORM pg_notify('wallet_changed', OLD.id::text);
+  END IF;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER wallets_notify_changed
+AFTER UPDATE OR DELETE ON wallets
+FOR EACH ROW EXECUTE FUNCTION notify_wallet_changed();
+
+CREATE TRIGGER wallets_notify_truncated
+AFTER TRUNCATE ON wallets
+FOR EACH STATEMENT EXECUTE FUNCTION notify_wallet_changed();
@@ -0,0 +1,46 @@
+-- the newest 10 transactions of each wallet, newest first, in the json of
+-- `models::TransactionItem`. kept by the trigger below in the same
+-- transaction as the insert, so an unfiltered statement is one row away
+ALTER TABLE wallets ADD COLUMN recent_transactions JSONB NOT NULL DEFAULT '[]';
+
+CREATE FUNCTION remember_recent_transaction() RETURNS trigger AS $$
+BEGIN
+  UPDATE wallets
+  SET recent_transactions = (
+    SELECT jsonb_agg(recent.item ORDER BY recent.position)
+    FROM jsonb_array_elements(
+      jsonb_build_array(jsonb_build_object(
+        'valor', NEW.value,
+        'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+        'descricao', NEW.description,
+        'realizada_em', NEW.inserted_at
+      )) || wallets.recent_transactions
+    ) WITH ORDINALITY AS recent(item, position)
+    WHERE recent.position <= 10
+  )
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_remember_recent
+AFTER INSERT ON transactions
+FOR EACH ROW EXECUTE FUNCTION remember_recent_transaction();
+
+UPDATE wallets
+SET recent_transactions = COALESCE((
+  SELECT jsonb_agg(jsonb_build_object(
+    'valor', recent.value,
+    'tipo', CASE recent.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+    'descricao', recent.description,
+    'realizada_em', recent.inserted_at
+  ) ORDER BY recent.inserted_at DESC, recent.id DESC)
+  FROM (
+    SELECT id, value, kind, description, inserted_at
+    FROM transactions
+    WHERE transactions.wallet_id = wallets.id
+    ORDER BY inserted_at DESC, id DESC
+    LIMIT 10
+  ) AS recent
+), '[]');
@@ -0,0 +1,40 @@
+-- the whole write behind one call: the limit check, the balance update and
+-- the row. no rows for an unknown wallet; a debit past the limit raises the
+-- same error as the `positive_balance` constraint, without tripping it
+CREATE FUNCTION register_transaction(
+  p_wallet_id INTEGER,
+  p_value INTEGER,
+  p_kind transaction_kind,
+  p_description TEXT
+) RETURNS TABLE (
+  balance INTEGER,
+  credit_limit INTEGER,
+  public_id UUID,
+  inserted_at TIMESTAMP WITH TIME ZONE
+) AS $$
+#variable_conflict use_column
+DECLARE
+  v_balance INTEGER;
+  v_credit_limit INTEGER;
+BEGIN
+  UPDATE wallets
+  SET balance = wallets.balance + CASE p_kind WHEN 'credit' THEN p_value ELSE -p_value END
+  WHERE wallets.id = p_wallet_id
+    AND (p_kind = 'credit' OR wallets.balance - p_value >= -wallets.credit_limit)
+  RETURNING wallets.balance, wallets.credit_limit INTO v_balance, v_credit_limit;
+
+  IF NOT FOUND THEN
+    IF EXISTS (SELECT 1 FROM wallets WHERE wallets.id = p_wallet_id) THEN
+      RAISE check_violation USING
+        MESSAGE = 'transaction would exceed the credit limit',
+        CONSTRAINT = 'positive_balance';
+    END IF;
+    RETURN;
+  END IF;
+
+  RETURN QUERY
+  INSERT INTO transactions (wallet_id, value, kind, description)
+  VALUES (p_wallet_id, p_value, p_kind, p_description)
+  RETURNING v_balance, v_credit_limit, transactions.public_id, transactions.inserted_at;
+END;
+$$ LANGUAGE plpgsql;
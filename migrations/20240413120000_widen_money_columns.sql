@@ -0,0 +1,48 @@
+-- amounts and balances go past what INT holds well before anyone's rich.
+-- the function's signature names the old types, so it's dropped and created
+-- again with the new ones
+DROP FUNCTION register_transaction(INTEGER, INTEGER, transaction_kind, TEXT);
+
+ALTER TABLE wallets
+  ALTER COLUMN balance TYPE BIGINT,
+  ALTER COLUMN credit_limit TYPE BIGINT;
+
+ALTER TABLE transactions ALTER COLUMN value TYPE BIGINT;
+
+CREATE FUNCTION register_transaction(
+  p_wallet_id INTEGER,
+  p_value BIGINT,
+  p_kind transaction_kind,
+  p_description TEXT
+) RETURNS TABLE (
+  balance BIGINT,
+  credit_limit BIGINT,
+  public_id UUID,
+  inserted_at TIMESTAMP WITH TIME ZONE
+) AS $$
+#variable_conflict use_column
+DECLARE
+  v_balance BIGINT;
+  v_credit_limit BIGINT;
+BEGIN
+  UPDATE wallets
+  SET balance = wallets.balance + CASE p_kind WHEN 'credit' THEN p_value ELSE -p_value END
+  WHERE wallets.id = p_wallet_id
+    AND (p_kind = 'credit' OR wallets.balance - p_value >= -wallets.credit_limit)
+  RETURNING wallets.balance, wallets.credit_limit INTO v_balance, v_credit_limit;
+
+  IF NOT FOUND THEN
+    IF EXISTS (SELECT 1 FROM wallets WHERE wallets.id = p_wallet_id) THEN
+      RAISE check_violation USING
+        MESSAGE = 'transaction would exceed the credit limit',
+        CONSTRAINT = 'positive_balance';
+    END IF;
+    RETURN;
+  END IF;
+
+  RETURN QUERY
+  INSERT INTO transactions (wallet_id, value, kind, description)
+  VALUES (p_wallet_id, p_value, p_kind, p_description)
+  RETURNING v_balance, v_credit_limit, transactions.public_id, transactions.inserted_at;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,7 @@
+-- SUM over a BIGINT comes back as NUMERIC. this one keeps the type of the
+-- column it adds up, whichever that is, and is 0 for no rows at all
+CREATE AGGREGATE money_sum(BIGINT) (
+  SFUNC = int8pl,
+  STYPE = BIGINT,
+  INITCOND = '0'
+);
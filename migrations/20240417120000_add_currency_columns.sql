@@ -0,0 +1,33 @@
+-- the ISO 4217 code of the money a wallet holds, fixed once it's created.
+-- the wallets that came before are all reais
+ALTER TABLE wallets
+  ADD COLUMN currency CHAR(3) NOT NULL DEFAULT 'BRL'
+  CONSTRAINT currency_code CHECK (currency ~ '^[A-Z]{3}$');
+
+-- always the wallet's. no default, so the trigger below can fill in the rows
+-- that don't say and turn away the ones that say another
+ALTER TABLE transactions ADD COLUMN currency CHAR(3);
+UPDATE transactions SET currency = 'BRL';
+ALTER TABLE transactions ALTER COLUMN currency SET NOT NULL;
+
+CREATE FUNCTION fill_transaction_currency() RETURNS trigger AS $$
+DECLARE
+  v_currency CHAR(3);
+BEGIN
+  SELECT currency INTO v_currency FROM wallets WHERE id = NEW.wallet_id;
+
+  IF NEW.currency IS NULL THEN
+    NEW.currency := v_currency;
+  ELSIF NEW.currency IS DISTINCT FROM v_currency THEN
+    RAISE check_violation USING
+      MESSAGE = 'transaction currency doesn''t match the wallet''s',
+      CONSTRAINT = 'currency_mismatch';
+  END IF;
+
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_fill_currency
+BEFORE INSERT ON transactions
+FOR EACH ROW EXECUTE FUNCTION fill_transaction_currency();
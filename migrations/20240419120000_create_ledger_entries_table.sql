@@ -0,0 +1,50 @@
+-- the double-entry side of every transaction: the wallet's account moves by
+-- its value and the cash account by the same value the other way, so the
+-- entries of a transaction always add up to zero. the trigger below writes
+-- them whichever path inserted the transaction
+CREATE TYPE ledger_account AS ENUM ('wallet', 'cash');
+
+CREATE TABLE ledger_entries (
+  id BIGSERIAL PRIMARY KEY,
+  transaction_id INT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+  -- the transaction's wallet, on both of its entries
+  wallet_id INT NOT NULL REFERENCES wallets(id),
+  account ledger_account NOT NULL,
+  -- positive when the account's balance goes up
+  amount BIGINT NOT NULL
+);
+
+CREATE INDEX ledger_entries_transaction_id_index ON ledger_entries (transaction_id);
+
+CREATE INDEX ledger_entries_wallet_id_index ON ledger_entries (wallet_id, account);
+
+-- once per statement, so a batch or a transfer writes all of its entries in
+-- one insert
+CREATE FUNCTION record_ledger_entries() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO ledger_entries (transaction_id, wallet_id, account, amount)
+  SELECT
+    inserted.id,
+    inserted.wallet_id,
+    entry.account,
+    entry.sign * CASE inserted.kind WHEN 'credit' THEN inserted.value ELSE -inserted.value END
+  FROM inserted,
+    (VALUES ('wallet'::ledger_account, 1), ('cash'::ledger_account, -1)) AS entry(account, sign);
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_record_ledger
+AFTER INSERT ON transactions
+REFERENCING NEW TABLE AS inserted
+FOR EACH STATEMENT EXECUTE FUNCTION record_ledger_entries();
+
+INSERT INTO ledger_entries (transaction_id, wallet_id, account, amount)
+SELECT
+  transactions.id,
+  transactions.wallet_id,
+  entry.account,
+  entry.sign * CASE transactions.kind WHEN 'credit' THEN transactions.value ELSE -transactions.value END
+FROM transactions,
+  (VALUES ('wallet'::ledger_account, 1), ('cash'::ledger_account, -1)) AS entry(account, sign);
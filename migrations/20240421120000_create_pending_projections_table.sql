@@ -0,0 +1,35 @@
+-- the balance changes the event sourcing mode appended and the projector
+-- hasn't folded into `wallets.balance` yet. that mode's writes only ever
+-- insert, here and into `transactions`, and a wallet's balance is its
+-- projection plus its rows here. the other mode never writes to it
+CREATE TABLE pending_projections (
+  id BIGSERIAL PRIMARY KEY,
+  wallet_id INT NOT NULL REFERENCES wallets(id),
+  delta BIGINT NOT NULL
+);
+
+CREATE INDEX pending_projections_wallet_id_index ON pending_projections (wallet_id);
+
+-- the events carry the balance including what's still pending, the mode's
+-- writes append their pending row before their transactions
+CREATE OR REPLACE FUNCTION notify_transaction() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('transactions', json_build_object(
+    'cliente_id', NEW.wallet_id,
+    'saldo', COALESCE(wallets.balance, 0) + COALESCE((
+      SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = NEW.wallet_id
+    ), 0),
+    'limite', COALESCE(wallets.credit_limit, 0),
+    'transacao', json_build_object(
+      'valor', NEW.value,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'realizada_em', NEW.inserted_at
+    )
+  )::text)
+  FROM wallets
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,48 @@
+-- urls that get every new transaction of their wallet posted to them,
+-- signed with `secret`
+CREATE TABLE webhooks (
+  id SERIAL PRIMARY KEY,
+  wallet_id INT NOT NULL REFERENCES wallets(id),
+  url TEXT NOT NULL,
+  secret TEXT NOT NULL,
+  inserted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX webhooks_wallet_id_index ON webhooks (wallet_id);
+
+-- the deliveries still owed. the trigger below writes them in the same
+-- transaction as the row they announce, so there's one for every committed
+-- transaction and none for a rolled back one. a row goes once its delivery
+-- succeeded, and stays with `failed_at` set once the attempts ran out
+CREATE TABLE webhook_outbox (
+  id BIGSERIAL PRIMARY KEY,
+  webhook_id INT NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+  transaction_id INT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+  attempts INT NOT NULL DEFAULT 0,
+  next_attempt_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  last_error TEXT,
+  failed_at TIMESTAMP WITH TIME ZONE
+);
+
+CREATE INDEX webhook_outbox_due_index ON webhook_outbox (next_attempt_at) WHERE failed_at IS NULL;
+
+CREATE INDEX webhook_outbox_transaction_id_index ON webhook_outbox (transaction_id);
+
+-- once per statement like the ledger's, a wallet without webhooks costs a
+-- lookup in `webhooks_wallet_id_index`
+CREATE FUNCTION enqueue_webhook_deliveries() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO webhook_outbox (webhook_id, transaction_id)
+  SELECT webhooks.id, inserted.id
+  FROM inserted
+  JOIN webhooks ON webhooks.wallet_id = inserted.wallet_id
+  ORDER BY inserted.id, webhooks.id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_enqueue_webhooks
+AFTER INSERT ON transactions
+REFERENCING NEW TABLE AS inserted
+FOR EACH STATEMENT EXECUTE FUNCTION enqueue_webhook_deliveries();
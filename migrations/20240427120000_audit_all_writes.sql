@@ -0,0 +1,87 @@
+-- the audit log goes from credit limit changes to every write: the triggers
+-- below add a row for each new wallet and each new transaction, whichever
+-- path inserted it. `db::begin` sets `rinha.actor` and `rinha.request_id`
+-- for the transaction, writes made without them are the `system`'s
+ALTER TABLE audit_log ADD COLUMN request_id VARCHAR(128);
+
+CREATE INDEX audit_log_inserted_at_index ON audit_log (inserted_at);
+
+ALTER TABLE api_keys DROP CONSTRAINT api_keys_scope_check;
+ALTER TABLE api_keys ADD CONSTRAINT api_keys_scope_check
+  CHECK (scope IN ('read', 'write', 'admin'));
+
+-- empty rather than missing once a transaction that set it ended
+CREATE FUNCTION audit_setting(p_name TEXT) RETURNS TEXT AS $$
+  SELECT NULLIF(current_setting(p_name, true), '');
+$$ LANGUAGE sql STABLE;
+
+CREATE FUNCTION audit_wallets() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, request_id)
+  SELECT
+    inserted.id,
+    'wallet_created',
+    NULL,
+    jsonb_strip_nulls(jsonb_build_object(
+      'saldo', inserted.balance,
+      'limite', inserted.credit_limit,
+      'moeda', inserted.currency,
+      'id_externo', inserted.external_id
+    )),
+    COALESCE(audit_setting('rinha.actor'), 'system'),
+    audit_setting('rinha.request_id')
+  FROM inserted
+  ORDER BY inserted.id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER wallets_audit
+AFTER INSERT ON wallets
+REFERENCING NEW TABLE AS inserted
+FOR EACH STATEMENT EXECUTE FUNCTION audit_wallets();
+
+CREATE FUNCTION audit_transactions() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, request_id)
+  SELECT
+    inserted.wallet_id,
+    'transaction_created',
+    NULL,
+    jsonb_strip_nulls(jsonb_build_object(
+      'id', inserted.public_id,
+      'valor', inserted.value,
+      'tipo', CASE inserted.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', inserted.description,
+      'moeda', inserted.currency,
+      'transferencia_id', inserted.transfer_id
+    )),
+    COALESCE(audit_setting('rinha.actor'), 'system'),
+    audit_setting('rinha.request_id')
+  FROM inserted
+  ORDER BY inserted.id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_audit
+AFTER INSERT ON transactions
+REFERENCING NEW TABLE AS inserted
+FOR EACH STATEMENT EXECUTE FUNCTION audit_transactions();
+
+-- rows are only ever added
+CREATE FUNCTION forbid_audit_log_changes() RETURNS trigger AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_log_append_only
+BEFORE UPDATE OR DELETE ON audit_log
+FOR EACH ROW EXECUTE FUNCTION forbid_audit_log_changes();
+
+CREATE TRIGGER audit_log_no_truncate
+BEFORE TRUNCATE ON audit_log
+FOR EACH STATEMENT EXECUTE FUNCTION forbid_audit_log_changes();
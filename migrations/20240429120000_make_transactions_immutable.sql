@@ -0,0 +1,23 @@
+-- a transaction is never edited nor deleted once written: a mistake is undone
+-- by another transaction going the other way, which leaves both on record.
+-- the one exception is `db::seed` wiping the seed wallets between load test
+-- runs, which sets `rinha.reset_history` for its own transaction. STRICT_LEDGER
+-- refuses to start when these triggers are missing or disabled
+CREATE FUNCTION forbid_transaction_changes() RETURNS trigger AS $$
+BEGIN
+  IF current_setting('rinha.reset_history', true) = 'on' THEN
+    RETURN OLD;
+  END IF;
+
+  RAISE EXCEPTION 'transactions are immutable, reverse them with a new one instead'
+    USING ERRCODE = 'restrict_violation';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_immutable
+BEFORE UPDATE OR DELETE ON transactions
+FOR EACH ROW EXECUTE FUNCTION forbid_transaction_changes();
+
+CREATE TRIGGER transactions_no_truncate
+BEFORE TRUNCATE ON transactions
+FOR EACH STATEMENT EXECUTE FUNCTION forbid_transaction_changes();
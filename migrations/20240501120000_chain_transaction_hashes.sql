@@ -0,0 +1,106 @@
+-- every transaction carries the sha256 of its own contents and of the hash of
+-- the wallet's transaction before it, so editing, removing or reordering one
+-- breaks every link after it. `chain_position` counts the wallet's
+-- transactions from 1 and the wallet keeps the last hash and position, which
+-- is what gives away the newest ones going missing. verified by
+-- `GET /clientes/:id/transacoes/verify`
+ALTER TABLE transactions
+  ADD COLUMN chain_position BIGINT,
+  ADD COLUMN prev_hash BYTEA,
+  ADD COLUMN hash BYTEA;
+
+ALTER TABLE wallets
+  ADD COLUMN chain_head BYTEA,
+  ADD COLUMN chain_length BIGINT NOT NULL DEFAULT 0;
+
+-- what goes into the hash: every column a reader sees plus the link, as a
+-- json array so no two rows serialize alike. the timestamp as epoch
+-- microseconds, which doesn't depend on the session's time zone
+CREATE FUNCTION transaction_hash(p_transaction transactions) RETURNS BYTEA AS $$
+  SELECT sha256(convert_to(jsonb_build_array(
+    encode(p_transaction.prev_hash, 'hex'),
+    p_transaction.chain_position,
+    p_transaction.wallet_id,
+    p_transaction.public_id,
+    p_transaction.value,
+    p_transaction.kind,
+    p_transaction.description,
+    p_transaction.currency,
+    p_transaction.transfer_id,
+    (extract(epoch FROM p_transaction.inserted_at) * 1000000)::BIGINT
+  )::TEXT, 'UTF8'));
+$$ LANGUAGE sql IMMUTABLE;
+
+-- takes the wallet's row lock, so two inserts on the same wallet can't both
+-- link to the same transaction, whichever path they come from
+CREATE FUNCTION chain_transaction() RETURNS trigger AS $$
+BEGIN
+  SELECT chain_head, chain_length + 1
+  INTO NEW.prev_hash, NEW.chain_position
+  FROM wallets
+  WHERE id = NEW.wallet_id
+  FOR UPDATE;
+
+  NEW.hash := transaction_hash(NEW);
+
+  UPDATE wallets
+  SET chain_head = NEW.hash, chain_length = NEW.chain_position
+  WHERE id = NEW.wallet_id;
+
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- the transactions there already are, in the order they were inserted. the
+-- one update `transactions_immutable` lets through is this one
+ALTER TABLE transactions DISABLE TRIGGER transactions_immutable;
+
+DO $$
+DECLARE
+  chained transactions;
+  head BYTEA;
+  position BIGINT;
+  wallet INT;
+BEGIN
+  FOR chained IN SELECT * FROM transactions ORDER BY wallet_id, id LOOP
+    IF wallet IS DISTINCT FROM chained.wallet_id THEN
+      wallet := chained.wallet_id;
+      head := NULL;
+      position := 0;
+    END IF;
+
+    position := position + 1;
+    chained.prev_hash := head;
+    chained.chain_position := position;
+    head := transaction_hash(chained);
+
+    UPDATE transactions
+    SET chain_position = position, prev_hash = chained.prev_hash, hash = head
+    WHERE id = chained.id;
+  END LOOP;
+END;
+$$;
+
+ALTER TABLE transactions ENABLE TRIGGER transactions_immutable;
+
+UPDATE wallets
+SET chain_head = last.hash, chain_length = last.chain_position
+FROM (
+  SELECT DISTINCT ON (wallet_id) wallet_id, hash, chain_position
+  FROM transactions
+  ORDER BY wallet_id, chain_position DESC
+) AS last
+WHERE wallets.id = last.wallet_id;
+
+ALTER TABLE transactions
+  ALTER COLUMN chain_position SET NOT NULL,
+  ALTER COLUMN hash SET NOT NULL;
+
+CREATE UNIQUE INDEX transactions_wallet_id_chain_position_index
+  ON transactions (wallet_id, chain_position);
+
+-- after `transactions_fill_currency`, triggers of the same kind fire in name
+-- order and the currency is part of the hash
+CREATE TRIGGER transactions_hash_chain
+BEFORE INSERT ON transactions
+FOR EACH ROW EXECUTE FUNCTION chain_transaction();
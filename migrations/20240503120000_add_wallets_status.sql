@@ -0,0 +1,30 @@
+-- a blocked wallet takes no new transactions, whichever path they come
+-- from: the trigger below turns each one away with `wallet_blocked`, which
+-- rolls back the balance update of the same statement. reads are left alone
+CREATE TYPE wallet_status AS ENUM ('active', 'blocked');
+
+ALTER TABLE wallets ADD COLUMN status wallet_status NOT NULL DEFAULT 'active';
+
+-- the write-behind queue inserts rows whose balance update went through
+-- before the wallet was blocked, and sets `rinha.write_behind` to get them in
+CREATE FUNCTION check_wallet_status() RETURNS trigger AS $$
+BEGIN
+  IF current_setting('rinha.write_behind', true) = 'on' THEN
+    RETURN NEW;
+  END IF;
+
+  IF EXISTS (SELECT 1 FROM wallets WHERE id = NEW.wallet_id AND status = 'blocked') THEN
+    RAISE check_violation USING
+      MESSAGE = 'the wallet is blocked',
+      CONSTRAINT = 'wallet_blocked';
+  END IF;
+
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- before `transactions_fill_currency` and `transactions_hash_chain`, which
+-- fire in name order after it
+CREATE TRIGGER transactions_check_status
+BEFORE INSERT ON transactions
+FOR EACH ROW EXECUTE FUNCTION check_wallet_status();
@@ -0,0 +1,65 @@
+-- how much a wallet may debit in a day (utc), transfers out included. none
+-- when NULL. the trigger below adds up the day's debits under the wallet's
+-- row lock, so two concurrent debits can't both squeeze in under it
+ALTER TABLE wallets ADD COLUMN daily_debit_limit BIGINT
+  CONSTRAINT daily_debit_limit_not_negative CHECK (daily_debit_limit >= 0);
+
+-- the rows of a multi-row insert that came before are already visible here,
+-- so a batch counts its own debits too. the write-behind queue only takes
+-- debits of wallets without a limit, see `db::update_balance`
+CREATE FUNCTION check_daily_debits() RETURNS trigger AS $$
+BEGIN
+  IF NEW.kind = 'credit' OR current_setting('rinha.write_behind', true) = 'on' THEN
+    RETURN NEW;
+  END IF;
+
+  PERFORM 1
+  FROM wallets
+  WHERE id = NEW.wallet_id
+    AND daily_debit_limit IS NOT NULL
+    AND NEW.value + (
+      SELECT money_sum(value)
+      FROM transactions
+      WHERE wallet_id = NEW.wallet_id
+        AND kind = 'debit'
+        AND inserted_at >= date_trunc('day', now(), 'UTC')
+    ) > daily_debit_limit
+  FOR UPDATE;
+
+  IF FOUND THEN
+    RAISE check_violation USING
+      MESSAGE = 'transaction would exceed the daily debit limit',
+      CONSTRAINT = 'daily_debit_limit';
+  END IF;
+
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- after `transactions_check_status`, a blocked wallet is reported as such
+CREATE TRIGGER transactions_limit_daily_debits
+BEFORE INSERT ON transactions
+FOR EACH ROW EXECUTE FUNCTION check_daily_debits();
+
+CREATE OR REPLACE FUNCTION audit_wallets() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, request_id)
+  SELECT
+    inserted.id,
+    'wallet_created',
+    NULL,
+    jsonb_strip_nulls(jsonb_build_object(
+      'saldo', inserted.balance,
+      'limite', inserted.credit_limit,
+      'limite_diario', inserted.daily_debit_limit,
+      'moeda', inserted.currency,
+      'id_externo', inserted.external_id
+    )),
+    COALESCE(audit_setting('rinha.actor'), 'system'),
+    audit_setting('rinha.request_id')
+  FROM inserted
+  ORDER BY inserted.id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,60 @@
+CREATE TYPE hold_status AS ENUM ('pending', 'captured', 'released', 'expired');
+
+-- what the pending holds of a wallet keep aside. it comes off what the
+-- wallet can spend, so a debit that would dig into it fails on the
+-- `positive_balance` constraint like one past the credit limit
+ALTER TABLE wallets ADD COLUMN held BIGINT NOT NULL DEFAULT 0
+  CONSTRAINT held_not_negative CHECK (held >= 0);
+
+ALTER TABLE wallets DROP CONSTRAINT positive_balance;
+ALTER TABLE wallets ADD CONSTRAINT positive_balance
+  CHECK (credit_limit + balance - held >= 0);
+
+-- an amount set aside until it's captured into a debit, released, or it
+-- expires. `held` is moved with the status, by the same statement or
+-- transaction
+CREATE TABLE holds (
+  id SERIAL PRIMARY KEY,
+  public_id UUID NOT NULL DEFAULT gen_random_uuid() UNIQUE,
+  wallet_id INT NOT NULL REFERENCES wallets(id),
+  value BIGINT NOT NULL CONSTRAINT positive_value CHECK (value > 0),
+  description VARCHAR(10) NOT NULL,
+  status hold_status NOT NULL DEFAULT 'pending',
+  expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  inserted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  settled_at TIMESTAMP WITH TIME ZONE,
+  -- the debit a captured hold became
+  transaction_id UUID
+);
+
+CREATE INDEX holds_wallet_id_index ON holds (wallet_id);
+
+-- what the sweeper goes through
+CREATE INDEX holds_pending_expires_at_index ON holds (expires_at) WHERE status = 'pending';
+
+CREATE FUNCTION audit_holds() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, request_id)
+  VALUES (
+    NEW.wallet_id,
+    CASE WHEN TG_OP = 'INSERT' THEN 'hold_created' ELSE 'hold_' || NEW.status END,
+    CASE WHEN TG_OP = 'UPDATE' THEN jsonb_build_object('id', OLD.public_id, 'status', OLD.status) END,
+    jsonb_strip_nulls(jsonb_build_object(
+      'id', NEW.public_id,
+      'valor', NEW.value,
+      'descricao', NEW.description,
+      'status', NEW.status,
+      'expira_em', NEW.expires_at,
+      'transacao_id', NEW.transaction_id
+    )),
+    COALESCE(audit_setting('rinha.actor'), 'system'),
+    audit_setting('rinha.request_id')
+  );
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER holds_audit
+AFTER INSERT OR UPDATE OF status ON holds
+FOR EACH ROW EXECUTE FUNCTION audit_holds();
@@ -0,0 +1,56 @@
+CREATE TYPE scheduled_transaction_status AS ENUM ('pending', 'applied', 'failed', 'cancelled');
+
+-- transactions posted with `agendada_para`, waiting for the scheduler to
+-- apply them at that time. the balance checks run then, one that's turned
+-- away is kept as failed along with the error code. the currency was
+-- checked when it was scheduled, a wallet's never changes
+CREATE TABLE scheduled_transactions (
+  id SERIAL PRIMARY KEY,
+  public_id UUID NOT NULL DEFAULT gen_random_uuid() UNIQUE,
+  wallet_id INT NOT NULL REFERENCES wallets(id),
+  value BIGINT NOT NULL CONSTRAINT positive_value CHECK (value > 0),
+  kind transaction_kind NOT NULL,
+  description VARCHAR(10) NOT NULL,
+  due_at TIMESTAMP WITH TIME ZONE NOT NULL,
+  status scheduled_transaction_status NOT NULL DEFAULT 'pending',
+  inserted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  settled_at TIMESTAMP WITH TIME ZONE,
+  -- the transaction an applied one became
+  transaction_id UUID,
+  failure VARCHAR(64)
+);
+
+CREATE INDEX scheduled_transactions_wallet_id_index ON scheduled_transactions (wallet_id);
+
+-- what the scheduler goes through
+CREATE INDEX scheduled_transactions_pending_due_at_index
+  ON scheduled_transactions (due_at) WHERE status = 'pending';
+
+CREATE FUNCTION audit_scheduled_transactions() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, request_id)
+  VALUES (
+    NEW.wallet_id,
+    CASE WHEN TG_OP = 'INSERT' THEN 'transaction_scheduled' ELSE 'scheduled_transaction_' || NEW.status END,
+    CASE WHEN TG_OP = 'UPDATE' THEN jsonb_build_object('id', OLD.public_id, 'status', OLD.status) END,
+    jsonb_strip_nulls(jsonb_build_object(
+      'id', NEW.public_id,
+      'valor', NEW.value,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'agendada_para', NEW.due_at,
+      'status', NEW.status,
+      'transacao_id', NEW.transaction_id,
+      'motivo', NEW.failure
+    )),
+    COALESCE(audit_setting('rinha.actor'), 'system'),
+    audit_setting('rinha.request_id')
+  );
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER scheduled_transactions_audit
+AFTER INSERT OR UPDATE OF status ON scheduled_transactions
+FOR EACH ROW EXECUTE FUNCTION audit_scheduled_transactions();
@@ -0,0 +1,67 @@
+-- transactions made again and again on a crontab-like `schedule`, in utc.
+-- the scheduler applies each one at `next_run_at` and works out the run
+-- after it, runs missed while no instance was up are skipped. a paused one
+-- has no `next_run_at`. one the limits or the wallet's status turn away
+-- keeps the error code in `last_failure` until the next run
+CREATE TABLE recurring_transactions (
+  id SERIAL PRIMARY KEY,
+  wallet_id INT NOT NULL REFERENCES wallets(id),
+  value BIGINT NOT NULL CONSTRAINT positive_value CHECK (value > 0),
+  kind transaction_kind NOT NULL,
+  description VARCHAR(10) NOT NULL,
+  schedule VARCHAR(255) NOT NULL,
+  next_run_at TIMESTAMP WITH TIME ZONE,
+  last_run_at TIMESTAMP WITH TIME ZONE,
+  last_failure VARCHAR(64),
+  inserted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+CREATE INDEX recurring_transactions_wallet_id_index ON recurring_transactions (wallet_id);
+
+-- what the scheduler goes through
+CREATE INDEX recurring_transactions_next_run_at_index
+  ON recurring_transactions (next_run_at) WHERE next_run_at IS NOT NULL;
+
+-- the definitions' changes, not the runs: the transactions those make are
+-- in the log already
+CREATE FUNCTION audit_recurring_transactions() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'UPDATE' AND (OLD.value, OLD.kind, OLD.description, OLD.schedule, OLD.next_run_at IS NULL)
+      IS NOT DISTINCT FROM (NEW.value, NEW.kind, NEW.description, NEW.schedule, NEW.next_run_at IS NULL) THEN
+    RETURN NULL;
+  END IF;
+
+  INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, request_id)
+  SELECT
+    COALESCE(NEW.wallet_id, OLD.wallet_id),
+    CASE TG_OP
+      WHEN 'INSERT' THEN 'recurrence_created'
+      WHEN 'UPDATE' THEN 'recurrence_updated'
+      ELSE 'recurrence_deleted'
+    END,
+    CASE WHEN TG_OP <> 'INSERT' THEN jsonb_build_object(
+      'id', OLD.id,
+      'valor', OLD.value,
+      'tipo', CASE OLD.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', OLD.description,
+      'cron', OLD.schedule,
+      'ativa', OLD.next_run_at IS NOT NULL
+    ) END,
+    CASE WHEN TG_OP <> 'DELETE' THEN jsonb_build_object(
+      'id', NEW.id,
+      'valor', NEW.value,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'cron', NEW.schedule,
+      'ativa', NEW.next_run_at IS NOT NULL
+    ) END,
+    COALESCE(audit_setting('rinha.actor'), 'system'),
+    audit_setting('rinha.request_id');
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER recurring_transactions_audit
+AFTER INSERT OR UPDATE OR DELETE ON recurring_transactions
+FOR EACH ROW EXECUTE FUNCTION audit_recurring_transactions();
@@ -0,0 +1,40 @@
+-- what the system made a transaction as, e.g. `juros` for the interest
+-- the accruals debit. NULL for the clients' own. the writes that make one
+-- set `rinha.category` for their transaction, this picks it up whichever
+-- path inserts the row
+ALTER TABLE transactions ADD COLUMN category VARCHAR(32);
+
+CREATE FUNCTION categorize_transaction() RETURNS trigger AS $$
+BEGIN
+  NEW.category := COALESCE(NEW.category, audit_setting('rinha.category'));
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_categorize
+BEFORE INSERT ON transactions
+FOR EACH ROW EXECUTE FUNCTION categorize_transaction();
+
+-- the daily rate charged on a negative balance, e.g. 0.001 for 0.1% a day.
+-- NULL leaves the wallet to INTEREST_DAILY_RATE
+ALTER TABLE wallets ADD COLUMN interest_rate NUMERIC
+  CONSTRAINT interest_rate_range CHECK (interest_rate BETWEEN 0 AND 1);
+
+-- one row per wallet and (utc) day interest was worked out for, whether it
+-- came to a debit or not, so a day's never charged twice. the debit's
+-- transaction, or the code of the error that turned it away
+CREATE TABLE interest_accruals (
+  wallet_id INT NOT NULL REFERENCES wallets(id),
+  day DATE NOT NULL,
+  value BIGINT NOT NULL,
+  transaction_id UUID,
+  failure VARCHAR(64),
+  inserted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+  PRIMARY KEY (wallet_id, day)
+);
+
+-- a day's interest on `balance`, rounded to the cent. the decimal
+-- migrations add the NUMERIC one
+CREATE FUNCTION interest_on(balance BIGINT, rate NUMERIC) RETURNS BIGINT AS $$
+  SELECT GREATEST(ROUND(-balance * rate), 0)::bigint;
+$$ LANGUAGE sql IMMUTABLE;
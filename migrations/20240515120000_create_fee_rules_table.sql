@@ -0,0 +1,37 @@
+-- what's charged alongside every transaction of `kind` of at least
+-- `min_value`: `flat` plus `percentage` of its value, as a debit described
+-- like the rule
+CREATE TABLE fee_rules (
+  id SERIAL PRIMARY KEY,
+  kind transaction_kind NOT NULL,
+  min_value BIGINT,
+  flat BIGINT NOT NULL DEFAULT 0 CHECK (flat >= 0),
+  percentage NUMERIC NOT NULL DEFAULT 0 CHECK (percentage BETWEEN 0 AND 1),
+  description VARCHAR(10) NOT NULL,
+  active BOOLEAN NOT NULL DEFAULT true,
+  inserted_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+-- the transaction a fee was charged for. the writes that charge one set
+-- `rinha.fee_of` around the fee's insert, like `rinha.category`
+ALTER TABLE transactions ADD COLUMN fee_of UUID;
+
+CREATE FUNCTION link_fee() RETURNS trigger AS $$
+BEGIN
+  NEW.fee_of := COALESCE(NEW.fee_of, audit_setting('rinha.fee_of')::uuid);
+  IF NEW.fee_of IS NOT NULL THEN
+    NEW.category := COALESCE(NEW.category, 'tarifa');
+  END IF;
+  RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_link_fee
+BEFORE INSERT ON transactions
+FOR EACH ROW EXECUTE FUNCTION link_fee();
+
+-- a rule's fee on `value`, rounded to the cent. the decimal migrations add
+-- the NUMERIC one
+CREATE FUNCTION fee_on(value BIGINT, flat BIGINT, percentage NUMERIC) RETURNS BIGINT AS $$
+  SELECT flat + ROUND(value * percentage)::bigint;
+$$ LANGUAGE sql IMMUTABLE;
@@ -0,0 +1,103 @@
+-- the clients' own labels. `category` already keeps the ones the system
+-- gives, like `juros`, and now takes theirs as well
+ALTER TABLE transactions ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+
+-- carried over to the transactions the scheduler makes
+ALTER TABLE scheduled_transactions
+  ADD COLUMN category VARCHAR(32),
+  ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+
+ALTER TABLE recurring_transactions
+  ADD COLUMN category VARCHAR(32),
+  ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}';
+
+-- the function takes both. the decimal builds drop their own in theirs
+DROP FUNCTION IF EXISTS register_transaction(INTEGER, BIGINT, transaction_kind, TEXT);
+
+CREATE FUNCTION register_transaction(
+  p_wallet_id INTEGER,
+  p_value BIGINT,
+  p_kind transaction_kind,
+  p_description TEXT,
+  p_category TEXT,
+  p_tags TEXT[]
+) RETURNS TABLE (
+  balance BIGINT,
+  credit_limit BIGINT,
+  public_id UUID,
+  inserted_at TIMESTAMP WITH TIME ZONE
+) AS $$
+#variable_conflict use_column
+DECLARE
+  v_balance BIGINT;
+  v_credit_limit BIGINT;
+BEGIN
+  UPDATE wallets
+  SET balance = wallets.balance + CASE p_kind WHEN 'credit' THEN p_value ELSE -p_value END
+  WHERE wallets.id = p_wallet_id
+    AND (p_kind = 'credit' OR wallets.balance - p_value >= -wallets.credit_limit)
+  RETURNING wallets.balance, wallets.credit_limit INTO v_balance, v_credit_limit;
+
+  IF NOT FOUND THEN
+    IF EXISTS (SELECT 1 FROM wallets WHERE wallets.id = p_wallet_id) THEN
+      RAISE check_violation USING
+        MESSAGE = 'transaction would exceed the credit limit',
+        CONSTRAINT = 'positive_balance';
+    END IF;
+    RETURN;
+  END IF;
+
+  RETURN QUERY
+  INSERT INTO transactions (wallet_id, value, kind, description, category, tags)
+  VALUES (p_wallet_id, p_value, p_kind, p_description, p_category, p_tags)
+  RETURNING v_balance, v_credit_limit, transactions.public_id, transactions.inserted_at;
+END;
+$$ LANGUAGE plpgsql;
+
+-- the json of `models::TransactionItem` carries them, when there are any
+CREATE OR REPLACE FUNCTION notify_transaction() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('transactions', json_build_object(
+    'cliente_id', NEW.wallet_id,
+    'saldo', COALESCE(wallets.balance, 0) + COALESCE((
+      SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = NEW.wallet_id
+    ), 0),
+    'limite', COALESCE(wallets.credit_limit, 0),
+    'transacao', json_strip_nulls(json_build_object(
+      'valor', NEW.value,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'realizada_em', NEW.inserted_at,
+      'categoria', NEW.category,
+      'tags', NULLIF(NEW.tags, '{}')
+    ))
+  )::text)
+  FROM wallets
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION remember_recent_transaction() RETURNS trigger AS $$
+BEGIN
+  UPDATE wallets
+  SET recent_transactions = (
+    SELECT jsonb_agg(recent.item ORDER BY recent.position)
+    FROM jsonb_array_elements(
+      jsonb_build_array(jsonb_strip_nulls(jsonb_build_object(
+        'valor', NEW.value,
+        'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+        'descricao', NEW.description,
+        'realizada_em', NEW.inserted_at,
+        'categoria', NEW.category,
+        'tags', NULLIF(NEW.tags, '{}')
+      ))) || wallets.recent_transactions
+    ) WITH ORDINALITY AS recent(item, position)
+    WHERE recent.position <= 10
+  )
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,103 @@
+-- what integrators attach to a transaction, an object like
+-- `{"pedido": 123}`. the scheduled and recurring ones carry it over like the
+-- tags
+ALTER TABLE transactions ADD COLUMN metadata JSONB;
+ALTER TABLE scheduled_transactions ADD COLUMN metadata JSONB;
+ALTER TABLE recurring_transactions ADD COLUMN metadata JSONB;
+
+-- the `metadata` member of the triggers' json, none without any. kept out of
+-- their jsonb_strip_nulls, which would take the nulls inside it as well
+CREATE FUNCTION transaction_metadata(p_metadata JSONB) RETURNS JSONB AS $$
+  SELECT CASE WHEN p_metadata IS NULL THEN '{}'::jsonb ELSE jsonb_build_object('metadata', p_metadata) END;
+$$ LANGUAGE sql IMMUTABLE;
+
+-- the function takes it as well. the decimal builds drop their own in theirs
+DROP FUNCTION IF EXISTS register_transaction(INTEGER, BIGINT, transaction_kind, TEXT, TEXT, TEXT[]);
+
+CREATE FUNCTION register_transaction(
+  p_wallet_id INTEGER,
+  p_value BIGINT,
+  p_kind transaction_kind,
+  p_description TEXT,
+  p_category TEXT,
+  p_tags TEXT[],
+  p_metadata JSONB
+) RETURNS TABLE (
+  balance BIGINT,
+  credit_limit BIGINT,
+  public_id UUID,
+  inserted_at TIMESTAMP WITH TIME ZONE
+) AS $$
+#variable_conflict use_column
+DECLARE
+  v_balance BIGINT;
+  v_credit_limit BIGINT;
+BEGIN
+  UPDATE wallets
+  SET balance = wallets.balance + CASE p_kind WHEN 'credit' THEN p_value ELSE -p_value END
+  WHERE wallets.id = p_wallet_id
+    AND (p_kind = 'credit' OR wallets.balance - p_value >= -wallets.credit_limit)
+  RETURNING wallets.balance, wallets.credit_limit INTO v_balance, v_credit_limit;
+
+  IF NOT FOUND THEN
+    IF EXISTS (SELECT 1 FROM wallets WHERE wallets.id = p_wallet_id) THEN
+      RAISE check_violation USING
+        MESSAGE = 'transaction would exceed the credit limit',
+        CONSTRAINT = 'positive_balance';
+    END IF;
+    RETURN;
+  END IF;
+
+  RETURN QUERY
+  INSERT INTO transactions (wallet_id, value, kind, description, category, tags, metadata)
+  VALUES (p_wallet_id, p_value, p_kind, p_description, p_category, p_tags, p_metadata)
+  RETURNING v_balance, v_credit_limit, transactions.public_id, transactions.inserted_at;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_transaction() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('transactions', json_build_object(
+    'cliente_id', NEW.wallet_id,
+    'saldo', COALESCE(wallets.balance, 0) + COALESCE((
+      SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = NEW.wallet_id
+    ), 0),
+    'limite', COALESCE(wallets.credit_limit, 0),
+    'transacao', jsonb_strip_nulls(jsonb_build_object(
+      'valor', NEW.value,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'realizada_em', NEW.inserted_at,
+      'categoria', NEW.category,
+      'tags', NULLIF(NEW.tags, '{}')
+    )) || transaction_metadata(NEW.metadata)
+  )::text)
+  FROM wallets
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION remember_recent_transaction() RETURNS trigger AS $$
+BEGIN
+  UPDATE wallets
+  SET recent_transactions = (
+    SELECT jsonb_agg(recent.item ORDER BY recent.position)
+    FROM jsonb_array_elements(
+      jsonb_build_array(jsonb_strip_nulls(jsonb_build_object(
+        'valor', NEW.value,
+        'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+        'descricao', NEW.description,
+        'realizada_em', NEW.inserted_at,
+        'categoria', NEW.category,
+        'tags', NULLIF(NEW.tags, '{}')
+      )) || transaction_metadata(NEW.metadata)) || wallets.recent_transactions
+    ) WITH ORDINALITY AS recent(item, position)
+    WHERE recent.position <= 10
+  )
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,30 @@
+-- the client an account belongs to. NULL for a client's own wallet, its
+-- default account, which keeps the id the old `/clientes/:id` urls use.
+-- accounts don't own accounts of their own
+ALTER TABLE wallets ADD COLUMN owner_id INT REFERENCES wallets (id);
+
+CREATE INDEX wallets_owner_id_idx ON wallets (owner_id) WHERE owner_id IS NOT NULL;
+
+CREATE OR REPLACE FUNCTION audit_wallets() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, request_id)
+  SELECT
+    inserted.id,
+    'wallet_created',
+    NULL,
+    jsonb_strip_nulls(jsonb_build_object(
+      'saldo', inserted.balance,
+      'limite', inserted.credit_limit,
+      'limite_diario', inserted.daily_debit_limit,
+      'moeda', inserted.currency,
+      'id_externo', inserted.external_id,
+      'cliente', inserted.owner_id
+    )),
+    COALESCE(audit_setting('rinha.actor'), 'system'),
+    audit_setting('rinha.request_id')
+  FROM inserted
+  ORDER BY inserted.id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,43 @@
+-- the tables of a tenant live in a schema of their own, but the notification
+-- channels are shared by the whole database. the tenants' ones are named after
+-- their schema, `tenant_acme.transactions`, the default dataset keeps the
+-- plain names
+CREATE FUNCTION schema_channel(schema_name TEXT, name TEXT) RETURNS TEXT AS $$
+  SELECT CASE schema_name WHEN 'public' THEN name ELSE schema_name || '.' || name END;
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION notify_transaction() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify(schema_channel(TG_TABLE_SCHEMA, 'transactions'), json_build_object(
+    'cliente_id', NEW.wallet_id,
+    'saldo', COALESCE(wallets.balance, 0) + COALESCE((
+      SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = NEW.wallet_id
+    ), 0),
+    'limite', COALESCE(wallets.credit_limit, 0),
+    'transacao', jsonb_strip_nulls(jsonb_build_object(
+      'valor', NEW.value,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'realizada_em', NEW.inserted_at,
+      'categoria', NEW.category,
+      'tags', NULLIF(NEW.tags, '{}')
+    )) || transaction_metadata(NEW.metadata)
+  )::text)
+  FROM wallets
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION notify_wallet_changed() RETURNS trigger AS $$
+BEGIN
+  IF TG_OP = 'TRUNCATE' THEN
+    PERFORM pg_notify(schema_channel(TG_TABLE_SCHEMA, 'wallet_changed'), '*');
+  ELSE
+    PERFORM pg_notify(schema_channel(TG_TABLE_SCHEMA, 'wallet_changed'), OLD.id::text);
+  END IF;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,5 @@
+-- the sha256 of the transaction a key was first used for, a retry under the
+-- same key with another one is turned away instead of answered with the
+-- first's response. NULL for the keys stored before, which are replayed
+-- whatever comes with them
+ALTER TABLE idempotency_keys ADD COLUMN fingerprint VARCHAR(64);
@@ -0,0 +1,107 @@
+-- only run by builds with the decimal feature, once the usual migrations are
+-- in. amounts can have fractions of a cent, so the money columns become
+-- NUMERIC. the function naming the old types is dropped and created again,
+-- and the json the triggers build carries amounts as strings, the way the
+-- api sends them
+DROP FUNCTION register_transaction(INTEGER, BIGINT, transaction_kind, TEXT);
+
+ALTER TABLE wallets
+  ALTER COLUMN balance TYPE NUMERIC,
+  ALTER COLUMN credit_limit TYPE NUMERIC;
+
+ALTER TABLE transactions ALTER COLUMN value TYPE NUMERIC;
+
+CREATE FUNCTION register_transaction(
+  p_wallet_id INTEGER,
+  p_value NUMERIC,
+  p_kind transaction_kind,
+  p_description TEXT
+) RETURNS TABLE (
+  balance NUMERIC,
+  credit_limit NUMERIC,
+  public_id UUID,
+  inserted_at TIMESTAMP WITH TIME ZONE
+) AS $$
+#variable_conflict use_column
+DECLARE
+  v_balance NUMERIC;
+  v_credit_limit NUMERIC;
+BEGIN
+  UPDATE wallets
+  SET balance = wallets.balance + CASE p_kind WHEN 'credit' THEN p_value ELSE -p_value END
+  WHERE wallets.id = p_wallet_id
+    AND (p_kind = 'credit' OR wallets.balance - p_value >= -wallets.credit_limit)
+  RETURNING wallets.balance, wallets.credit_limit INTO v_balance, v_credit_limit;
+
+  IF NOT FOUND THEN
+    IF EXISTS (SELECT 1 FROM wallets WHERE wallets.id = p_wallet_id) THEN
+      RAISE check_violation USING
+        MESSAGE = 'transaction would exceed the credit limit',
+        CONSTRAINT = 'positive_balance';
+    END IF;
+    RETURN;
+  END IF;
+
+  RETURN QUERY
+  INSERT INTO transactions (wallet_id, value, kind, description)
+  VALUES (p_wallet_id, p_value, p_kind, p_description)
+  RETURNING v_balance, v_credit_limit, transactions.public_id, transactions.inserted_at;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE AGGREGATE money_sum(NUMERIC) (
+  SFUNC = numeric_add,
+  STYPE = NUMERIC,
+  INITCOND = '0'
+);
+
+CREATE OR REPLACE FUNCTION notify_transaction() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('transactions', json_build_object(
+    'cliente_id', NEW.wallet_id,
+    'saldo', COALESCE(wallets.balance, 0)::text,
+    'limite', COALESCE(wallets.credit_limit, 0)::text,
+    'transacao', json_build_object(
+      'valor', NEW.value::text,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'realizada_em', NEW.inserted_at
+    )
+  )::text)
+  FROM wallets
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE OR REPLACE FUNCTION remember_recent_transaction() RETURNS trigger AS $$
+BEGIN
+  UPDATE wallets
+  SET recent_transactions = (
+    SELECT jsonb_agg(recent.item ORDER BY recent.position)
+    FROM jsonb_array_elements(
+      jsonb_build_array(jsonb_build_object(
+        'valor', NEW.value::text,
+        'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+        'descricao', NEW.description,
+        'realizada_em', NEW.inserted_at
+      )) || wallets.recent_transactions
+    ) WITH ORDINALITY AS recent(item, position)
+    WHERE recent.position <= 10
+  )
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+UPDATE wallets
+SET recent_transactions = COALESCE((
+  SELECT jsonb_agg(
+    jsonb_set(recent.item, '{valor}', to_jsonb(recent.item->>'valor'))
+    ORDER BY recent.position
+  )
+  FROM jsonb_array_elements(wallets.recent_transactions)
+    WITH ORDINALITY AS recent(item, position)
+), '[]');
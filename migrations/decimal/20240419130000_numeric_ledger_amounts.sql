@@ -0,0 +1,10 @@
+-- the ledger amounts are money like the rest. the backfill of the usual
+-- migration went through BIGINT and lost the fractions of a cent, so the
+-- entries are worked out again from their transactions
+ALTER TABLE ledger_entries ALTER COLUMN amount TYPE NUMERIC;
+
+UPDATE ledger_entries
+SET amount = CASE ledger_entries.account WHEN 'wallet' THEN 1 ELSE -1 END
+  * CASE transactions.kind WHEN 'credit' THEN transactions.value ELSE -transactions.value END
+FROM transactions
+WHERE transactions.id = ledger_entries.transaction_id;
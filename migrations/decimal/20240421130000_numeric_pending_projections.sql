@@ -0,0 +1,25 @@
+-- the pending deltas are money like the rest, and the events carry amounts
+-- as strings again now that the usual migration replaced the function
+ALTER TABLE pending_projections ALTER COLUMN delta TYPE NUMERIC;
+
+CREATE OR REPLACE FUNCTION notify_transaction() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify('transactions', json_build_object(
+    'cliente_id', NEW.wallet_id,
+    'saldo', (COALESCE(wallets.balance, 0) + COALESCE((
+      SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = NEW.wallet_id
+    ), 0))::text,
+    'limite', COALESCE(wallets.credit_limit, 0)::text,
+    'transacao', json_build_object(
+      'valor', NEW.value::text,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'realizada_em', NEW.inserted_at
+    )
+  )::text)
+  FROM wallets
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
@@ -0,0 +1,2 @@
+-- the daily limit is money like the credit limit
+ALTER TABLE wallets ALTER COLUMN daily_debit_limit TYPE NUMERIC;
@@ -0,0 +1,4 @@
+-- holds set money aside like any balance
+ALTER TABLE wallets ALTER COLUMN held TYPE NUMERIC;
+
+ALTER TABLE holds ALTER COLUMN value TYPE NUMERIC;
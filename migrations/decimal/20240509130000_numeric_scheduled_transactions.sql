@@ -0,0 +1,2 @@
+-- scheduled amounts are applied as they are
+ALTER TABLE scheduled_transactions ALTER COLUMN value TYPE NUMERIC;
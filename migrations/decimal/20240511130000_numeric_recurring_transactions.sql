@@ -0,0 +1,2 @@
+-- the amount is money like a transaction's
+ALTER TABLE recurring_transactions ALTER COLUMN value TYPE NUMERIC;
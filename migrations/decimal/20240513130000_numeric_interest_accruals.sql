@@ -0,0 +1,6 @@
+-- interest is money like the balance it's charged on
+ALTER TABLE interest_accruals ALTER COLUMN value TYPE NUMERIC;
+
+CREATE FUNCTION interest_on(balance NUMERIC, rate NUMERIC) RETURNS NUMERIC AS $$
+  SELECT GREATEST(ROUND(-balance * rate), 0);
+$$ LANGUAGE sql IMMUTABLE;
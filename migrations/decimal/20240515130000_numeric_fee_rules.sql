@@ -0,0 +1,7 @@
+-- fees are money like the transactions they're charged for
+ALTER TABLE fee_rules ALTER COLUMN min_value TYPE NUMERIC;
+ALTER TABLE fee_rules ALTER COLUMN flat TYPE NUMERIC;
+
+CREATE FUNCTION fee_on(value NUMERIC, flat NUMERIC, percentage NUMERIC) RETURNS NUMERIC AS $$
+  SELECT flat + ROUND(value * percentage);
+$$ LANGUAGE sql IMMUTABLE;
@@ -0,0 +1,24 @@
+-- the same channels, with the amounts as text
+CREATE OR REPLACE FUNCTION notify_transaction() RETURNS trigger AS $$
+BEGIN
+  PERFORM pg_notify(schema_channel(TG_TABLE_SCHEMA, 'transactions'), json_build_object(
+    'cliente_id', NEW.wallet_id,
+    'saldo', (COALESCE(wallets.balance, 0) + COALESCE((
+      SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = NEW.wallet_id
+    ), 0))::text,
+    'limite', COALESCE(wallets.credit_limit, 0)::text,
+    'transacao', jsonb_strip_nulls(jsonb_build_object(
+      'valor', NEW.value::text,
+      'tipo', CASE NEW.kind WHEN 'credit' THEN 'c' ELSE 'd' END,
+      'descricao', NEW.description,
+      'realizada_em', NEW.inserted_at,
+      'categoria', NEW.category,
+      'tags', NULLIF(NEW.tags, '{}')
+    )) || transaction_metadata(NEW.metadata)
+  )::text)
+  FROM wallets
+  WHERE wallets.id = NEW.wallet_id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
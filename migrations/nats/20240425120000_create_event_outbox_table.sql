@@ -0,0 +1,23 @@
+-- the transactions still to be published as `transaction.created`. the
+-- trigger below writes them in the same transaction as the row they
+-- announce, and a row goes once the broker acknowledged its event
+CREATE TABLE event_outbox (
+  id BIGSERIAL PRIMARY KEY,
+  transaction_id INT NOT NULL REFERENCES transactions(id) ON DELETE CASCADE
+);
+
+CREATE INDEX event_outbox_transaction_id_index ON event_outbox (transaction_id);
+
+CREATE FUNCTION enqueue_transaction_events() RETURNS trigger AS $$
+BEGIN
+  INSERT INTO event_outbox (transaction_id)
+  SELECT inserted.id FROM inserted ORDER BY inserted.id;
+
+  RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_enqueue_events
+AFTER INSERT ON transactions
+REFERENCING NEW TABLE AS inserted
+FOR EACH STATEMENT EXECUTE FUNCTION enqueue_transaction_events();
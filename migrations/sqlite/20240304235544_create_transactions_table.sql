@@ -0,0 +1,29 @@
+CREATE TABLE wallets (
+  id INTEGER PRIMARY KEY,
+  balance INTEGER NOT NULL DEFAULT 0,
+  credit_limit INTEGER NOT NULL DEFAULT 0,
+  CONSTRAINT positive_balance CHECK (credit_limit + balance >= 0)
+);
+
+CREATE TABLE transactions (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  wallet_id INTEGER NOT NULL REFERENCES wallets(id),
+  value INTEGER NOT NULL,
+  kind TEXT NOT NULL CHECK (kind IN ('c', 'd')),
+  description TEXT NOT NULL CHECK (description <> ''),
+  -- unix time in nanoseconds, sorts and compares correctly as an integer
+  inserted_at INTEGER NOT NULL
+);
+
+CREATE INDEX transactions_wallet_id_index ON transactions (wallet_id);
+
+CREATE INDEX transactions_inserted_at_index ON transactions (inserted_at);
+
+INSERT INTO
+  wallets (id, balance, credit_limit)
+VALUES
+  (1, 0, 100000),
+  (2, 0, 80000),
+  (3, 0, 1000000),
+  (4, 0, 10000000),
+  (5, 0, 500000);
@@ -0,0 +1,7 @@
+CREATE TABLE idempotency_keys (
+  wallet_id INTEGER NOT NULL REFERENCES wallets(id),
+  key TEXT NOT NULL,
+  response TEXT,
+  inserted_at TEXT DEFAULT CURRENT_TIMESTAMP,
+  PRIMARY KEY (wallet_id, key)
+);
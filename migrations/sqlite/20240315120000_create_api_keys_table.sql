@@ -0,0 +1,7 @@
+-- keys are stored as the hex sha256 of the key (`printf %s key | sha256sum`)
+CREATE TABLE api_keys (
+  key_hash TEXT PRIMARY KEY,
+  scope TEXT NOT NULL CHECK (scope IN ('read', 'write')),
+  description TEXT,
+  inserted_at TEXT DEFAULT CURRENT_TIMESTAMP
+);
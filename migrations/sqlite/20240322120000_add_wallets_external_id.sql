@@ -0,0 +1,4 @@
+-- the id the client is known by in some other system
+ALTER TABLE wallets ADD COLUMN external_id TEXT;
+
+CREATE UNIQUE INDEX wallets_external_id_index ON wallets (external_id);
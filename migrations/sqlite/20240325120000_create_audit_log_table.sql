@@ -0,0 +1,13 @@
+CREATE TABLE audit_log (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  wallet_id INTEGER NOT NULL REFERENCES wallets(id),
+  action TEXT NOT NULL,
+  -- json text
+  old_value TEXT,
+  new_value TEXT,
+  actor TEXT NOT NULL,
+  -- unix time in nanoseconds, like transactions.inserted_at
+  inserted_at INTEGER NOT NULL
+);
+
+CREATE INDEX audit_log_wallet_id_index ON audit_log (wallet_id);
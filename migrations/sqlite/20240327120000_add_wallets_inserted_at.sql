@@ -0,0 +1,6 @@
+-- unix time in nanoseconds. sqlite can't add a column with a computed
+-- default, so the existing wallets are stamped with the time of the migration
+ALTER TABLE wallets ADD COLUMN inserted_at INTEGER;
+
+UPDATE wallets
+SET inserted_at = CAST((julianday('now') - 2440587.5) * 86400000000000 AS INTEGER);
@@ -0,0 +1,5 @@
+-- the two rows of a transfer share its id, as hyphenated uuid text
+ALTER TABLE transactions ADD COLUMN transfer_id TEXT;
+
+CREATE INDEX transactions_transfer_id_index ON transactions (transfer_id)
+WHERE transfer_id IS NOT NULL;
@@ -0,0 +1,12 @@
+-- hyphenated uuid text, generated by the application. the existing rows get
+-- random v4 uuids
+ALTER TABLE transactions ADD COLUMN public_id TEXT;
+
+UPDATE transactions
+SET public_id = lower(
+  hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' || substr(hex(randomblob(2)), 2) || '-'
+  || substr('89ab', 1 + (abs(random()) % 4), 1) || substr(hex(randomblob(2)), 2) || '-'
+  || hex(randomblob(6))
+);
+
+CREATE UNIQUE INDEX transactions_public_id_index ON transactions (public_id);
@@ -0,0 +1,6 @@
+-- the ISO 4217 code of the money a wallet holds, fixed once it's created.
+-- the wallets that came before are all reais
+ALTER TABLE wallets ADD COLUMN currency TEXT NOT NULL DEFAULT 'BRL';
+
+-- always the wallet's, the inserts copy it over
+ALTER TABLE transactions ADD COLUMN currency TEXT NOT NULL DEFAULT 'BRL';
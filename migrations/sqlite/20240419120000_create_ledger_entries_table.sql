@@ -0,0 +1,30 @@
+-- the double-entry side of every transaction, see the postgres migration
+CREATE TABLE ledger_entries (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  transaction_id INTEGER NOT NULL REFERENCES transactions(id) ON DELETE CASCADE,
+  wallet_id INTEGER NOT NULL REFERENCES wallets(id),
+  account TEXT NOT NULL CHECK (account IN ('wallet', 'cash')),
+  -- positive when the account's balance goes up
+  amount INTEGER NOT NULL
+);
+
+CREATE INDEX ledger_entries_transaction_id_index ON ledger_entries (transaction_id);
+
+CREATE INDEX ledger_entries_wallet_id_index ON ledger_entries (wallet_id, account);
+
+CREATE TRIGGER transactions_record_ledger
+AFTER INSERT ON transactions
+BEGIN
+  INSERT INTO ledger_entries (transaction_id, wallet_id, account, amount)
+  VALUES
+    (NEW.id, NEW.wallet_id, 'wallet', CASE NEW.kind WHEN 'c' THEN NEW.value ELSE -NEW.value END),
+    (NEW.id, NEW.wallet_id, 'cash', CASE NEW.kind WHEN 'c' THEN -NEW.value ELSE NEW.value END);
+END;
+
+INSERT INTO ledger_entries (transaction_id, wallet_id, account, amount)
+SELECT id, wallet_id, 'wallet', CASE kind WHEN 'c' THEN value ELSE -value END
+FROM transactions;
+
+INSERT INTO ledger_entries (transaction_id, wallet_id, account, amount)
+SELECT id, wallet_id, 'cash', CASE kind WHEN 'c' THEN -value ELSE value END
+FROM transactions;
@@ -0,0 +1,4 @@
+-- a blocked wallet takes no new transactions, its reads are left alone.
+-- spelled like the api, the way `transactions.kind` is
+ALTER TABLE wallets ADD COLUMN status TEXT NOT NULL DEFAULT 'ativo'
+  CHECK (status IN ('ativo', 'bloqueado'));
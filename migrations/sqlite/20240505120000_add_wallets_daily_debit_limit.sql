@@ -0,0 +1,4 @@
+-- how much a wallet may debit in a day (utc), transfers out included. none
+-- when NULL
+ALTER TABLE wallets ADD COLUMN daily_debit_limit INTEGER
+  CHECK (daily_debit_limit >= 0);
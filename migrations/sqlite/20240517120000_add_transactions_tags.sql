@@ -0,0 +1,3 @@
+-- the clients' own labels. the tags are kept as a json array
+ALTER TABLE transactions ADD COLUMN category TEXT;
+ALTER TABLE transactions ADD COLUMN tags TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,2 @@
+-- an object as json text
+ALTER TABLE transactions ADD COLUMN metadata TEXT;
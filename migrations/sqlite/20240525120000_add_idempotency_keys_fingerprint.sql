@@ -0,0 +1,3 @@
+-- the sha256 of the transaction a key was first used for, NULL for the keys
+-- stored before
+ALTER TABLE idempotency_keys ADD COLUMN fingerprint TEXT;
@@ -0,0 +1,85 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use axum::{
+    body::HttpBody,
+    extract::{MatchedPath, Request},
+    http::{header, Response},
+    middleware::Next,
+};
+use tower_http::trace::{HttpMakeClassifier, MakeSpan, OnResponse, TraceLayer};
+use tracing::Span;
+
+// ACCESS_LOG, which a reload can flip while running
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+// one line per request, once its response is ready. the method and path come
+// with the request span around it, so the layer makes no span of its own
+pub fn layer() -> TraceLayer<HttpMakeClassifier, NoSpan, (), AccessLog, (), (), ()> {
+    TraceLayer::new_for_http()
+        .make_span_with(NoSpan)
+        .on_request(())
+        .on_response(AccessLog)
+        .on_body_chunk(())
+        .on_eos(())
+        // the errors log themselves where they happen
+        .on_failure(())
+}
+
+#[derive(Clone, Copy)]
+pub struct NoSpan;
+
+impl<B> MakeSpan<B> for NoSpan {
+    fn make_span(&mut self, _: &axum::http::Request<B>) -> Span {
+        Span::none()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct AccessLog;
+
+impl<B: HttpBody> OnResponse<B> for AccessLog {
+    fn on_response(self, response: &Response<B>, latency: Duration, _: &Span) {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let route = response
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_string());
+        // unknown for the streamed and the compressed bodies
+        let size = response
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok()?.parse::<u64>().ok())
+            .or_else(|| response.body().size_hint().exact());
+
+        tracing::info!(
+            route,
+            status = response.status().as_u16(),
+            latency_ms = latency.as_secs_f64() * 1000.0,
+            size,
+            "request handled",
+        );
+    }
+}
+
+// the route is only known once the router matched it, so it's handed back
+// out with the response
+pub async fn matched_route(req: Request, next: Next) -> axum::response::Response {
+    let route = req.extensions().get::<MatchedPath>().cloned();
+
+    let mut response = next.run(req).await;
+    if let Some(route) = route {
+        response.extensions_mut().insert(route);
+    }
+
+    response
+}
@@ -0,0 +1,77 @@
+use axum::{
+    extract::{Request, State},
+    http::{uri::PathAndQuery, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{errors::ApiError, repository::WalletRepository, versions};
+
+// the client whose account a request under `/clientes/:id/contas/:conta_id`
+// was made for, the auth layers check the client's id rather than the
+// account's
+#[derive(Debug, Clone, Copy)]
+pub struct AccountOwner {
+    pub client_id: i32,
+    pub account_id: i32,
+}
+
+// `/clientes/:id/contas/:conta_id/...` is the account's own
+// `/clientes/:conta_id/...`, so every route works for every account. this
+// runs before the routing, which only knows the latter, and leaves checking
+// the client owns the account to `check_owner`, past the auth layers. the
+// default account is the client itself, `/clientes/1/contas/1/extrato` is
+// `/clientes/1/extrato`. a version prefix stays where it was
+pub async fn rewrite(mut req: Request, next: Next) -> Response {
+    let (prefix, path) = versions::split_prefix(req.uri().path());
+    let Some((client_id, account_id, rest)) = split(path) else {
+        return next.run(req).await;
+    };
+
+    let path = match req.uri().query() {
+        Some(query) => format!("{}/clientes/{}{}?{}", prefix, account_id, rest, query),
+        None => format!("{}/clientes/{}{}", prefix, account_id, rest),
+    };
+    // the same characters as the uri it came from, so neither can fail
+    let mut parts = req.uri().clone().into_parts();
+    parts.path_and_query = PathAndQuery::try_from(path).ok();
+    if let Ok(uri) = Uri::from_parts(parts) {
+        *req.uri_mut() = uri;
+    }
+
+    req.extensions_mut().insert(AccountOwner {
+        client_id,
+        account_id,
+    });
+
+    next.run(req).await
+}
+
+// an account someone else owns is as unknown as one that doesn't exist
+pub async fn check_owner<R: WalletRepository>(
+    State(repo): State<R>,
+    req: Request,
+    next: Next,
+) -> Response {
+    if let Some(owner) = req.extensions().get::<AccountOwner>().copied() {
+        if owner.account_id != owner.client_id {
+            match repo.owns_account(owner.client_id, owner.account_id).await {
+                Ok(true) => {}
+                Ok(false) => return ApiError::NotFound.into_response(),
+                Err(err) => return err.into_response(),
+            }
+        }
+    }
+
+    next.run(req).await
+}
+
+// the client id, the account id and what comes after them
+fn split(path: &str) -> Option<(i32, i32, &str)> {
+    let rest = path.strip_prefix("/clientes/")?;
+    let (client_id, rest) = rest.split_once('/')?;
+    let rest = rest.strip_prefix("contas/")?;
+    let (account_id, rest) = rest.split_at(rest.find('/').unwrap_or(rest.len()));
+
+    Some((client_id.parse().ok()?, account_id.parse().ok()?, rest))
+}
@@ -0,0 +1,45 @@
+use std::future::Future;
+
+use axum::{extract::Request, middleware::Next, response::Response};
+
+use crate::auth::Actor;
+
+tokio::task_local! {
+    static CONTEXT: Context;
+}
+
+// who made the request being handled and its id, for the audit log rows its
+// writes leave behind. `db::begin` hands them to the database, where the
+// triggers pick them up
+#[derive(Debug, Clone, Default)]
+pub struct Context {
+    pub actor: Option<String>,
+    pub request_id: Option<String>,
+}
+
+impl Context {
+    // the context of the current task, empty outside of a request
+    pub fn current() -> Self {
+        CONTEXT.try_with(Context::clone).unwrap_or_default()
+    }
+
+    // for work handed to another task on the request's behalf
+    pub async fn scope<F: Future>(self, f: F) -> F::Output {
+        CONTEXT.scope(self, f).await
+    }
+}
+
+// has to run after the auth middlewares, which is where the actor comes from.
+// requests to an open api are `anonymous`, like in `update_credit_limit`
+pub async fn remember(req: Request, next: Next) -> Response {
+    let actor = req
+        .extensions()
+        .get::<Actor>()
+        .map_or_else(|| "anonymous".to_string(), |Actor(actor)| actor.clone());
+    let context = Context {
+        actor: Some(actor),
+        request_id: crate::request_id::current(),
+    };
+
+    context.scope(next.run(req)).await
+}
@@ -0,0 +1,454 @@
+use std::{collections::HashMap, fmt, str::FromStr, sync::Arc};
+
+use axum::{
+    extract::{RawPathParams, Request, State},
+    http::{header, HeaderName, HeaderValue, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    accounts::AccountOwner, config::Config, errors::ApiError, repository::WalletRepository, tenant,
+    versions,
+};
+
+pub const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+// the last segment of a wallet's routes that only an admin may use
+const ADMINISTERED: [&str; 5] = [
+    "bloquear",
+    "desbloquear",
+    "juros",
+    "limite",
+    "limite_diario",
+];
+
+// `Admin` implies `Write`, which implies `Read`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    Read,
+    Write,
+    Admin,
+}
+
+impl Scope {
+    // reads only need a read key, anything that can change a balance needs
+    // a write one. `/admin` is for the ones looking after the whole api, and
+    // so are creating a wallet or an account, with whatever limit it's given,
+    // blocking one and changing its limits, which its own token mustn't get
+    // to loosen. `/graphql` only needs a read key to get in, its mutations
+    // check for a write one themselves. a version prefix makes no difference
+    pub fn required_for(method: &Method, path: &str) -> Self {
+        let (_, path) = versions::split_prefix(path);
+        let creating = method == Method::POST
+            && (path == "/clientes"
+                || path
+                    .strip_prefix("/clientes/")
+                    .and_then(|rest| rest.split_once('/'))
+                    .is_some_and(|(_, action)| action == "contas"));
+        let administered = path.starts_with("/clientes/")
+            && path
+                .rsplit('/')
+                .next()
+                .is_some_and(|action| ADMINISTERED.contains(&action));
+
+        if path == "/admin" || path.starts_with("/admin/") || creating || administered {
+            Scope::Admin
+        } else if method == Method::GET || method == Method::HEAD || path == "/graphql" {
+            Scope::Read
+        } else {
+            Scope::Write
+        }
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Scope::Read),
+            "write" => Ok(Scope::Write),
+            "admin" => Ok(Scope::Admin),
+            _ => Err(format!(
+                "invalid scope {:?}, expected \"read\", \"write\" or \"admin\"",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::Read => write!(f, "read"),
+            Scope::Write => write!(f, "write"),
+            Scope::Admin => write!(f, "admin"),
+        }
+    }
+}
+
+// who a request was authenticated as, for the audit log: `api_key:` and the
+// start of the key's hash, or `jwt:` and the token's subject. handlers fall
+// back to `anonymous` when the api is open
+#[derive(Debug, Clone)]
+pub struct Actor(pub String);
+
+// the `sub` of the bearer token, for the handlers whose wallets are in the
+// body rather than the path
+#[derive(Debug, Clone)]
+pub struct TokenSubject(String);
+
+impl TokenSubject {
+    pub fn allows(&self, wallet_id: i32) -> bool {
+        self.0 == "*" || self.0 == wallet_id.to_string()
+    }
+}
+
+// the keys given in API_KEYS, plus the `api_keys` table when
+// API_KEYS_FROM_DB is set. a key given in API_KEYS only works for the tenant
+// it names, the default dataset for none, a table's keys are already its
+// own tenant's
+#[derive(Clone)]
+pub struct ApiKeys<R> {
+    keys: Arc<HashMap<String, (Scope, Option<String>)>>,
+    repo: Option<R>,
+}
+
+impl<R: WalletRepository> ApiKeys<R> {
+    // `None` when no key source is configured, which leaves the api open
+    pub fn from_config(config: &Config, repo: &R) -> Option<Self> {
+        if config.api_keys.is_empty() && !config.api_keys_from_db {
+            return None;
+        }
+
+        let keys = config
+            .api_keys
+            .iter()
+            .map(|(key, scope, tenant)| (hash_key(key), (*scope, tenant.clone())))
+            .collect();
+
+        Some(ApiKeys {
+            keys: Arc::new(keys),
+            repo: config.api_keys_from_db.then(|| repo.clone()),
+        })
+    }
+
+    // `Forbidden` for a key of another tenant than the request's
+    async fn scope(&self, key: &str) -> Result<Option<Scope>, ApiError> {
+        let key_hash = hash_key(key);
+
+        if let Some((scope, tenant)) = self.keys.get(&key_hash) {
+            if *tenant != tenant::current() {
+                return Err(ApiError::Forbidden);
+            }
+            return Ok(Some(*scope));
+        }
+
+        match &self.repo {
+            Some(repo) => repo.api_key_scope(&key_hash).await,
+            None => Ok(None),
+        }
+    }
+}
+
+// keys are only ever compared by their hash, the same form the table keeps
+pub fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+pub async fn require_api_key<R: WalletRepository>(
+    State(keys): State<ApiKeys<R>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let Some(key) = req
+        .headers()
+        .get(&API_KEY_HEADER)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return ApiError::Unauthorized.into_response();
+    };
+
+    let scope = match keys.scope(key).await {
+        Ok(Some(scope)) => scope,
+        Ok(None) => return ApiError::Unauthorized.into_response(),
+        Err(err) => return err.into_response(),
+    };
+
+    if scope < Scope::required_for(req.method(), req.uri().path()) {
+        return ApiError::Forbidden.into_response();
+    }
+
+    let actor = Actor(format!("api_key:{}", &hash_key(key)[..12]));
+    req.extensions_mut().insert(actor);
+    req.extensions_mut().insert(scope);
+
+    next.run(req).await
+}
+
+// bearer tokens whose `sub` is the only wallet id they may touch, or `*` for
+// all of them. an optional `scope` claim narrows them down to reads, and the
+// `tenant` claim names the only tenant they work for, the default dataset
+// without one
+#[derive(Clone)]
+pub struct Jwt {
+    key: Arc<DecodingKey>,
+    validation: Arc<Validation>,
+}
+
+#[derive(Deserialize)]
+struct Claims {
+    sub: String,
+    scope: Option<Scope>,
+    tenant: Option<String>,
+}
+
+impl Jwt {
+    // `None` when neither JWT_SECRET nor JWT_PUBLIC_KEY is set. the key was
+    // already checked by `Config::validate`
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let key = match config.jwt_algorithm {
+            Algorithm::RS256 => {
+                DecodingKey::from_rsa_pem(config.jwt_public_key.as_ref()?.as_bytes())
+                    .expect("JWT_PUBLIC_KEY is validated on startup")
+            }
+            _ => DecodingKey::from_secret(config.jwt_secret.as_ref()?.as_bytes()),
+        };
+
+        Some(Jwt {
+            key: Arc::new(key),
+            validation: Arc::new(Validation::new(config.jwt_algorithm)),
+        })
+    }
+
+    fn claims(&self, token: &str) -> Option<Claims> {
+        jsonwebtoken::decode::<Claims>(token, &self.key, &self.validation)
+            .map(|data| data.claims)
+            .ok()
+    }
+}
+
+impl Claims {
+    fn allows(&self, wallet_id: Option<i32>) -> bool {
+        match wallet_id {
+            Some(wallet_id) => TokenSubject(self.sub.clone()).allows(wallet_id),
+            None => true,
+        }
+    }
+}
+
+pub async fn require_jwt(
+    State(jwt): State<Jwt>,
+    params: Option<RawPathParams>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let claims = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .and_then(|token| jwt.claims(token.trim()));
+
+    let Some(claims) = claims else {
+        let mut response = ApiError::Unauthorized.into_response();
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        return response;
+    };
+
+    // the raw params rather than `Path<i32>`, which only matches routes with
+    // the client id as their single parameter. an account's routes are the
+    // client's, whatever the id in the path
+    let wallet_id = match req.extensions().get::<AccountOwner>() {
+        Some(owner) => Some(owner.client_id),
+        None => params.and_then(|params| {
+            params
+                .iter()
+                .find(|(name, _)| *name == "id")
+                .and_then(|(_, value)| value.parse().ok())
+        }),
+    };
+
+    let scope = claims.scope.unwrap_or(Scope::Write);
+    if claims.tenant != tenant::current()
+        || !claims.allows(wallet_id)
+        || scope < Scope::required_for(req.method(), req.uri().path())
+    {
+        return ApiError::Forbidden.into_response();
+    }
+
+    req.extensions_mut()
+        .insert(Actor(format!("jwt:{}", claims.sub)));
+    req.extensions_mut().insert(TokenSubject(claims.sub));
+    req.extensions_mut().insert(scope);
+
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repository::MemoryWalletRepository;
+
+    // every route of `versions::v1`, with ids filled in
+    const ROUTES: [(Method, &str, Scope); 48] = [
+        (Method::GET, "/", Scope::Read),
+        (Method::POST, "/clientes", Scope::Admin),
+        (Method::GET, "/clientes/1", Scope::Read),
+        (Method::GET, "/clientes/1/contas", Scope::Read),
+        (Method::POST, "/clientes/1/contas", Scope::Admin),
+        (Method::GET, "/clientes/1/extrato", Scope::Read),
+        (Method::HEAD, "/clientes/1/extrato", Scope::Read),
+        (Method::GET, "/clientes/1/extrato/export", Scope::Read),
+        (Method::GET, "/clientes/1/resumo", Scope::Read),
+        (Method::PATCH, "/clientes/1/limite", Scope::Admin),
+        (Method::PATCH, "/clientes/1/limite_diario", Scope::Admin),
+        (Method::PATCH, "/clientes/1/juros", Scope::Admin),
+        (Method::POST, "/clientes/1/bloquear", Scope::Admin),
+        (Method::POST, "/clientes/1/desbloquear", Scope::Admin),
+        (Method::POST, "/clientes/1/transacoes", Scope::Write),
+        (Method::GET, "/clientes/1/transacoes", Scope::Read),
+        (Method::POST, "/clientes/1/transacoes/lote", Scope::Write),
+        (Method::GET, "/clientes/1/transacoes/stream", Scope::Read),
+        (Method::GET, "/clientes/1/transacoes/verify", Scope::Read),
+        (Method::GET, "/clientes/1/transacoes/agendadas", Scope::Read),
+        (
+            Method::DELETE,
+            "/clientes/1/transacoes/agendadas/00000000-0000-0000-0000-000000000001",
+            Scope::Write,
+        ),
+        (
+            Method::GET,
+            "/clientes/1/transacoes/00000000-0000-0000-0000-000000000001",
+            Scope::Read,
+        ),
+        (Method::POST, "/clientes/1/webhooks", Scope::Write),
+        (Method::GET, "/clientes/1/webhooks", Scope::Read),
+        (Method::DELETE, "/clientes/1/webhooks/1", Scope::Write),
+        (Method::POST, "/clientes/1/recorrencias", Scope::Write),
+        (Method::GET, "/clientes/1/recorrencias", Scope::Read),
+        (Method::GET, "/clientes/1/recorrencias/1", Scope::Read),
+        (Method::PUT, "/clientes/1/recorrencias/1", Scope::Write),
+        (Method::DELETE, "/clientes/1/recorrencias/1", Scope::Write),
+        (Method::POST, "/clientes/1/holds", Scope::Write),
+        (
+            Method::GET,
+            "/holds/00000000-0000-0000-0000-000000000001",
+            Scope::Read,
+        ),
+        (
+            Method::DELETE,
+            "/holds/00000000-0000-0000-0000-000000000001",
+            Scope::Write,
+        ),
+        (
+            Method::POST,
+            "/holds/00000000-0000-0000-0000-000000000001/capture",
+            Scope::Write,
+        ),
+        (Method::POST, "/transferencias", Scope::Write),
+        (Method::POST, "/admin/clientes/1/reconciliar", Scope::Admin),
+        (Method::GET, "/admin/audit", Scope::Admin),
+        (Method::GET, "/admin/tarifas", Scope::Admin),
+        (Method::POST, "/admin/tarifas", Scope::Admin),
+        (Method::GET, "/admin/log-level", Scope::Admin),
+        (Method::PUT, "/admin/log-level", Scope::Admin),
+        (Method::PUT, "/admin/tarifas/1", Scope::Admin),
+        (Method::DELETE, "/admin/tarifas/1", Scope::Admin),
+        (Method::GET, "/ws/clientes/1", Scope::Read),
+        (Method::GET, "/graphql", Scope::Read),
+        (Method::POST, "/graphql", Scope::Read),
+        // an account's routes reach the auth layers as the account's own
+        (Method::PATCH, "/clientes/2/limite", Scope::Admin),
+        (Method::POST, "/clientes/2/transacoes", Scope::Write),
+    ];
+
+    #[test]
+    fn every_route_requires_its_scope() {
+        for (method, path, scope) in ROUTES {
+            assert_eq!(
+                Scope::required_for(&method, path),
+                scope,
+                "{} {}",
+                method,
+                path
+            );
+
+            let prefixed = format!("/v1{}", path.trim_end_matches('/'));
+            assert_eq!(
+                Scope::required_for(&method, &prefixed),
+                scope,
+                "{} {}",
+                method,
+                prefixed
+            );
+        }
+    }
+
+    #[test]
+    fn scopes_imply_the_lesser_ones() {
+        assert!(Scope::Read < Scope::Write);
+        assert!(Scope::Write < Scope::Admin);
+    }
+
+    #[tokio::test]
+    async fn a_key_only_works_for_its_own_tenant() {
+        let keys = ApiKeys::<MemoryWalletRepository> {
+            keys: Arc::new(HashMap::from([
+                (hash_key("acme"), (Scope::Write, Some("acme".to_string()))),
+                (hash_key("default"), (Scope::Write, None)),
+            ])),
+            repo: None,
+        };
+
+        let acme = tenant::scope(Some("acme".to_string()), keys.scope("acme")).await;
+        assert!(matches!(acme, Ok(Some(Scope::Write))));
+        let other = tenant::scope(Some("other".to_string()), keys.scope("acme")).await;
+        assert!(matches!(other, Err(ApiError::Forbidden)));
+        assert!(matches!(keys.scope("acme").await, Err(ApiError::Forbidden)));
+
+        let default = tenant::scope(Some("acme".to_string()), keys.scope("default")).await;
+        assert!(matches!(default, Err(ApiError::Forbidden)));
+        assert!(matches!(
+            keys.scope("default").await,
+            Ok(Some(Scope::Write))
+        ));
+    }
+
+    #[test]
+    fn a_subject_allows_its_own_wallet() {
+        let subject = TokenSubject("1".to_string());
+
+        assert!(subject.allows(1));
+        assert!(!subject.allows(2));
+        assert!(!subject.allows(11));
+    }
+
+    #[test]
+    fn a_star_subject_allows_every_wallet() {
+        let subject = TokenSubject("*".to_string());
+
+        assert!(subject.allows(1));
+        assert!(subject.allows(i32::MAX));
+    }
+
+    #[test]
+    fn claims_allow_routes_without_a_wallet() {
+        let claims = Claims {
+            sub: "1".to_string(),
+            scope: None,
+            tenant: None,
+        };
+
+        assert!(claims.allows(None));
+        assert!(claims.allows(Some(1)));
+        assert!(!claims.allows(Some(2)));
+    }
+}
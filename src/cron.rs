@@ -0,0 +1,346 @@
+use std::{fmt, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use time::{Date, Duration, OffsetDateTime, PrimitiveDateTime, Time};
+
+// how far `next_after` looks ahead, enough for a schedule that only comes up
+// on the 29th of february
+const HORIZON_DAYS: i64 = 5 * 366;
+
+// the five fields of a crontab line, `minute hour day-of-month month
+// day-of-week`, always in utc. each is `*`, a number, a range `a-b` or a list
+// of those, with an optional `/step`. sunday is 0 or 7. like cron, a day
+// matches either day field when both are restricted, and the other one when
+// only one is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    source: String,
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    // the first minute strictly after `after` the schedule comes up on. none
+    // for one that never does, e.g. `0 0 31 2 *`
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let after = after.to_offset(time::UtcOffset::UTC);
+        let minute = Time::from_hms(after.hour(), after.minute(), 0).expect("taken from a time");
+        let mut t = PrimitiveDateTime::new(after.date(), minute) + Duration::MINUTE;
+        let horizon = t + Duration::days(HORIZON_DAYS);
+
+        while t < horizon {
+            if !has(self.months, t.month() as u8) {
+                t = first_of_next_month(t.date()).midnight();
+            } else if !self.day_matches(t.date()) {
+                t = t.date().next_day()?.midnight();
+            } else if !has(self.hours, t.hour()) {
+                t = PrimitiveDateTime::new(t.date(), Time::from_hms(t.hour(), 0, 0).ok()?)
+                    + Duration::HOUR;
+            } else if !has(self.minutes, t.minute()) {
+                t += Duration::MINUTE;
+            } else {
+                return Some(t.assume_utc());
+            }
+        }
+
+        None
+    }
+
+    fn day_matches(&self, date: Date) -> bool {
+        let day = has(self.days, date.day());
+        let weekday = has(self.weekdays, date.weekday().number_days_from_sunday());
+
+        if self.any_day || self.any_weekday {
+            day && weekday
+        } else {
+            day || weekday
+        }
+    }
+}
+
+fn has(set: u64, value: u8) -> bool {
+    set & (1 << value) != 0
+}
+
+fn first_of_next_month(date: Date) -> Date {
+    let year = match date.month() {
+        time::Month::December => date.year() + 1,
+        _ => date.year(),
+    };
+    Date::from_calendar_date(year, date.month().next(), 1)
+        .expect("the first of a month is always a valid date")
+}
+
+// the values of one field as bits, `min..=max`
+fn parse_field(field: &str, min: u8, max: u8) -> Result<u64, ()> {
+    let mut set = 0;
+
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, u8::from_str(step).map_err(|_| ())?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(());
+        }
+
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (
+                    u8::from_str(start).map_err(|_| ())?,
+                    u8::from_str(end).map_err(|_| ())?,
+                ),
+                // `5/15` runs from 5 to the end, like `5-59/15`
+                None if step > 1 => (u8::from_str(range).map_err(|_| ())?, max),
+                None => {
+                    let value = u8::from_str(range).map_err(|_| ())?;
+                    (value, value)
+                }
+            },
+        };
+        if start < min || end > max || start > end {
+            return Err(());
+        }
+
+        for value in (start..=end).step_by(step.into()) {
+            set |= 1 << value;
+        }
+    }
+
+    Ok(set)
+}
+
+impl FromStr for Schedule {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // what the column keeps
+        if s.len() > 255 {
+            return Err(());
+        }
+
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(());
+        };
+
+        let mut weekday_set = parse_field(weekdays, 0, 7)?;
+        // 7 is another sunday
+        if has(weekday_set, 7) {
+            weekday_set |= 1;
+        }
+
+        Ok(Schedule {
+            source: fields.join(" "),
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: weekday_set,
+            any_day: days.starts_with('*'),
+            any_weekday: weekdays.starts_with('*'),
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Serialize for Schedule {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Schedule {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Schedule::from_str(&s).map_err(|_| de::Error::custom("invalid crontab schedule"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::macros::datetime;
+
+    use super::*;
+
+    fn schedule(s: &str) -> Schedule {
+        s.parse().unwrap()
+    }
+
+    fn bits(values: &[u8]) -> u64 {
+        values.iter().fold(0, |set, value| set | 1 << value)
+    }
+
+    #[test]
+    fn parses_ranges_and_steps() {
+        let schedule = schedule("*/15 9-17/4 1-5 * *");
+
+        assert_eq!(schedule.minutes, bits(&[0, 15, 30, 45]));
+        assert_eq!(schedule.hours, bits(&[9, 13, 17]));
+        assert_eq!(schedule.days, bits(&[1, 2, 3, 4, 5]));
+        assert_eq!(schedule.months, bits(&(1..=12).collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn a_step_without_a_range_runs_to_the_end() {
+        assert_eq!(schedule("5/20 * * * *").minutes, bits(&[5, 25, 45]));
+    }
+
+    #[test]
+    fn parses_lists() {
+        let schedule = schedule("0,30 8,12-13,20 * 1,7 *");
+
+        assert_eq!(schedule.minutes, bits(&[0, 30]));
+        assert_eq!(schedule.hours, bits(&[8, 12, 13, 20]));
+        assert_eq!(schedule.months, bits(&[1, 7]));
+    }
+
+    #[test]
+    fn seven_is_sunday() {
+        assert_eq!(schedule("0 0 * * 7").weekdays & 1, 1);
+    }
+
+    #[test]
+    fn rejects_invalid_fields() {
+        for invalid in [
+            "60 * * * *",
+            "* 24 * * *",
+            "* * 0 * *",
+            "* * 32 * *",
+            "* * * 13 *",
+            "* * * * 8",
+            "*/0 * * * *",
+            "30-10 * * * *",
+            "a * * * *",
+            "1-a * * * *",
+            "* * * *",
+            "* * * * * *",
+            "",
+        ] {
+            assert!(invalid.parse::<Schedule>().is_err(), "{:?} parsed", invalid);
+        }
+    }
+
+    #[test]
+    fn keeps_the_source_with_single_spaces() {
+        assert_eq!(schedule(" 0  12 * *   1 ").to_string(), "0 12 * * 1");
+    }
+
+    #[test]
+    fn next_is_strictly_after_and_on_the_minute() {
+        let every_minute = schedule("* * * * *");
+
+        assert_eq!(
+            every_minute.next_after(datetime!(2024-05-10 10:00:30 UTC)),
+            Some(datetime!(2024-05-10 10:01 UTC))
+        );
+        assert_eq!(
+            every_minute.next_after(datetime!(2024-05-10 10:01 UTC)),
+            Some(datetime!(2024-05-10 10:02 UTC))
+        );
+    }
+
+    #[test]
+    fn next_is_in_utc() {
+        assert_eq!(
+            schedule("0 12 * * *").next_after(datetime!(2024-05-10 10:00 +03:00)),
+            Some(datetime!(2024-05-10 12:00 UTC))
+        );
+    }
+
+    #[test]
+    fn next_skips_to_the_next_hour_in_range() {
+        let schedule = schedule("*/15 9-17/4 * * *");
+
+        assert_eq!(
+            schedule.next_after(datetime!(2024-05-10 09:50 UTC)),
+            Some(datetime!(2024-05-10 13:00 UTC))
+        );
+        assert_eq!(
+            schedule.next_after(datetime!(2024-05-10 17:45 UTC)),
+            Some(datetime!(2024-05-11 09:00 UTC))
+        );
+    }
+
+    #[test]
+    fn either_day_field_matches_when_both_are_restricted() {
+        // the 10th, or any friday
+        let schedule = schedule("0 0 10 * 5");
+
+        // 2024-09-07 is a saturday
+        assert_eq!(
+            schedule.next_after(datetime!(2024-09-07 00:00 UTC)),
+            Some(datetime!(2024-09-10 00:00 UTC))
+        );
+        assert_eq!(
+            schedule.next_after(datetime!(2024-09-10 00:00 UTC)),
+            Some(datetime!(2024-09-13 00:00 UTC))
+        );
+    }
+
+    #[test]
+    fn the_restricted_day_field_matches_when_the_other_is_not() {
+        // fridays only
+        assert_eq!(
+            schedule("0 0 * * 5").next_after(datetime!(2024-09-07 00:00 UTC)),
+            Some(datetime!(2024-09-13 00:00 UTC))
+        );
+        // the 10th only
+        assert_eq!(
+            schedule("0 0 10 * *").next_after(datetime!(2024-09-10 00:00 UTC)),
+            Some(datetime!(2024-10-10 00:00 UTC))
+        );
+    }
+
+    #[test]
+    fn next_rolls_over_the_end_of_a_month() {
+        assert_eq!(
+            schedule("0 0 1 * *").next_after(datetime!(2024-01-31 12:00 UTC)),
+            Some(datetime!(2024-02-01 00:00 UTC))
+        );
+        // april has no 31st
+        assert_eq!(
+            schedule("0 0 31 * *").next_after(datetime!(2024-03-31 00:00 UTC)),
+            Some(datetime!(2024-05-31 00:00 UTC))
+        );
+    }
+
+    #[test]
+    fn next_rolls_over_the_end_of_a_year() {
+        assert_eq!(
+            schedule("30 * * * *").next_after(datetime!(2024-12-31 23:45 UTC)),
+            Some(datetime!(2025-01-01 00:30 UTC))
+        );
+        assert_eq!(
+            schedule("59 23 31 12 *").next_after(datetime!(2024-12-31 23:59 UTC)),
+            Some(datetime!(2025-12-31 23:59 UTC))
+        );
+    }
+
+    #[test]
+    fn next_finds_the_next_leap_day() {
+        assert_eq!(
+            schedule("0 0 29 2 *").next_after(datetime!(2024-03-01 00:00 UTC)),
+            Some(datetime!(2028-02-29 00:00 UTC))
+        );
+    }
+
+    #[test]
+    fn next_is_none_for_a_day_that_never_comes() {
+        assert_eq!(
+            schedule("0 0 31 2 *").next_after(datetime!(2024-01-01 00:00 UTC)),
+            None
+        );
+    }
+}
@@ -0,0 +1,3171 @@
+use serde_json::Value;
+use sqlx::{
+    migrate::{MigrateError, Migrator},
+    pool::PoolConnection,
+    postgres::{PgConnectOptions, PgPoolOptions},
+    types::Json,
+    Connection, PgConnection, PgExecutor, PgPool, Postgres, Transaction,
+};
+use time::{Date, OffsetDateTime};
+use tokio_stream::{Stream, StreamExt};
+use uuid::Uuid;
+
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    audit,
+    config::Config,
+    models::{
+        Account, AuditEntry, AuditFilter, Cents, ChainLink, CreatedWallet, FeeRule, HistoryCursor,
+        HistoryFilter, Hold, HoldStatus, KindTotal, LedgerViolation, PostFeeRule, PostHold,
+        PostRecurrence, PostTransaction, PostTransfer, PostWallet, Recurrence, ScheduledStatus,
+        ScheduledTransaction, Statement, StatementFilter, SummaryMonth, TransactionDetails,
+        TransactionItem, TransactionKind, TransactionReceipt, Wallet, WalletDetails, WalletStatus,
+        Webhook, WebhookDelivery,
+    },
+    tenant,
+};
+
+#[cfg(not(any(feature = "decimal", feature = "nats")))]
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+// the usual migrations, then those of the features that change the schema:
+// `migrations/decimal` turns the money columns into NUMERIC, and
+// `migrations/nats` adds the outbox the events are published from. a
+// database migrated like this can't be used by a build without the
+// features, which sees migrations it doesn't know
+#[cfg(any(feature = "decimal", feature = "nats"))]
+pub static MIGRATOR: std::sync::LazyLock<Migrator> = std::sync::LazyLock::new(|| {
+    let usual = sqlx::migrate!();
+
+    let mut migrations = usual.migrations.to_vec();
+    #[cfg(feature = "decimal")]
+    migrations.extend_from_slice(&sqlx::migrate!("migrations/decimal").migrations);
+    #[cfg(feature = "nats")]
+    migrations.extend_from_slice(&sqlx::migrate!("migrations/nats").migrations);
+    migrations.sort_by_key(|migration| migration.version);
+
+    Migrator {
+        migrations: migrations.into(),
+        ..usual
+    }
+});
+
+// where the `transactions_notify` trigger announces new transactions
+pub const TRANSACTIONS_CHANNEL: &str = "transactions";
+
+// where the `wallets_notify_changed` trigger announces the wallets a write
+// touched
+pub const WALLET_CHANGED_CHANNEL: &str = "wallet_changed";
+
+// the five clients (id, credit limit) the rinha test suite expects
+pub const SEED_WALLETS: [(i32, i64); 5] = [
+    (1, 100000),
+    (2, 80000),
+    (3, 1000000),
+    (4, 10000000),
+    (5, 500000),
+];
+
+pub async fn connect(config: &Config) -> Result<PgPool, sqlx::Error> {
+    connect_with(config, config.db_max_connections).await
+}
+
+// a second pool for the statement reads, so a burst of them can't hold every
+// connection the writes need. `None` when they share the one from `connect`
+pub async fn connect_read(config: &Config) -> Result<Option<PgPool>, sqlx::Error> {
+    if config.db_read_max_connections == 0 {
+        return Ok(None);
+    }
+    connect_with(config, config.db_read_max_connections)
+        .await
+        .map(Some)
+}
+
+// one pool per replica, each sized like the read pool. they connect on first
+// use, so a replica that's down doesn't keep the service from starting
+pub fn connect_replicas(config: &Config) -> Result<Vec<PgPool>, sqlx::Error> {
+    let max_connections = match config.db_read_max_connections {
+        0 => config.db_max_connections,
+        max_connections => max_connections,
+    };
+
+    config
+        .database_read_urls
+        .iter()
+        .map(|url| {
+            Ok(PgPoolOptions::new()
+                .max_connections(max_connections)
+                .acquire_timeout(config.db_acquire_timeout)
+                .connect_lazy_with(connect_options(config, url)?))
+        })
+        .collect()
+}
+
+async fn connect_with(config: &Config, max_connections: u32) -> Result<PgPool, sqlx::Error> {
+    PgPoolOptions::new()
+        .max_connections(max_connections)
+        .min_connections(config.db_min_connections.min(max_connections))
+        .acquire_timeout(config.db_acquire_timeout)
+        .connect_with(connect_options(config, &config.database_url)?)
+        .await
+}
+
+// a tenant's connections only have its schema on the search path, so a table
+// missing from it fails the query rather than reading the default dataset's
+fn connect_options(config: &Config, url: &str) -> Result<PgConnectOptions, sqlx::Error> {
+    let options = PgConnectOptions::from_str(url)?;
+
+    Ok(match &config.tenant {
+        Some(tenant) => options.options([("search_path", tenant::schema(tenant))]),
+        None => options,
+    })
+}
+
+// `name` for the default dataset, `<schema>.name` for a tenant's, like the
+// `schema_channel` the triggers notify on
+pub fn channel(tenant: Option<&str>, name: &str) -> String {
+    match tenant {
+        Some(tenant) => format!("{}.{}", tenant::schema(tenant), name),
+        None => name.to_string(),
+    }
+}
+
+// a tenant's schema is created along with its first migration. those run
+// with public on the search path too, it has the pg_trgm operator class the
+// description index is built with, and on a connection of their own that's
+// closed afterwards
+pub async fn run_migrations(pool: &PgPool, tenant: Option<&str>) -> Result<(), MigrateError> {
+    let Some(tenant) = tenant else {
+        return MIGRATOR.run(pool).await;
+    };
+    let schema = tenant::schema(tenant);
+
+    // `tenant::is_valid` names never need quoting
+    sqlx::query(&format!("CREATE SCHEMA IF NOT EXISTS {}", schema))
+        .execute(pool)
+        .await?;
+
+    // a pool of its own, whose search path also has `public` for the
+    // extensions the migrations use
+    let options = (*pool.connect_options())
+        .clone()
+        .options([("search_path", format!("{},public", schema))]);
+    let migrations_pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_with(options)
+        .await?;
+
+    let migrated = MIGRATOR.run(&migrations_pool).await;
+    migrations_pool.close().await;
+
+    migrated
+}
+
+// the triggers keeping the transactions from being edited or deleted
+const LEDGER_GUARDS: [&str; 2] = ["transactions_immutable", "transactions_no_truncate"];
+
+// the `LEDGER_GUARDS` the transactions table lacks, or has disabled
+pub async fn missing_ledger_guards(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    let present: Vec<String> = sqlx::query_scalar!(
+        r#"
+        SELECT tgname::text as "tgname!"
+        FROM pg_trigger
+        WHERE tgrelid = to_regclass('transactions') AND tgenabled <> 'D'
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(LEDGER_GUARDS
+        .into_iter()
+        .filter(|guard| !present.iter().any(|name| name == guard))
+        .map(str::to_string)
+        .collect())
+}
+
+// the indexes the migrations create, which the queries count on
+const INDEXES: [&str; 22] = [
+    "audit_log_inserted_at_index",
+    "audit_log_wallet_id_index",
+    "holds_pending_expires_at_index",
+    "holds_wallet_id_index",
+    "ledger_entries_transaction_id_index",
+    "ledger_entries_wallet_id_index",
+    "pending_projections_wallet_id_index",
+    "recurring_transactions_next_run_at_index",
+    "recurring_transactions_wallet_id_index",
+    "scheduled_transactions_pending_due_at_index",
+    "scheduled_transactions_wallet_id_index",
+    "transactions_description_trigram_index",
+    "transactions_inserted_at_index",
+    "transactions_public_id_index",
+    "transactions_transfer_id_index",
+    "transactions_wallet_id_chain_position_index",
+    "transactions_wallet_id_index",
+    "transactions_wallet_id_inserted_at_id_index",
+    "wallets_owner_id_idx",
+    "webhook_outbox_due_index",
+    "webhook_outbox_transaction_id_index",
+    "webhooks_wallet_id_index",
+];
+
+// the `INDEXES` the schema the pool works on lacks, or has left invalid by
+// a failed `CREATE INDEX CONCURRENTLY`
+pub async fn missing_indexes(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    let present: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT class.relname::text
+        FROM pg_index index
+        JOIN pg_class class ON class.oid = index.indexrelid
+        WHERE class.relnamespace = current_schema()::regnamespace AND index.indisvalid
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(INDEXES
+        .into_iter()
+        .filter(|index| !present.iter().any(|name| name == index))
+        .map(str::to_string)
+        .collect())
+}
+
+// embedded migrations that haven't been applied yet, as `(version,
+// description)`. a database that was never migrated reports all of them
+pub async fn pending_migrations(pool: &PgPool) -> Result<Vec<(i64, String)>, sqlx::Error> {
+    let tracked: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+        .fetch_one(pool)
+        .await?;
+
+    let applied: Vec<i64> = if tracked {
+        sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+            .fetch_all(pool)
+            .await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(MIGRATOR
+        .iter()
+        .filter(|migration| !applied.contains(&migration.version))
+        .map(|migration| (migration.version, migration.description.to_string()))
+        .collect())
+}
+
+// creates the canonical wallets, leaving existing ones alone. with `reset`
+// their balances and limits are restored and their history is wiped, which
+// is what you want between load test runs
+pub async fn seed(pool: &PgPool, reset: bool) -> Result<(), sqlx::Error> {
+    let (ids, limits): (Vec<i32>, Vec<i64>) = SEED_WALLETS.into_iter().unzip();
+
+    let mut transaction = pool.begin().await?;
+
+    if reset {
+        // the only deletes the `transactions_immutable` trigger lets through
+        sqlx::query!("SELECT set_config('rinha.reset_history', 'on', true)")
+            .fetch_one(&mut *transaction)
+            .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM idempotency_keys WHERE wallet_id = ANY($1);
+            "#,
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM transactions WHERE wallet_id = ANY($1);
+            "#,
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM pending_projections WHERE wallet_id = ANY($1);
+            "#,
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        // along with the deliveries they still had queued
+        sqlx::query!(
+            r#"
+            DELETE FROM webhooks WHERE wallet_id = ANY($1);
+            "#,
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+
+        sqlx::query!(
+            r#"
+            DELETE FROM holds WHERE wallet_id = ANY($1);
+            "#,
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM scheduled_transactions WHERE wallet_id = ANY($1);
+            "#,
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM recurring_transactions WHERE wallet_id = ANY($1);
+            "#,
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+        sqlx::query!(
+            r#"
+            DELETE FROM interest_accruals WHERE wallet_id = ANY($1);
+            "#,
+            &ids
+        )
+        .execute(&mut *transaction)
+        .await?;
+    }
+
+    sqlx::query!(
+        r#"
+        INSERT INTO wallets (id, balance, credit_limit)
+        SELECT id, 0, credit_limit FROM UNNEST($1::int[], $2::bigint[]) AS seed (id, credit_limit)
+        ON CONFLICT (id) DO UPDATE
+        SET balance = 0, credit_limit = EXCLUDED.credit_limit, recent_transactions = '[]',
+            chain_head = NULL, chain_length = 0, status = 'active', daily_debit_limit = NULL,
+            held = 0, interest_rate = NULL
+        WHERE $3;
+        "#,
+        &ids,
+        &limits,
+        reset
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    // ids were inserted explicitly, keep the serial from handing them out again
+    sqlx::query!(
+        r#"
+        SELECT setval('wallets_id_seq', (SELECT MAX(id) FROM wallets));
+        "#
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    transaction.commit().await
+}
+
+// handlers acquire explicitly instead of passing the pool as the executor so
+// the time spent waiting for a free connection shows up in the metrics
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn acquire(pool: &PgPool) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+    let start = Instant::now();
+    let conn = pool.acquire().await;
+    crate::metrics::record_acquire(start.elapsed());
+    conn
+}
+
+// starts a transaction whose statements are cancelled past `timeout`, which
+// comes back as `query_canceled`. a zero timeout leaves the server's
+pub async fn begin(
+    conn: &mut PgConnection,
+    timeout: Duration,
+) -> Result<Transaction<'_, Postgres>, sqlx::Error> {
+    let mut db_transaction = conn.begin().await?;
+    set_statement_timeout(&mut db_transaction, timeout).await?;
+    set_audit_context(&mut db_transaction, &audit::Context::current()).await?;
+    Ok(db_transaction)
+}
+
+// starts a read-only transaction that sees a single snapshot of the database,
+// so reads made inside it can't observe a write landing in between them
+pub async fn begin_snapshot(
+    conn: &mut PgConnection,
+    timeout: Duration,
+) -> Result<Transaction<'_, Postgres>, sqlx::Error> {
+    let mut db_transaction = conn.begin().await?;
+
+    // has to come before anything else in the transaction
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .execute(&mut *db_transaction)
+        .await?;
+    set_statement_timeout(&mut db_transaction, timeout).await?;
+
+    Ok(db_transaction)
+}
+
+// for the triggers writing the audit log rows, until the transaction ends.
+// outside of a request there's nothing to set and the rows are the `system`'s
+async fn set_audit_context(
+    conn: &mut PgConnection,
+    context: &audit::Context,
+) -> Result<(), sqlx::Error> {
+    if context.actor.is_none() && context.request_id.is_none() {
+        return Ok(());
+    }
+
+    sqlx::query!(
+        r#"
+        SELECT
+            set_config('rinha.actor', COALESCE($1, ''), true) as actor,
+            set_config('rinha.request_id', COALESCE($2, ''), true) as request_id
+        "#,
+        context.actor.as_deref(),
+        context.request_id.as_deref()
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(())
+}
+
+// `SET LOCAL`, which can't take a bind parameter. it ends with the transaction
+async fn set_statement_timeout(
+    conn: &mut PgConnection,
+    timeout: Duration,
+) -> Result<(), sqlx::Error> {
+    if timeout.is_zero() {
+        return Ok(());
+    }
+
+    sqlx::query!(
+        "SELECT set_config('statement_timeout', $1, true)",
+        format!("{}ms", timeout.as_millis())
+    )
+    .fetch_one(conn)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_wallet<'e, E>(executor: E, wallet_id: i32) -> Result<Option<Wallet>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Wallet,
+        r#"
+        SELECT
+            balance + COALESCE((SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = $1), 0) as "balance: Cents",
+            credit_limit as "credit_limit: Cents"
+        FROM wallets
+        WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// never changes once the wallet exists, so it can be read outside of the
+// transaction that writes to the wallet
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_currency<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> Result<Option<String>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_scalar!(
+        r#"
+        SELECT currency FROM wallets WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// what the wallet's pending holds keep aside, it comes off the credit limit
+// for a debit. the `positive_balance` constraint only checks the wallet rows,
+// which the event sourcing mode's writes leave alone
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_held<'e, E>(executor: E, wallet_id: i32) -> Result<Option<Cents>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_scalar!(
+        r#"
+        SELECT held as "held: Cents" FROM wallets WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// whether the wallet takes new transactions, the `transactions_check_status`
+// trigger turns them away otherwise
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_status<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> Result<Option<WalletStatus>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_scalar!(
+        r#"
+        SELECT status as "status: WalletStatus" FROM wallets WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// false for an unknown wallet
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn update_status<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    status: WalletStatus,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let updated = sqlx::query!(
+        r#"
+        UPDATE wallets SET status = $2 WHERE id = $1
+        "#,
+        wallet_id,
+        status as _
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(updated.rows_affected() > 0)
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_wallet_details<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> Result<Option<WalletDetails>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        WalletDetails,
+        r#"
+        SELECT
+            id,
+            COALESCE(balance, 0) + COALESCE((SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = $1), 0) as "balance!: Cents",
+            COALESCE(credit_limit, 0) as "credit_limit!: Cents",
+            external_id,
+            currency,
+            inserted_at as "inserted_at!",
+            (SELECT COUNT(*) FROM transactions WHERE wallet_id = wallets.id) as "transaction_count!",
+            status as "status: WalletStatus",
+            daily_debit_limit as "daily_debit_limit: Cents",
+            (
+                SELECT money_sum(value) FROM transactions
+                WHERE wallet_id = wallets.id AND kind = 'debit' AND inserted_at >= date_trunc('day', now(), 'UTC')
+            ) as "debited_today!: Cents",
+            held as "held: Cents",
+            interest_rate::float8 as "interest_rate"
+        FROM wallets
+        WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn create_wallet<'e, E>(
+    executor: E,
+    post_wallet: &PostWallet,
+) -> Result<CreatedWallet, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        CreatedWallet,
+        r#"
+        INSERT INTO wallets (balance, credit_limit, external_id, currency, daily_debit_limit)
+        VALUES (0, $1, $2, $3, $4)
+        RETURNING
+            id,
+            balance as "balance!: Cents",
+            credit_limit as "credit_limit!: Cents",
+            external_id,
+            currency,
+            daily_debit_limit as "daily_debit_limit: Cents"
+        "#,
+        post_wallet.credit_limit as _,
+        post_wallet.external_id,
+        post_wallet.currency,
+        post_wallet.daily_debit_limit as _
+    )
+    .fetch_one(executor)
+    .await
+}
+
+// `None` for an unknown client or one that's itself an account
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = client_id))]
+pub async fn create_account<'e, E>(
+    executor: E,
+    client_id: i32,
+    post_wallet: &PostWallet,
+) -> Result<Option<CreatedWallet>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        CreatedWallet,
+        r#"
+        INSERT INTO wallets (balance, credit_limit, external_id, currency, daily_debit_limit, owner_id)
+        SELECT 0, $2, $3, $4, $5, id
+        FROM wallets
+        WHERE id = $1 AND owner_id IS NULL
+        RETURNING
+            id,
+            balance as "balance!: Cents",
+            credit_limit as "credit_limit!: Cents",
+            external_id,
+            currency,
+            daily_debit_limit as "daily_debit_limit: Cents"
+        "#,
+        client_id,
+        post_wallet.credit_limit as _,
+        post_wallet.external_id,
+        post_wallet.currency,
+        post_wallet.daily_debit_limit as _
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// empty for an unknown client or one that's itself an account
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = client_id))]
+pub async fn fetch_accounts<'e, E>(executor: E, client_id: i32) -> Result<Vec<Account>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Account,
+        r#"
+        SELECT
+            id,
+            balance as "balance!: Cents",
+            credit_limit as "credit_limit!: Cents",
+            external_id,
+            currency,
+            owner_id IS NULL as "default!"
+        FROM wallets
+        WHERE (id = $1 AND owner_id IS NULL) OR owner_id = $1
+        ORDER BY owner_id NULLS FIRST, id
+        "#,
+        client_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = client_id))]
+pub async fn owns_account<'e, E>(
+    executor: E,
+    client_id: i32,
+    account_id: i32,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (SELECT 1 FROM wallets WHERE id = $2 AND owner_id = $1) as "owned!"
+        "#,
+        client_id,
+        account_id
+    )
+    .fetch_one(executor)
+    .await
+}
+
+// false for an unknown wallet. `None` lifts the limit
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn update_daily_debit_limit<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    limit: Option<Cents>,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let updated = sqlx::query!(
+        r#"
+        UPDATE wallets SET daily_debit_limit = $2 WHERE id = $1
+        "#,
+        wallet_id,
+        limit as _
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(updated.rows_affected() > 0)
+}
+
+// the balance and the newest 10 transactions in one row, from the
+// `recent_transactions` the `transactions_remember_recent` trigger keeps.
+// like every read of a balance, it adds what's still pending projection
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_statement<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> Result<Option<Statement>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            balance + COALESCE((SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = $1), 0) as "balance: Cents",
+            credit_limit as "credit_limit: Cents",
+            currency,
+            recent_transactions as "recent_transactions: Json<Vec<TransactionItem>>"
+        FROM wallets
+        WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| Statement {
+        wallet: Wallet {
+            balance: row.balance,
+            credit_limit: row.credit_limit,
+        },
+        currency: row.currency,
+        transactions: row.recent_transactions.0,
+    }))
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_transactions<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    filter: &StatementFilter,
+) -> Result<Vec<TransactionItem>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        TransactionItem,
+        r#"
+        SELECT value as "value: Cents", kind as "kind: TransactionKind", description, inserted_at as "inserted_at!", category, tags, metadata
+        FROM transactions
+        WHERE wallet_id = $1
+          AND ($2::timestamptz IS NULL OR inserted_at >= $2)
+          AND ($3::timestamptz IS NULL OR inserted_at < $3)
+          AND ($4::transaction_kind IS NULL OR kind = $4)
+        -- the rows of a batch share their timestamp
+        ORDER BY inserted_at DESC, id DESC
+        LIMIT 10;
+        "#,
+        wallet_id,
+        filter.from,
+        filter.to,
+        filter.kind as _
+    )
+    .fetch_all(executor)
+    .await
+}
+
+// updates the balance and records the transaction in one roundtrip. returns
+// `None` when the wallet doesn't exist; a debit past the limit fails on the
+// `positive_balance` constraint. the UPDATE takes the wallet's row lock
+// before reading the balance, so concurrent writes to the same wallet are
+// applied one after the other and the constraint always sees the latest one
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn register_transaction<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    post_transaction: &PostTransaction,
+) -> Result<Option<TransactionReceipt>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query!(
+        r#"
+        WITH updated AS (
+            UPDATE wallets SET balance = balance + $2 WHERE id = $1 RETURNING balance, credit_limit
+        ), inserted AS (
+            INSERT INTO transactions (wallet_id, value, kind, description, category, tags, metadata)
+            SELECT $1, $3, $4, $5, $6, $7, $8 FROM updated
+            RETURNING public_id, inserted_at
+        )
+        SELECT
+            balance as "balance: Cents",
+            credit_limit as "credit_limit: Cents",
+            public_id,
+            inserted_at as "inserted_at!"
+        FROM updated, inserted;
+        "#,
+        wallet_id,
+        post_transaction.delta() as _,
+        post_transaction.value as _,
+        post_transaction.kind as _,
+        post_transaction.description,
+        post_transaction.category,
+        &post_transaction.tags,
+        post_transaction.metadata
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| TransactionReceipt {
+        wallet: Wallet {
+            balance: row.balance,
+            credit_limit: row.credit_limit,
+        },
+        id: Some(row.public_id),
+        inserted_at: Some(row.inserted_at),
+    }))
+}
+
+// `register_transaction` through the function of the same name, for when
+// API_USE_DB_FUNC is set. the same results, the same errors
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn call_register_transaction<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    post_transaction: &PostTransaction,
+) -> Result<Option<TransactionReceipt>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            balance as "balance: Cents",
+            credit_limit as "credit_limit: Cents",
+            public_id as "public_id!",
+            inserted_at as "inserted_at!"
+        FROM register_transaction($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        wallet_id,
+        post_transaction.value as _,
+        post_transaction.kind as _,
+        post_transaction.description,
+        post_transaction.category,
+        &post_transaction.tags,
+        post_transaction.metadata
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| TransactionReceipt {
+        wallet: Wallet {
+            balance: row.balance,
+            credit_limit: row.credit_limit,
+        },
+        id: Some(row.public_id),
+        inserted_at: Some(row.inserted_at),
+    }))
+}
+
+// only the balance half of `register_transaction`, for the write-behind
+// queue that inserts the row later. `None` for an unknown wallet, a blocked
+// one, see `fetch_status`, or a debit of a wallet with a daily limit. the
+// `transactions_limit_daily_debits` trigger only sees the rows already
+// inserted, so those debits have to take the path that inserts right away
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn update_balance<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    post_transaction: &PostTransaction,
+) -> Result<Option<Wallet>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Wallet,
+        r#"
+        UPDATE wallets SET balance = balance + $2
+        WHERE id = $1 AND status = 'active' AND (daily_debit_limit IS NULL OR $2 > 0)
+        RETURNING balance as "balance: Cents", credit_limit as "credit_limit: Cents"
+        "#,
+        wallet_id,
+        post_transaction.delta() as _
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// the rows of any number of wallets in one statement, ids and timestamps
+// included. the balances were already updated by `update_balance`, which is
+// where a blocked wallet was turned away: `rinha.write_behind` lets the rows
+// of a wallet blocked since then past the `transactions_check_status` trigger
+#[tracing::instrument(level = "debug", skip_all, fields(rows = rows.len()))]
+pub async fn insert_transaction_rows(
+    pool: &PgPool,
+    rows: &[(i32, TransactionDetails)],
+) -> Result<(), sqlx::Error> {
+    let wallet_ids: Vec<i32> = rows.iter().map(|(wallet_id, _)| *wallet_id).collect();
+    let public_ids: Vec<Uuid> = rows.iter().map(|(_, row)| row.id).collect();
+    let values: Vec<Cents> = rows.iter().map(|(_, row)| row.transaction.value).collect();
+    let kinds: Vec<TransactionKind> = rows.iter().map(|(_, row)| row.transaction.kind).collect();
+    let descriptions: Vec<String> = rows
+        .iter()
+        .map(|(_, row)| row.transaction.description.clone())
+        .collect();
+    let inserted_at: Vec<OffsetDateTime> = rows
+        .iter()
+        .map(|(_, row)| row.transaction.inserted_at)
+        .collect();
+    let categories: Vec<Option<String>> = rows
+        .iter()
+        .map(|(_, row)| row.transaction.category.clone())
+        .collect();
+    let tags = tag_lists(rows.iter().map(|(_, row)| &row.transaction.tags));
+    let metadata: Vec<Option<Value>> = rows
+        .iter()
+        .map(|(_, row)| row.transaction.metadata.clone())
+        .collect();
+
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!("SELECT set_config('rinha.write_behind', 'on', true)")
+        .fetch_one(&mut *transaction)
+        .await?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO transactions (wallet_id, public_id, value, kind, description, inserted_at, category, tags, metadata)
+        SELECT
+            item.wallet_id, item.public_id, item.value, item.kind, item.description, item.inserted_at,
+            item.category, ARRAY(SELECT jsonb_array_elements_text(item.tags)), item.metadata
+        FROM UNNEST(
+            $1::int[], $2::uuid[], $3::numeric[], $4::transaction_kind[], $5::text[], $6::timestamptz[],
+            $7::text[], $8::jsonb[], $9::jsonb[]
+        ) AS item(wallet_id, public_id, value, kind, description, inserted_at, category, tags, metadata)
+        "#,
+        &wallet_ids,
+        &public_ids,
+        &values as _,
+        &kinds as _,
+        &descriptions,
+        &inserted_at,
+        &categories as _,
+        &tags,
+        &metadata as _
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    transaction.commit().await
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_transaction<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    transaction_id: Uuid,
+) -> Result<Option<TransactionDetails>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query!(
+        r#"
+        SELECT public_id, value as "value: Cents", kind as "kind: TransactionKind", description, inserted_at as "inserted_at!", category, tags, metadata
+        FROM transactions
+        WHERE wallet_id = $1 AND public_id = $2
+        "#,
+        wallet_id,
+        transaction_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| TransactionDetails {
+        id: row.public_id,
+        transaction: TransactionItem {
+            value: row.value,
+            kind: row.kind,
+            description: row.description,
+            inserted_at: row.inserted_at,
+            category: row.category,
+            tags: row.tags,
+            metadata: row.metadata,
+        },
+    }))
+}
+
+// up to `limit` rows after the cursor, oldest first, each along with the
+// cursor that points right after it
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_history<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    filter: &HistoryFilter,
+    limit: i64,
+) -> Result<Vec<(HistoryCursor, TransactionDetails)>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    // a first page compares against the lowest possible cursor rather than
+    // skipping the comparison, so the index is used the same way every time
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, public_id, value as "value: Cents", kind as "kind: TransactionKind", description, inserted_at as "inserted_at!", category, tags, metadata
+        FROM transactions
+        WHERE wallet_id = $1
+          AND (inserted_at, id) > (COALESCE($2, '-infinity'::timestamptz), COALESCE($3::bigint, 0))
+          AND ($5::text IS NULL OR description ILIKE $5)
+          AND ($6::text IS NULL OR category = $6)
+          AND ($7::text IS NULL OR $7 = ANY(tags))
+          AND NOT EXISTS (
+              SELECT 1 FROM jsonb_each_text($8) AS wanted
+              WHERE metadata ->> wanted.key IS DISTINCT FROM wanted.value
+          )
+        ORDER BY inserted_at, id
+        LIMIT $4
+        "#,
+        wallet_id,
+        filter.after.map(|cursor| cursor.inserted_at),
+        filter.after.map(|cursor| cursor.id),
+        limit,
+        filter.search_pattern(),
+        filter.category,
+        filter.tag,
+        Json(&filter.metadata) as _
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let cursor = HistoryCursor {
+                inserted_at: row.inserted_at,
+                id: row.id.into(),
+            };
+            let details = TransactionDetails {
+                id: row.public_id,
+                transaction: TransactionItem {
+                    value: row.value,
+                    kind: row.kind,
+                    description: row.description,
+                    inserted_at: row.inserted_at,
+                    category: row.category,
+                    tags: row.tags,
+                    metadata: row.metadata,
+                },
+            };
+            (cursor, details)
+        })
+        .collect())
+}
+
+// the totals of each kind and category of transaction made during the month, `None` for an unknown wallet. the left join keeps a row for a
+// wallet without any transactions in the month
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_monthly_totals<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    month: SummaryMonth,
+) -> Result<Option<Vec<KindTotal>>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let (from, to) = month.range();
+
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            transactions.kind as "kind?: TransactionKind",
+            transactions.category,
+            money_sum(transactions.value) as "sum!: Cents",
+            COUNT(transactions.id) as "count!"
+        FROM wallets
+        LEFT JOIN transactions
+            ON transactions.wallet_id = wallets.id
+            AND transactions.inserted_at >= $2
+            AND transactions.inserted_at < $3
+        WHERE wallets.id = $1
+        GROUP BY wallets.id, transactions.kind, transactions.category
+        "#,
+        wallet_id,
+        from,
+        to
+    )
+    .fetch_all(executor)
+    .await?;
+
+    if rows.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        rows.into_iter()
+            .filter_map(|row| {
+                Some(KindTotal {
+                    kind: row.kind?,
+                    category: row.category,
+                    sum: row.sum,
+                    count: row.count,
+                })
+            })
+            .collect(),
+    ))
+}
+
+// every row of the wallet oldest first, decoded as they arrive instead of
+// collected up front
+pub fn stream_transactions<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> impl Stream<Item = Result<TransactionDetails, sqlx::Error>> + Send + 'e
+where
+    E: PgExecutor<'e> + 'e,
+{
+    sqlx::query!(
+        r#"
+        SELECT public_id, value as "value: Cents", kind as "kind: TransactionKind", description, inserted_at as "inserted_at!", category, tags, metadata
+        FROM transactions
+        WHERE wallet_id = $1
+        ORDER BY inserted_at, id
+        "#,
+        wallet_id
+    )
+    .fetch(executor)
+    .map(|row| {
+        row.map(|row| TransactionDetails {
+            id: row.public_id,
+            transaction: TransactionItem {
+                value: row.value,
+                kind: row.kind,
+                description: row.description,
+                inserted_at: row.inserted_at,
+                category: row.category,
+                tags: row.tags,
+                metadata: row.metadata,
+            },
+        })
+    })
+}
+
+// `(last hash, length)` of the wallet's chain in hex, as the
+// `transactions_hash_chain` trigger left them. `None` for an unknown wallet
+pub async fn fetch_chain_head<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> Result<Option<(Option<String>, i64)>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query!(
+        r#"
+        SELECT encode(chain_head, 'hex') as chain_head, chain_length
+        FROM wallets
+        WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| (row.chain_head, row.chain_length)))
+}
+
+// the links of the wallet's chain in position order, each with the hash its
+// row works out to now
+pub fn stream_chain<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> impl Stream<Item = Result<ChainLink, sqlx::Error>> + Send + 'e
+where
+    E: PgExecutor<'e> + 'e,
+{
+    sqlx::query!(
+        r#"
+        SELECT
+            public_id,
+            chain_position,
+            encode(prev_hash, 'hex') as prev_hash,
+            encode(hash, 'hex') as "hash!",
+            encode(transaction_hash(transactions), 'hex') as "recomputed!"
+        FROM transactions
+        WHERE wallet_id = $1
+        ORDER BY chain_position
+        "#,
+        wallet_id
+    )
+    .fetch(executor)
+    .map(|row| {
+        row.map(|row| ChainLink {
+            id: row.public_id,
+            position: row.chain_position,
+            prev_hash: row.prev_hash,
+            hash: row.hash,
+            recomputed: row.recomputed,
+        })
+    })
+}
+
+// the tags of each row as one json array, UNNEST only takes flat arrays
+fn tag_lists<'a>(tags: impl Iterator<Item = &'a Vec<String>>) -> Vec<Value> {
+    tags.map(|tags| Value::from(tags.clone())).collect()
+}
+
+// one balance update by `delta` and one multi-row insert for the whole
+// batch, expected to run with the wallet already locked. returns the
+// `(public_id, inserted_at)` of each row in the order of `transactions`.
+// the notify trigger fires after the insert, so every event of the batch
+// carries the balance after all of it
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id, size = transactions.len()))]
+pub async fn register_transaction_batch<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    delta: Cents,
+    transactions: &[PostTransaction],
+) -> Result<Vec<(Uuid, OffsetDateTime)>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let values: Vec<Cents> = transactions.iter().map(|t| t.value).collect();
+    let kinds: Vec<TransactionKind> = transactions.iter().map(|t| t.kind).collect();
+    let descriptions: Vec<String> = transactions.iter().map(|t| t.description.clone()).collect();
+    let categories: Vec<Option<String>> = transactions.iter().map(|t| t.category.clone()).collect();
+    let tags = tag_lists(transactions.iter().map(|t| &t.tags));
+    let metadata: Vec<Option<Value>> = transactions.iter().map(|t| t.metadata.clone()).collect();
+
+    let mut rows = sqlx::query!(
+        r#"
+        WITH updated AS (
+            UPDATE wallets SET balance = balance + $2 WHERE id = $1 RETURNING id
+        )
+        INSERT INTO transactions (wallet_id, value, kind, description, category, tags, metadata)
+        SELECT
+            updated.id, item.value, item.kind, item.description,
+            item.category, ARRAY(SELECT jsonb_array_elements_text(item.tags)), item.metadata
+        FROM updated,
+            UNNEST($3::numeric[], $4::transaction_kind[], $5::text[], $6::text[], $7::jsonb[], $8::jsonb[])
+                WITH ORDINALITY AS item(value, kind, description, category, tags, metadata, position)
+        ORDER BY item.position
+        RETURNING id, public_id, inserted_at as "inserted_at!"
+        "#,
+        wallet_id,
+        delta as _,
+        &values as _,
+        &kinds as _,
+        &descriptions,
+        &categories as _,
+        &tags,
+        &metadata as _
+    )
+    .fetch_all(executor)
+    .await?;
+
+    // the serial ids follow the ORDER BY, RETURNING itself has no order
+    rows.sort_by_key(|row| row.id);
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.public_id, row.inserted_at))
+        .collect())
+}
+
+// locks the wallet row until the end of the transaction, serializing every
+// other write to it. returns false when the wallet doesn't exist
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn lock_wallet<'e, E>(executor: E, wallet_id: i32) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let locked = sqlx::query!(
+        r#"
+        SELECT id
+        FROM wallets
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(locked.is_some())
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn update_credit_limit<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    credit_limit: Cents,
+) -> Result<Option<Wallet>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Wallet,
+        r#"
+        UPDATE wallets SET credit_limit = $2 WHERE id = $1
+        RETURNING balance as "balance: Cents", credit_limit as "credit_limit: Cents"
+        "#,
+        wallet_id,
+        credit_limit as _
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn insert_audit_log<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    action: &str,
+    old_value: &Value,
+    new_value: &Value,
+    actor: &str,
+    request_id: Option<&str>,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, request_id)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        wallet_id,
+        action,
+        old_value,
+        new_value,
+        actor,
+        request_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// newest first, `limit` rows from below `filter.before`
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn fetch_audit_log<'e, E>(
+    executor: E,
+    filter: &AuditFilter,
+    limit: i64,
+) -> Result<Vec<AuditEntry>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        AuditEntry,
+        r#"
+        SELECT
+            id,
+            wallet_id,
+            action::text as "action!",
+            old_value,
+            new_value,
+            actor::text as "actor!",
+            request_id::text,
+            inserted_at as "inserted_at!"
+        FROM audit_log
+        WHERE ($1::int IS NULL OR wallet_id = $1)
+          AND ($2::text IS NULL OR action = $2)
+          AND ($3::text IS NULL OR actor = $3)
+          AND ($4::text IS NULL OR request_id = $4)
+          AND ($5::timestamptz IS NULL OR inserted_at >= $5)
+          AND ($6::timestamptz IS NULL OR inserted_at < $6)
+          AND ($7::bigint IS NULL OR id < $7)
+        ORDER BY id DESC
+        LIMIT $8
+        "#,
+        filter.wallet_id,
+        filter.action.as_deref(),
+        filter.actor.as_deref(),
+        filter.request_id.as_deref(),
+        filter.from,
+        filter.to,
+        filter.before,
+        limit
+    )
+    .fetch_all(executor)
+    .await
+}
+
+// locks the wallets in id order, so two transfers going opposite ways
+// between the same wallets can't deadlock. returns how many exist
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn lock_wallets<'e, E>(executor: E, wallet_ids: &[i32]) -> Result<usize, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let locked = sqlx::query!(
+        r#"
+        SELECT id
+        FROM wallets
+        WHERE id = ANY($1)
+        ORDER BY id
+        FOR UPDATE
+        "#,
+        wallet_ids
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(locked.len())
+}
+
+// what the event sourcing mode locks instead of the wallet row, which its
+// writes never touch. keyed on the table's oid so it can't clash with
+// another use of advisory locks. returns false when the wallet doesn't exist
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn lock_wallet_log<'e, E>(executor: E, wallet_id: i32) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let locked = sqlx::query!(
+        r#"
+        SELECT pg_advisory_xact_lock('wallets'::regclass::oid::int, id)
+        FROM wallets
+        WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(locked.is_some())
+}
+
+// `lock_wallet_log` for several wallets, in id order like `lock_wallets`
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn lock_wallet_logs<'e, E>(executor: E, wallet_ids: &[i32]) -> Result<usize, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let locked = sqlx::query!(
+        r#"
+        SELECT pg_advisory_xact_lock('wallets'::regclass::oid::int, wallet.id)
+        FROM (SELECT id FROM wallets WHERE id = ANY($1) ORDER BY id) AS wallet
+        "#,
+        wallet_ids
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(locked.len())
+}
+
+// a balance change for the projector to fold in, appended ahead of the
+// transactions it stands for so their events see it
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn append_pending_projection<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    delta: Cents,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        INSERT INTO pending_projections (wallet_id, delta) VALUES ($1, $2)
+        "#,
+        wallet_id,
+        delta as _
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// the rows of `transactions` alone, the event sourcing counterpart of
+// `register_transaction_batch`. returns the `(public_id, inserted_at)` of
+// each row in the order of `transactions`
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id, size = transactions.len()))]
+pub async fn append_transactions<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    transactions: &[PostTransaction],
+) -> Result<Vec<(Uuid, OffsetDateTime)>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let values: Vec<Cents> = transactions.iter().map(|t| t.value).collect();
+    let kinds: Vec<TransactionKind> = transactions.iter().map(|t| t.kind).collect();
+    let descriptions: Vec<String> = transactions.iter().map(|t| t.description.clone()).collect();
+    let categories: Vec<Option<String>> = transactions.iter().map(|t| t.category.clone()).collect();
+    let tags = tag_lists(transactions.iter().map(|t| &t.tags));
+    let metadata: Vec<Option<Value>> = transactions.iter().map(|t| t.metadata.clone()).collect();
+
+    let mut rows = sqlx::query!(
+        r#"
+        INSERT INTO transactions (wallet_id, value, kind, description, category, tags, metadata)
+        SELECT
+            $1, item.value, item.kind, item.description,
+            item.category, ARRAY(SELECT jsonb_array_elements_text(item.tags)), item.metadata
+        FROM UNNEST($2::numeric[], $3::transaction_kind[], $4::text[], $5::text[], $6::jsonb[], $7::jsonb[])
+            WITH ORDINALITY AS item(value, kind, description, category, tags, metadata, position)
+        ORDER BY item.position
+        RETURNING id, public_id, inserted_at as "inserted_at!"
+        "#,
+        wallet_id,
+        &values as _,
+        &kinds as _,
+        &descriptions,
+        &categories as _,
+        &tags,
+        &metadata as _
+    )
+    .fetch_all(executor)
+    .await?;
+
+    rows.sort_by_key(|row| row.id);
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.public_id, row.inserted_at))
+        .collect())
+}
+
+// the debit and the credit of a transfer, the event sourcing counterpart of
+// `register_transfer`
+#[tracing::instrument(level = "debug", skip_all, fields(transfer_id = %transfer_id))]
+pub async fn append_transfer<'e, E>(
+    executor: E,
+    transfer_id: Uuid,
+    transfer: &PostTransfer,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        INSERT INTO transactions (wallet_id, value, kind, description, transfer_id)
+        VALUES ($1, $3, 'debit', $4, $5), ($2, $3, 'credit', $4, $5)
+        "#,
+        transfer.from,
+        transfer.to,
+        transfer.value as _,
+        transfer.description,
+        transfer_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// folds every pending row of up to `max_wallets` wallets into their
+// balances. always all of a wallet's rows, a balance halfway through them
+// may not fit a limit lowered since. returns the rows folded in
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn project_pending<'e, E>(executor: E, max_wallets: i64) -> Result<u64, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let projected = sqlx::query_scalar!(
+        r#"
+        WITH drained AS (
+            DELETE FROM pending_projections
+            WHERE wallet_id IN (
+                SELECT DISTINCT wallet_id FROM pending_projections LIMIT $1
+            )
+            RETURNING wallet_id, delta
+        ), totals AS (
+            SELECT wallet_id, money_sum(delta) as delta, COUNT(*) as projected
+            FROM drained
+            GROUP BY wallet_id
+        ), updated AS (
+            UPDATE wallets SET balance = balance + totals.delta
+            FROM totals
+            WHERE wallets.id = totals.wallet_id
+        )
+        SELECT COALESCE(SUM(projected), 0)::bigint as "projected!" FROM totals
+        "#,
+        max_wallets
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(projected as u64)
+}
+
+// `project_pending` for a single wallet, so its row has the balance every
+// read adds up. expected to run with the wallet's advisory lock, no new rows
+// can show up halfway
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn project_wallet<'e, E>(executor: E, wallet_id: i32) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        WITH drained AS (
+            DELETE FROM pending_projections WHERE wallet_id = $1 RETURNING delta
+        )
+        UPDATE wallets SET balance = balance + (SELECT money_sum(delta) FROM drained)
+        WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// works every balance out again from the transactions, throwing away the
+// pending rows they already include. the tables are locked against writes
+// meanwhile. returns the number of wallets
+pub async fn rebuild_projections(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!("LOCK TABLE wallets, pending_projections IN EXCLUSIVE MODE")
+        .execute(&mut *transaction)
+        .await?;
+
+    let rebuilt = sqlx::query!(
+        r#"
+        UPDATE wallets
+        SET balance = COALESCE((
+            SELECT money_sum(CASE kind WHEN 'credit' THEN value ELSE -value END)
+            FROM transactions
+            WHERE transactions.wallet_id = wallets.id
+        ), 0)
+        "#
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+
+    sqlx::query!("DELETE FROM pending_projections")
+        .execute(&mut *transaction)
+        .await?;
+
+    transaction.commit().await?;
+
+    Ok(rebuilt)
+}
+
+// moves the value between the wallets and records the debit and the credit
+// under the transfer's id. the wallets must already be locked; a debit past
+// the source's limit fails on the `positive_balance` constraint. the insert
+// reads the updates so they land before it, the `transactions_hash_chain`
+// trigger updates the same rows
+#[tracing::instrument(level = "debug", skip_all, fields(transfer_id = %transfer_id))]
+pub async fn register_transfer<'e, E>(
+    executor: E,
+    transfer_id: Uuid,
+    transfer: &PostTransfer,
+) -> Result<Wallet, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Wallet,
+        r#"
+        WITH debited AS (
+            UPDATE wallets SET balance = balance - $3 WHERE id = $1 RETURNING id, balance, credit_limit
+        ), credited AS (
+            UPDATE wallets SET balance = balance + $3 WHERE id = $2 RETURNING id
+        ), inserted AS (
+            INSERT INTO transactions (wallet_id, value, kind, description, transfer_id)
+            SELECT item.wallet_id, $3, item.kind, $4, $5
+            FROM debited, credited,
+                LATERAL (VALUES (debited.id, 'debit'::transaction_kind), (credited.id, 'credit'))
+                    AS item(wallet_id, kind)
+        )
+        SELECT balance as "balance: Cents", credit_limit as "credit_limit: Cents" FROM debited;
+        "#,
+        transfer.from,
+        transfer.to,
+        transfer.value as _,
+        transfer.description,
+        transfer_id
+    )
+    .fetch_one(executor)
+    .await
+}
+
+// the wallets whose balance, with what's still pending, isn't what their
+// transactions add up to. they all start at zero
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn fetch_drifted_wallets<'e, E>(executor: E) -> Result<Vec<i32>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_scalar!(
+        r#"
+        SELECT wallets.id
+        FROM wallets
+        LEFT JOIN (
+            SELECT wallet_id, money_sum(CASE kind WHEN 'credit' THEN value ELSE -value END) as total
+            FROM transactions
+            GROUP BY wallet_id
+        ) totals ON totals.wallet_id = wallets.id
+        LEFT JOIN (
+            SELECT wallet_id, money_sum(delta) as total
+            FROM pending_projections
+            GROUP BY wallet_id
+        ) pending ON pending.wallet_id = wallets.id
+        WHERE COALESCE(wallets.balance, 0) + COALESCE(pending.total, 0) <> COALESCE(totals.total, 0)
+        ORDER BY wallets.id
+        "#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+// `(balance, expected)`: the balance with what's still pending, and what the
+// wallet's transactions add up to. `None` for an unknown wallet
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_balance_drift<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> Result<Option<(Cents, Cents)>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            COALESCE(balance, 0) + COALESCE((SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = $1), 0) as "balance!: Cents",
+            COALESCE((
+                SELECT money_sum(CASE kind WHEN 'credit' THEN value ELSE -value END)
+                FROM transactions
+                WHERE wallet_id = $1
+            ), 0) as "expected!: Cents"
+        FROM wallets
+        WHERE id = $1
+        "#,
+        wallet_id
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| (row.balance, row.expected)))
+}
+
+// moves the balance by `correction`, unless that takes it past the credit
+// limit, less what the holds keep aside. false when it didn't
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn correct_balance<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    correction: Cents,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let corrected = sqlx::query!(
+        r#"
+        UPDATE wallets
+        SET balance = balance + $2
+        WHERE id = $1 AND balance + $2 - held >= -credit_limit
+        RETURNING id
+        "#,
+        wallet_id,
+        correction as _
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(corrected.is_some())
+}
+
+// transactions whose ledger entries don't add up to zero, or that don't have
+// the two the `transactions_record_ledger` trigger writes
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn fetch_unbalanced_transactions<'e, E>(
+    executor: E,
+) -> Result<Vec<LedgerViolation>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            transactions.public_id,
+            COUNT(ledger_entries.id) as "entries!",
+            COALESCE(money_sum(ledger_entries.amount), 0) as "sum!: Cents"
+        FROM transactions
+        LEFT JOIN ledger_entries ON ledger_entries.transaction_id = transactions.id
+        GROUP BY transactions.id
+        HAVING COUNT(ledger_entries.id) <> 2
+            OR COALESCE(money_sum(ledger_entries.amount), 0) <> 0
+        ORDER BY transactions.id
+        "#
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| LedgerViolation::Unbalanced {
+            transaction_id: row.public_id,
+            entries: row.entries,
+            sum: row.sum,
+        })
+        .collect())
+}
+
+// wallets whose balance isn't the sum of the entries of their account
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn fetch_balance_mismatches<'e, E>(
+    executor: E,
+) -> Result<Vec<LedgerViolation>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            wallets.id,
+            COALESCE(wallets.balance, 0) + COALESCE((SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = wallets.id), 0) as "balance!: Cents",
+            COALESCE(money_sum(ledger_entries.amount), 0) as "entries!: Cents"
+        FROM wallets
+        LEFT JOIN ledger_entries
+            ON ledger_entries.wallet_id = wallets.id AND ledger_entries.account = 'wallet'
+        GROUP BY wallets.id
+        HAVING COALESCE(wallets.balance, 0) + COALESCE((SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = wallets.id), 0)
+            <> COALESCE(money_sum(ledger_entries.amount), 0)
+        ORDER BY wallets.id
+        "#
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| LedgerViolation::BalanceMismatch {
+            wallet_id: row.id,
+            balance: row.balance,
+            entries: row.entries,
+        })
+        .collect())
+}
+
+// returns false when the key was already claimed, in which case the stored
+// response should be replayed
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn claim_idempotency_key<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    key: &str,
+    fingerprint: &str,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let claimed = sqlx::query!(
+        r#"
+        INSERT INTO idempotency_keys (wallet_id, key, fingerprint) VALUES ($1, $2, $3)
+        ON CONFLICT DO NOTHING;
+        "#,
+        wallet_id,
+        key,
+        fingerprint
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(claimed.rows_affected() > 0)
+}
+
+// the response stored for the key, and the fingerprint of the transaction it
+// was first used for
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_idempotent_response<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    key: &str,
+) -> Result<(Value, Option<String>), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let stored = sqlx::query!(
+        r#"
+        SELECT response as "response!: Json<Value>", fingerprint
+        FROM idempotency_keys
+        WHERE wallet_id = $1 AND key = $2
+        "#,
+        wallet_id,
+        key
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok((stored.response.0, stored.fingerprint))
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn store_idempotent_response<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    key: &str,
+    response: &Value,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        UPDATE idempotency_keys SET response = $1 WHERE wallet_id = $2 AND key = $3;
+        "#,
+        response,
+        wallet_id,
+        key
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn fetch_api_key_scope<'e, E>(
+    executor: E,
+    key_hash: &str,
+) -> Result<Option<String>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let row = sqlx::query!(
+        r#"
+        SELECT scope
+        FROM api_keys
+        WHERE key_hash = $1
+        "#,
+        key_hash
+    )
+    .fetch_optional(executor)
+    .await?;
+
+    Ok(row.map(|row| row.scope))
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn insert_webhook<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    url: &str,
+    secret: &str,
+) -> Result<Webhook, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Webhook,
+        r#"
+        INSERT INTO webhooks (wallet_id, url, secret)
+        VALUES ($1, $2, $3)
+        RETURNING id, url, inserted_at
+        "#,
+        wallet_id,
+        url,
+        secret
+    )
+    .fetch_one(executor)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_webhooks<'e, E>(executor: E, wallet_id: i32) -> Result<Vec<Webhook>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Webhook,
+        r#"
+        SELECT id, url, inserted_at
+        FROM webhooks
+        WHERE wallet_id = $1
+        ORDER BY id
+        "#,
+        wallet_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+// false when the wallet has no such webhook
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn delete_webhook<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    webhook_id: i32,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM webhooks WHERE wallet_id = $1 AND id = $2
+        "#,
+        wallet_id,
+        webhook_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(deleted > 0)
+}
+
+// sets the hold's amount aside on the wallet and records it. an amount the
+// wallet can't spend fails on the `positive_balance` constraint, the way a
+// debit would
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn insert_hold<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    hold: &PostHold,
+    expires_at: OffsetDateTime,
+) -> Result<Option<Hold>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Hold,
+        r#"
+        WITH updated AS (
+            UPDATE wallets SET held = held + $2 WHERE id = $1 RETURNING id
+        )
+        INSERT INTO holds (wallet_id, value, description, expires_at)
+        SELECT updated.id, $2, $3, $4 FROM updated
+        RETURNING
+            public_id as id,
+            wallet_id,
+            value as "value: Cents",
+            description,
+            status as "status: HoldStatus",
+            expires_at,
+            inserted_at,
+            transaction_id
+        "#,
+        wallet_id,
+        hold.value as _,
+        hold.description,
+        expires_at
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// with `for_update` the row stays locked until the end of the transaction,
+// which settles the hold
+#[tracing::instrument(level = "debug", skip_all, fields(hold_id = %hold_id))]
+pub async fn fetch_hold<'e, E>(
+    executor: E,
+    hold_id: Uuid,
+    for_update: bool,
+) -> Result<Option<Hold>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    if for_update {
+        return sqlx::query_as!(
+            Hold,
+            r#"
+            SELECT
+                public_id as id,
+                wallet_id,
+                value as "value: Cents",
+                description,
+                status as "status: HoldStatus",
+                expires_at,
+                inserted_at,
+                transaction_id
+            FROM holds
+            WHERE public_id = $1
+            FOR UPDATE
+            "#,
+            hold_id
+        )
+        .fetch_optional(executor)
+        .await;
+    }
+
+    sqlx::query_as!(
+        Hold,
+        r#"
+        SELECT
+            public_id as id,
+            wallet_id,
+            value as "value: Cents",
+            description,
+            status as "status: HoldStatus",
+            expires_at,
+            inserted_at,
+            transaction_id
+        FROM holds
+        WHERE public_id = $1
+        "#,
+        hold_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// gives back what a settled hold kept aside on the wallet
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn release_held<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    value: Cents,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        UPDATE wallets SET held = held - $2 WHERE id = $1
+        "#,
+        wallet_id,
+        value as _
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// a pending hold's last status, with the debit it was captured into. the
+// caller gives its amount back with `release_held`
+#[tracing::instrument(level = "debug", skip_all, fields(hold_id = %hold_id))]
+pub async fn settle_hold<'e, E>(
+    executor: E,
+    hold_id: Uuid,
+    status: HoldStatus,
+    transaction_id: Option<Uuid>,
+) -> Result<Option<Hold>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Hold,
+        r#"
+        UPDATE holds SET status = $2, settled_at = now(), transaction_id = $3
+        WHERE public_id = $1 AND status = 'pending'
+        RETURNING
+            public_id as id,
+            wallet_id,
+            value as "value: Cents",
+            description,
+            status as "status: HoldStatus",
+            expires_at,
+            inserted_at,
+            transaction_id
+        "#,
+        hold_id,
+        status as _,
+        transaction_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// expires up to `limit` of the pending holds past their time and gives
+// their amounts back, in one statement. holds being captured or released
+// right now are skipped, they're settled either way. returns the holds
+// expired
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn expire_holds<'e, E>(executor: E, limit: i64) -> Result<u64, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let expired = sqlx::query_scalar!(
+        r#"
+        WITH due AS (
+            SELECT id FROM holds
+            WHERE status = 'pending' AND expires_at <= now()
+            ORDER BY expires_at
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        ), expired AS (
+            UPDATE holds SET status = 'expired', settled_at = now()
+            FROM due
+            WHERE holds.id = due.id
+            RETURNING holds.wallet_id, holds.value
+        ), totals AS (
+            SELECT wallet_id, money_sum(value) as value, COUNT(*) as expired
+            FROM expired
+            GROUP BY wallet_id
+        ), updated AS (
+            UPDATE wallets SET held = held - totals.value
+            FROM totals
+            WHERE wallets.id = totals.wallet_id
+        )
+        SELECT COALESCE(SUM(expired), 0)::bigint as "expired!" FROM totals
+        "#,
+        limit
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(expired as u64)
+}
+
+// stores a transaction to be applied at `due_at`. none when the wallet
+// doesn't exist
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn insert_scheduled<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    transaction: &PostTransaction,
+    due_at: OffsetDateTime,
+) -> Result<Option<ScheduledTransaction>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        ScheduledTransaction,
+        r#"
+        INSERT INTO scheduled_transactions (wallet_id, value, kind, description, due_at, category, tags, metadata)
+        SELECT id, $2, $3, $4, $5, $6, $7, $8 FROM wallets WHERE id = $1
+        RETURNING
+            public_id as id,
+            wallet_id,
+            value as "value: Cents",
+            kind as "kind: TransactionKind",
+            description,
+            category,
+            tags,
+            metadata,
+            due_at,
+            status as "status: ScheduledStatus",
+            inserted_at,
+            transaction_id,
+            failure
+        "#,
+        wallet_id,
+        transaction.value as _,
+        transaction.kind as _,
+        transaction.description,
+        due_at,
+        transaction.category,
+        &transaction.tags,
+        transaction.metadata
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// the wallet's pending scheduled transactions, the next one due first
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_scheduled<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> Result<Vec<ScheduledTransaction>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        ScheduledTransaction,
+        r#"
+        SELECT
+            public_id as id,
+            wallet_id,
+            value as "value: Cents",
+            kind as "kind: TransactionKind",
+            description,
+            category,
+            tags,
+            metadata,
+            due_at,
+            status as "status: ScheduledStatus",
+            inserted_at,
+            transaction_id,
+            failure
+        FROM scheduled_transactions
+        WHERE wallet_id = $1 AND status = 'pending'
+        ORDER BY due_at, id
+        "#,
+        wallet_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+// the scheduled transaction due the earliest, locked until the end of the
+// transaction that applies it. those another instance is applying are
+// skipped
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn claim_scheduled<'e, E>(
+    executor: E,
+) -> Result<Option<ScheduledTransaction>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        ScheduledTransaction,
+        r#"
+        SELECT
+            public_id as id,
+            wallet_id,
+            value as "value: Cents",
+            kind as "kind: TransactionKind",
+            description,
+            category,
+            tags,
+            metadata,
+            due_at,
+            status as "status: ScheduledStatus",
+            inserted_at,
+            transaction_id,
+            failure
+        FROM scheduled_transactions
+        WHERE status = 'pending' AND due_at <= now()
+        ORDER BY due_at, id
+        LIMIT 1
+        FOR UPDATE SKIP LOCKED
+        "#
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// a pending scheduled transaction's last status, with the transaction it
+// became or the code of the error that turned it away. none when it's no
+// longer pending, or isn't the wallet's
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id, scheduled_id = %scheduled_id))]
+pub async fn settle_scheduled<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    scheduled_id: Uuid,
+    status: ScheduledStatus,
+    transaction_id: Option<Uuid>,
+    failure: Option<&str>,
+) -> Result<Option<ScheduledTransaction>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        ScheduledTransaction,
+        r#"
+        UPDATE scheduled_transactions
+        SET status = $3, settled_at = now(), transaction_id = $4, failure = $5
+        WHERE public_id = $2 AND wallet_id = $1 AND status = 'pending'
+        RETURNING
+            public_id as id,
+            wallet_id,
+            value as "value: Cents",
+            kind as "kind: TransactionKind",
+            description,
+            category,
+            tags,
+            metadata,
+            due_at,
+            status as "status: ScheduledStatus",
+            inserted_at,
+            transaction_id,
+            failure
+        "#,
+        wallet_id,
+        scheduled_id,
+        status as _,
+        transaction_id,
+        failure
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// whether the wallet has the scheduled transaction, settled or not
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id, scheduled_id = %scheduled_id))]
+pub async fn scheduled_exists<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    scheduled_id: Uuid,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM scheduled_transactions WHERE public_id = $2 AND wallet_id = $1
+        ) as "exists!"
+        "#,
+        wallet_id,
+        scheduled_id
+    )
+    .fetch_one(executor)
+    .await
+}
+
+// a recurrence's first run is `next_run_at`, none for a paused one. none
+// when the wallet doesn't exist
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn insert_recurrence<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    recurrence: &PostRecurrence,
+    next_run_at: Option<OffsetDateTime>,
+) -> Result<Option<Recurrence>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Recurrence,
+        r#"
+        INSERT INTO recurring_transactions (wallet_id, value, kind, description, schedule, next_run_at, category, tags, metadata)
+        SELECT id, $2, $3, $4, $5, $6, $7, $8, $9 FROM wallets WHERE id = $1
+        RETURNING
+            id,
+            wallet_id,
+            value as "value: Cents",
+            kind as "kind: TransactionKind",
+            description,
+            category,
+            tags,
+            metadata,
+            schedule,
+            next_run_at IS NOT NULL as "active!",
+            next_run_at,
+            last_run_at,
+            last_failure,
+            inserted_at
+        "#,
+        wallet_id,
+        recurrence.value as _,
+        recurrence.kind as _,
+        recurrence.description,
+        recurrence.schedule.to_string(),
+        next_run_at,
+        recurrence.category,
+        &recurrence.tags,
+        recurrence.metadata
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// the wallet's recurrences, oldest first
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_recurrences<'e, E>(
+    executor: E,
+    wallet_id: i32,
+) -> Result<Vec<Recurrence>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Recurrence,
+        r#"
+        SELECT
+            id,
+            wallet_id,
+            value as "value: Cents",
+            kind as "kind: TransactionKind",
+            description,
+            category,
+            tags,
+            metadata,
+            schedule,
+            next_run_at IS NOT NULL as "active!",
+            next_run_at,
+            last_run_at,
+            last_failure,
+            inserted_at
+        FROM recurring_transactions
+        WHERE wallet_id = $1
+        ORDER BY id
+        "#,
+        wallet_id
+    )
+    .fetch_all(executor)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id, recurrence_id = recurrence_id))]
+pub async fn fetch_recurrence<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    recurrence_id: i32,
+) -> Result<Option<Recurrence>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Recurrence,
+        r#"
+        SELECT
+            id,
+            wallet_id,
+            value as "value: Cents",
+            kind as "kind: TransactionKind",
+            description,
+            category,
+            tags,
+            metadata,
+            schedule,
+            next_run_at IS NOT NULL as "active!",
+            next_run_at,
+            last_run_at,
+            last_failure,
+            inserted_at
+        FROM recurring_transactions
+        WHERE id = $2 AND wallet_id = $1
+        "#,
+        wallet_id,
+        recurrence_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// replaces the definition, starting over from `next_run_at`. what the last
+// run did stays. none when the wallet has no such recurrence
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id, recurrence_id = recurrence_id))]
+pub async fn update_recurrence<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    recurrence_id: i32,
+    recurrence: &PostRecurrence,
+    next_run_at: Option<OffsetDateTime>,
+) -> Result<Option<Recurrence>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Recurrence,
+        r#"
+        UPDATE recurring_transactions
+        SET value = $3, kind = $4, description = $5, schedule = $6, next_run_at = $7,
+            category = $8, tags = $9, metadata = $10
+        WHERE id = $2 AND wallet_id = $1
+        RETURNING
+            id,
+            wallet_id,
+            value as "value: Cents",
+            kind as "kind: TransactionKind",
+            description,
+            category,
+            tags,
+            metadata,
+            schedule,
+            next_run_at IS NOT NULL as "active!",
+            next_run_at,
+            last_run_at,
+            last_failure,
+            inserted_at
+        "#,
+        wallet_id,
+        recurrence_id,
+        recurrence.value as _,
+        recurrence.kind as _,
+        recurrence.description,
+        recurrence.schedule.to_string(),
+        next_run_at,
+        recurrence.category,
+        &recurrence.tags,
+        recurrence.metadata
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// false when the wallet has no such recurrence
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id, recurrence_id = recurrence_id))]
+pub async fn delete_recurrence<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    recurrence_id: i32,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM recurring_transactions WHERE id = $2 AND wallet_id = $1
+        "#,
+        wallet_id,
+        recurrence_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(deleted > 0)
+}
+
+// the recurrence due the earliest, locked until the end of the transaction
+// that runs it. those another instance is running are skipped
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn claim_recurrence<'e, E>(executor: E) -> Result<Option<Recurrence>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        Recurrence,
+        r#"
+        SELECT
+            id,
+            wallet_id,
+            value as "value: Cents",
+            kind as "kind: TransactionKind",
+            description,
+            category,
+            tags,
+            metadata,
+            schedule,
+            next_run_at IS NOT NULL as "active!",
+            next_run_at,
+            last_run_at,
+            last_failure,
+            inserted_at
+        FROM recurring_transactions
+        WHERE next_run_at <= now()
+        ORDER BY next_run_at, id
+        LIMIT 1
+        FOR UPDATE SKIP LOCKED
+        "#
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// records a run, along with the code of the error that turned it away, and
+// moves the recurrence on to its next one
+#[tracing::instrument(level = "debug", skip_all, fields(recurrence_id = recurrence_id))]
+pub async fn advance_recurrence<'e, E>(
+    executor: E,
+    recurrence_id: i32,
+    next_run_at: Option<OffsetDateTime>,
+    failure: Option<&str>,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        UPDATE recurring_transactions
+        SET next_run_at = $2, last_run_at = now(), last_failure = $3
+        WHERE id = $1
+        "#,
+        recurrence_id,
+        next_run_at,
+        failure
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// the next wallet that owes interest for `day`: a negative balance, a rate
+// above zero, its own or `default_rate`, and nothing accrued for the day
+// yet. locked until the end of the transaction, those another instance is
+// accruing are skipped
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn claim_interest_wallet<'e, E>(
+    executor: E,
+    day: Date,
+    default_rate: f64,
+) -> Result<Option<i32>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_scalar!(
+        r#"
+        SELECT id FROM wallets
+        WHERE COALESCE(interest_rate, $2::float8::numeric) > 0
+            AND balance + COALESCE((SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = wallets.id), 0) < 0
+            AND NOT EXISTS (SELECT 1 FROM interest_accruals WHERE wallet_id = wallets.id AND day = $1)
+        ORDER BY id
+        LIMIT 1
+        FOR UPDATE SKIP LOCKED
+        "#,
+        day,
+        default_rate
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// a day's interest on the wallet's balance, no more than what's left of its
+// limit so the debit always goes through
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn fetch_interest_due<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    default_rate: f64,
+) -> Result<Option<Cents>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_scalar!(
+        r#"
+        WITH wallet AS (
+            SELECT
+                balance + COALESCE((SELECT money_sum(delta) FROM pending_projections WHERE wallet_id = $1), 0) as balance,
+                credit_limit,
+                held,
+                COALESCE(interest_rate, $2::float8::numeric) as rate
+            FROM wallets
+            WHERE id = $1
+        )
+        SELECT GREATEST(LEAST(interest_on(balance, rate), credit_limit + balance - held), 0) as "value!: Cents"
+        FROM wallet
+        "#,
+        wallet_id,
+        default_rate
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// the `category` the `transactions_categorize` trigger gives the
+// transactions the rest of the database transaction inserts
+pub async fn set_category<'e, E>(executor: E, category: &str) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!("SELECT set_config('rinha.category', $1, true)", category)
+        .fetch_one(executor)
+        .await?;
+
+    Ok(())
+}
+
+// records `day` as accrued, along with the debit or the code of the error
+// that turned it away
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn insert_interest_accrual<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    day: Date,
+    value: Cents,
+    transaction_id: Option<Uuid>,
+    failure: Option<&str>,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        INSERT INTO interest_accruals (wallet_id, day, value, transaction_id, failure)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        wallet_id,
+        day,
+        value as _,
+        transaction_id,
+        failure
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// false for an unknown wallet. `None` leaves it to the default rate
+#[tracing::instrument(level = "debug", skip_all, fields(wallet_id = wallet_id))]
+pub async fn update_interest_rate<'e, E>(
+    executor: E,
+    wallet_id: i32,
+    rate: Option<f64>,
+) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let updated = sqlx::query!(
+        r#"
+        UPDATE wallets SET interest_rate = $2::float8::numeric WHERE id = $1
+        "#,
+        wallet_id,
+        rate
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(updated.rows_affected() > 0)
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn insert_fee_rule<'e, E>(executor: E, rule: &PostFeeRule) -> Result<FeeRule, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        FeeRule,
+        r#"
+        INSERT INTO fee_rules (kind, min_value, flat, percentage, description, active)
+        VALUES ($1, $2, $3, $4::float8::numeric, $5, $6)
+        RETURNING
+            id,
+            kind as "kind: TransactionKind",
+            min_value as "min_value: Cents",
+            flat as "flat: Cents",
+            percentage::float8 as "percentage!",
+            description,
+            active,
+            inserted_at
+        "#,
+        rule.kind as _,
+        rule.min_value as _,
+        rule.flat as _,
+        rule.percentage,
+        rule.description,
+        rule.active
+    )
+    .fetch_one(executor)
+    .await
+}
+
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn fetch_fee_rules<'e, E>(executor: E) -> Result<Vec<FeeRule>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        FeeRule,
+        r#"
+        SELECT
+            id,
+            kind as "kind: TransactionKind",
+            min_value as "min_value: Cents",
+            flat as "flat: Cents",
+            percentage::float8 as "percentage!",
+            description,
+            active,
+            inserted_at
+        FROM fee_rules
+        ORDER BY id
+        "#
+    )
+    .fetch_all(executor)
+    .await
+}
+
+// none for an unknown rule
+#[tracing::instrument(level = "debug", skip_all, fields(rule_id = rule_id))]
+pub async fn update_fee_rule<'e, E>(
+    executor: E,
+    rule_id: i32,
+    rule: &PostFeeRule,
+) -> Result<Option<FeeRule>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query_as!(
+        FeeRule,
+        r#"
+        UPDATE fee_rules
+        SET kind = $2, min_value = $3, flat = $4, percentage = $5::float8::numeric,
+            description = $6, active = $7
+        WHERE id = $1
+        RETURNING
+            id,
+            kind as "kind: TransactionKind",
+            min_value as "min_value: Cents",
+            flat as "flat: Cents",
+            percentage::float8 as "percentage!",
+            description,
+            active,
+            inserted_at
+        "#,
+        rule_id,
+        rule.kind as _,
+        rule.min_value as _,
+        rule.flat as _,
+        rule.percentage,
+        rule.description,
+        rule.active
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+// false for an unknown rule. the fees it charged stay
+#[tracing::instrument(level = "debug", skip_all, fields(rule_id = rule_id))]
+pub async fn delete_fee_rule<'e, E>(executor: E, rule_id: i32) -> Result<bool, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let deleted = sqlx::query!(
+        r#"
+        DELETE FROM fee_rules WHERE id = $1
+        "#,
+        rule_id
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+
+    Ok(deleted > 0)
+}
+
+// the debits the active rules charge for a transaction of `kind` and
+// `value`, one per rule, in the order the rules were made. the rules that
+// come to nothing are left out
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn fetch_fees<'e, E>(
+    executor: E,
+    kind: TransactionKind,
+    value: Cents,
+) -> Result<Vec<PostTransaction>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let rows = sqlx::query!(
+        r#"
+        SELECT fee.value as "value!: Cents", fee.description
+        FROM fee_rules, LATERAL (
+            SELECT fee_on($2, flat, percentage) as value, description
+        ) fee
+        WHERE active AND kind = $1 AND $2 >= COALESCE(min_value, 0) AND fee.value > 0
+        ORDER BY id
+        "#,
+        kind as _,
+        value as _
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| PostTransaction {
+            value: row.value,
+            kind: TransactionKind::Debit,
+            description: row.description,
+            currency: None,
+            category: None,
+            tags: Vec::new(),
+            metadata: None,
+        })
+        .collect())
+}
+
+// the transaction the fees the rest of the database transaction inserts
+// are linked to, by the `transactions_link_fee` trigger. none stops that
+pub async fn set_fee_of<'e, E>(executor: E, transaction_id: Option<Uuid>) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        "SELECT set_config('rinha.fee_of', COALESCE($1::text, ''), true)",
+        transaction_id.map(|id| id.to_string())
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(())
+}
+
+// up to `limit` of the deliveries that are due, oldest first. each is
+// pushed `lease` into the future, which keeps the other instances off it
+// while it's being delivered and hands it to them if this one dies halfway
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn claim_webhook_deliveries<'e, E>(
+    executor: E,
+    limit: i64,
+    lease: Duration,
+) -> Result<Vec<WebhookDelivery>, sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    let rows = sqlx::query!(
+        r#"
+        WITH due AS (
+            SELECT id
+            FROM webhook_outbox
+            WHERE failed_at IS NULL AND next_attempt_at <= now()
+            ORDER BY next_attempt_at, id
+            LIMIT $1
+            FOR UPDATE SKIP LOCKED
+        ), leased AS (
+            UPDATE webhook_outbox
+            SET next_attempt_at = now() + make_interval(secs => $2)
+            FROM due
+            WHERE webhook_outbox.id = due.id
+            RETURNING webhook_outbox.id, webhook_outbox.webhook_id, webhook_outbox.transaction_id, webhook_outbox.attempts
+        )
+        SELECT
+            leased.id,
+            leased.attempts,
+            webhooks.url,
+            webhooks.secret,
+            transactions.wallet_id,
+            transactions.public_id,
+            transactions.value as "value: Cents",
+            transactions.kind as "kind: TransactionKind",
+            transactions.description,
+            transactions.inserted_at as "inserted_at!",
+            transactions.category,
+            transactions.tags,
+            transactions.metadata
+        FROM leased
+        JOIN webhooks ON webhooks.id = leased.webhook_id
+        JOIN transactions ON transactions.id = leased.transaction_id
+        ORDER BY leased.id
+        "#,
+        limit,
+        lease.as_secs_f64()
+    )
+    .fetch_all(executor)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| WebhookDelivery {
+            id: row.id,
+            attempts: row.attempts,
+            url: row.url,
+            secret: row.secret,
+            wallet_id: row.wallet_id,
+            transaction: TransactionDetails {
+                id: row.public_id,
+                transaction: TransactionItem {
+                    value: row.value,
+                    kind: row.kind,
+                    description: row.description,
+                    inserted_at: row.inserted_at,
+                    category: row.category,
+                    tags: row.tags,
+                    metadata: row.metadata,
+                },
+            },
+        })
+        .collect())
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(delivery_id = delivery_id))]
+pub async fn complete_webhook_delivery<'e, E>(
+    executor: E,
+    delivery_id: i64,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        DELETE FROM webhook_outbox WHERE id = $1
+        "#,
+        delivery_id
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// counts the failed attempt and schedules the next one `retry_in` from now,
+// or gives up on the delivery without one
+#[tracing::instrument(level = "debug", skip_all, fields(delivery_id = delivery_id))]
+pub async fn fail_webhook_delivery<'e, E>(
+    executor: E,
+    delivery_id: i64,
+    error: &str,
+    retry_in: Option<Duration>,
+) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        UPDATE webhook_outbox
+        SET
+            attempts = attempts + 1,
+            last_error = $2,
+            next_attempt_at = now() + make_interval(secs => COALESCE($3::float8, 0)),
+            failed_at = CASE WHEN $3 IS NULL THEN now() END
+        WHERE id = $1
+        "#,
+        delivery_id,
+        error,
+        retry_in.map(|retry_in| retry_in.as_secs_f64())
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
+
+// the oldest `limit` events still to be published, locked until `conn`'s
+// transaction ends so another instance publishing at the same time skips them
+#[cfg(feature = "nats")]
+#[tracing::instrument(level = "debug", skip_all)]
+pub async fn claim_outbox_events(
+    conn: &mut PgConnection,
+    limit: i64,
+) -> Result<Vec<crate::models::OutboxEvent>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT
+            event_outbox.id,
+            transactions.wallet_id,
+            transactions.public_id,
+            transactions.value as "value: Cents",
+            transactions.kind as "kind: TransactionKind",
+            transactions.description,
+            transactions.inserted_at as "inserted_at!",
+            transactions.category,
+            transactions.tags,
+            transactions.metadata
+        FROM event_outbox
+        JOIN transactions ON transactions.id = event_outbox.transaction_id
+        ORDER BY event_outbox.id
+        LIMIT $1
+        FOR UPDATE OF event_outbox SKIP LOCKED
+        "#,
+        limit
+    )
+    .fetch_all(conn)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| crate::models::OutboxEvent {
+            id: row.id,
+            wallet_id: row.wallet_id,
+            transaction: TransactionDetails {
+                id: row.public_id,
+                transaction: TransactionItem {
+                    value: row.value,
+                    kind: row.kind,
+                    description: row.description,
+                    inserted_at: row.inserted_at,
+                    category: row.category,
+                    tags: row.tags,
+                    metadata: row.metadata,
+                },
+            },
+        })
+        .collect())
+}
+
+#[cfg(feature = "nats")]
+#[tracing::instrument(level = "debug", skip_all, fields(events = event_ids.len()))]
+pub async fn delete_outbox_events<'e, E>(executor: E, event_ids: &[i64]) -> Result<(), sqlx::Error>
+where
+    E: PgExecutor<'e>,
+{
+    sqlx::query!(
+        r#"
+        DELETE FROM event_outbox WHERE id = ANY($1)
+        "#,
+        event_ids
+    )
+    .execute(executor)
+    .await?;
+
+    Ok(())
+}
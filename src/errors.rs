@@ -0,0 +1,343 @@
+use axum::{
+    extract::rejection::{BytesRejection, JsonRejection, PathRejection, QueryRejection},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use metrics::counter;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use std::{any::Any, time::Duration};
+
+use crate::models::FieldError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("resource not found")]
+    NotFound,
+    #[error("transaction would exceed the credit limit")]
+    LimitExceeded,
+    #[error("the transaction's currency isn't the wallet's")]
+    CurrencyMismatch,
+    #[error("the wallet is blocked and takes no new transactions")]
+    WalletBlocked,
+    #[error("transaction would exceed the daily debit limit")]
+    DailyLimitExceeded,
+    #[error("the hold was already captured, released or expired")]
+    HoldSettled,
+    #[error("the scheduled transaction was already applied, failed or was cancelled")]
+    ScheduledSettled,
+    #[error("the Idempotency-Key was already used for another transaction")]
+    IdempotencyKeyReused,
+    #[error("resource already exists")]
+    Conflict,
+    #[error("the route doesn't take this method")]
+    MethodNotAllowed(Vec<String>),
+    #[error("invalid request")]
+    Validation(Vec<FieldError>),
+    #[error("missing or invalid credentials")]
+    Unauthorized,
+    #[error("these credentials don't allow this operation")]
+    Forbidden,
+    #[error("too many requests for this client, slow down")]
+    RateLimited { retry_after: Duration },
+    #[error("server is at capacity, try again later")]
+    Overloaded,
+    #[error("the database is unavailable, try again later")]
+    Unavailable,
+    #[error("the database took too long to answer")]
+    Timeout,
+    #[error("this storage can't do that, it needs postgres")]
+    NotSupported,
+    #[error("no tenant goes by the X-Tenant-Id given")]
+    UnknownTenant,
+    #[error("{0}")]
+    MalformedJson(String),
+    #[error("expected a json body, sent with `Content-Type: application/json`")]
+    UnsupportedMediaType,
+    #[error("{0}")]
+    InvalidBody(String),
+    #[error("{0}")]
+    InvalidParameter(String),
+    #[error("the body is larger than the server takes")]
+    BodyTooLarge,
+    #[error("internal error")]
+    Internal,
+    #[error("database error")]
+    Database(#[source] sqlx::Error),
+}
+
+impl ApiError {
+    fn status(&self) -> StatusCode {
+        match self {
+            ApiError::NotFound => StatusCode::NOT_FOUND,
+            ApiError::Conflict | ApiError::HoldSettled | ApiError::ScheduledSettled => {
+                StatusCode::CONFLICT
+            }
+            ApiError::LimitExceeded
+            | ApiError::CurrencyMismatch
+            | ApiError::WalletBlocked
+            | ApiError::DailyLimitExceeded
+            | ApiError::IdempotencyKeyReused
+            | ApiError::Validation(_)
+            | ApiError::InvalidBody(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            ApiError::MethodNotAllowed(_) => StatusCode::METHOD_NOT_ALLOWED,
+            ApiError::Unauthorized => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden => StatusCode::FORBIDDEN,
+            ApiError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Overloaded | ApiError::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::Timeout => StatusCode::GATEWAY_TIMEOUT,
+            ApiError::NotSupported => StatusCode::NOT_IMPLEMENTED,
+            ApiError::UnknownTenant
+            | ApiError::MalformedJson(_)
+            | ApiError::InvalidParameter(_) => StatusCode::BAD_REQUEST,
+            ApiError::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiError::BodyTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::Internal | ApiError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            ApiError::NotFound => "not_found",
+            ApiError::LimitExceeded => "limit_exceeded",
+            ApiError::CurrencyMismatch => "currency_mismatch",
+            ApiError::WalletBlocked => "wallet_blocked",
+            ApiError::DailyLimitExceeded => "daily_limit_exceeded",
+            ApiError::HoldSettled => "hold_settled",
+            ApiError::ScheduledSettled => "scheduled_transaction_settled",
+            ApiError::IdempotencyKeyReused => "idempotency_key_reused",
+            ApiError::Conflict => "conflict",
+            ApiError::Validation(_) => "validation_failed",
+            ApiError::MethodNotAllowed(_) => "method_not_allowed",
+            ApiError::Unauthorized => "unauthorized",
+            ApiError::Forbidden => "forbidden",
+            ApiError::RateLimited { .. } => "rate_limited",
+            ApiError::Overloaded => "overloaded",
+            ApiError::Unavailable => "unavailable",
+            ApiError::Timeout => "timeout",
+            ApiError::NotSupported => "not_supported",
+            ApiError::UnknownTenant => "unknown_tenant",
+            ApiError::MalformedJson(_) => "malformed_json",
+            ApiError::UnsupportedMediaType => "unsupported_media_type",
+            ApiError::InvalidBody(_) => "invalid_body",
+            ApiError::InvalidParameter(_) => "invalid_parameter",
+            ApiError::BodyTooLarge => "body_too_large",
+            ApiError::Internal => "internal_error",
+            ApiError::Database(_) => "database_error",
+        }
+    }
+
+    fn title(&self) -> &'static str {
+        match self {
+            ApiError::NotFound => "Not found",
+            ApiError::LimitExceeded => "Credit limit exceeded",
+            ApiError::CurrencyMismatch => "Currency mismatch",
+            ApiError::WalletBlocked => "Wallet blocked",
+            ApiError::DailyLimitExceeded => "Daily debit limit exceeded",
+            ApiError::HoldSettled => "Hold already settled",
+            ApiError::ScheduledSettled => "Scheduled transaction already settled",
+            ApiError::IdempotencyKeyReused => "Idempotency key reused",
+            ApiError::Conflict => "Conflict",
+            ApiError::Validation(_) => "Validation failed",
+            ApiError::MethodNotAllowed(_) => "Method not allowed",
+            ApiError::Unauthorized => "Unauthorized",
+            ApiError::Forbidden => "Forbidden",
+            ApiError::RateLimited { .. } => "Too many requests",
+            ApiError::Overloaded => "Overloaded",
+            ApiError::Unavailable => "Database unavailable",
+            ApiError::Timeout => "Database timeout",
+            ApiError::NotSupported => "Not supported",
+            ApiError::UnknownTenant => "Unknown tenant",
+            ApiError::MalformedJson(_) => "Malformed JSON",
+            ApiError::UnsupportedMediaType => "Unsupported media type",
+            ApiError::InvalidBody(_) => "Invalid body",
+            ApiError::InvalidParameter(_) => "Invalid parameter",
+            ApiError::BodyTooLarge => "Body too large",
+            ApiError::Internal => "Internal error",
+            ApiError::Database(_) => "Database error",
+        }
+    }
+}
+
+impl ApiError {
+    // the full message including the underlying driver error, for logs and
+    // the cli. responses only ever get `to_string`
+    pub fn source_message(&self) -> String {
+        match self {
+            ApiError::Database(err) => format!("{}: {}", self, err),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl From<Vec<FieldError>> for ApiError {
+    fn from(errors: Vec<FieldError>) -> Self {
+        ApiError::Validation(errors)
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        if let Some(db_err) = err.as_database_error() {
+            if db_err.constraint() == Some("positive_balance") {
+                return ApiError::LimitExceeded;
+            }
+            if db_err.constraint() == Some("currency_mismatch") {
+                return ApiError::CurrencyMismatch;
+            }
+            if db_err.constraint() == Some("wallet_blocked") {
+                return ApiError::WalletBlocked;
+            }
+            if db_err.constraint() == Some("daily_debit_limit") {
+                return ApiError::DailyLimitExceeded;
+            }
+            if db_err.is_foreign_key_violation() {
+                return ApiError::NotFound;
+            }
+            if db_err.is_unique_violation() {
+                return ApiError::Conflict;
+            }
+            // numeric_value_out_of_range, a balance past what BIGINT holds.
+            // the other backends turn it away the same way
+            if db_err.code().as_deref() == Some("22003") {
+                return ApiError::LimitExceeded;
+            }
+            // query_canceled, what `statement_timeout` raises
+            if db_err.code().as_deref() == Some("57014") {
+                return ApiError::Timeout;
+            }
+        }
+
+        ApiError::Database(err)
+    }
+}
+
+// axum's own rejections answer in plain text, `extract` turns them into these
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(err) => ApiError::InvalidBody(err.body_text()),
+            JsonRejection::JsonSyntaxError(err) => ApiError::MalformedJson(err.body_text()),
+            JsonRejection::MissingJsonContentType(_) => ApiError::UnsupportedMediaType,
+            JsonRejection::BytesRejection(err) => err.into(),
+            rejection => ApiError::MalformedJson(rejection.body_text()),
+        }
+    }
+}
+
+impl From<BytesRejection> for ApiError {
+    fn from(rejection: BytesRejection) -> Self {
+        if rejection.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            ApiError::BodyTooLarge
+        } else {
+            ApiError::MalformedJson(rejection.body_text())
+        }
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        // a route whose parameters don't fit the handler's, our mistake
+        if rejection.status().is_server_error() {
+            tracing::error!(error = %rejection.body_text(), "can't extract the path parameters");
+            return ApiError::Internal;
+        }
+
+        ApiError::InvalidParameter(rejection.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        ApiError::InvalidParameter(rejection.body_text())
+    }
+}
+
+// what a request whose handler panicked gets, a problem like any other
+// internal error instead of the connection dropping without an answer
+pub fn panicked(panic: Box<dyn Any + Send + 'static>) -> Response {
+    let message = match panic.downcast_ref::<String>() {
+        Some(message) => message.as_str(),
+        None => panic.downcast_ref::<&str>().copied().unwrap_or("unknown"),
+    };
+    tracing::error!(panic = message, "handler panicked");
+    counter!("panics_total").increment(1);
+
+    ApiError::Internal.into_response()
+}
+
+// RFC 7807 body of every error response, sent as `application/problem+json`
+#[derive(Serialize, ToSchema)]
+pub struct Problem {
+    // `urn:rinha:problem:` followed by the error code, stable for clients to
+    // match on
+    #[serde(rename = "type")]
+    #[schema(example = "urn:rinha:problem:limit_exceeded")]
+    pub problem_type: String,
+    #[schema(example = "Credit limit exceeded")]
+    pub title: &'static str,
+    #[schema(example = 422)]
+    pub status: u16,
+    #[schema(example = "transaction would exceed the credit limit")]
+    pub detail: String,
+    // the problem with each field, only for validation failures
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<Vec<FieldError>>,
+    // the methods the route takes, only for a method it doesn't
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = json!(["GET", "POST"]))]
+    pub allowed: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        // the driver message can leak schema details, keep it in the logs
+        if let ApiError::Database(err) = &self {
+            tracing::error!(error = %err, "database error");
+        }
+
+        let status = self.status();
+        let body = Problem {
+            problem_type: format!("urn:rinha:problem:{}", self.code()),
+            title: self.title(),
+            status: status.as_u16(),
+            detail: self.to_string(),
+            errors: match &self {
+                ApiError::Validation(errors) => Some(errors.clone()),
+                _ => None,
+            },
+            allowed: match &self {
+                ApiError::MethodNotAllowed(allowed) => Some(allowed.clone()),
+                _ => None,
+            },
+            request_id: crate::request_id::current(),
+        };
+
+        let mut response = (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            Json(body),
+        )
+            .into_response();
+
+        if let ApiError::RateLimited { retry_after } = &self {
+            // whole seconds, rounded up so a client that honours it doesn't
+            // come back before a token is available
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        if let ApiError::MethodNotAllowed(allowed) = &self {
+            if let Ok(allowed) = HeaderValue::from_str(&allowed.join(", ")) {
+                response.headers_mut().insert(header::ALLOW, allowed);
+            }
+        }
+
+        response
+    }
+}
@@ -0,0 +1,47 @@
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+
+use crate::models::{Cents, TransactionItem};
+
+// how many events a slow subscriber can fall behind before it starts
+// missing them
+const CAPACITY: usize = 1024;
+
+// a committed transaction and the balance it left the wallet with
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransactionEvent {
+    #[serde(rename = "cliente_id")]
+    pub wallet_id: i32,
+    #[serde(rename = "saldo")]
+    pub balance: Cents,
+    #[serde(rename = "limite")]
+    pub credit_limit: Cents,
+    #[serde(rename = "transacao")]
+    pub transaction: TransactionItem,
+}
+
+// fans the events of every wallet out to the live streams, which pick the
+// wallet they care about. publishing with nobody listening is a no-op
+#[derive(Clone)]
+pub struct Events {
+    sender: broadcast::Sender<TransactionEvent>,
+}
+
+impl Default for Events {
+    fn default() -> Self {
+        Events {
+            sender: broadcast::channel(CAPACITY).0,
+        }
+    }
+}
+
+impl Events {
+    pub fn publish(&self, event: TransactionEvent) {
+        let _ = self.sender.send(event);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,144 @@
+use axum::{body::Body, extract::State, http::header, response::IntoResponse};
+use serde::Deserialize;
+use time::format_description::well_known::Rfc3339;
+use tokio_stream::StreamExt;
+use utoipa::IntoParams;
+
+use crate::{
+    errors::ApiError,
+    extract::{Path, Query},
+    models::{FieldError, TransactionDetails},
+    repository::WalletRepository,
+};
+
+const CSV_HEADER: &str = "id,valor,tipo,descricao,realizada_em\n";
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    // `csv` when left out
+    #[param(example = "ndjson")]
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Csv,
+    Ndjson,
+}
+
+impl Format {
+    fn parse(value: Option<&str>) -> Result<Format, Vec<FieldError>> {
+        match value {
+            None | Some("csv") => Ok(Format::Csv),
+            Some("ndjson") => Ok(Format::Ndjson),
+            Some(_) => Err(vec![FieldError {
+                field: "format".into(),
+                message: "must be \"csv\" or \"ndjson\"",
+            }]),
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            Format::Csv => "text/csv; charset=utf-8",
+            Format::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Ndjson => "ndjson",
+        }
+    }
+
+    fn line(self, row: &TransactionDetails) -> Result<String, ApiError> {
+        match self {
+            Format::Csv => {
+                let inserted_at = row
+                    .transaction
+                    .inserted_at
+                    .format(&Rfc3339)
+                    .map_err(|err| {
+                        tracing::error!(error = %err, "can't format timestamp");
+                        ApiError::Internal
+                    })?;
+
+                Ok(format!(
+                    "{},{},{},{},{}\n",
+                    row.id,
+                    row.transaction.value.0,
+                    row.transaction.kind,
+                    csv_field(&row.transaction.description),
+                    inserted_at
+                ))
+            }
+            Format::Ndjson => {
+                let mut line =
+                    serde_json::to_string(row).expect("transactions are always serializable");
+                line.push('\n');
+                Ok(line)
+            }
+        }
+    }
+}
+
+// quoted only when it has to be, with the quotes inside doubled
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/extrato/export",
+    params(("id" = i32, Path, description = "client id"), ExportQuery),
+    responses(
+        (status = 200, description = "the whole history oldest first, one transaction per line", content_type = ["text/csv", "application/x-ndjson"], body = String),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "unknown format", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn export<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+    Query(query): Query<ExportQuery>,
+) -> Result<impl IntoResponse, ApiError> {
+    let format = Format::parse(query.format.as_deref())?;
+
+    let rows = repo.export(wallet_id).await?;
+
+    // the status is already sent by the time a row fails, all that's left is
+    // cutting the body short so the client can tell it's incomplete
+    let lines = rows.map(move |row| {
+        row.and_then(|row| format.line(&row)).inspect_err(|err| {
+            tracing::error!(error = %err.source_message(), wallet_id, "export failed");
+        })
+    });
+    let body = match format {
+        Format::Csv => {
+            Body::from_stream(tokio_stream::once(Ok(CSV_HEADER.to_string())).chain(lines))
+        }
+        Format::Ndjson => Body::from_stream(lines),
+    };
+
+    let disposition = format!(
+        "attachment; filename=\"extrato-{}.{}\"",
+        wallet_id,
+        format.extension()
+    );
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    ))
+}
@@ -0,0 +1,288 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use async_trait::async_trait;
+use axum::{
+    body::Bytes,
+    extract::{FromRequest, FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap},
+    Json,
+};
+use serde::{
+    de::{self, value::Error, DeserializeOwned, IgnoredAny, Visitor},
+    forward_to_deserialize_any, Deserializer,
+};
+use serde_json::Value;
+
+use crate::{
+    errors::ApiError,
+    models::{
+        FieldError, RawLogLevel, RawPatchCreditLimit, RawPatchDailyDebitLimit,
+        RawPatchInterestRate, RawPostFeeRule, RawPostHold, RawPostRecurrence, RawPostTransaction,
+        RawPostTransactionBatch, RawPostTransactionRequest, RawPostTransfer, RawPostWallet,
+        RawPostWebhook,
+    },
+};
+
+// STRICT_PARSING, which a reload can flip while running
+static STRICT_PARSING: AtomicBool = AtomicBool::new(false);
+
+pub fn set_strict_parsing(strict: bool) {
+    STRICT_PARSING.store(strict, Ordering::Relaxed);
+}
+
+// `Json`, unless STRICT_PARSING is set. then the fields the body isn't known
+// to have are a validation error, like a missing one, and so is anything
+// after the json value
+pub struct JsonBody<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for JsonBody<T>
+where
+    T: KnownFields,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !STRICT_PARSING.load(Ordering::Relaxed) {
+            let Json(body) = Json::<T>::from_request(req, state).await?;
+            return Ok(JsonBody(body));
+        }
+
+        if !json_content_type(req.headers()) {
+            return Err(ApiError::UnsupportedMediaType);
+        }
+        let bytes = Bytes::from_request(req, state).await?;
+
+        // the syntax errors are the ones `Json` reports
+        let Json(value) = Json::<Value>::from_bytes(&bytes)?;
+        if serde_json::Deserializer::from_slice(&bytes)
+            .into_iter::<IgnoredAny>()
+            .nth(1)
+            .is_some()
+        {
+            return Err(ApiError::MalformedJson(
+                "Failed to parse the request body as JSON: trailing characters".to_string(),
+            ));
+        }
+
+        let unknown = T::unknown_fields(&value);
+        if !unknown.is_empty() {
+            return Err(ApiError::Validation(unknown));
+        }
+
+        let Json(body) = Json::<T>::from_bytes(&bytes)?;
+        Ok(JsonBody(body))
+    }
+}
+
+// `axum::extract::Path`, turning a path that doesn't parse, like
+// `/clientes/abc`, away in the api's format
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Path<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Path(params) =
+            axum::extract::Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Path(params))
+    }
+}
+
+// `axum::extract::Query`, the same way
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequestParts<S> for Query<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let axum::extract::Query(query) =
+            axum::extract::Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Query(query))
+    }
+}
+
+// `application/json`, or a type with a `+json` suffix, as `Json` takes
+fn json_content_type(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+
+    essence == "application/json"
+        || (essence.starts_with("application/") && essence.ends_with("+json"))
+}
+
+// the fields a body can have, for STRICT_PARSING. they're read off the type's
+// `Deserialize`, which lists them, aliases included, unless the struct has a
+// flattened field. those name their own
+pub trait KnownFields: DeserializeOwned + Send {
+    fn unknown_fields(body: &Value) -> Vec<FieldError> {
+        unknown(body, fields::<Self>(), "")
+    }
+}
+
+impl KnownFields for RawPostWallet {}
+impl KnownFields for RawPatchCreditLimit {}
+impl KnownFields for RawPatchDailyDebitLimit {}
+impl KnownFields for RawPatchInterestRate {}
+impl KnownFields for RawPostTransfer {}
+impl KnownFields for RawPostWebhook {}
+impl KnownFields for RawPostHold {}
+impl KnownFields for RawPostFeeRule {}
+impl KnownFields for RawLogLevel {}
+
+impl KnownFields for RawPostTransactionRequest {
+    fn unknown_fields(body: &Value) -> Vec<FieldError> {
+        with_transaction(body, &["agendada_para"])
+    }
+}
+
+impl KnownFields for RawPostRecurrence {
+    fn unknown_fields(body: &Value) -> Vec<FieldError> {
+        with_transaction(body, &["cron", "ativa"])
+    }
+}
+
+// the items are checked as well, named like their other errors
+impl KnownFields for RawPostTransactionBatch {
+    fn unknown_fields(body: &Value) -> Vec<FieldError> {
+        let mut errors = unknown(body, fields::<Self>(), "");
+
+        if let Some(Value::Array(items)) = body.get("transacoes") {
+            for (index, item) in items.iter().enumerate() {
+                let prefix = format!("transacoes[{}].", index);
+                errors.extend(unknown(item, fields::<RawPostTransaction>(), &prefix));
+            }
+        }
+
+        errors
+    }
+}
+
+// a transaction's fields and `own`
+fn with_transaction(body: &Value, own: &[&str]) -> Vec<FieldError> {
+    let mut known = fields::<RawPostTransaction>().to_vec();
+    known.extend_from_slice(own);
+
+    unknown(body, &known, "")
+}
+
+// the members of `body` that aren't in `known`. anything but an object is
+// left to the deserializing
+fn unknown(body: &Value, known: &[&str], prefix: &str) -> Vec<FieldError> {
+    let Value::Object(members) = body else {
+        return Vec::new();
+    };
+
+    members
+        .keys()
+        .filter(|key| !known.contains(&key.as_str()))
+        .map(|key| FieldError {
+            field: format!("{}{}", prefix, key).into(),
+            message: "is not a known field",
+        })
+        .collect()
+}
+
+// the names `T` deserializes a struct from, by asking it to deserialize from
+// a deserializer that only takes them down
+fn fields<T: DeserializeOwned>() -> &'static [&'static str] {
+    struct Fields<'a>(&'a mut &'static [&'static str]);
+
+    impl<'de> Deserializer<'de> for Fields<'_> {
+        type Error = Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, _: V) -> Result<V::Value, Error> {
+            Err(de::Error::custom("not a struct"))
+        }
+
+        fn deserialize_struct<V: Visitor<'de>>(
+            self,
+            _: &'static str,
+            fields: &'static [&'static str],
+            _: V,
+        ) -> Result<V::Value, Error> {
+            *self.0 = fields;
+            Err(de::Error::custom("fields taken down"))
+        }
+
+        forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+            bytes byte_buf option unit unit_struct newtype_struct seq tuple
+            tuple_struct map enum identifier ignored_any
+        }
+    }
+
+    let mut fields: &'static [&'static str] = &[];
+    let _ = T::deserialize(Fields(&mut fields));
+
+    fields
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use tokio::sync::Mutex;
+
+    use super::*;
+
+    // STRICT_PARSING is the whole process's
+    static STRICT: Mutex<()> = Mutex::const_new(());
+
+    async fn parse(body: &str) -> Result<RawPostTransactionRequest, ApiError> {
+        let req = Request::builder()
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let JsonBody(body) = JsonBody::from_request(req, &()).await?;
+        Ok(body)
+    }
+
+    #[tokio::test]
+    async fn an_unknown_field_is_only_turned_away_when_strict() {
+        let _strict = STRICT.lock().await;
+        let body = r#"{"valor": 100, "tipo": "c", "descricao": "pix", "valr": 1}"#;
+
+        set_strict_parsing(false);
+        assert!(parse(body).await.is_ok());
+
+        set_strict_parsing(true);
+        let result = parse(body).await;
+        set_strict_parsing(false);
+        let Err(ApiError::Validation(errors)) = result else {
+            panic!("{:?}", result.map(drop));
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].field, "valr");
+    }
+
+    #[tokio::test]
+    async fn anything_after_the_json_is_turned_away_when_strict() {
+        let _strict = STRICT.lock().await;
+
+        set_strict_parsing(true);
+        let result = parse(r#"{"valor": 100, "tipo": "c", "descricao": "pix"} {}"#).await;
+        set_strict_parsing(false);
+        assert!(matches!(result, Err(ApiError::MalformedJson(_))));
+    }
+}
@@ -0,0 +1,299 @@
+use std::{collections::BTreeMap, marker::PhantomData};
+
+use async_graphql::{
+    connection::{Connection, Edge},
+    http::GraphiQLSource,
+    Context, EmptySubscription, Enum, ErrorExtensions, Object, Schema, SimpleObject,
+};
+use axum::{response::Html, Extension, Json};
+use serde_json::json;
+use time::format_description::well_known::Rfc3339;
+
+use crate::{
+    auth::{Scope, TokenSubject},
+    errors::ApiError,
+    models::{
+        self, Cents, HistoryCursor, HistoryFilter, PostTransaction, RawPostTransaction, Statement,
+        StatementFilter, MAX_HISTORY_PAGE_SIZE,
+    },
+    repository::WalletRepository,
+};
+
+// transactions per page when `first` isn't given
+const DEFAULT_PAGE_SIZE: i32 = 10;
+
+pub type WalletSchema<R> = Schema<QueryRoot<R>, MutationRoot<R>, EmptySubscription>;
+
+// the repository goes into the schema data, resolvers get it back from the
+// context
+pub fn schema<R: WalletRepository>(repo: R) -> WalletSchema<R> {
+    Schema::build(
+        QueryRoot(PhantomData),
+        MutationRoot(PhantomData),
+        EmptySubscription,
+    )
+    .data(repo)
+    .finish()
+}
+
+// /graphql has no client id in its path for `require_jwt` to check, so the
+// token's subject goes into the request data for the resolvers to. so does
+// the credential's scope, a read one is enough to get here
+pub async fn execute<R: WalletRepository>(
+    Extension(schema): Extension<WalletSchema<R>>,
+    subject: Option<Extension<TokenSubject>>,
+    scope: Option<Extension<Scope>>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    let request = match subject {
+        Some(Extension(subject)) => request.data(subject),
+        None => request,
+    };
+    let request = match scope {
+        Some(Extension(scope)) => request.data(scope),
+        None => request,
+    };
+    Json(schema.execute(request).await)
+}
+
+pub async fn graphiql() -> Html<String> {
+    Html(GraphiQLSource::build().endpoint("/graphql").finish())
+}
+
+pub struct QueryRoot<R>(PhantomData<R>);
+
+#[Object]
+impl<R: WalletRepository> QueryRoot<R> {
+    // `null` for an unknown client
+    async fn wallet(&self, ctx: &Context<'_>, id: i32) -> async_graphql::Result<Option<Wallet<R>>> {
+        check_subject(ctx, id)?;
+        let repo = ctx.data_unchecked::<R>();
+
+        match repo.get_statement(id, &StatementFilter::default()).await {
+            Ok(statement) => Ok(Some(Wallet {
+                id,
+                statement,
+                repo: PhantomData,
+            })),
+            Err(ApiError::NotFound) => Ok(None),
+            Err(err) => Err(graphql_error(err)),
+        }
+    }
+}
+
+pub struct MutationRoot<R>(PhantomData<R>);
+
+#[Object]
+impl<R: WalletRepository> MutationRoot<R> {
+    async fn register_transaction(
+        &self,
+        ctx: &Context<'_>,
+        client_id: i32,
+        value: i64,
+        kind: TransactionKind,
+        description: String,
+        idempotency_key: Option<String>,
+    ) -> async_graphql::Result<Balance> {
+        check_scope(ctx, Scope::Write)?;
+        check_subject(ctx, client_id)?;
+        let repo = ctx.data_unchecked::<R>();
+
+        // goes through the same validation as the json body
+        let transaction = PostTransaction::try_from(RawPostTransaction {
+            valor: Some(json!(value)),
+            tipo: Some(json!(models::TransactionKind::from(kind).to_string())),
+            descricao: Some(json!(description)),
+            moeda: None,
+            categoria: None,
+            tags: None,
+            metadata: None,
+        })
+        .map_err(|errors| graphql_error(errors.into()))?;
+
+        let receipt = repo
+            .insert_transaction(client_id, &transaction, idempotency_key.as_deref())
+            .await
+            .map_err(graphql_error)?;
+
+        Ok(Balance {
+            balance: cents(receipt.wallet.balance.unwrap_or_default())?,
+            credit_limit: cents(receipt.wallet.credit_limit.unwrap_or_default())?,
+        })
+    }
+}
+
+pub struct Wallet<R> {
+    id: i32,
+    // for the balance, the transactions are paged through the history
+    statement: Statement,
+    repo: PhantomData<R>,
+}
+
+#[Object]
+impl<R: WalletRepository> Wallet<R> {
+    async fn id(&self) -> i32 {
+        self.id
+    }
+
+    async fn balance(&self) -> async_graphql::Result<i64> {
+        cents(self.statement.wallet.balance.unwrap_or_default())
+    }
+
+    async fn credit_limit(&self) -> async_graphql::Result<i64> {
+        cents(self.statement.wallet.credit_limit.unwrap_or_default())
+    }
+
+    async fn currency(&self) -> &str {
+        &self.statement.currency
+    }
+
+    // oldest first, like the history. the cursor is the history's
+    // `(inserted_at, id)` of an edge, so `after` continues right past it
+    // even among the rows of a batch, which share their `performedAt`
+    async fn transactions(
+        &self,
+        ctx: &Context<'_>,
+        first: Option<i32>,
+        after: Option<String>,
+    ) -> async_graphql::Result<Connection<String, Transaction>> {
+        let first = first.unwrap_or(DEFAULT_PAGE_SIZE);
+        if !(1..=MAX_HISTORY_PAGE_SIZE as i32).contains(&first) {
+            return Err(async_graphql::Error::new(format!(
+                "first must be between 1 and {}",
+                MAX_HISTORY_PAGE_SIZE
+            )));
+        }
+
+        let after = after
+            .map(|cursor| cursor.parse::<HistoryCursor>())
+            .transpose()
+            .map_err(|_| async_graphql::Error::new("after is not a valid cursor"))?;
+        let filter = HistoryFilter {
+            after,
+            limit: first as u32,
+            search: None,
+            category: None,
+            tag: None,
+            metadata: BTreeMap::new(),
+        };
+
+        // the history reads a row past the page, there's a next one only if
+        // it was there
+        let page = ctx
+            .data_unchecked::<R>()
+            .get_history(self.id, &filter)
+            .await
+            .map_err(graphql_error)?;
+
+        let mut connection = Connection::new(after.is_some(), page.next.is_some());
+        for (cursor, details) in page.cursors.into_iter().zip(page.transactions) {
+            let node = Transaction::try_from(details.transaction)?;
+            connection.edges.push(Edge::new(cursor.to_string(), node));
+        }
+
+        Ok(connection)
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct Balance {
+    balance: i64,
+    credit_limit: i64,
+}
+
+#[derive(SimpleObject)]
+pub struct Transaction {
+    value: i64,
+    kind: TransactionKind,
+    description: String,
+    // RFC 3339
+    performed_at: String,
+}
+
+impl TryFrom<models::TransactionItem> for Transaction {
+    type Error = async_graphql::Error;
+
+    fn try_from(item: models::TransactionItem) -> Result<Self, Self::Error> {
+        Ok(Transaction {
+            value: cents(item.value)?,
+            kind: item.kind.into(),
+            description: item.description,
+            performed_at: item.inserted_at.format(&Rfc3339).map_err(|err| {
+                tracing::error!(error = %err, "can't format timestamp");
+                graphql_error(ApiError::Internal)
+            })?,
+        })
+    }
+}
+
+#[derive(Enum, Clone, Copy, PartialEq, Eq)]
+pub enum TransactionKind {
+    Credit,
+    Debit,
+}
+
+impl From<TransactionKind> for models::TransactionKind {
+    fn from(kind: TransactionKind) -> Self {
+        match kind {
+            TransactionKind::Credit => models::TransactionKind::Credit,
+            TransactionKind::Debit => models::TransactionKind::Debit,
+        }
+    }
+}
+
+impl From<models::TransactionKind> for TransactionKind {
+    fn from(kind: models::TransactionKind) -> Self {
+        match kind {
+            models::TransactionKind::Credit => TransactionKind::Credit,
+            models::TransactionKind::Debit => TransactionKind::Debit,
+        }
+    }
+}
+
+// a client's token only gets to its own wallet, as in the json api
+fn check_subject(ctx: &Context<'_>, wallet_id: i32) -> async_graphql::Result<()> {
+    match ctx.data_opt::<TokenSubject>() {
+        Some(subject) if !subject.allows(wallet_id) => Err(graphql_error(ApiError::Forbidden)),
+        _ => Ok(()),
+    }
+}
+
+// the credential's scope has to cover what the resolver does, as the auth
+// layers check for the json api. an open api has no scope to check
+fn check_scope(ctx: &Context<'_>, required: Scope) -> async_graphql::Result<()> {
+    match ctx.data_opt::<Scope>() {
+        Some(scope) if *scope < required => Err(graphql_error(ApiError::Forbidden)),
+        _ => Ok(()),
+    }
+}
+
+// the schema has whole cents, only the json api has room for a fraction of
+// one
+fn cents(amount: Cents) -> async_graphql::Result<i64> {
+    amount.to_i64().ok_or_else(|| {
+        tracing::error!(amount = %amount.0, "amount has a fraction of a cent, which graphql can't carry");
+        graphql_error(ApiError::Internal)
+    })
+}
+
+// `code` is the last part of the `type` urn in the http problem bodies
+fn graphql_error(err: ApiError) -> async_graphql::Error {
+    if let ApiError::Database(db_err) = &err {
+        tracing::error!(error = %db_err, "database error");
+    }
+
+    let code = err.code();
+    let errors = match &err {
+        ApiError::Validation(errors) => Some(json!(errors)),
+        _ => None,
+    };
+
+    async_graphql::Error::new(err.to_string()).extend_with(|_, extensions| {
+        extensions.set("code", code);
+        if let Some(errors) = &errors {
+            if let Ok(errors) = async_graphql::Value::from_json(errors.clone()) {
+                extensions.set("errors", errors);
+            }
+        }
+    })
+}
@@ -0,0 +1,188 @@
+use std::{future::Future, net::SocketAddr};
+
+use serde_json::json;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tonic::{Request, Response, Status};
+
+use crate::{
+    errors::ApiError,
+    models::{Cents, PostTransaction, RawPostTransaction, StatementQuery, TransactionItem},
+    repository::WalletRepository,
+};
+
+pub mod proto {
+    tonic::include_proto!("rinha.v1");
+}
+
+use proto::{
+    wallets_server::{Wallets, WalletsServer},
+    GetStatementRequest, GetStatementResponse, RegisterTransactionRequest,
+    RegisterTransactionResponse, Transaction, TransactionKind,
+};
+
+// the grpc port is meant for internal consumers and skips the http
+// middleware: no api keys, tokens or rate limits
+pub struct WalletService<R> {
+    repo: R,
+}
+
+pub async fn serve<R, F>(
+    repo: R,
+    addr: SocketAddr,
+    shutdown: F,
+) -> Result<(), tonic::transport::Error>
+where
+    R: WalletRepository,
+    F: Future<Output = ()>,
+{
+    tonic::transport::Server::builder()
+        .add_service(WalletsServer::new(WalletService { repo }))
+        .serve_with_shutdown(addr, shutdown)
+        .await
+}
+
+#[tonic::async_trait]
+impl<R: WalletRepository> Wallets for WalletService<R> {
+    async fn register_transaction(
+        &self,
+        request: Request<RegisterTransactionRequest>,
+    ) -> Result<Response<RegisterTransactionResponse>, Status> {
+        let request = request.into_inner();
+
+        // goes through the same validation as the json body
+        let tipo = match request.kind() {
+            TransactionKind::Credit => Some(json!("c")),
+            TransactionKind::Debit => Some(json!("d")),
+            TransactionKind::Unspecified => None,
+        };
+        let transaction = PostTransaction::try_from(RawPostTransaction {
+            valor: Some(json!(request.value)),
+            tipo,
+            descricao: Some(json!(request.description)),
+            moeda: request.currency.map(|currency| json!(currency)),
+            categoria: None,
+            tags: None,
+            metadata: None,
+        })
+        .map_err(ApiError::from)?;
+
+        let receipt = self
+            .repo
+            .insert_transaction(
+                request.client_id,
+                &transaction,
+                request.idempotency_key.as_deref(),
+            )
+            .await?;
+
+        Ok(Response::new(RegisterTransactionResponse {
+            balance: cents(receipt.wallet.balance.unwrap_or_default())?,
+            credit_limit: cents(receipt.wallet.credit_limit.unwrap_or_default())?,
+        }))
+    }
+
+    async fn get_statement(
+        &self,
+        request: Request<GetStatementRequest>,
+    ) -> Result<Response<GetStatementResponse>, Status> {
+        let request = request.into_inner();
+
+        let filter = StatementQuery {
+            de: request.from,
+            ate: request.to,
+            tipo: None,
+        }
+        .filter()
+        .map_err(ApiError::from)?;
+
+        let statement = self.repo.get_statement(request.client_id, &filter).await?;
+
+        Ok(Response::new(GetStatementResponse {
+            balance: cents(statement.wallet.balance.unwrap_or_default())?,
+            credit_limit: cents(statement.wallet.credit_limit.unwrap_or_default())?,
+            statement_date: rfc3339(OffsetDateTime::now_utc())?,
+            transactions: statement
+                .transactions
+                .into_iter()
+                .map(transaction)
+                .collect::<Result<_, ApiError>>()?,
+            currency: statement.currency,
+        }))
+    }
+}
+
+fn transaction(item: TransactionItem) -> Result<Transaction, ApiError> {
+    let kind = match item.kind {
+        crate::models::TransactionKind::Credit => TransactionKind::Credit,
+        crate::models::TransactionKind::Debit => TransactionKind::Debit,
+    };
+
+    Ok(Transaction {
+        value: cents(item.value)?,
+        kind: kind.into(),
+        description: item.description,
+        performed_at: rfc3339(item.inserted_at)?,
+    })
+}
+
+// the messages carry whole cents, only the json api has room for a fraction
+// of one
+fn cents(amount: Cents) -> Result<i64, ApiError> {
+    amount.to_i64().ok_or_else(|| {
+        tracing::error!(amount = %amount.0, "amount has a fraction of a cent, which grpc can't carry");
+        ApiError::Internal
+    })
+}
+
+fn rfc3339(timestamp: OffsetDateTime) -> Result<String, ApiError> {
+    timestamp.format(&Rfc3339).map_err(|err| {
+        tracing::error!(error = %err, "can't format timestamp");
+        ApiError::Internal
+    })
+}
+
+impl From<ApiError> for Status {
+    fn from(err: ApiError) -> Self {
+        if let ApiError::Database(db_err) = &err {
+            tracing::error!(error = %db_err, "database error");
+        }
+
+        let message = match &err {
+            ApiError::Validation(errors) => errors
+                .iter()
+                .map(|error| format!("{} {}", error.field, error.message))
+                .collect::<Vec<_>>()
+                .join(", "),
+            _ => err.to_string(),
+        };
+
+        match err {
+            ApiError::NotFound => Status::not_found(message),
+            ApiError::LimitExceeded
+            | ApiError::CurrencyMismatch
+            | ApiError::WalletBlocked
+            | ApiError::DailyLimitExceeded
+            | ApiError::HoldSettled
+            | ApiError::ScheduledSettled => Status::failed_precondition(message),
+            ApiError::Conflict => Status::already_exists(message),
+            ApiError::Validation(_)
+            | ApiError::IdempotencyKeyReused
+            | ApiError::UnknownTenant
+            | ApiError::MalformedJson(_)
+            | ApiError::UnsupportedMediaType
+            | ApiError::InvalidBody(_)
+            | ApiError::InvalidParameter(_) => Status::invalid_argument(message),
+            ApiError::Unauthorized => Status::unauthenticated(message),
+            ApiError::Forbidden => Status::permission_denied(message),
+            ApiError::RateLimited { .. } | ApiError::BodyTooLarge => {
+                Status::resource_exhausted(message)
+            }
+            ApiError::Overloaded | ApiError::Unavailable => Status::unavailable(message),
+            ApiError::Timeout => Status::deadline_exceeded(message),
+            ApiError::NotSupported | ApiError::MethodNotAllowed(_) => {
+                Status::unimplemented(message)
+            }
+            ApiError::Internal | ApiError::Database(_) => Status::internal(message),
+        }
+    }
+}
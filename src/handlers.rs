@@ -0,0 +1,1201 @@
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, StatusCode},
+    middleware::Next,
+    response::{
+        sse::{Event, KeepAlive},
+        IntoResponse, Response, Sse,
+    },
+    Extension, Json,
+};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio_stream::{
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    StreamExt,
+};
+use uuid::Uuid;
+
+use crate::{
+    auth::{Actor, TokenSubject},
+    errors::ApiError,
+    extract::{JsonBody, Path, Query},
+    models::{
+        Account, AuditPage, AuditQuery, CapturedHold, ChainVerification, FeeRule, FieldError,
+        HistoryPage, HistoryQuery, Hold, LogLevel, MonthlySummary, PatchCreditLimit,
+        PatchDailyDebitLimit, PatchInterestRate, PostFeeRule, PostHold, PostRecurrence,
+        PostTransactionBatch, PostTransactionRequest, PostTransfer, PostWallet, PostWebhook,
+        RawLogLevel, RawPatchCreditLimit, RawPatchDailyDebitLimit, RawPatchInterestRate,
+        RawPostFeeRule, RawPostHold, RawPostRecurrence, RawPostTransactionBatch,
+        RawPostTransactionRequest, RawPostTransfer, RawPostWallet, RawPostWebhook, ReconcileQuery,
+        Reconciliation, Recurrence, ScheduledTransaction, StatementFilter, StatementQuery,
+        StatementResponse, SummaryQuery, TransactionBatchReceipt, TransactionDetails,
+        TransferReceipt, Wallet, WalletDetails, WalletStatus, Webhook,
+    },
+    repository::WalletRepository,
+};
+
+const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+pub async fn hello_world() -> String {
+    "Hello, World!".to_string()
+}
+
+// the routes' fallback, in the api's format rather than axum's empty body
+pub async fn not_found() -> ApiError {
+    ApiError::NotFound
+}
+
+// axum answers a method a route doesn't take with an empty 405 and the
+// methods it does in `Allow`, those come back as a problem as well
+pub async fn method_not_allowed(req: Request, next: Next) -> Response {
+    let response = next.run(req).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED
+        || response.headers().contains_key(header::CONTENT_TYPE)
+    {
+        return response;
+    }
+
+    let allowed = response
+        .headers()
+        .get(header::ALLOW)
+        .and_then(|allowed| allowed.to_str().ok())
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|method| !method.is_empty())
+        .map(str::to_string)
+        .collect();
+
+    ApiError::MethodNotAllowed(allowed).into_response()
+}
+
+pub async fn healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+// what the binary was built from, embedded by build.rs, so a deployment can
+// be checked from the outside
+pub async fn version() -> Json<Value> {
+    let built_at = env!("RINHA_BUILT_AT")
+        .parse()
+        .ok()
+        .and_then(|secs| OffsetDateTime::from_unix_timestamp(secs).ok())
+        .and_then(|built_at| built_at.format(&Rfc3339).ok());
+    let features: Vec<&str> = env!("RINHA_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect();
+
+    Json(json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "git_sha": env!("RINHA_GIT_SHA"),
+        "built_at": built_at,
+        "features": features,
+        "rustc": env!("RINHA_RUSTC_VERSION"),
+    }))
+}
+
+// unlike `healthz` this goes all the way to the database, so an instance
+// whose pool is wedged or exhausted gets taken out of rotation
+pub async fn readyz<R: WalletRepository>(State(repo): State<R>) -> (StatusCode, Json<Value>) {
+    // sampled before the ping so our own connection isn't counted
+    let pool_status = repo.pool_status().map(|pool| {
+        json!({
+            "size": pool.size,
+            "idle": pool.idle,
+            "in_use": (pool.size as usize).saturating_sub(pool.idle),
+            "max": pool.max,
+        })
+    });
+
+    match repo.ping().await {
+        Ok(()) => (
+            StatusCode::OK,
+            Json(json!({ "status": "ok", "database": "up", "pool": pool_status })),
+        ),
+        Err(err) => {
+            tracing::warn!(error = %err, "readiness check failed");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "status": "unavailable", "database": "down", "pool": pool_status })),
+            )
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/clientes",
+    request_body = PostWallet,
+    responses(
+        (status = 201, description = "the new wallet, its url is in `Location`", body = CreatedWallet),
+        (status = 409, description = "the external id is already taken", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all)]
+pub async fn create_wallet<R: WalletRepository>(
+    State(repo): State<R>,
+    JsonBody(raw_wallet): JsonBody<RawPostWallet>,
+) -> Result<impl IntoResponse, ApiError> {
+    let post_wallet = PostWallet::try_from(raw_wallet)?;
+
+    let wallet = repo.create_wallet(&post_wallet).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, format!("/clientes/{}", wallet.id))],
+        Json(wallet),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}",
+    params(("id" = i32, Path, description = "client id")),
+    responses(
+        (status = 200, description = "balance and limit, without the transactions", body = WalletDetails),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn wallet<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+) -> Result<Json<WalletDetails>, ApiError> {
+    Ok(Json(repo.get_wallet(wallet_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/clientes/{id}/contas",
+    params(("id" = i32, Path, description = "client id")),
+    request_body = PostWallet,
+    responses(
+        (status = 201, description = "the new account, its url is in `Location`", body = CreatedWallet),
+        (status = 404, description = "unknown client, or one that's itself an account", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "the external id is already taken", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage only keeps the default accounts", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = client_id))]
+pub async fn create_account<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(client_id): Path<i32>,
+    JsonBody(raw_wallet): JsonBody<RawPostWallet>,
+) -> Result<impl IntoResponse, ApiError> {
+    let post_wallet = PostWallet::try_from(raw_wallet)?;
+
+    let account = repo.create_account(client_id, &post_wallet).await?;
+
+    Ok((
+        StatusCode::CREATED,
+        [(
+            header::LOCATION,
+            format!("/clientes/{}/contas/{}", client_id, account.id),
+        )],
+        Json(account),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/contas",
+    params(("id" = i32, Path, description = "client id")),
+    responses(
+        (status = 200, description = "the client's accounts, the default one first", body = [Account]),
+        (status = 404, description = "unknown client, or one that's itself an account", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage only keeps the default accounts", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = client_id))]
+pub async fn accounts<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(client_id): Path<i32>,
+) -> Result<Json<Vec<Account>>, ApiError> {
+    Ok(Json(repo.list_accounts(client_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/extrato",
+    params(
+        ("id" = i32, Path, description = "client id"),
+        StatementQuery,
+        ("If-None-Match" = Option<String>, Header, description = "the `ETag` of a previous response"),
+    ),
+    responses(
+        (status = 200, description = "balance and the last 10 transactions, with an `ETag`", body = StatementResponse),
+        (status = 304, description = "nothing changed since the response with the `If-None-Match` tag"),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid date or kind filter", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn statement<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+    Query(query): Query<StatementQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let filter = query.filter()?;
+
+    let statement = repo.get_statement(wallet_id, &filter).await?;
+
+    // polling clients get a bodyless 304 until something changes, and are
+    // asked to always come back for it instead of caching on their own
+    let etag = statement.etag();
+    let cache_headers = [
+        (header::ETAG, etag.clone()),
+        (header::CACHE_CONTROL, "no-cache".to_string()),
+    ];
+    if if_none_match(&headers, &etag) {
+        return Ok((StatusCode::NOT_MODIFIED, cache_headers).into_response());
+    }
+
+    let body = StatementResponse::new(statement, OffsetDateTime::now_utc());
+    Ok((cache_headers, Json(body)).into_response())
+}
+
+// weak comparison, the only one `If-None-Match` uses
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    fn opaque(tag: &str) -> &str {
+        tag.trim().trim_start_matches("W/")
+    }
+
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == opaque(etag))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/transacoes",
+    params(
+        ("id" = i32, Path, description = "client id"),
+        HistoryQuery,
+        ("metadata.{chave}" = Option<String>, Query, description = "only transactions whose metadata has this value under the key, as many as needed"),
+    ),
+    responses(
+        (status = 200, description = "a page of the whole history, oldest first", body = HistoryPage),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid cursor, page size, search or filter", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn history<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+    Query(query): Query<HistoryQuery>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<Json<HistoryPage>, ApiError> {
+    let filter = query.filter(&params)?;
+
+    Ok(Json(repo.get_history(wallet_id, &filter).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/resumo",
+    params(("id" = i32, Path, description = "client id"), SummaryQuery),
+    responses(
+        (status = 200, description = "totals of the month", body = MonthlySummary),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid month", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn summary<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+    Query(query): Query<SummaryQuery>,
+) -> Result<Json<MonthlySummary>, ApiError> {
+    let month = query.month()?;
+
+    Ok(Json(repo.get_summary(wallet_id, month).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/clientes/{id}/transacoes",
+    params(
+        ("id" = i32, Path, description = "client id"),
+        ("Idempotency-Key" = Option<String>, Header, description = "retries with the same key get the first response back"),
+    ),
+    request_body = PostTransactionRequest,
+    responses(
+        (status = 200, description = "the balance after the transaction, with a `Location` of the new transaction", body = TransactionReceipt),
+        (status = 202, description = "the transaction stored to be applied at `agendada_para`, with a `Location` of it", body = ScheduledTransaction),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body, a debit past the credit limit or the daily debit limit, a blocked wallet, or an Idempotency-Key already used for another transaction", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn insert_transaction<R: WalletRepository>(
+    Path(wallet_id): Path<i32>,
+    State(repo): State<R>,
+    headers: HeaderMap,
+    JsonBody(raw_request): JsonBody<RawPostTransactionRequest>,
+) -> Result<Response, ApiError> {
+    let PostTransactionRequest {
+        transaction: post_transaction,
+        due_at,
+    } = PostTransactionRequest::try_from(raw_request)?;
+    let idempotency_key = idempotency_key(&headers)?;
+
+    if let Some(due_at) = due_at {
+        // the key's response is stored along with the transaction, which
+        // doesn't exist yet
+        if idempotency_key.is_some() {
+            return Err(ApiError::Validation(vec![FieldError {
+                field: "Idempotency-Key".into(),
+                message: "can't be combined with agendada_para",
+            }]));
+        }
+
+        let scheduled = repo
+            .schedule_transaction(wallet_id, &post_transaction, due_at)
+            .await?;
+        let location = format!(
+            "/clientes/{}/transacoes/agendadas/{}",
+            wallet_id, scheduled.id
+        );
+
+        return Ok((
+            StatusCode::ACCEPTED,
+            [(header::LOCATION, location)],
+            Json(scheduled),
+        )
+            .into_response());
+    }
+
+    let receipt = repo
+        .insert_transaction(wallet_id, &post_transaction, idempotency_key.as_deref())
+        .await?;
+
+    // stays a 200 rather than a 201, the rinha clients expect it. replays of
+    // responses stored before transactions had ids come without a location
+    let mut response_headers = HeaderMap::new();
+    if let Some(id) = receipt.id {
+        let location = format!("/clientes/{}/transacoes/{}", wallet_id, id);
+        response_headers.insert(
+            header::LOCATION,
+            location.parse().expect("a path is a valid header value"),
+        );
+    }
+
+    Ok((response_headers, Json(receipt)).into_response())
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/transacoes/agendadas",
+    params(("id" = i32, Path, description = "client id")),
+    responses(
+        (status = 200, description = "the pending scheduled transactions, the next one due first", body = [ScheduledTransaction]),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage backend doesn't schedule transactions", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn scheduled_transactions<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+) -> Result<Json<Vec<ScheduledTransaction>>, ApiError> {
+    Ok(Json(repo.list_scheduled(wallet_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/clientes/{id}/transacoes/agendadas/{agendada_id}",
+    params(
+        ("id" = i32, Path, description = "client id"),
+        ("agendada_id" = Uuid, Path, description = "id returned when the transaction was scheduled"),
+    ),
+    responses(
+        (status = 200, description = "the cancelled scheduled transaction", body = ScheduledTransaction),
+        (status = 404, description = "unknown client, or a scheduled transaction of another client", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "already applied, failed or cancelled", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage backend doesn't schedule transactions", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn cancel_scheduled_transaction<R: WalletRepository>(
+    State(repo): State<R>,
+    Path((wallet_id, scheduled_id)): Path<(i32, Uuid)>,
+) -> Result<Json<ScheduledTransaction>, ApiError> {
+    Ok(Json(repo.cancel_scheduled(wallet_id, scheduled_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/clientes/{id}/transacoes/lote",
+    params(("id" = i32, Path, description = "client id")),
+    request_body = PostTransactionBatch,
+    responses(
+        (status = 200, description = "the balance after the batch, and the id and balance of each transaction", body = TransactionBatchReceipt),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 413, description = "body over MAX_BODY_BYTES"),
+        (status = 422, description = "invalid body, a debit past the credit limit anywhere in the batch, debits past the daily debit limit, or a blocked wallet; nothing is applied", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn insert_transactions<R: WalletRepository>(
+    Path(wallet_id): Path<i32>,
+    State(repo): State<R>,
+    JsonBody(raw_batch): JsonBody<RawPostTransactionBatch>,
+) -> Result<Json<TransactionBatchReceipt>, ApiError> {
+    let batch = PostTransactionBatch::try_from(raw_batch)?;
+
+    let receipt = repo
+        .insert_transactions(wallet_id, &batch.transactions)
+        .await?;
+
+    Ok(Json(receipt))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/transacoes/{transacao_id}",
+    params(
+        ("id" = i32, Path, description = "client id"),
+        ("transacao_id" = Uuid, Path, description = "id returned when the transaction was made"),
+    ),
+    responses(
+        (status = 200, description = "a single transaction", body = TransactionDetails),
+        (status = 404, description = "unknown client, or a transaction of another client", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn transaction<R: WalletRepository>(
+    State(repo): State<R>,
+    Path((wallet_id, transaction_id)): Path<(i32, Uuid)>,
+) -> Result<Json<TransactionDetails>, ApiError> {
+    Ok(Json(repo.get_transaction(wallet_id, transaction_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/transferencias",
+    request_body = PostTransfer,
+    responses(
+        (status = 200, description = "the source's balance after the transfer", body = TransferReceipt),
+        (status = 403, description = "the token belongs to another client than `de`", body = Problem, content_type = "application/problem+json"),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body, a debit past the source's credit limit or daily debit limit, or a blocked wallet on either side", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all)]
+pub async fn transfer<R: WalletRepository>(
+    State(repo): State<R>,
+    subject: Option<Extension<TokenSubject>>,
+    JsonBody(raw_transfer): JsonBody<RawPostTransfer>,
+) -> Result<Json<TransferReceipt>, ApiError> {
+    let transfer = PostTransfer::try_from(raw_transfer)?;
+
+    // a client's token can send from its own wallet to anyone
+    if let Some(Extension(subject)) = subject {
+        if !subject.allows(transfer.from) {
+            return Err(ApiError::Forbidden);
+        }
+    }
+
+    Ok(Json(repo.transfer(&transfer).await?))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/clientes/{id}/limite",
+    params(("id" = i32, Path, description = "client id")),
+    request_body = PatchCreditLimit,
+    responses(
+        (status = 200, description = "the balance under the new limit", body = Wallet),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body, or a limit the current balance is already past", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn update_credit_limit<R: WalletRepository>(
+    Path(wallet_id): Path<i32>,
+    State(repo): State<R>,
+    actor: Option<Extension<Actor>>,
+    JsonBody(raw_limit): JsonBody<RawPatchCreditLimit>,
+) -> Result<Json<Wallet>, ApiError> {
+    let patch = PatchCreditLimit::try_from(raw_limit)?;
+    let actor = actor.map_or_else(|| "anonymous".to_string(), |Extension(Actor(actor))| actor);
+
+    let wallet = repo
+        .update_credit_limit(wallet_id, patch.credit_limit, &actor)
+        .await?;
+
+    Ok(Json(wallet))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/clientes/{id}/limite_diario",
+    params(("id" = i32, Path, description = "client id")),
+    request_body = PatchDailyDebitLimit,
+    responses(
+        (status = 200, description = "the wallet under the new daily debit limit", body = WalletDetails),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn update_daily_debit_limit<R: WalletRepository>(
+    Path(wallet_id): Path<i32>,
+    State(repo): State<R>,
+    actor: Option<Extension<Actor>>,
+    JsonBody(raw_limit): JsonBody<RawPatchDailyDebitLimit>,
+) -> Result<Json<WalletDetails>, ApiError> {
+    let patch = PatchDailyDebitLimit::try_from(raw_limit)?;
+    let actor = actor.map_or_else(|| "anonymous".to_string(), |Extension(Actor(actor))| actor);
+
+    let wallet = repo
+        .set_daily_debit_limit(wallet_id, patch.daily_debit_limit, &actor)
+        .await?;
+
+    Ok(Json(wallet))
+}
+
+#[utoipa::path(
+    patch,
+    path = "/clientes/{id}/juros",
+    params(("id" = i32, Path, description = "client id")),
+    request_body = PatchInterestRate,
+    responses(
+        (status = 200, description = "the wallet under the new daily interest rate", body = WalletDetails),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't accrue interest", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn update_interest_rate<R: WalletRepository>(
+    Path(wallet_id): Path<i32>,
+    State(repo): State<R>,
+    actor: Option<Extension<Actor>>,
+    JsonBody(raw_rate): JsonBody<RawPatchInterestRate>,
+) -> Result<Json<WalletDetails>, ApiError> {
+    let patch = PatchInterestRate::try_from(raw_rate)?;
+    let actor = actor.map_or_else(|| "anonymous".to_string(), |Extension(Actor(actor))| actor);
+
+    let wallet = repo
+        .set_interest_rate(wallet_id, patch.interest_rate, &actor)
+        .await?;
+
+    Ok(Json(wallet))
+}
+
+#[utoipa::path(
+    post,
+    path = "/clientes/{id}/webhooks",
+    params(("id" = i32, Path, description = "client id")),
+    request_body = PostWebhook,
+    responses(
+        (status = 201, description = "the new webhook along with its secret, which isn't shown again", body = CreatedWebhook),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't deliver webhooks", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn create_webhook<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+    JsonBody(raw_webhook): JsonBody<RawPostWebhook>,
+) -> Result<impl IntoResponse, ApiError> {
+    let post_webhook = PostWebhook::try_from(raw_webhook)?;
+
+    let webhook = repo.create_webhook(wallet_id, &post_webhook).await?;
+
+    Ok((StatusCode::CREATED, Json(webhook)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/webhooks",
+    params(("id" = i32, Path, description = "client id")),
+    responses(
+        (status = 200, description = "the client's webhooks, without their secrets", body = [Webhook]),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't deliver webhooks", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn webhooks<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+) -> Result<Json<Vec<Webhook>>, ApiError> {
+    Ok(Json(repo.list_webhooks(wallet_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/clientes/{id}/webhooks/{webhook_id}",
+    params(
+        ("id" = i32, Path, description = "client id"),
+        ("webhook_id" = i32, Path, description = "id returned when the webhook was created"),
+    ),
+    responses(
+        (status = 204, description = "deleted, along with the deliveries it still had queued"),
+        (status = 404, description = "unknown client, or a webhook of another client", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't deliver webhooks", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn delete_webhook<R: WalletRepository>(
+    State(repo): State<R>,
+    Path((wallet_id, webhook_id)): Path<(i32, i32)>,
+) -> Result<StatusCode, ApiError> {
+    repo.delete_webhook(wallet_id, webhook_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/clientes/{id}/recorrencias",
+    params(("id" = i32, Path, description = "client id")),
+    request_body = PostRecurrence,
+    responses(
+        (status = 201, description = "the new recurrence, with a `Location` of it", body = Recurrence),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body, another currency than the client's, or a blocked wallet", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage backend doesn't schedule transactions", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn create_recurrence<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+    JsonBody(raw_recurrence): JsonBody<RawPostRecurrence>,
+) -> Result<impl IntoResponse, ApiError> {
+    let post_recurrence = PostRecurrence::try_from(raw_recurrence)?;
+
+    let recurrence = repo.create_recurrence(wallet_id, &post_recurrence).await?;
+    let location = format!("/clientes/{}/recorrencias/{}", wallet_id, recurrence.id);
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(recurrence),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/recorrencias",
+    params(("id" = i32, Path, description = "client id")),
+    responses(
+        (status = 200, description = "the client's recurrences, oldest first", body = [Recurrence]),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage backend doesn't schedule transactions", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn recurrences<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+) -> Result<Json<Vec<Recurrence>>, ApiError> {
+    Ok(Json(repo.list_recurrences(wallet_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/recorrencias/{recorrencia_id}",
+    params(
+        ("id" = i32, Path, description = "client id"),
+        ("recorrencia_id" = i32, Path, description = "id returned when the recurrence was created"),
+    ),
+    responses(
+        (status = 200, description = "a single recurrence, with its next and last runs", body = Recurrence),
+        (status = 404, description = "unknown client, or a recurrence of another client", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage backend doesn't schedule transactions", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn recurrence<R: WalletRepository>(
+    State(repo): State<R>,
+    Path((wallet_id, recurrence_id)): Path<(i32, i32)>,
+) -> Result<Json<Recurrence>, ApiError> {
+    Ok(Json(repo.get_recurrence(wallet_id, recurrence_id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/clientes/{id}/recorrencias/{recorrencia_id}",
+    params(
+        ("id" = i32, Path, description = "client id"),
+        ("recorrencia_id" = i32, Path, description = "id returned when the recurrence was created"),
+    ),
+    request_body = PostRecurrence,
+    responses(
+        (status = 200, description = "the recurrence with its new definition, and its next run worked out again", body = Recurrence),
+        (status = 404, description = "unknown client, or a recurrence of another client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body, or another currency than the client's", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage backend doesn't schedule transactions", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn update_recurrence<R: WalletRepository>(
+    State(repo): State<R>,
+    Path((wallet_id, recurrence_id)): Path<(i32, i32)>,
+    JsonBody(raw_recurrence): JsonBody<RawPostRecurrence>,
+) -> Result<Json<Recurrence>, ApiError> {
+    let post_recurrence = PostRecurrence::try_from(raw_recurrence)?;
+
+    Ok(Json(
+        repo.update_recurrence(wallet_id, recurrence_id, &post_recurrence)
+            .await?,
+    ))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/clientes/{id}/recorrencias/{recorrencia_id}",
+    params(
+        ("id" = i32, Path, description = "client id"),
+        ("recorrencia_id" = i32, Path, description = "id returned when the recurrence was created"),
+    ),
+    responses(
+        (status = 204, description = "deleted, the transactions it made stay"),
+        (status = 404, description = "unknown client, or a recurrence of another client", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage backend doesn't schedule transactions", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn delete_recurrence<R: WalletRepository>(
+    State(repo): State<R>,
+    Path((wallet_id, recurrence_id)): Path<(i32, i32)>,
+) -> Result<StatusCode, ApiError> {
+    repo.delete_recurrence(wallet_id, recurrence_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    post,
+    path = "/clientes/{id}/holds",
+    params(("id" = i32, Path, description = "client id")),
+    request_body = PostHold,
+    responses(
+        (status = 201, description = "the amount is set aside until the hold is captured, released or expires", body = Hold),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body, an amount past what the client can spend, or a blocked wallet", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't keep holds", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "holds"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn create_hold<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+    JsonBody(raw_hold): JsonBody<RawPostHold>,
+) -> Result<impl IntoResponse, ApiError> {
+    let post_hold = PostHold::try_from(raw_hold)?;
+
+    let hold = repo.create_hold(wallet_id, &post_hold).await?;
+
+    Ok((StatusCode::CREATED, Json(hold)))
+}
+
+// a hold's url carries no client, it's only known once the hold is read. a
+// client's token gets the same 404 for another client's hold as for one that
+// doesn't exist, which keeps it from probing the ids of the others
+async fn owned_hold<R: WalletRepository>(
+    repo: &R,
+    subject: Option<Extension<TokenSubject>>,
+    hold_id: Uuid,
+) -> Result<Hold, ApiError> {
+    let hold = repo.get_hold(hold_id).await?;
+
+    match subject {
+        Some(Extension(subject)) if !subject.allows(hold.wallet_id) => Err(ApiError::NotFound),
+        _ => Ok(hold),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/holds/{hold_id}",
+    params(("hold_id" = Uuid, Path, description = "id returned when the hold was created")),
+    responses(
+        (status = 200, description = "a single hold", body = Hold),
+        (status = 404, description = "unknown hold, or another client's", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't keep holds", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "holds"
+)]
+#[tracing::instrument(skip_all, fields(hold_id = %hold_id))]
+pub async fn hold<R: WalletRepository>(
+    State(repo): State<R>,
+    subject: Option<Extension<TokenSubject>>,
+    Path(hold_id): Path<Uuid>,
+) -> Result<Json<Hold>, ApiError> {
+    Ok(Json(owned_hold(&repo, subject, hold_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/holds/{hold_id}/capture",
+    params(("hold_id" = Uuid, Path, description = "id returned when the hold was created")),
+    responses(
+        (status = 200, description = "the hold along with the balance after its debit", body = CapturedHold),
+        (status = 404, description = "unknown hold, or another client's", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "the hold was already captured, released or expired", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "a debit past the daily debit limit, or a blocked wallet", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't keep holds", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "holds"
+)]
+#[tracing::instrument(skip_all, fields(hold_id = %hold_id))]
+pub async fn capture_hold<R: WalletRepository>(
+    State(repo): State<R>,
+    subject: Option<Extension<TokenSubject>>,
+    Path(hold_id): Path<Uuid>,
+) -> Result<Json<CapturedHold>, ApiError> {
+    owned_hold(&repo, subject, hold_id).await?;
+
+    Ok(Json(repo.capture_hold(hold_id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/holds/{hold_id}",
+    params(("hold_id" = Uuid, Path, description = "id returned when the hold was created")),
+    responses(
+        (status = 200, description = "the released hold, its amount is available again", body = Hold),
+        (status = 404, description = "unknown hold, or another client's", body = Problem, content_type = "application/problem+json"),
+        (status = 409, description = "the hold was already captured, released or expired", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't keep holds", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "holds"
+)]
+#[tracing::instrument(skip_all, fields(hold_id = %hold_id))]
+pub async fn release_hold<R: WalletRepository>(
+    State(repo): State<R>,
+    subject: Option<Extension<TokenSubject>>,
+    Path(hold_id): Path<Uuid>,
+) -> Result<Json<Hold>, ApiError> {
+    owned_hold(&repo, subject, hold_id).await?;
+
+    Ok(Json(repo.release_hold(hold_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/clientes/{id}/bloquear",
+    params(("id" = i32, Path, description = "client id")),
+    responses(
+        (status = 200, description = "the blocked wallet, which turns new transactions away with `wallet_blocked`", body = WalletDetails),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "admin"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn block_wallet<R: WalletRepository>(
+    Path(wallet_id): Path<i32>,
+    State(repo): State<R>,
+    actor: Option<Extension<Actor>>,
+) -> Result<Json<WalletDetails>, ApiError> {
+    set_wallet_status(repo, wallet_id, WalletStatus::Blocked, actor).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/clientes/{id}/desbloquear",
+    params(("id" = i32, Path, description = "client id")),
+    responses(
+        (status = 200, description = "the wallet, taking transactions again", body = WalletDetails),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "admin"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn unblock_wallet<R: WalletRepository>(
+    Path(wallet_id): Path<i32>,
+    State(repo): State<R>,
+    actor: Option<Extension<Actor>>,
+) -> Result<Json<WalletDetails>, ApiError> {
+    set_wallet_status(repo, wallet_id, WalletStatus::Active, actor).await
+}
+
+async fn set_wallet_status<R: WalletRepository>(
+    repo: R,
+    wallet_id: i32,
+    status: WalletStatus,
+    actor: Option<Extension<Actor>>,
+) -> Result<Json<WalletDetails>, ApiError> {
+    let actor = actor.map_or_else(|| "anonymous".to_string(), |Extension(Actor(actor))| actor);
+
+    Ok(Json(
+        repo.set_wallet_status(wallet_id, status, &actor).await?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/transacoes/verify",
+    params(("id" = i32, Path, description = "client id")),
+    responses(
+        (status = 200, description = "whether every transaction still links to the one before it and matches its hash, and the first one that doesn't", body = ChainVerification),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't chain the transactions", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn verify_chain<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+) -> Result<Json<ChainVerification>, ApiError> {
+    Ok(Json(repo.verify_chain(wallet_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/clientes/{id}/reconciliar",
+    params(("id" = i32, Path, description = "client id"), ReconcileQuery),
+    responses(
+        (status = 200, description = "the balance next to what the transactions add up to, and whether it was repaired", body = Reconciliation),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't reconcile balances", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "admin"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn reconcile<R: WalletRepository>(
+    Path(wallet_id): Path<i32>,
+    State(repo): State<R>,
+    actor: Option<Extension<Actor>>,
+    Query(query): Query<ReconcileQuery>,
+) -> Result<Json<Reconciliation>, ApiError> {
+    let repair = query.repair()?;
+    let actor = actor.map_or_else(|| "anonymous".to_string(), |Extension(Actor(actor))| actor);
+
+    Ok(Json(repo.reconcile(wallet_id, repair, &actor).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/audit",
+    params(AuditQuery),
+    responses(
+        (status = 200, description = "every write matching the filters, newest first", body = AuditPage),
+        (status = 422, description = "invalid filters", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't record every write", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "admin"
+)]
+#[tracing::instrument(skip_all)]
+pub async fn audit_log<R: WalletRepository>(
+    State(repo): State<R>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<AuditPage>, ApiError> {
+    let filter = query.filter()?;
+
+    Ok(Json(repo.audit_log(&filter).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/tarifas",
+    request_body = PostFeeRule,
+    responses(
+        (status = 201, description = "the new fee rule, charged from now on, with a `Location` of it", body = FeeRule),
+        (status = 422, description = "invalid body", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't charge fees", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "admin"
+)]
+#[tracing::instrument(skip_all)]
+pub async fn create_fee_rule<R: WalletRepository>(
+    State(repo): State<R>,
+    JsonBody(raw_rule): JsonBody<RawPostFeeRule>,
+) -> Result<impl IntoResponse, ApiError> {
+    let post_rule = PostFeeRule::try_from(raw_rule)?;
+
+    let rule = repo.create_fee_rule(&post_rule).await?;
+    let location = format!("/admin/tarifas/{}", rule.id);
+
+    Ok((
+        StatusCode::CREATED,
+        [(header::LOCATION, location)],
+        Json(rule),
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/tarifas",
+    responses(
+        (status = 200, description = "every fee rule, in the order the fees are charged", body = [FeeRule]),
+        (status = 501, description = "the storage doesn't charge fees", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "admin"
+)]
+#[tracing::instrument(skip_all)]
+pub async fn fee_rules<R: WalletRepository>(
+    State(repo): State<R>,
+) -> Result<Json<Vec<FeeRule>>, ApiError> {
+    Ok(Json(repo.list_fee_rules().await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/tarifas/{tarifa_id}",
+    params(("tarifa_id" = i32, Path, description = "id returned when the rule was created")),
+    request_body = PostFeeRule,
+    responses(
+        (status = 200, description = "the rule with its new definition, the fees already charged stay", body = FeeRule),
+        (status = 404, description = "unknown rule", body = Problem, content_type = "application/problem+json"),
+        (status = 422, description = "invalid body", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't charge fees", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "admin"
+)]
+#[tracing::instrument(skip_all, fields(rule_id = rule_id))]
+pub async fn update_fee_rule<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(rule_id): Path<i32>,
+    JsonBody(raw_rule): JsonBody<RawPostFeeRule>,
+) -> Result<Json<FeeRule>, ApiError> {
+    let post_rule = PostFeeRule::try_from(raw_rule)?;
+
+    Ok(Json(repo.update_fee_rule(rule_id, &post_rule).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/admin/tarifas/{tarifa_id}",
+    params(("tarifa_id" = i32, Path, description = "id returned when the rule was created")),
+    responses(
+        (status = 204, description = "deleted, the fees it charged stay"),
+        (status = 404, description = "unknown rule", body = Problem, content_type = "application/problem+json"),
+        (status = 501, description = "the storage doesn't charge fees", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "admin"
+)]
+#[tracing::instrument(skip_all, fields(rule_id = rule_id))]
+pub async fn delete_fee_rule<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(rule_id): Path<i32>,
+) -> Result<StatusCode, ApiError> {
+    repo.delete_fee_rule(rule_id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/log-level",
+    responses(
+        (status = 200, description = "the log filter in effect", body = LogLevel),
+    ),
+    tag = "admin"
+)]
+pub async fn log_level() -> Result<Json<LogLevel>, ApiError> {
+    let filter = crate::log_level::current().ok_or(ApiError::NotSupported)?;
+    Ok(Json(LogLevel { filter }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/admin/log-level",
+    request_body = LogLevel,
+    responses(
+        (status = 200, description = "the new filter, in effect until the next one or a SIGHUP, which goes back to RUST_LOG's", body = LogLevel),
+        (status = 422, description = "invalid body or filter", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "admin"
+)]
+pub async fn update_log_level(
+    JsonBody(raw_log_level): JsonBody<RawLogLevel>,
+) -> Result<Json<LogLevel>, ApiError> {
+    let log_level = LogLevel::try_from(raw_log_level)?;
+    crate::log_level::set(&log_level.filter)?;
+    tracing::info!(filter = %log_level.filter, "log filter changed");
+
+    Ok(Json(log_level))
+}
+
+#[utoipa::path(
+    get,
+    path = "/clientes/{id}/transacoes/stream",
+    params(("id" = i32, Path, description = "client id")),
+    responses(
+        (status = 200, description = "server-sent events: a `transacao` for each new transaction, and a `lagged` with the number of dropped events when the client falls behind", content_type = "text/event-stream", body = TransactionEvent),
+        (status = 404, description = "unknown client", body = Problem, content_type = "application/problem+json"),
+    ),
+    tag = "clientes"
+)]
+#[tracing::instrument(skip_all, fields(wallet_id = wallet_id))]
+pub async fn transaction_stream<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+) -> Result<impl IntoResponse, ApiError> {
+    // subscribed before the wallet is looked up, so nothing committed in
+    // between goes missing
+    let events = BroadcastStream::new(repo.subscribe());
+    repo.get_statement(wallet_id, &StatementFilter::default())
+        .await?;
+
+    let stream = events.filter_map(move |event| match event {
+        Ok(event) if event.wallet_id == wallet_id => {
+            Some(Event::default().event("transacao").json_data(event))
+        }
+        Ok(_) => None,
+        // the client is told so it can fetch the extrato again to catch up
+        Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(Ok(Event::default()
+            .event("lagged")
+            .data(skipped.to_string()))),
+    });
+
+    Ok((
+        // or nginx holds the events back until its buffer fills up
+        [(HeaderName::from_static("x-accel-buffering"), "no")],
+        Sse::new(stream).keep_alive(KeepAlive::default()),
+    ))
+}
+
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<String>, Vec<FieldError>> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(None);
+    };
+
+    match value.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= 255 => Ok(Some(key.to_string())),
+        _ => Err(vec![FieldError {
+            field: "Idempotency-Key".into(),
+            message: "must be a visible ASCII string between 1 and 255 characters",
+        }]),
+    }
+}
@@ -0,0 +1,230 @@
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+
+use crate::errors::ApiError;
+
+// the english name of each field the responses name in portuguese. the rest,
+// like `id`, `total` or `tags`, are the same in both
+const ENGLISH: [(&str, &str); 56] = [
+    ("acao", "action"),
+    ("agendada_para", "scheduled_for"),
+    ("antes", "before"),
+    ("ativa", "active"),
+    ("ator", "actor"),
+    ("categoria", "category"),
+    ("categorias", "categories"),
+    ("cliente_id", "client_id"),
+    ("criado_em", "created_at"),
+    ("data_extrato", "statement_date"),
+    ("de", "from"),
+    ("debitado_hoje", "debited_today"),
+    ("depois", "after"),
+    ("descricao", "description"),
+    ("entradas", "entries"),
+    ("esperado", "expected"),
+    ("expira_em", "expires_at"),
+    ("expira_em_segundos", "expires_in_secs"),
+    ("filtro", "filter"),
+    ("fixa", "flat"),
+    ("id_externo", "external_id"),
+    ("limite", "limit"),
+    ("limite_diario", "daily_limit"),
+    ("mes", "month"),
+    ("moeda", "currency"),
+    ("motivo", "reason"),
+    ("padrao", "default"),
+    ("para", "to"),
+    ("percentual", "percentage"),
+    ("posicao", "position"),
+    ("proxima_em", "next_at"),
+    ("proximo", "next"),
+    ("quebra", "break"),
+    ("realizada_em", "performed_at"),
+    ("reparado", "repaired"),
+    ("requisicao_id", "request_id"),
+    ("reserva", "hold"),
+    ("reservado", "held"),
+    ("saldo", "balance"),
+    ("segredo", "secret"),
+    ("taxa_diaria", "daily_rate"),
+    ("taxa_juros_diaria", "daily_interest_rate"),
+    ("tipo", "type"),
+    ("total_creditos", "total_credits"),
+    ("total_debitos", "total_debits"),
+    ("total_transacoes", "transaction_count"),
+    ("transacao", "transaction"),
+    ("transacao_id", "transaction_id"),
+    ("transacoes", "transactions"),
+    ("ultima_em", "last_at"),
+    ("ultimas_transacoes", "latest_transactions"),
+    ("ultimo_erro", "last_error"),
+    ("valido", "valid"),
+    ("valor", "value"),
+    ("valor_minimo", "min_value"),
+    ("variacao", "net_change"),
+];
+
+// the largest body read whole to be translated. the json ones are pages and
+// single resources, way under this, and the streamed ones, like the export,
+// aren't json
+const MAX_TRANSLATED_BYTES: usize = 4 * 1024 * 1024;
+
+// what an english statement's etag ends with, so it never matches the
+// portuguese one
+const ETAG_SUFFIX: &str = "-en";
+
+// the json bodies come out with english field names for `?lang=en`, or an
+// `Accept-Language` that prefers english over portuguese. `?lang=pt` keeps
+// them in portuguese whatever the header says. the handlers always write
+// portuguese, so the bodies are rewritten here, and go out uncompressed. the
+// problem details are english already, and graphql has a schema of its own
+pub async fn translate(mut req: Request, next: Next) -> Response {
+    let (english, by_header) = match query_lang(req.uri().query()) {
+        Some(lang) => (lang == "en", false),
+        None => (prefers_english(req.headers()), true),
+    };
+
+    if !english || req.uri().path() == "/graphql" {
+        let mut response = next.run(req).await;
+        if by_header {
+            vary(&mut response);
+        }
+        return response;
+    }
+
+    // the body can't be rewritten once compressed
+    req.headers_mut().remove(header::ACCEPT_ENCODING);
+    // the handlers compare against their own etags
+    if let Some(tags) = req.headers().get(header::IF_NONE_MATCH) {
+        let tags = tags
+            .to_str()
+            .unwrap_or_default()
+            .replace(&format!("{}\"", ETAG_SUFFIX), "\"");
+        if let Ok(tags) = HeaderValue::from_str(&tags) {
+            req.headers_mut().insert(header::IF_NONE_MATCH, tags);
+        }
+    }
+
+    let response = next.run(req).await;
+    let (mut parts, body) = response.into_parts();
+
+    if let Some(etag) = parts.headers.get(header::ETAG) {
+        let etag = etag.to_str().unwrap_or_default();
+        let etag = match etag.strip_suffix('"') {
+            Some(etag) => format!("{}{}\"", etag, ETAG_SUFFIX),
+            None => etag.to_string(),
+        };
+        if let Ok(etag) = HeaderValue::from_str(&etag) {
+            parts.headers.insert(header::ETAG, etag);
+        }
+    }
+
+    let json = parts
+        .headers
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|content_type| content_type == "application/json");
+    if !json {
+        let mut response = Response::from_parts(parts, body);
+        if by_header {
+            vary(&mut response);
+        }
+        return response;
+    }
+
+    let body = match to_bytes(body, MAX_TRANSLATED_BYTES).await {
+        Ok(body) => body,
+        Err(err) => {
+            tracing::error!(error = %err, "can't read a response body to translate");
+            return ApiError::Internal.into_response();
+        }
+    };
+    let body = match serde_json::from_slice(&body).map(english_keys) {
+        Ok(value) => Body::from(value.to_string()),
+        Err(err) => {
+            tracing::error!(error = %err, "can't translate a response body");
+            return ApiError::Internal.into_response();
+        }
+    };
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let mut response = Response::from_parts(parts, body);
+    if by_header {
+        vary(&mut response);
+    }
+
+    response
+}
+
+// the value of `lang` in the query string, when given
+fn query_lang(query: Option<&str>) -> Option<&str> {
+    query?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("lang="))
+}
+
+// english weighs more than portuguese, or is the only one of the two listed.
+// `en-US;q=0.9, pt;q=0.8` is english, `pt-BR, en` and `*` are portuguese
+fn prefers_english(headers: &HeaderMap) -> bool {
+    let Some(accepted) = headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+
+    let weight = |wanted: &str| {
+        accepted
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim();
+                let primary = tag.split('-').next()?;
+                if !primary.eq_ignore_ascii_case(wanted) {
+                    return None;
+                }
+                let q = parts
+                    .find_map(|param| param.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.parse::<f32>().ok())?;
+                Some(q)
+            })
+            .fold(0.0, f32::max)
+    };
+
+    let english = weight("en");
+    english > 0.0 && english > weight("pt")
+}
+
+fn vary(response: &mut Response) {
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept-language"));
+}
+
+fn english_keys(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| {
+                    // the integrator's own object, as it was given
+                    if key == "metadata" {
+                        return (key, value);
+                    }
+                    let key = match ENGLISH.iter().find(|(pt, _)| *pt == key) {
+                        Some((_, en)) => en.to_string(),
+                        None => key,
+                    };
+                    (key, english_keys(value))
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(english_keys).collect()),
+        value => value,
+    }
+}
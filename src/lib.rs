@@ -0,0 +1,149 @@
+use crate::{
+    auth::{ApiKeys, Jwt},
+    config::Config,
+    repository::WalletRepository,
+};
+use axum::{error_handling::HandleErrorLayer, middleware, routing::get, Router};
+use tower::{limit::GlobalConcurrencyLimitLayer, load_shed::LoadShedLayer, Layer, ServiceBuilder};
+use tower_http::{
+    catch_panic::CatchPanicLayer, limit::RequestBodyLimitLayer, timeout::TimeoutLayer,
+};
+
+pub mod access_log;
+pub mod accounts;
+pub mod audit;
+pub mod auth;
+pub mod config;
+pub mod cron;
+pub mod db;
+pub mod errors;
+pub mod events;
+pub mod export;
+pub mod extract;
+#[cfg(feature = "graphql")]
+pub mod graphql;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod handlers;
+pub mod lang;
+pub mod load_shed;
+pub mod log_level;
+pub mod metrics;
+pub mod models;
+pub mod openapi;
+pub mod rate_limit;
+pub mod reload;
+#[cfg(feature = "sentry")]
+pub mod reporting;
+pub mod repository;
+pub mod request_id;
+pub mod server;
+pub mod slow_queries;
+#[cfg(feature = "otel")]
+pub mod telemetry;
+pub mod tenant;
+#[cfg(feature = "tls")]
+pub mod tls;
+pub mod versions;
+pub mod ws;
+
+pub fn app<R: WalletRepository>(repo: R, config: &Config) -> Router {
+    if crate::metrics::install(config).is_none() {
+        crate::metrics::report_pool(repo.clone());
+    }
+
+    let v1 = layered(crate::versions::v1(&repo, config), &repo, config);
+
+    let api = Router::new()
+        .nest("/v1", v1.clone())
+        // the unprefixed paths are v1's, the ones the rinha test harness
+        // calls. a later version only gets a prefix of its own
+        .merge(v1)
+        .route("/healthz", get(handlers::healthz))
+        .route("/readyz", get(handlers::readyz::<R>))
+        .route("/version", get(handlers::version))
+        .route("/metrics", get(crate::metrics::render::<R>))
+        .route("/openapi.json", get(crate::openapi::spec))
+        .route("/docs", get(crate::openapi::swagger_ui))
+        .fallback(handlers::not_found)
+        .layer(middleware::from_fn(crate::access_log::matched_route))
+        .with_state(repo);
+
+    // around the whole router, the `Allow` header is only set on the way out
+    // of it
+    let api = middleware::from_fn(handlers::method_not_allowed).layer(api);
+
+    // the accounts' urls are rewritten before they get to the routes above,
+    // which a layer of their own would only see once they're matched
+    let api = middleware::from_fn(crate::accounts::rewrite).layer(api);
+
+    Router::new()
+        .fallback_service(api)
+        // inside the request id's layer, so the problem carries it
+        .layer(CatchPanicLayer::custom(crate::errors::panicked))
+        // a client trickling its body in can't hold on to a pool connection
+        // for longer than this, nor make us buffer more than the limit
+        .layer(RequestBodyLimitLayer::new(config.max_body_bytes))
+        .layer(TimeoutLayer::new(config.request_timeout))
+        // outside everything that reaches the repository, the api keys from
+        // the database included
+        .layer(middleware::from_fn(crate::tenant::select))
+        // in the request's span, and around the limits above so the requests
+        // they turn away get a line too
+        .layer(crate::access_log::layer())
+        .layer(middleware::from_fn(crate::request_id::propagate))
+}
+
+// the layers every version's routes go through. they're nested under the
+// version's prefix once layered, so the layers see the paths without it
+fn layered<R: WalletRepository>(mut api: Router<R>, repo: &R, config: &Config) -> Router<R> {
+    // innermost, so only the bodies the handlers write are translated
+    api = api.route_layer(middleware::from_fn(crate::lang::translate));
+
+    // inside the auth layers below, so only a client that may use the
+    // account finds out whether it exists
+    api = api.route_layer(middleware::from_fn_with_state(
+        repo.clone(),
+        crate::accounts::check_owner::<R>,
+    ));
+
+    // inside the auth layers below, which find out who the actor is
+    api = api.route_layer(middleware::from_fn(crate::audit::remember));
+    // probes and scrapes stay open so orchestrators don't need credentials
+    if let Some(jwt) = Jwt::from_config(config) {
+        api = api.route_layer(middleware::from_fn_with_state(
+            jwt,
+            crate::auth::require_jwt,
+        ));
+    }
+    if let Some(keys) = ApiKeys::from_config(config, repo) {
+        api = api.route_layer(middleware::from_fn_with_state(
+            keys,
+            crate::auth::require_api_key::<R>,
+        ));
+    }
+
+    // past the limit requests are rejected with a 503 right away instead of
+    // piling up behind the connection pool. the semaphore is shared between
+    // all the routes, so it bounds the api as a whole
+    if config.max_concurrent_requests > 0 {
+        api = api.route_layer(
+            ServiceBuilder::new()
+                .layer(HandleErrorLayer::new(crate::load_shed::handle_error))
+                .layer(LoadShedLayer::new())
+                .layer(GlobalConcurrencyLimitLayer::new(
+                    config.max_concurrent_requests,
+                )),
+        );
+    }
+
+    // around the auth layers too, whose key lookups can fail
+    #[cfg(feature = "sentry")]
+    {
+        api = api.route_layer(middleware::from_fn(crate::reporting::scope));
+    }
+
+    // only the routes above are tracked, probes and scrapes would just drown
+    // out the api traffic
+    api.route_layer(middleware::from_fn(crate::metrics::track_metrics))
+}
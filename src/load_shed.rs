@@ -0,0 +1,18 @@
+use axum::BoxError;
+use metrics::counter;
+use tower::load_shed::error::Overloaded;
+
+use crate::errors::ApiError;
+
+// turns the errors of the concurrency limit stack into responses. a shed
+// request never reached a handler, so it fails in microseconds instead of
+// waiting out the acquire timeout behind everyone else
+pub async fn handle_error(err: BoxError) -> ApiError {
+    if err.is::<Overloaded>() {
+        counter!("http_requests_shed_total").increment(1);
+        return ApiError::Overloaded;
+    }
+
+    tracing::error!(error = %err, "unhandled middleware error");
+    ApiError::Internal
+}
@@ -0,0 +1,151 @@
+use std::sync::{Mutex, OnceLock};
+
+use tracing::{Metadata, Subscriber};
+use tracing_subscriber::{layer::Context, reload, EnvFilter};
+
+use crate::{errors::ApiError, models::FieldError};
+
+// what's logged without RUST_LOG, or with one that doesn't parse
+const DEFAULT_FILTER: &str = "rinha=debug,rinha_rust=debug";
+
+// the runtime's instrumentation, which tokio-console reads whatever the
+// filter is
+#[cfg(feature = "console")]
+const RUNTIME_DIRECTIVES: &str = "tokio=trace,runtime=trace";
+
+// hands the filter in effect to a closure
+type WithCurrent = dyn Fn(&mut dyn FnMut(&EnvFilter)) + Send + Sync;
+
+struct Reload {
+    apply: Box<dyn Fn(EnvFilter) -> Result<(), reload::Error> + Send + Sync>,
+    with_current: Box<WithCurrent>,
+    // RUST_LOG's, the configuration file's included, what a SIGHUP goes
+    // back to
+    initial: Mutex<String>,
+    current: Mutex<String>,
+}
+
+static RELOAD: OnceLock<Reload> = OnceLock::new();
+
+// the filter of the logs, from RUST_LOG, which `set` swaps for another while
+// running. there's only one, the first call's
+pub fn filter<S: Subscriber>() -> reload::Layer<EnvFilter, S> {
+    let initial = std::env::var("RUST_LOG")
+        .ok()
+        .filter(|directives| EnvFilter::try_new(directives).is_ok())
+        .unwrap_or_else(|| DEFAULT_FILTER.to_string());
+
+    let (layer, handle) = reload::Layer::new(EnvFilter::new(with_runtime(&initial)));
+    let current = handle.clone();
+    let _ = RELOAD.set(Reload {
+        apply: Box::new(move |filter| handle.reload(filter)),
+        with_current: Box::new(move |f| {
+            let _ = current.with_current(|filter| f(filter));
+        }),
+        current: Mutex::new(initial.clone()),
+        initial: Mutex::new(initial),
+    });
+
+    layer
+}
+
+// the directives in effect, `None` when nothing set up a filter to change
+pub fn current() -> Option<String> {
+    let reload = RELOAD.get()?;
+    let current = reload.current.lock().unwrap_or_else(|err| err.into_inner());
+    Some(current.clone())
+}
+
+// whether the filter in effect lets `metadata` through, for a layer that
+// sees more than it does. everything gets through when nothing set up a
+// filter
+pub fn enabled<S>(metadata: &Metadata<'_>, ctx: &Context<'_, S>) -> bool {
+    let Some(reload) = RELOAD.get() else {
+        return true;
+    };
+
+    let mut enabled = true;
+    (reload.with_current)(&mut |filter| enabled = filter.enabled(metadata, ctx.clone()));
+    enabled
+}
+
+// takes effect right away, for every request and background task, until the
+// next `set` or `reset`
+pub fn set(directives: &str) -> Result<(), ApiError> {
+    let reload = RELOAD.get().ok_or(ApiError::NotSupported)?;
+    let filter = EnvFilter::try_new(with_runtime(directives)).map_err(|_| {
+        ApiError::Validation(vec![FieldError {
+            field: "filtro".into(),
+            message: "isn't a valid RUST_LOG filter",
+        }])
+    })?;
+
+    let mut current = reload.current.lock().unwrap_or_else(|err| err.into_inner());
+    (reload.apply)(filter).map_err(|err| {
+        tracing::error!(error = %err, "can't change the log filter");
+        ApiError::Internal
+    })?;
+    *current = directives.to_string();
+
+    Ok(())
+}
+
+// the targets of the spans and events `RUNTIME_DIRECTIVES` lets through
+pub fn is_runtime(target: &str) -> bool {
+    target == "tokio" || target.starts_with("tokio::") || target.starts_with("runtime")
+}
+
+#[cfg(feature = "console")]
+fn with_runtime(directives: &str) -> String {
+    format!("{},{}", directives, RUNTIME_DIRECTIVES)
+}
+
+#[cfg(not(feature = "console"))]
+fn with_runtime(directives: &str) -> String {
+    directives.to_string()
+}
+
+// RUST_LOG from the configuration, which may differ from the environment's
+// once the file is read. it takes over when it changed, a filter `set`
+// while running stays otherwise
+pub fn configure(directives: Option<&str>) {
+    let Some(reload) = RELOAD.get() else {
+        return;
+    };
+
+    let directives = match directives {
+        Some(directives) if EnvFilter::try_new(directives).is_ok() => directives,
+        Some(directives) => {
+            tracing::warn!(filter = %directives, "RUST_LOG isn't a valid filter, ignoring it");
+            DEFAULT_FILTER
+        }
+        None => DEFAULT_FILTER,
+    };
+
+    let mut initial = reload.initial.lock().unwrap_or_else(|err| err.into_inner());
+    if *initial == directives {
+        return;
+    }
+    *initial = directives.to_string();
+    drop(initial);
+
+    if set(directives).is_ok() {
+        tracing::info!(filter = %directives, "log filter configured");
+    }
+}
+
+// back to RUST_LOG's, once the incident is over
+pub fn reset() {
+    let Some(reload) = RELOAD.get() else {
+        return;
+    };
+
+    let initial = reload
+        .initial
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .clone();
+    if set(&initial).is_ok() {
+        tracing::info!(filter = %initial, "log filter reset");
+    }
+}
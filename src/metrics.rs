@@ -0,0 +1,130 @@
+use std::{
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::IntoResponse,
+};
+use metrics::{counter, gauge, histogram};
+use metrics_exporter_dogstatsd::DogStatsDBuilder;
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+
+use crate::{
+    config::{Config, MetricsExporter},
+    errors::ApiError,
+    repository::WalletRepository,
+};
+
+const LATENCY_BUCKETS: &[f64] = &[
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0,
+];
+
+// how often the pool gauges are pushed to statsd, which prometheus reads on
+// every scrape instead
+const POOL_REPORT_INTERVAL: Duration = Duration::from_secs(10);
+
+// `None` when METRICS_EXPORTER=statsd, there's nothing to scrape then
+static HANDLE: OnceLock<Option<PrometheusHandle>> = OnceLock::new();
+
+tokio::task_local! {
+    static ROUTE: String;
+}
+
+// the route template of the request the current task handles, for the
+// metrics recorded away from the handlers
+pub fn current_route() -> Option<String> {
+    ROUTE.try_with(|route| route.clone()).ok()
+}
+
+// the recorder is process global, so it's installed once no matter how many
+// routers get built
+pub fn install(config: &Config) -> Option<&'static PrometheusHandle> {
+    HANDLE
+        .get_or_init(|| match config.metrics_exporter {
+            MetricsExporter::Prometheus => Some(
+                PrometheusBuilder::new()
+                    .set_buckets_for_metric(
+                        Matcher::Suffix("duration_seconds".to_string()),
+                        LATENCY_BUCKETS,
+                    )
+                    .expect("invalid histogram buckets")
+                    .install_recorder()
+                    .expect("can't install metrics recorder"),
+            ),
+            // the address was checked with the rest of the configuration
+            MetricsExporter::Statsd => {
+                DogStatsDBuilder::default()
+                    .with_remote_address(&config.statsd_addr)
+                    .expect("invalid statsd address")
+                    .with_telemetry(false)
+                    // `h`, which more than the datadog agent take, unlike
+                    // the distributions
+                    .send_histograms_as_distributions(false)
+                    .install()
+                    .expect("can't install metrics recorder");
+                None
+            }
+        })
+        .as_ref()
+}
+
+// statsd is pushed to, so the pool gauges are set every once in a while
+// instead of on each scrape. must be called from within the runtime
+pub fn report_pool<R: WalletRepository>(repo: R) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(POOL_REPORT_INTERVAL);
+        loop {
+            interval.tick().await;
+            record_pool(&repo);
+        }
+    });
+}
+
+pub async fn track_metrics(req: Request, next: Next) -> impl IntoResponse {
+    let start = Instant::now();
+
+    // label by route template instead of the raw uri, otherwise every wallet
+    // id becomes its own series
+    let path = match req.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => req.uri().path().to_string(),
+    };
+    let method = req.method().to_string();
+
+    let response = ROUTE.scope(path.clone(), next.run(req)).await;
+
+    let latency = start.elapsed().as_secs_f64();
+    let status = response.status().as_u16().to_string();
+    let labels = [("method", method), ("path", path), ("status", status)];
+
+    counter!("http_requests_total", &labels).increment(1);
+    histogram!("http_requests_duration_seconds", &labels).record(latency);
+
+    response
+}
+
+pub async fn render<R: WalletRepository>(State(repo): State<R>) -> Result<String, ApiError> {
+    let handle = HANDLE
+        .get()
+        .and_then(Option::as_ref)
+        .ok_or(ApiError::NotFound)?;
+    record_pool(&repo);
+
+    Ok(handle.render())
+}
+
+fn record_pool<R: WalletRepository>(repo: &R) {
+    if let Some(pool) = repo.pool_status() {
+        gauge!("db_pool_connections", "state" => "idle").set(pool.idle as f64);
+        gauge!("db_pool_connections", "state" => "active")
+            .set((pool.size as usize).saturating_sub(pool.idle) as f64);
+        gauge!("db_pool_max_connections").set(pool.max as f64);
+    }
+}
+
+pub fn record_acquire(elapsed: std::time::Duration) {
+    histogram!("db_pool_acquire_duration_seconds").record(elapsed.as_secs_f64());
+}
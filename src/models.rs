@@ -0,0 +1,2843 @@
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, Date, Month,
+    OffsetDateTime,
+};
+use utoipa::{
+    openapi::{ObjectBuilder, RefOr, Schema, SchemaType},
+    IntoParams, ToSchema,
+};
+use uuid::Uuid;
+
+use std::{borrow::Cow, collections::BTreeMap, fmt, str::FromStr};
+
+use crate::cron::Schedule;
+
+#[derive(Clone, Deserialize, Serialize, ToSchema)]
+pub struct PostTransaction {
+    // in cents, always positive
+    #[serde(rename = "valor", alias = "value")]
+    pub value: Cents,
+    #[serde(rename = "tipo", alias = "type")]
+    pub kind: TransactionKind,
+    #[serde(rename = "descricao", alias = "description")]
+    #[schema(min_length = 1, max_length = 10, example = "padaria")]
+    pub description: String,
+    // turned away unless it's the wallet's, when given
+    #[serde(
+        rename = "moeda",
+        alias = "currency",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(min_length = 3, max_length = 3, example = "BRL")]
+    pub currency: Option<String>,
+    // the history can be filtered by both, and the summary adds up each
+    // category
+    #[serde(
+        rename = "categoria",
+        alias = "category",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(min_length = 1, max_length = 32, example = "mercado")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(max_items = 10, example = json!(["pix"]))]
+    pub tags: Vec<String>,
+    // anything the integrator wants back along with the transaction, always
+    // an object. the history can be filtered by its top level values
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>, example = json!({"pedido": "A-123"}))]
+    pub metadata: Option<Value>,
+}
+
+// what the `category` column and each of the tags keep
+const MAX_CATEGORY_CHARS: usize = 32;
+const MAX_TAGS: usize = 10;
+// serialized, it has to fit in the events' notifications with room to spare
+const MAX_METADATA_BYTES: usize = 2048;
+
+impl PostTransaction {
+    // `balance` once this transaction is applied, `None` if it doesn't fit
+    pub fn apply(&self, balance: Cents) -> Option<Cents> {
+        match self.kind {
+            TransactionKind::Credit => balance.checked_add(self.value),
+            TransactionKind::Debit => balance.checked_sub(self.value),
+        }
+    }
+
+    // how much the balance moves, negative for a debit
+    pub fn delta(&self) -> Cents {
+        self.apply(Cents::ZERO)
+            .expect("a positive value always fits below zero")
+    }
+
+    // what a retry under the same Idempotency-Key has to match, the sha256
+    // of the transaction as it's serialized. the aliases and the order of
+    // the fields in the body don't change it
+    pub fn fingerprint(&self) -> String {
+        let serialized = serde_json::to_vec(self).expect("a transaction is always serializable");
+        format!("{:x}", Sha256::digest(serialized))
+    }
+}
+
+// the body is first read with every field optional and untyped, so that all
+// the problems can be reported at once instead of stopping at the first one
+#[derive(Deserialize)]
+pub struct RawPostTransaction {
+    // the english names are accepted as well
+    #[serde(alias = "value")]
+    pub valor: Option<Value>,
+    #[serde(alias = "type")]
+    pub tipo: Option<Value>,
+    #[serde(alias = "description")]
+    pub descricao: Option<Value>,
+    #[serde(alias = "currency")]
+    pub moeda: Option<Value>,
+    #[serde(alias = "category")]
+    pub categoria: Option<Value>,
+    pub tags: Option<Value>,
+    pub metadata: Option<Value>,
+}
+
+#[derive(Serialize, Debug, Clone, ToSchema)]
+pub struct FieldError {
+    // owned only for the items of a batch, e.g. `transacoes[2].valor`
+    #[schema(value_type = String)]
+    pub field: Cow<'static, str>,
+    pub message: &'static str,
+}
+
+impl TryFrom<RawPostTransaction> for PostTransaction {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPostTransaction) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+
+        let value = match raw.valor {
+            None | Some(Value::Null) => {
+                errors.push(FieldError {
+                    field: "valor".into(),
+                    message: "is required",
+                });
+                None
+            }
+            Some(v) => match Cents::deserialize(&v).ok() {
+                Some(v) if v.is_positive() => Some(v),
+                Some(_) => {
+                    errors.push(FieldError {
+                        field: "valor".into(),
+                        message: "must be greater than zero",
+                    });
+                    None
+                }
+                None => {
+                    errors.push(FieldError {
+                        field: "valor".into(),
+                        message: AMOUNT_EXPECTED,
+                    });
+                    None
+                }
+            },
+        };
+
+        let kind = match raw.tipo {
+            None | Some(Value::Null) => {
+                errors.push(FieldError {
+                    field: "tipo".into(),
+                    message: "is required",
+                });
+                None
+            }
+            Some(v) => match v.as_str().map(TransactionKind::from_str) {
+                Some(Ok(kind)) => Some(kind),
+                _ => {
+                    errors.push(FieldError {
+                        field: "tipo".into(),
+                        message: "must be \"c\" or \"d\"",
+                    });
+                    None
+                }
+            },
+        };
+
+        let description = match raw.descricao {
+            None | Some(Value::Null) => {
+                errors.push(FieldError {
+                    field: "descricao".into(),
+                    message: "is required",
+                });
+                None
+            }
+            Some(Value::String(s)) if (1..=10).contains(&s.chars().count()) => Some(s),
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "descricao".into(),
+                    message: "must be a string between 1 and 10 characters",
+                });
+                None
+            }
+        };
+
+        let currency = parse_currency(raw.moeda, &mut errors);
+
+        let category = match raw.categoria {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) if (1..=MAX_CATEGORY_CHARS).contains(&s.chars().count()) => {
+                Some(s)
+            }
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "categoria".into(),
+                    message: "must be a string between 1 and 32 characters",
+                });
+                None
+            }
+        };
+
+        let tags = match raw.tags {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(tags)) if tags.len() <= MAX_TAGS => {
+                let tags: Option<Vec<String>> = tags
+                    .into_iter()
+                    .map(|tag| match tag {
+                        Value::String(tag)
+                            if (1..=MAX_CATEGORY_CHARS).contains(&tag.chars().count()) =>
+                        {
+                            Some(tag)
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                tags.unwrap_or_else(|| {
+                    errors.push(FieldError {
+                        field: "tags".into(),
+                        message: "each tag must be a string between 1 and 32 characters",
+                    });
+                    Vec::new()
+                })
+            }
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "tags".into(),
+                    message: "must be an array of at most 10 strings",
+                });
+                Vec::new()
+            }
+        };
+
+        let metadata = match raw.metadata {
+            None | Some(Value::Null) => None,
+            Some(metadata @ Value::Object(_))
+                if metadata.to_string().len() <= MAX_METADATA_BYTES =>
+            {
+                Some(metadata)
+            }
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "metadata".into(),
+                    message: "must be an object of at most 2048 bytes",
+                });
+                None
+            }
+        };
+
+        match (value, kind, description) {
+            (Some(value), Some(kind), Some(description)) if errors.is_empty() => {
+                Ok(PostTransaction {
+                    value,
+                    kind,
+                    description,
+                    currency,
+                    category,
+                    tags,
+                    metadata,
+                })
+            }
+            _ => Err(errors),
+        }
+    }
+}
+
+// body of `POST /clientes/:id/transacoes`, a transaction applied right away
+// unless it's scheduled for later
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PostTransactionRequest {
+    #[serde(flatten)]
+    pub transaction: PostTransaction,
+    // stored and applied at this time, with the balance checks of then
+    #[serde(
+        rename = "agendada_para",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "rfc3339::option"
+    )]
+    pub due_at: Option<OffsetDateTime>,
+}
+
+#[derive(Deserialize)]
+pub struct RawPostTransactionRequest {
+    #[serde(flatten)]
+    pub transaction: RawPostTransaction,
+    pub agendada_para: Option<Value>,
+}
+
+impl TryFrom<RawPostTransactionRequest> for PostTransactionRequest {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPostTransactionRequest) -> Result<Self, Self::Error> {
+        let (transaction, mut errors) = match PostTransaction::try_from(raw.transaction) {
+            Ok(transaction) => (Some(transaction), Vec::new()),
+            Err(errors) => (None, errors),
+        };
+
+        let due_at = match raw.agendada_para {
+            None | Some(Value::Null) => None,
+            Some(v) => match v.as_str().map(|s| OffsetDateTime::parse(s, &Rfc3339)) {
+                Some(Ok(due_at)) if due_at > OffsetDateTime::now_utc() => Some(due_at),
+                _ => {
+                    errors.push(FieldError {
+                        field: "agendada_para".into(),
+                        message: "must be an rfc 3339 timestamp in the future",
+                    });
+                    None
+                }
+            },
+        };
+
+        match transaction {
+            Some(transaction) if errors.is_empty() => Ok(PostTransactionRequest {
+                transaction,
+                due_at,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+// the most transactions a single batch can carry
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PostTransactionBatch {
+    // applied in this order
+    #[serde(rename = "transacoes")]
+    #[schema(min_items = 1, max_items = 1000)]
+    pub transactions: Vec<PostTransaction>,
+}
+
+// same idea as `RawPostTransaction`
+#[derive(Deserialize)]
+pub struct RawPostTransactionBatch {
+    pub transacoes: Option<Value>,
+}
+
+impl TryFrom<RawPostTransactionBatch> for PostTransactionBatch {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPostTransactionBatch) -> Result<Self, Self::Error> {
+        let items = match raw.transacoes {
+            Some(Value::Array(items)) if (1..=MAX_BATCH_SIZE).contains(&items.len()) => items,
+            _ => {
+                return Err(vec![FieldError {
+                    field: "transacoes".into(),
+                    message: "must be a list of 1 to 1000 transactions",
+                }])
+            }
+        };
+
+        let mut errors = Vec::new();
+        let mut transactions = Vec::with_capacity(items.len());
+
+        // the errors of each item are prefixed with its position
+        for (index, item) in items.into_iter().enumerate() {
+            let parsed = serde_json::from_value::<RawPostTransaction>(item)
+                .map_err(|_| {
+                    vec![FieldError {
+                        field: "".into(),
+                        message: "must be an object",
+                    }]
+                })
+                .and_then(PostTransaction::try_from);
+
+            match parsed {
+                Ok(transaction) => transactions.push(transaction),
+                Err(item_errors) => errors.extend(item_errors.into_iter().map(|error| {
+                    let field = match error.field.as_ref() {
+                        "" => format!("transacoes[{}]", index),
+                        field => format!("transacoes[{}].{}", index, field),
+                    };
+                    FieldError {
+                        field: field.into(),
+                        message: error.message,
+                    }
+                })),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(PostTransactionBatch { transactions })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PostWallet {
+    #[serde(rename = "limite")]
+    pub credit_limit: Cents,
+    // the client's id in some other system, unique among the wallets
+    #[serde(rename = "id_externo")]
+    #[schema(min_length = 1, max_length = 255, example = "crm-4521")]
+    pub external_id: Option<String>,
+    // `DEFAULT_CURRENCY` when not given, and never changes after
+    #[serde(rename = "moeda")]
+    #[schema(min_length = 3, max_length = 3, example = "BRL")]
+    pub currency: String,
+    // how much the wallet may debit in a day (utc), no limit when not given
+    #[serde(rename = "limite_diario")]
+    pub daily_debit_limit: Option<Cents>,
+}
+
+// same idea as `RawPostTransaction`
+#[derive(Deserialize)]
+pub struct RawPostWallet {
+    pub limite: Option<Value>,
+    pub id_externo: Option<Value>,
+    pub moeda: Option<Value>,
+    pub limite_diario: Option<Value>,
+}
+
+impl TryFrom<RawPostWallet> for PostWallet {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPostWallet) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+
+        let credit_limit = parse_credit_limit(raw.limite, &mut errors);
+
+        let external_id = match raw.id_externo {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) if (1..=255).contains(&s.chars().count()) => Some(s),
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "id_externo".into(),
+                    message: "must be a string between 1 and 255 characters",
+                });
+                None
+            }
+        };
+
+        let currency =
+            parse_currency(raw.moeda, &mut errors).unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+
+        let daily_debit_limit = parse_daily_debit_limit(raw.limite_diario, &mut errors);
+
+        match credit_limit {
+            Some(credit_limit) if errors.is_empty() => Ok(PostWallet {
+                credit_limit,
+                external_id,
+                currency,
+                daily_debit_limit,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+// body of `PATCH /clientes/:id/limite`
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PatchCreditLimit {
+    #[serde(rename = "limite")]
+    pub credit_limit: Cents,
+}
+
+#[derive(Deserialize)]
+pub struct RawPatchCreditLimit {
+    pub limite: Option<Value>,
+}
+
+impl TryFrom<RawPatchCreditLimit> for PatchCreditLimit {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPatchCreditLimit) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+
+        match parse_credit_limit(raw.limite, &mut errors) {
+            Some(credit_limit) => Ok(PatchCreditLimit { credit_limit }),
+            None => Err(errors),
+        }
+    }
+}
+
+// body of `PATCH /clientes/:id/limite_diario`, a null `limite_diario` lifts
+// the limit
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PatchDailyDebitLimit {
+    #[serde(rename = "limite_diario")]
+    pub daily_debit_limit: Option<Cents>,
+}
+
+#[derive(Deserialize)]
+pub struct RawPatchDailyDebitLimit {
+    #[serde(default, deserialize_with = "present")]
+    pub limite_diario: Option<Value>,
+}
+
+impl TryFrom<RawPatchDailyDebitLimit> for PatchDailyDebitLimit {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPatchDailyDebitLimit) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+
+        if raw.limite_diario.is_none() {
+            errors.push(FieldError {
+                field: "limite_diario".into(),
+                message: "is required, null lifts the limit",
+            });
+        }
+        let daily_debit_limit = parse_daily_debit_limit(raw.limite_diario, &mut errors);
+
+        if errors.is_empty() {
+            Ok(PatchDailyDebitLimit { daily_debit_limit })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// body of `PATCH /clientes/:id/juros`, a null `taxa_diaria` leaves
+// the wallet to INTEREST_DAILY_RATE
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PatchInterestRate {
+    #[serde(rename = "taxa_diaria")]
+    #[schema(minimum = 0, maximum = 1, example = 0.001)]
+    pub interest_rate: Option<f64>,
+}
+
+#[derive(Deserialize)]
+pub struct RawPatchInterestRate {
+    #[serde(default, deserialize_with = "present")]
+    pub taxa_diaria: Option<Value>,
+}
+
+impl TryFrom<RawPatchInterestRate> for PatchInterestRate {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPatchInterestRate) -> Result<Self, Self::Error> {
+        let interest_rate = match raw.taxa_diaria {
+            None => {
+                return Err(vec![FieldError {
+                    field: "taxa_diaria".into(),
+                    message: "is required, null leaves the wallet to the default rate",
+                }])
+            }
+            Some(Value::Null) => None,
+            Some(v) => match v.as_f64() {
+                Some(rate) if (0.0..=1.0).contains(&rate) => Some(rate),
+                _ => {
+                    return Err(vec![FieldError {
+                        field: "taxa_diaria".into(),
+                        message: "must be a number between 0 and 1",
+                    }])
+                }
+            },
+        };
+
+        Ok(PatchInterestRate { interest_rate })
+    }
+}
+
+// `Some(Value::Null)` for a key given as null, which a plain `Option<Value>`
+// can't tell from a missing one
+fn present<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Value>, D::Error> {
+    Value::deserialize(deserializer).map(Some)
+}
+
+// the currency of a wallet that doesn't name one, and of every wallet from
+// before they could
+pub const DEFAULT_CURRENCY: &str = "BRL";
+
+// an ISO 4217 code like `BRL`. `moeda` is always optional, so a missing one
+// isn't an error
+fn parse_currency(value: Option<Value>, errors: &mut Vec<FieldError>) -> Option<String> {
+    match value {
+        None | Some(Value::Null) => None,
+        Some(Value::String(s)) if s.len() == 3 && s.bytes().all(|b| b.is_ascii_uppercase()) => {
+            Some(s)
+        }
+        Some(_) => {
+            errors.push(FieldError {
+                field: "moeda".into(),
+                message: "must be a three letter currency code like \"BRL\"",
+            });
+            None
+        }
+    }
+}
+
+fn parse_credit_limit(value: Option<Value>, errors: &mut Vec<FieldError>) -> Option<Cents> {
+    match value {
+        None | Some(Value::Null) => {
+            errors.push(FieldError {
+                field: "limite".into(),
+                message: "is required",
+            });
+            None
+        }
+        Some(v) => match Cents::deserialize(&v).ok() {
+            Some(v) if !v.is_negative() => Some(v),
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "limite".into(),
+                    message: "must not be negative",
+                });
+                None
+            }
+            None => {
+                errors.push(FieldError {
+                    field: "limite".into(),
+                    message: AMOUNT_EXPECTED,
+                });
+                None
+            }
+        },
+    }
+}
+
+// optional everywhere, null or missing means no limit
+fn parse_daily_debit_limit(value: Option<Value>, errors: &mut Vec<FieldError>) -> Option<Cents> {
+    match value {
+        None | Some(Value::Null) => None,
+        Some(v) => match Cents::deserialize(&v).ok() {
+            Some(v) if !v.is_negative() => Some(v),
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "limite_diario".into(),
+                    message: "must not be negative",
+                });
+                None
+            }
+            None => {
+                errors.push(FieldError {
+                    field: "limite_diario".into(),
+                    message: AMOUNT_EXPECTED,
+                });
+                None
+            }
+        },
+    }
+}
+
+// body of `POST /transferencias`
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PostTransfer {
+    #[serde(rename = "de")]
+    #[schema(example = 1)]
+    pub from: i32,
+    #[serde(rename = "para")]
+    #[schema(example = 2)]
+    pub to: i32,
+    #[serde(rename = "valor")]
+    pub value: Cents,
+    #[serde(rename = "descricao")]
+    #[schema(min_length = 1, max_length = 10, example = "aluguel")]
+    pub description: String,
+    // both wallets hold the same one whether or not it's given, and it's
+    // that one when it is
+    #[serde(rename = "moeda", skip_serializing_if = "Option::is_none")]
+    #[schema(min_length = 3, max_length = 3, example = "BRL")]
+    pub currency: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RawPostTransfer {
+    pub de: Option<Value>,
+    pub para: Option<Value>,
+    pub valor: Option<Value>,
+    pub descricao: Option<Value>,
+    pub moeda: Option<Value>,
+}
+
+impl TryFrom<RawPostTransfer> for PostTransfer {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPostTransfer) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+
+        let from = parse_wallet_id(raw.de, "de", &mut errors);
+        let to = parse_wallet_id(raw.para, "para", &mut errors);
+
+        if from.is_some() && from == to {
+            errors.push(FieldError {
+                field: "para".into(),
+                message: "must be a different client than de",
+            });
+        }
+
+        // the amount and the description follow the same rules as a debit
+        let transaction = match PostTransaction::try_from(RawPostTransaction {
+            valor: raw.valor,
+            tipo: Some(Value::from("d")),
+            descricao: raw.descricao,
+            moeda: raw.moeda,
+            categoria: None,
+            tags: None,
+            metadata: None,
+        }) {
+            Ok(transaction) => Some(transaction),
+            Err(transaction_errors) => {
+                errors.extend(transaction_errors);
+                None
+            }
+        };
+
+        match (from, to, transaction) {
+            (Some(from), Some(to), Some(transaction)) if errors.is_empty() => Ok(PostTransfer {
+                from,
+                to,
+                value: transaction.value,
+                description: transaction.description,
+                currency: transaction.currency,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+// body of `POST /clientes/:id/webhooks`
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PostWebhook {
+    #[schema(max_length = 2048, example = "https://example.com/rinha")]
+    pub url: String,
+    // signs the deliveries, one is made up when it's left out. only ever
+    // shown in the response to this request
+    #[serde(rename = "segredo", skip_serializing_if = "Option::is_none")]
+    #[schema(min_length = 16, max_length = 255)]
+    pub secret: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RawPostWebhook {
+    pub url: Option<Value>,
+    pub segredo: Option<Value>,
+}
+
+impl TryFrom<RawPostWebhook> for PostWebhook {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPostWebhook) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+
+        let url = match raw.url {
+            None | Some(Value::Null) => {
+                errors.push(FieldError {
+                    field: "url".into(),
+                    message: "is required",
+                });
+                None
+            }
+            Some(Value::String(s))
+                if s.len() <= 2048
+                    && (s.starts_with("http://") || s.starts_with("https://"))
+                    && !s.contains(char::is_whitespace) =>
+            {
+                Some(s)
+            }
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "url".into(),
+                    message: "must be an http or https url of up to 2048 characters",
+                });
+                None
+            }
+        };
+
+        let secret = match raw.segredo {
+            None | Some(Value::Null) => None,
+            Some(Value::String(s)) if (16..=255).contains(&s.chars().count()) => Some(s),
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "segredo".into(),
+                    message: "must be a string between 16 and 255 characters",
+                });
+                None
+            }
+        };
+
+        match url {
+            Some(url) if errors.is_empty() => Ok(PostWebhook { url, secret }),
+            _ => Err(errors),
+        }
+    }
+}
+
+// how long a hold lasts by default, 7 days, and the longest it can be for,
+// 30 days
+pub const DEFAULT_HOLD_TTL_SECS: u64 = 7 * 24 * 60 * 60;
+pub const MAX_HOLD_TTL_SECS: u64 = 30 * 24 * 60 * 60;
+
+// body of `POST /clientes/:id/holds`
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PostHold {
+    #[serde(rename = "valor")]
+    pub value: Cents,
+    #[serde(rename = "descricao")]
+    #[schema(min_length = 1, max_length = 10, example = "hotel")]
+    pub description: String,
+    #[serde(rename = "moeda", skip_serializing_if = "Option::is_none")]
+    #[schema(min_length = 3, max_length = 3, example = "BRL")]
+    pub currency: Option<String>,
+    // HOLD_TTL_SECS when it's left out
+    #[serde(rename = "expira_em_segundos", skip_serializing_if = "Option::is_none")]
+    #[schema(minimum = 1, maximum = 2592000, example = 3600)]
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct RawPostHold {
+    pub valor: Option<Value>,
+    pub descricao: Option<Value>,
+    pub moeda: Option<Value>,
+    pub expira_em_segundos: Option<Value>,
+}
+
+impl TryFrom<RawPostHold> for PostHold {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPostHold) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+
+        // it's captured into a debit, so it follows the rules of one
+        let transaction = match PostTransaction::try_from(RawPostTransaction {
+            valor: raw.valor,
+            tipo: Some(Value::from("d")),
+            descricao: raw.descricao,
+            moeda: raw.moeda,
+            categoria: None,
+            tags: None,
+            metadata: None,
+        }) {
+            Ok(transaction) => Some(transaction),
+            Err(transaction_errors) => {
+                errors.extend(transaction_errors);
+                None
+            }
+        };
+
+        let ttl_secs = match raw.expira_em_segundos {
+            None | Some(Value::Null) => None,
+            Some(v) => match v.as_u64() {
+                Some(secs) if (1..=MAX_HOLD_TTL_SECS).contains(&secs) => Some(secs),
+                _ => {
+                    errors.push(FieldError {
+                        field: "expira_em_segundos".into(),
+                        message: "must be a whole number of seconds between 1 and 2592000",
+                    });
+                    None
+                }
+            },
+        };
+
+        match transaction {
+            Some(transaction) if errors.is_empty() => Ok(PostHold {
+                value: transaction.value,
+                description: transaction.description,
+                currency: transaction.currency,
+                ttl_secs,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+// body of `POST /clientes/:id/recorrencias`, and of the `PUT` that replaces
+// one
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PostRecurrence {
+    #[serde(rename = "valor")]
+    pub value: Cents,
+    #[serde(rename = "tipo")]
+    pub kind: TransactionKind,
+    #[serde(rename = "descricao")]
+    #[schema(min_length = 1, max_length = 10, example = "aluguel")]
+    pub description: String,
+    #[serde(rename = "moeda", skip_serializing_if = "Option::is_none")]
+    #[schema(min_length = 3, max_length = 3, example = "BRL")]
+    pub currency: Option<String>,
+    // carried over to the transactions it makes
+    #[serde(rename = "categoria", default, skip_serializing_if = "Option::is_none")]
+    #[schema(min_length = 1, max_length = 32, example = "moradia")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(max_items = 10)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub metadata: Option<Value>,
+    #[serde(rename = "cron")]
+    #[schema(value_type = String, example = "0 9 5 * *")]
+    pub schedule: Schedule,
+    // a paused one keeps its definition but isn't applied
+    #[serde(rename = "ativa", default = "active_by_default")]
+    #[schema(default = true)]
+    pub active: bool,
+}
+
+fn active_by_default() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct RawPostRecurrence {
+    #[serde(flatten)]
+    pub transaction: RawPostTransaction,
+    pub cron: Option<Value>,
+    pub ativa: Option<Value>,
+}
+
+impl TryFrom<RawPostRecurrence> for PostRecurrence {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPostRecurrence) -> Result<Self, Self::Error> {
+        let (transaction, mut errors) = match PostTransaction::try_from(raw.transaction) {
+            Ok(transaction) => (Some(transaction), Vec::new()),
+            Err(errors) => (None, errors),
+        };
+
+        let schedule = match raw.cron {
+            None | Some(Value::Null) => {
+                errors.push(FieldError {
+                    field: "cron".into(),
+                    message: "is required",
+                });
+                None
+            }
+            Some(v) => match v.as_str().map(Schedule::from_str) {
+                // one that never comes up would sit there forever
+                Some(Ok(schedule)) if schedule.next_after(OffsetDateTime::now_utc()).is_some() => {
+                    Some(schedule)
+                }
+                _ => {
+                    errors.push(FieldError {
+                        field: "cron".into(),
+                        message:
+                            "must be a five field crontab schedule that comes up, e.g. `0 9 5 * *`",
+                    });
+                    None
+                }
+            },
+        };
+
+        let active = match raw.ativa {
+            None | Some(Value::Null) => true,
+            Some(Value::Bool(active)) => active,
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "ativa".into(),
+                    message: "must be a boolean",
+                });
+                true
+            }
+        };
+
+        match (transaction, schedule) {
+            (Some(transaction), Some(schedule)) if errors.is_empty() => Ok(PostRecurrence {
+                value: transaction.value,
+                kind: transaction.kind,
+                description: transaction.description,
+                currency: transaction.currency,
+                category: transaction.category,
+                tags: transaction.tags,
+                metadata: transaction.metadata,
+                schedule,
+                active,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+// body of `POST /admin/tarifas`, and of the `PUT` that replaces a rule
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct PostFeeRule {
+    // the kind of the transactions it's charged for
+    #[serde(rename = "tipo")]
+    pub kind: TransactionKind,
+    // those of a lower value go free
+    #[serde(rename = "valor_minimo", skip_serializing_if = "Option::is_none")]
+    pub min_value: Option<Cents>,
+    #[serde(rename = "fixa", default)]
+    pub flat: Cents,
+    // of the transaction's value, rounded to the cent
+    #[serde(rename = "percentual", default)]
+    #[schema(minimum = 0, maximum = 1, example = 0.01)]
+    pub percentage: f64,
+    // of the fee's own transaction
+    #[serde(rename = "descricao")]
+    #[schema(min_length = 1, max_length = 10, example = "tarifa")]
+    pub description: String,
+    #[serde(rename = "ativa", default = "active_by_default")]
+    #[schema(default = true)]
+    pub active: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RawPostFeeRule {
+    pub tipo: Option<Value>,
+    pub valor_minimo: Option<Value>,
+    pub fixa: Option<Value>,
+    pub percentual: Option<Value>,
+    pub descricao: Option<Value>,
+    pub ativa: Option<Value>,
+}
+
+impl TryFrom<RawPostFeeRule> for PostFeeRule {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawPostFeeRule) -> Result<Self, Self::Error> {
+        let mut errors = Vec::new();
+
+        let kind = match raw.tipo {
+            None | Some(Value::Null) => {
+                errors.push(FieldError {
+                    field: "tipo".into(),
+                    message: "is required",
+                });
+                None
+            }
+            Some(v) => match v.as_str().map(TransactionKind::from_str) {
+                Some(Ok(kind)) => Some(kind),
+                _ => {
+                    errors.push(FieldError {
+                        field: "tipo".into(),
+                        message: "must be \"c\" or \"d\"",
+                    });
+                    None
+                }
+            },
+        };
+
+        let min_value = parse_fee_amount(raw.valor_minimo, "valor_minimo", &mut errors);
+        let flat = parse_fee_amount(raw.fixa, "fixa", &mut errors).unwrap_or_default();
+
+        let percentage = match raw.percentual {
+            None | Some(Value::Null) => 0.0,
+            Some(v) => match v.as_f64() {
+                Some(percentage) if (0.0..=1.0).contains(&percentage) => percentage,
+                _ => {
+                    errors.push(FieldError {
+                        field: "percentual".into(),
+                        message: "must be a number between 0 and 1",
+                    });
+                    0.0
+                }
+            },
+        };
+
+        let description = match raw.descricao {
+            None | Some(Value::Null) => {
+                errors.push(FieldError {
+                    field: "descricao".into(),
+                    message: "is required",
+                });
+                None
+            }
+            Some(Value::String(s)) if (1..=10).contains(&s.chars().count()) => Some(s),
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "descricao".into(),
+                    message: "must be a string between 1 and 10 characters",
+                });
+                None
+            }
+        };
+
+        let active = match raw.ativa {
+            None | Some(Value::Null) => true,
+            Some(Value::Bool(active)) => active,
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "ativa".into(),
+                    message: "must be a boolean",
+                });
+                true
+            }
+        };
+
+        match (kind, description) {
+            (Some(kind), Some(description)) if errors.is_empty() => Ok(PostFeeRule {
+                kind,
+                min_value,
+                flat,
+                percentage,
+                description,
+                active,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+// body of `PUT /admin/log-level`, and what `GET` answers. the directives are
+// RUST_LOG's, like `info,rinha_rust=debug`
+#[derive(Deserialize, Serialize, ToSchema)]
+pub struct LogLevel {
+    #[serde(rename = "filtro")]
+    #[schema(example = "info,rinha_rust=debug")]
+    pub filter: String,
+}
+
+#[derive(Deserialize)]
+pub struct RawLogLevel {
+    #[serde(alias = "filter")]
+    pub filtro: Option<Value>,
+}
+
+impl TryFrom<RawLogLevel> for LogLevel {
+    type Error = Vec<FieldError>;
+
+    fn try_from(raw: RawLogLevel) -> Result<Self, Self::Error> {
+        match raw.filtro {
+            Some(Value::String(filter)) if !filter.trim().is_empty() => Ok(LogLevel { filter }),
+            _ => Err(vec![FieldError {
+                field: "filtro".into(),
+                message: "is required, a string of RUST_LOG directives",
+            }]),
+        }
+    }
+}
+
+// none when it's not given
+fn parse_fee_amount(
+    value: Option<Value>,
+    field: &'static str,
+    errors: &mut Vec<FieldError>,
+) -> Option<Cents> {
+    let v = value.filter(|v| !v.is_null())?;
+    match Cents::deserialize(&v).ok() {
+        Some(v) if !v.is_negative() => Some(v),
+        Some(_) => {
+            errors.push(FieldError {
+                field: field.into(),
+                message: "must not be negative",
+            });
+            None
+        }
+        None => {
+            errors.push(FieldError {
+                field: field.into(),
+                message: AMOUNT_EXPECTED,
+            });
+            None
+        }
+    }
+}
+
+fn parse_wallet_id(
+    value: Option<Value>,
+    field: &'static str,
+    errors: &mut Vec<FieldError>,
+) -> Option<i32> {
+    match value {
+        None | Some(Value::Null) => {
+            errors.push(FieldError {
+                field: field.into(),
+                message: "is required",
+            });
+            None
+        }
+        Some(v) => match v.as_i64().and_then(|v| i32::try_from(v).ok()) {
+            Some(id) => Some(id),
+            None => {
+                errors.push(FieldError {
+                    field: field.into(),
+                    message: "must be a client id",
+                });
+                None
+            }
+        },
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StatementQuery {
+    // only transactions from this day on, `YYYY-MM-DD`
+    #[param(format = Date, example = "2024-01-01")]
+    pub de: Option<String>,
+    // only transactions before this day, `YYYY-MM-DD`
+    #[param(format = Date, example = "2024-02-01")]
+    pub ate: Option<String>,
+    // only credits (`c`) or only debits (`d`)
+    #[param(example = "d")]
+    pub tipo: Option<String>,
+}
+
+impl StatementQuery {
+    // `de` is inclusive and `ate` is exclusive, both taken as midnight UTC, so
+    // `?de=2024-01-01&ate=2024-02-01` is exactly january
+    pub fn filter(&self) -> Result<StatementFilter, Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let from = parse_date(self.de.as_deref(), "de", &mut errors);
+        let to = parse_date(self.ate.as_deref(), "ate", &mut errors);
+
+        let kind = match self.tipo.as_deref().map(TransactionKind::from_str) {
+            None => None,
+            Some(Ok(kind)) => Some(kind),
+            Some(Err(_)) => {
+                errors.push(FieldError {
+                    field: "tipo".into(),
+                    message: "must be \"c\" or \"d\"",
+                });
+                None
+            }
+        };
+
+        if let (Some(from), Some(to)) = (from, to) {
+            if from > to {
+                errors.push(FieldError {
+                    field: "ate".into(),
+                    message: "must not be before de",
+                });
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(StatementFilter { from, to, kind })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// transactions per page of the full history
+pub const DEFAULT_HISTORY_PAGE_SIZE: u32 = 100;
+pub const MAX_HISTORY_PAGE_SIZE: u32 = 1000;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    // the `proximo` of the previous page, leave it out for the first one
+    pub depois: Option<String>,
+    #[param(value_type = Option<u32>, minimum = 1, maximum = 1000, example = 100)]
+    pub limite: Option<String>,
+    // only transactions whose description contains this, ignoring case
+    #[param(min_length = 1, max_length = 10, example = "mercado")]
+    pub q: Option<String>,
+    // only transactions of this category
+    #[param(min_length = 1, max_length = 32, example = "mercado")]
+    pub categoria: Option<String>,
+    // only transactions tagged with this
+    #[param(min_length = 1, max_length = 32, example = "pix")]
+    pub tag: Option<String>,
+}
+
+// the prefix of the query parameters that filter by a metadata key, e.g.
+// `metadata.pedido=A-123`. they can't be fields of `HistoryQuery`, so they're
+// handed to `filter` on their own
+pub const METADATA_PARAM_PREFIX: &str = "metadata.";
+
+impl HistoryQuery {
+    // `params` are all of the query's, those that aren't metadata filters are
+    // left alone
+    pub fn filter(&self, params: &[(String, String)]) -> Result<HistoryFilter, Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let after = match self.depois.as_deref().map(HistoryCursor::from_str) {
+            None => None,
+            Some(Ok(cursor)) => Some(cursor),
+            Some(Err(_)) => {
+                errors.push(FieldError {
+                    field: "depois".into(),
+                    message: "must be the proximo of a previous page",
+                });
+                None
+            }
+        };
+
+        let limit = match self.limite.as_deref().map(u32::from_str) {
+            None => DEFAULT_HISTORY_PAGE_SIZE,
+            Some(Ok(limit)) if (1..=MAX_HISTORY_PAGE_SIZE).contains(&limit) => limit,
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "limite".into(),
+                    message: "must be an integer between 1 and 1000",
+                });
+                DEFAULT_HISTORY_PAGE_SIZE
+            }
+        };
+
+        let search = match &self.q {
+            None => None,
+            Some(q) if (1..=10).contains(&q.chars().count()) => Some(q.clone()),
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "q".into(),
+                    message: "must be between 1 and 10 characters",
+                });
+                None
+            }
+        };
+
+        let category = parse_label(self.categoria.as_deref(), "categoria", &mut errors);
+        let tag = parse_label(self.tag.as_deref(), "tag", &mut errors);
+
+        let mut metadata = BTreeMap::new();
+        for (name, value) in params {
+            let Some(key) = name.strip_prefix(METADATA_PARAM_PREFIX) else {
+                continue;
+            };
+            if key.is_empty() || value.chars().count() > MAX_METADATA_VALUE_CHARS {
+                errors.push(FieldError {
+                    field: name.clone().into(),
+                    message: "must name a key, with a value of up to 255 characters",
+                });
+                continue;
+            }
+            metadata.insert(key.to_string(), value.clone());
+        }
+
+        if errors.is_empty() {
+            Ok(HistoryFilter {
+                after,
+                limit,
+                search,
+                category,
+                tag,
+                metadata,
+            })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HistoryFilter {
+    pub after: Option<HistoryCursor>,
+    pub limit: u32,
+    pub search: Option<String>,
+    pub category: Option<String>,
+    pub tag: Option<String>,
+    // top level key and value, all of them have to match
+    pub metadata: BTreeMap<String, String>,
+}
+
+const MAX_METADATA_VALUE_CHARS: usize = 255;
+
+// a category or a tag to filter by
+fn parse_label(
+    value: Option<&str>,
+    field: &'static str,
+    errors: &mut Vec<FieldError>,
+) -> Option<String> {
+    match value {
+        None => None,
+        Some(label) if (1..=MAX_CATEGORY_CHARS).contains(&label.chars().count()) => {
+            Some(label.to_string())
+        }
+        Some(_) => {
+            errors.push(FieldError {
+                field: field.into(),
+                message: "must be between 1 and 32 characters",
+            });
+            None
+        }
+    }
+}
+
+impl HistoryFilter {
+    // `search` as a LIKE pattern, with its own wildcards taken literally
+    pub fn search_pattern(&self) -> Option<String> {
+        let search = self.search.as_ref()?;
+        let escaped = search
+            .replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_");
+        Some(format!("%{}%", escaped))
+    }
+
+    // whether `metadata` has every value the filter asks for, compared like
+    // postgres' `->>`: strings as they are, anything else as json
+    pub fn matches_metadata(&self, metadata: Option<&Value>) -> bool {
+        self.metadata.iter().all(|(key, wanted)| {
+            match metadata.and_then(|metadata| metadata.get(key)) {
+                None | Some(Value::Null) => false,
+                Some(Value::String(value)) => value == wanted,
+                Some(value) => *wanted == format!("{value}"),
+            }
+        })
+    }
+}
+
+// the `(inserted_at, id)` of the last row of a page, the next one starts
+// right after it. `id` is the row's position in the table, not the public
+// uuid. clients get it as `<unix nanos>_<id>` and shouldn't look inside
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HistoryCursor {
+    pub inserted_at: OffsetDateTime,
+    pub id: i64,
+}
+
+impl fmt::Display for HistoryCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}_{}", self.inserted_at.unix_timestamp_nanos(), self.id)
+    }
+}
+
+impl FromStr for HistoryCursor {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (nanos, id) = s.split_once('_').ok_or(())?;
+        let nanos = i128::from_str(nanos).map_err(|_| ())?;
+
+        Ok(HistoryCursor {
+            inserted_at: OffsetDateTime::from_unix_timestamp_nanos(nanos).map_err(|_| ())?,
+            id: i64::from_str(id).map_err(|_| ())?,
+        })
+    }
+}
+
+impl Serialize for HistoryCursor {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+// body of `GET /clientes/:id/transacoes`, oldest first
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct HistoryPage {
+    #[serde(rename = "transacoes")]
+    pub transactions: Vec<TransactionDetails>,
+    // `null` on the last page
+    #[serde(rename = "proximo")]
+    #[schema(value_type = Option<String>, example = "1709251200000000000_42")]
+    pub next: Option<HistoryCursor>,
+    // each transaction's own, for the graphql edges
+    #[serde(skip)]
+    pub cursors: Vec<HistoryCursor>,
+}
+
+// audit log rows per page of `GET /admin/audit`
+pub const DEFAULT_AUDIT_PAGE_SIZE: u32 = 100;
+pub const MAX_AUDIT_PAGE_SIZE: u32 = 1000;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    // only the rows of this client
+    #[param(value_type = Option<i32>, example = 1)]
+    pub cliente_id: Option<String>,
+    // `wallet_created`, `transaction_created` or `credit_limit_changed`
+    #[param(example = "credit_limit_changed")]
+    pub acao: Option<String>,
+    // e.g. `api_key:` and the start of the key's hash, `jwt:` and the
+    // token's subject, `anonymous` or `system`
+    pub ator: Option<String>,
+    // the `x-request-id` of the request that made the change
+    pub requisicao_id: Option<String>,
+    // only rows from this day on, `YYYY-MM-DD`
+    #[param(format = Date, example = "2024-01-01")]
+    pub de: Option<String>,
+    // only rows before this day, `YYYY-MM-DD`
+    #[param(format = Date, example = "2024-02-01")]
+    pub ate: Option<String>,
+    // the `proximo` of the previous page, leave it out for the first one
+    #[param(value_type = Option<i64>)]
+    pub antes: Option<String>,
+    #[param(value_type = Option<u32>, minimum = 1, maximum = 1000, example = 100)]
+    pub limite: Option<String>,
+}
+
+impl AuditQuery {
+    pub fn filter(&self) -> Result<AuditFilter, Vec<FieldError>> {
+        let mut errors = Vec::new();
+
+        let wallet_id = match self.cliente_id.as_deref().map(i32::from_str) {
+            None => None,
+            Some(Ok(id)) => Some(id),
+            Some(Err(_)) => {
+                errors.push(FieldError {
+                    field: "cliente_id".into(),
+                    message: "must be a client id",
+                });
+                None
+            }
+        };
+
+        let from = parse_date(self.de.as_deref(), "de", &mut errors);
+        let to = parse_date(self.ate.as_deref(), "ate", &mut errors);
+        if let (Some(from), Some(to)) = (from, to) {
+            if from > to {
+                errors.push(FieldError {
+                    field: "ate".into(),
+                    message: "must not be before de",
+                });
+            }
+        }
+
+        let before = match self.antes.as_deref().map(i64::from_str) {
+            None => None,
+            Some(Ok(id)) => Some(id),
+            Some(Err(_)) => {
+                errors.push(FieldError {
+                    field: "antes".into(),
+                    message: "must be the proximo of a previous page",
+                });
+                None
+            }
+        };
+
+        let limit = match self.limite.as_deref().map(u32::from_str) {
+            None => DEFAULT_AUDIT_PAGE_SIZE,
+            Some(Ok(limit)) if (1..=MAX_AUDIT_PAGE_SIZE).contains(&limit) => limit,
+            Some(_) => {
+                errors.push(FieldError {
+                    field: "limite".into(),
+                    message: "must be an integer between 1 and 1000",
+                });
+                DEFAULT_AUDIT_PAGE_SIZE
+            }
+        };
+
+        if errors.is_empty() {
+            Ok(AuditFilter {
+                wallet_id,
+                action: self.acao.clone(),
+                actor: self.ator.clone(),
+                request_id: self.requisicao_id.clone(),
+                from,
+                to,
+                before,
+                limit,
+            })
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+// `from` is inclusive and `to` exclusive, like in `StatementFilter`
+#[derive(Debug, Clone)]
+pub struct AuditFilter {
+    pub wallet_id: Option<i32>,
+    pub action: Option<String>,
+    pub actor: Option<String>,
+    pub request_id: Option<String>,
+    pub from: Option<OffsetDateTime>,
+    pub to: Option<OffsetDateTime>,
+    // the pages go from the newest row back, each starting below this id
+    pub before: Option<i64>,
+    pub limit: u32,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditEntry {
+    pub id: i64,
+    #[serde(rename = "cliente_id")]
+    pub wallet_id: i32,
+    #[serde(rename = "acao")]
+    #[schema(example = "credit_limit_changed")]
+    pub action: String,
+    // `null` when there was nothing before, like for a new transaction
+    #[serde(rename = "antes")]
+    #[schema(value_type = Option<Object>)]
+    pub old_value: Option<Value>,
+    #[serde(rename = "depois")]
+    #[schema(value_type = Option<Object>)]
+    pub new_value: Option<Value>,
+    #[serde(rename = "ator")]
+    #[schema(example = "jwt:1")]
+    pub actor: String,
+    #[serde(rename = "requisicao_id")]
+    pub request_id: Option<String>,
+    #[serde(rename = "criado_em", with = "rfc3339")]
+    pub inserted_at: OffsetDateTime,
+}
+
+// body of `GET /admin/audit`, newest first
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AuditPage {
+    #[serde(rename = "entradas")]
+    pub entries: Vec<AuditEntry>,
+    // `null` on the last page
+    #[serde(rename = "proximo")]
+    #[schema(example = 42)]
+    pub next: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SummaryQuery {
+    // `YYYY-MM`, the current month in UTC when left out
+    #[param(example = "2024-02")]
+    pub mes: Option<String>,
+}
+
+impl SummaryQuery {
+    pub fn month(&self) -> Result<SummaryMonth, Vec<FieldError>> {
+        let Some(mes) = self.mes.as_deref() else {
+            let today = OffsetDateTime::now_utc().date();
+            return Ok(SummaryMonth {
+                year: today.year(),
+                month: today.month(),
+            });
+        };
+
+        let parsed = mes.split_once('-').and_then(|(year, month)| {
+            if year.len() != 4 || month.len() != 2 {
+                return None;
+            }
+            Some(SummaryMonth {
+                year: year.parse().ok()?,
+                month: Month::try_from(month.parse::<u8>().ok()?).ok()?,
+            })
+        });
+
+        parsed.ok_or_else(|| {
+            vec![FieldError {
+                field: "mes".into(),
+                message: "must be a month in the YYYY-MM format",
+            }]
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SummaryMonth {
+    pub year: i32,
+    pub month: Month,
+}
+
+impl SummaryMonth {
+    // from midnight UTC of the first day to that of the next month, the end
+    // excluded
+    pub fn range(&self) -> (OffsetDateTime, OffsetDateTime) {
+        let start = |year, month| {
+            Date::from_calendar_date(year, month, 1)
+                .expect("the first of a month is always a valid date")
+                .midnight()
+                .assume_utc()
+        };
+        let next_year = match self.month {
+            Month::December => self.year + 1,
+            _ => self.year,
+        };
+
+        (
+            start(self.year, self.month),
+            start(next_year, self.month.next()),
+        )
+    }
+}
+
+impl fmt::Display for SummaryMonth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month as u8)
+    }
+}
+
+fn parse_date(
+    value: Option<&str>,
+    field: &'static str,
+    errors: &mut Vec<FieldError>,
+) -> Option<OffsetDateTime> {
+    match Date::parse(value?, format_description!("[year]-[month]-[day]")) {
+        Ok(date) => Some(date.midnight().assume_utc()),
+        Err(_) => {
+            errors.push(FieldError {
+                field: field.into(),
+                message: "must be a date in the YYYY-MM-DD format",
+            });
+            None
+        }
+    }
+}
+
+// the number inside `Cents`. with the `decimal` feature it's a `Decimal`, so
+// amounts can have fractions of a cent. those go over the wire as strings,
+// `"10.25"`, since a json number would get them through an f64
+#[cfg(not(feature = "decimal"))]
+pub type Amount = i64;
+#[cfg(feature = "decimal")]
+pub type Amount = rust_decimal::Decimal;
+
+// what a malformed amount is told
+#[cfg(not(feature = "decimal"))]
+const AMOUNT_EXPECTED: &str = "must be an integer";
+#[cfg(feature = "decimal")]
+const AMOUNT_EXPECTED: &str = "must be an integer or a decimal string";
+
+// an amount of money in cents. the amounts of transactions are always
+// positive, balances go as far below zero as their credit limit. on the wire
+// and in the database it's the bare number
+#[derive(sqlx::Type, Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(
+    not(feature = "decimal"),
+    derive(Serialize, Deserialize),
+    serde(transparent)
+)]
+#[sqlx(transparent)]
+pub struct Cents(pub Amount);
+
+impl Cents {
+    #[cfg(not(feature = "decimal"))]
+    pub const ZERO: Cents = Cents(0);
+    #[cfg(feature = "decimal")]
+    pub const ZERO: Cents = Cents(rust_decimal::Decimal::ZERO);
+
+    pub fn checked_add(self, other: Cents) -> Option<Cents> {
+        self.0.checked_add(other.0).map(Cents)
+    }
+
+    pub fn checked_sub(self, other: Cents) -> Option<Cents> {
+        self.0.checked_sub(other.0).map(Cents)
+    }
+
+    // for totals, and for comparing a balance with its limit: a sum that
+    // doesn't fit is past any bound it's checked against
+    pub fn saturating_add(self, other: Cents) -> Cents {
+        Cents(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Cents) -> Cents {
+        Cents(self.0.saturating_sub(other.0))
+    }
+
+    pub fn is_positive(self) -> bool {
+        self > Cents::ZERO
+    }
+
+    pub fn is_negative(self) -> bool {
+        self < Cents::ZERO
+    }
+
+    // for the grpc and graphql apis, which only carry whole cents. `None`
+    // for an amount with a fraction of one
+    #[cfg(not(feature = "decimal"))]
+    pub fn to_i64(self) -> Option<i64> {
+        Some(self.0)
+    }
+
+    #[cfg(feature = "decimal")]
+    pub fn to_i64(self) -> Option<i64> {
+        self.0
+            .is_integer()
+            .then(|| i64::try_from(self.0).ok())
+            .flatten()
+    }
+}
+
+impl From<i64> for Cents {
+    #[cfg(not(feature = "decimal"))]
+    fn from(cents: i64) -> Self {
+        Cents(cents)
+    }
+
+    #[cfg(feature = "decimal")]
+    fn from(cents: i64) -> Self {
+        Cents(cents.into())
+    }
+}
+
+// `-12.34`, for people. the api keeps sending the bare number
+impl fmt::Display for Cents {
+    #[cfg(not(feature = "decimal"))]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.is_negative() { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+
+    // rounded to the cent
+    #[cfg(feature = "decimal")]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.2}", self.0 / rust_decimal::Decimal::ONE_HUNDRED)
+    }
+}
+
+impl<'s> ToSchema<'s> for Cents {
+    #[cfg(not(feature = "decimal"))]
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        use utoipa::openapi::{KnownFormat, SchemaFormat};
+
+        let schema = ObjectBuilder::new()
+            .schema_type(SchemaType::Integer)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::Int64)))
+            .description(Some("in cents"));
+        ("Cents", schema.into())
+    }
+
+    #[cfg(feature = "decimal")]
+    fn schema() -> (&'s str, RefOr<Schema>) {
+        let schema = ObjectBuilder::new()
+            .schema_type(SchemaType::String)
+            .pattern(Some(r"^-?\d+(\.\d+)?$"))
+            .description(Some("in cents, an integer is taken as well"))
+            .example(Some("10.25".into()));
+        ("Cents", schema.into())
+    }
+}
+
+// an integer, or a string of digits with an optional sign and fraction.
+// exponents, separators, blanks and floats are turned away, as are more
+// digits than a `Decimal` holds
+#[cfg(feature = "decimal")]
+impl<'de> Deserialize<'de> for Cents {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+
+        impl serde::de::Visitor<'_> for Visitor {
+            type Value = Cents;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("an integer or a decimal string")
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, v: i64) -> Result<Cents, E> {
+                Ok(Cents(v.into()))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, v: u64) -> Result<Cents, E> {
+                Ok(Cents(v.into()))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Cents, E> {
+                let digits = v.strip_prefix('-').unwrap_or(v);
+                let (whole, fraction) = digits.split_once('.').unwrap_or((digits, "0"));
+                let all_digits =
+                    |part: &str| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit());
+
+                let unexpected = || E::invalid_value(serde::de::Unexpected::Str(v), &self);
+                if !all_digits(whole) || !all_digits(fraction) {
+                    return Err(unexpected());
+                }
+                rust_decimal::Decimal::from_str_exact(v)
+                    .map(Cents)
+                    .map_err(|_| unexpected())
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+#[cfg(feature = "decimal")]
+impl Serialize for Cents {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&self.0)
+    }
+}
+
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "transaction_kind", rename_all = "lowercase")]
+pub enum TransactionKind {
+    #[serde(rename = "c")]
+    Credit,
+    #[serde(rename = "d")]
+    Debit,
+}
+
+// the derive doesn't cover arrays, which the batch insert binds
+impl sqlx::postgres::PgHasArrayType for TransactionKind {
+    fn array_type_info() -> sqlx::postgres::PgTypeInfo {
+        sqlx::postgres::PgTypeInfo::with_name("_transaction_kind")
+    }
+}
+
+impl FromStr for TransactionKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "c" => Ok(TransactionKind::Credit),
+            "d" => Ok(TransactionKind::Debit),
+            _ => Err(format!("Invalid transaction kind: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for TransactionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransactionKind::Credit => write!(f, "c"),
+            TransactionKind::Debit => write!(f, "d"),
+        }
+    }
+}
+
+// a blocked wallet takes no new transactions, its statement and history
+// can still be read
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "wallet_status", rename_all = "lowercase")]
+pub enum WalletStatus {
+    #[serde(rename = "ativo")]
+    Active,
+    #[serde(rename = "bloqueado")]
+    Blocked,
+}
+
+impl FromStr for WalletStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ativo" => Ok(WalletStatus::Active),
+            "bloqueado" => Ok(WalletStatus::Blocked),
+            _ => Err(format!("Invalid wallet status: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for WalletStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WalletStatus::Active => write!(f, "ativo"),
+            WalletStatus::Blocked => write!(f, "bloqueado"),
+        }
+    }
+}
+
+// only a pending hold sets money aside. the others are settled for good
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "hold_status", rename_all = "lowercase")]
+pub enum HoldStatus {
+    #[serde(rename = "pendente")]
+    Pending,
+    #[serde(rename = "capturada")]
+    Captured,
+    #[serde(rename = "liberada")]
+    Released,
+    #[serde(rename = "expirada")]
+    Expired,
+}
+
+// a scheduled transaction waits as pending until it's applied, turned away
+// by the checks of then, or cancelled
+#[derive(sqlx::Type, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[sqlx(type_name = "scheduled_transaction_status", rename_all = "lowercase")]
+pub enum ScheduledStatus {
+    #[serde(rename = "pendente")]
+    Pending,
+    #[serde(rename = "aplicada")]
+    Applied,
+    #[serde(rename = "falhou")]
+    Failed,
+    #[serde(rename = "cancelada")]
+    Cancelled,
+}
+
+// the balance after a write
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Wallet {
+    #[serde(rename = "saldo")]
+    pub balance: Option<Cents>,
+    #[serde(rename = "limite")]
+    pub credit_limit: Option<Cents>,
+}
+
+// body of a successful `POST /clientes/:id/transacoes`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransactionReceipt {
+    #[serde(flatten)]
+    pub wallet: Wallet,
+    // both missing from the responses stored for idempotency keys before
+    // transactions had an id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Uuid>,
+    #[serde(
+        rename = "realizada_em",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "rfc3339::option"
+    )]
+    pub inserted_at: Option<OffsetDateTime>,
+}
+
+// body of a successful `POST /clientes/:id/transacoes/lote`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransactionBatchReceipt {
+    // after the whole batch, and the fees charged for it
+    #[serde(rename = "saldo")]
+    pub balance: Cents,
+    #[serde(rename = "limite")]
+    pub credit_limit: Cents,
+    // one per transaction of the request, in the same order
+    #[serde(rename = "transacoes")]
+    pub transactions: Vec<TransactionBatchItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransactionBatchItem {
+    pub id: Uuid,
+    // right after this transaction
+    #[serde(rename = "saldo")]
+    pub balance: Cents,
+    #[serde(rename = "realizada_em", with = "rfc3339")]
+    pub inserted_at: OffsetDateTime,
+}
+
+// body of `GET /clientes/:id/transacoes/:transacao_id`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransactionDetails {
+    pub id: Uuid,
+    #[serde(flatten)]
+    pub transaction: TransactionItem,
+}
+
+// body of a successful `POST /clientes`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreatedWallet {
+    pub id: i32,
+    #[serde(rename = "saldo")]
+    pub balance: Cents,
+    #[serde(rename = "limite")]
+    pub credit_limit: Cents,
+    #[serde(rename = "id_externo", skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    #[serde(rename = "moeda")]
+    #[schema(example = "BRL")]
+    pub currency: String,
+    #[serde(rename = "limite_diario", skip_serializing_if = "Option::is_none")]
+    pub daily_debit_limit: Option<Cents>,
+}
+
+// an item of `GET /clientes/:id/contas`. the default account is the
+// client's own wallet, the one its id refers to
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Account {
+    pub id: i32,
+    #[serde(rename = "saldo")]
+    pub balance: Cents,
+    #[serde(rename = "limite")]
+    pub credit_limit: Cents,
+    #[serde(rename = "id_externo", skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    #[serde(rename = "moeda")]
+    #[schema(example = "BRL")]
+    pub currency: String,
+    #[serde(rename = "padrao")]
+    pub default: bool,
+}
+
+// an item of `GET /clientes/:id/webhooks`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Webhook {
+    pub id: i32,
+    #[schema(example = "https://example.com/rinha")]
+    pub url: String,
+    #[serde(rename = "criado_em", with = "rfc3339")]
+    pub inserted_at: OffsetDateTime,
+}
+
+// body of a successful `POST /clientes/:id/webhooks`, the one place the
+// secret shows up
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CreatedWebhook {
+    #[serde(flatten)]
+    pub webhook: Webhook,
+    #[serde(rename = "segredo")]
+    pub secret: String,
+}
+
+// body of a successful `POST /transferencias`: the source's balance after
+// the transfer. the destination's stays private to its owner
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransferReceipt {
+    pub id: Uuid,
+    #[serde(rename = "saldo")]
+    pub balance: Cents,
+    #[serde(rename = "limite")]
+    pub credit_limit: Cents,
+}
+
+// body of `GET /clientes/:id`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WalletDetails {
+    pub id: i32,
+    #[serde(rename = "saldo")]
+    pub balance: Cents,
+    #[serde(rename = "limite")]
+    pub credit_limit: Cents,
+    #[serde(rename = "id_externo", skip_serializing_if = "Option::is_none")]
+    pub external_id: Option<String>,
+    #[serde(rename = "moeda")]
+    #[schema(example = "BRL")]
+    pub currency: String,
+    #[serde(rename = "criado_em", with = "rfc3339")]
+    pub inserted_at: OffsetDateTime,
+    #[serde(rename = "total_transacoes")]
+    pub transaction_count: i64,
+    pub status: WalletStatus,
+    #[serde(rename = "limite_diario", skip_serializing_if = "Option::is_none")]
+    pub daily_debit_limit: Option<Cents>,
+    // what the wallet debited so far today (utc), counted against the limit
+    #[serde(rename = "debitado_hoje")]
+    pub debited_today: Cents,
+    // set aside by pending holds, it can't be spent until they're settled
+    #[serde(rename = "reservado")]
+    pub held: Cents,
+    // charged daily on a negative balance, INTEREST_DAILY_RATE's when none
+    #[serde(rename = "taxa_juros_diaria", skip_serializing_if = "Option::is_none")]
+    #[schema(example = 0.001)]
+    pub interest_rate: Option<f64>,
+}
+
+// body of `GET /holds/:id`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Hold {
+    pub id: Uuid,
+    #[serde(rename = "cliente_id")]
+    pub wallet_id: i32,
+    #[serde(rename = "valor")]
+    pub value: Cents,
+    #[serde(rename = "descricao")]
+    pub description: String,
+    pub status: HoldStatus,
+    #[serde(rename = "expira_em", with = "rfc3339")]
+    pub expires_at: OffsetDateTime,
+    #[serde(rename = "criado_em", with = "rfc3339")]
+    pub inserted_at: OffsetDateTime,
+    // the debit it was captured into
+    #[serde(rename = "transacao_id", skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<Uuid>,
+}
+
+// body of a successful `POST /holds/:id/capture`: the hold and the balance
+// after its debit
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CapturedHold {
+    #[serde(rename = "reserva")]
+    pub hold: Hold,
+    #[serde(rename = "saldo")]
+    pub balance: Cents,
+    #[serde(rename = "limite")]
+    pub credit_limit: Cents,
+}
+
+// body of a scheduled `POST /clientes/:id/transacoes`, and an item of
+// `GET /clientes/:id/transacoes/agendadas`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ScheduledTransaction {
+    pub id: Uuid,
+    #[serde(rename = "cliente_id")]
+    pub wallet_id: i32,
+    #[serde(rename = "valor")]
+    pub value: Cents,
+    #[serde(rename = "tipo")]
+    pub kind: TransactionKind,
+    #[serde(rename = "descricao")]
+    pub description: String,
+    #[serde(rename = "categoria", default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub metadata: Option<Value>,
+    #[serde(rename = "agendada_para", with = "rfc3339")]
+    pub due_at: OffsetDateTime,
+    pub status: ScheduledStatus,
+    #[serde(rename = "criado_em", with = "rfc3339")]
+    pub inserted_at: OffsetDateTime,
+    // the transaction it became
+    #[serde(rename = "transacao_id", skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<Uuid>,
+    // the code of the error that turned it away, e.g. `limit_exceeded`
+    #[serde(rename = "motivo", skip_serializing_if = "Option::is_none")]
+    pub failure: Option<String>,
+}
+
+// an item of `GET /clientes/:id/recorrencias`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Recurrence {
+    pub id: i32,
+    #[serde(rename = "cliente_id")]
+    pub wallet_id: i32,
+    #[serde(rename = "valor")]
+    pub value: Cents,
+    #[serde(rename = "tipo")]
+    pub kind: TransactionKind,
+    #[serde(rename = "descricao")]
+    pub description: String,
+    #[serde(rename = "categoria", default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub metadata: Option<Value>,
+    #[serde(rename = "cron")]
+    #[schema(example = "0 9 5 * *")]
+    pub schedule: String,
+    #[serde(rename = "ativa")]
+    pub active: bool,
+    // none while it's paused
+    #[serde(
+        rename = "proxima_em",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "rfc3339::option"
+    )]
+    pub next_run_at: Option<OffsetDateTime>,
+    #[serde(
+        rename = "ultima_em",
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "rfc3339::option"
+    )]
+    pub last_run_at: Option<OffsetDateTime>,
+    // the code of the error that turned the last run away
+    #[serde(rename = "ultimo_erro", skip_serializing_if = "Option::is_none")]
+    pub last_failure: Option<String>,
+    #[serde(rename = "criado_em", with = "rfc3339")]
+    pub inserted_at: OffsetDateTime,
+}
+
+// an item of `GET /admin/tarifas`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct FeeRule {
+    pub id: i32,
+    #[serde(rename = "tipo")]
+    pub kind: TransactionKind,
+    #[serde(rename = "valor_minimo", skip_serializing_if = "Option::is_none")]
+    pub min_value: Option<Cents>,
+    #[serde(rename = "fixa")]
+    pub flat: Cents,
+    #[serde(rename = "percentual")]
+    #[schema(example = 0.01)]
+    pub percentage: f64,
+    #[serde(rename = "descricao")]
+    pub description: String,
+    #[serde(rename = "ativa")]
+    pub active: bool,
+    #[serde(rename = "criado_em", with = "rfc3339")]
+    pub inserted_at: OffsetDateTime,
+}
+
+// a delivery claimed from the webhook outbox, with what gets posted
+#[derive(Debug, Clone)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    // the ones that failed before
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+    pub wallet_id: i32,
+    pub transaction: TransactionDetails,
+}
+
+// a transaction waiting in the event outbox to be published
+#[cfg(feature = "nats")]
+#[derive(Debug, Clone)]
+pub struct OutboxEvent {
+    pub id: i64,
+    pub wallet_id: i32,
+    pub transaction: TransactionDetails,
+}
+
+// body of `POST /admin/clientes/:id/reconciliar`. the wallets all start at
+// zero, so the balance should be what its transactions add up to
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Reconciliation {
+    #[serde(rename = "cliente_id")]
+    pub wallet_id: i32,
+    // before the repair, if there was one
+    #[serde(rename = "saldo")]
+    pub balance: Cents,
+    #[serde(rename = "esperado")]
+    pub expected: Cents,
+    #[serde(rename = "reparado")]
+    pub repaired: bool,
+}
+
+impl Reconciliation {
+    pub fn drifted(&self) -> bool {
+        self.balance != self.expected
+    }
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReconcileQuery {
+    // `false` only reports the drift
+    #[param(value_type = Option<bool>, example = false)]
+    pub reparar: Option<String>,
+}
+
+impl ReconcileQuery {
+    pub fn repair(&self) -> Result<bool, Vec<FieldError>> {
+        match self.reparar.as_deref() {
+            None | Some("true") => Ok(true),
+            Some("false") => Ok(false),
+            Some(_) => Err(vec![FieldError {
+                field: "reparar".into(),
+                message: "must be true or false",
+            }]),
+        }
+    }
+}
+
+// a transaction's place in its wallet's hash chain, hashes in hex
+#[derive(Debug, Clone)]
+pub struct ChainLink {
+    pub id: Uuid,
+    pub position: i64,
+    pub prev_hash: Option<String>,
+    pub hash: String,
+    // what the hash works out to from the row as it is now
+    pub recomputed: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub enum ChainBreakReason {
+    // the transaction was edited after its hash was taken
+    #[serde(rename = "adulterada")]
+    Tampered,
+    // it doesn't link to the transaction before it, which was edited or
+    // swapped for another
+    #[serde(rename = "elo_quebrado")]
+    BrokenLink,
+    // the position has no transaction
+    #[serde(rename = "ausente")]
+    Missing,
+    // the wallet's last hash isn't the last transaction's, some of the
+    // newest are gone
+    #[serde(rename = "final_ausente")]
+    MissingTail,
+}
+
+// the first link that doesn't hold, everything after it can't be vouched for
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChainBreak {
+    #[serde(rename = "posicao")]
+    pub position: i64,
+    // `None` when the transaction is the one missing
+    #[serde(rename = "transacao_id", skip_serializing_if = "Option::is_none")]
+    pub transaction_id: Option<Uuid>,
+    #[serde(rename = "motivo")]
+    pub reason: ChainBreakReason,
+}
+
+// body of `GET /clientes/:id/transacoes/verify`, worked out one link at a
+// time with `check` and closed with `finish`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChainVerification {
+    #[serde(rename = "cliente_id")]
+    pub wallet_id: i32,
+    #[serde(rename = "valido")]
+    pub valid: bool,
+    // the ones checked before the break, if there was one
+    #[serde(rename = "transacoes")]
+    pub transactions: i64,
+    // of the last transaction checked, to compare with an earlier copy
+    #[serde(rename = "hash", skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    #[serde(rename = "quebra", skip_serializing_if = "Option::is_none")]
+    pub broken: Option<ChainBreak>,
+}
+
+impl ChainVerification {
+    pub fn new(wallet_id: i32) -> Self {
+        ChainVerification {
+            wallet_id,
+            valid: true,
+            transactions: 0,
+            hash: None,
+            broken: None,
+        }
+    }
+
+    // takes the links in position order. false once the chain broke, the
+    // ones after it aren't worth reading
+    pub fn check(&mut self, link: ChainLink) -> bool {
+        let position = self.transactions + 1;
+        let reason = if link.position != position {
+            Some(ChainBreakReason::Missing)
+        } else if link.prev_hash != self.hash {
+            Some(ChainBreakReason::BrokenLink)
+        } else if link.hash != link.recomputed {
+            Some(ChainBreakReason::Tampered)
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            self.break_at(
+                position,
+                (reason != ChainBreakReason::Missing).then_some(link.id),
+                reason,
+            );
+            return false;
+        }
+
+        self.transactions = position;
+        self.hash = Some(link.hash);
+        true
+    }
+
+    // compares the end of the chain with the last link the wallet recorded,
+    // which is what catches the newest transactions going missing
+    pub fn finish(mut self, head: Option<String>, length: i64) -> Self {
+        if self.valid && (length != self.transactions || head != self.hash) {
+            self.break_at(self.transactions + 1, None, ChainBreakReason::MissingTail);
+        }
+        self
+    }
+
+    fn break_at(&mut self, position: i64, transaction_id: Option<Uuid>, reason: ChainBreakReason) {
+        self.valid = false;
+        self.broken = Some(ChainBreak {
+            position,
+            transaction_id,
+            reason,
+        });
+    }
+}
+
+// what `WalletRepository::check_ledger` turns up
+#[derive(Debug, Clone)]
+pub enum LedgerViolation {
+    // the entries of the transaction don't add up to zero, or it doesn't
+    // have the two it should
+    Unbalanced {
+        transaction_id: Uuid,
+        entries: i64,
+        sum: Cents,
+    },
+    // the wallet's balance isn't what the entries of its account add up to
+    BalanceMismatch {
+        wallet_id: i32,
+        balance: Cents,
+        entries: Cents,
+    },
+}
+
+impl fmt::Display for LedgerViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerViolation::Unbalanced {
+                transaction_id,
+                entries,
+                sum,
+            } => write!(
+                f,
+                "transaction {} has {} entries adding up to {}",
+                transaction_id, entries, sum
+            ),
+            LedgerViolation::BalanceMismatch {
+                wallet_id,
+                balance,
+                entries,
+            } => write!(
+                f,
+                "wallet {} has a balance of {} but its entries add up to {}",
+                wallet_id, balance, entries
+            ),
+        }
+    }
+}
+
+// body of `GET /clientes/:id/resumo`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct MonthlySummary {
+    #[serde(rename = "mes")]
+    #[schema(example = "2024-02")]
+    pub month: String,
+    #[serde(rename = "total_creditos")]
+    pub credits: Cents,
+    #[serde(rename = "total_debitos")]
+    pub debits: Cents,
+    // credits minus debits, how much the balance moved over the month
+    #[serde(rename = "variacao")]
+    pub net_change: Cents,
+    #[serde(rename = "total_transacoes")]
+    pub transaction_count: i64,
+    // the same totals for each category, by name, the transactions without
+    // one last
+    #[serde(rename = "categorias")]
+    pub categories: Vec<CategorySummary>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CategorySummary {
+    // null for the transactions without one
+    #[serde(rename = "categoria")]
+    #[schema(example = "mercado")]
+    pub category: Option<String>,
+    #[serde(rename = "total_creditos")]
+    pub credits: Cents,
+    #[serde(rename = "total_debitos")]
+    pub debits: Cents,
+    #[serde(rename = "total_transacoes")]
+    pub transaction_count: i64,
+}
+
+// what the transactions of one kind and category add up to
+#[derive(Debug, Clone)]
+pub struct KindTotal {
+    pub kind: TransactionKind,
+    pub category: Option<String>,
+    pub sum: Cents,
+    pub count: i64,
+}
+
+impl MonthlySummary {
+    // from the totals of each kind and category seen during the month
+    pub fn new(month: SummaryMonth, totals: impl IntoIterator<Item = KindTotal>) -> Self {
+        let mut summary = MonthlySummary {
+            month: month.to_string(),
+            credits: Cents::ZERO,
+            debits: Cents::ZERO,
+            net_change: Cents::ZERO,
+            transaction_count: 0,
+            categories: Vec::new(),
+        };
+
+        for total in totals {
+            let position = summary
+                .categories
+                .iter()
+                .position(|category| category.category == total.category)
+                .unwrap_or_else(|| {
+                    summary.categories.push(CategorySummary {
+                        category: total.category.clone(),
+                        credits: Cents::ZERO,
+                        debits: Cents::ZERO,
+                        transaction_count: 0,
+                    });
+                    summary.categories.len() - 1
+                });
+            let category = &mut summary.categories[position];
+
+            match total.kind {
+                TransactionKind::Credit => {
+                    summary.credits = summary.credits.saturating_add(total.sum);
+                    category.credits = category.credits.saturating_add(total.sum);
+                }
+                TransactionKind::Debit => {
+                    summary.debits = summary.debits.saturating_add(total.sum);
+                    category.debits = category.debits.saturating_add(total.sum);
+                }
+            }
+            summary.transaction_count += total.count;
+            category.transaction_count += total.count;
+        }
+        // only totals no balance could hold would overflow
+        summary.net_change = summary.credits.saturating_sub(summary.debits);
+        summary.categories.sort_by(|a, b| {
+            (a.category.is_none(), &a.category).cmp(&(b.category.is_none(), &b.category))
+        });
+
+        summary
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TransactionItem {
+    #[serde(rename = "valor")]
+    pub value: Cents,
+    #[serde(rename = "tipo")]
+    pub kind: TransactionKind,
+    #[serde(rename = "descricao")]
+    pub description: String,
+    #[serde(rename = "realizada_em", with = "rfc3339")]
+    pub inserted_at: OffsetDateTime,
+    #[serde(rename = "categoria", default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub metadata: Option<Value>,
+}
+
+// serialized only by the shared cache, responses go through `StatementResponse`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Statement {
+    pub wallet: Wallet,
+    pub currency: String,
+    pub transactions: Vec<TransactionItem>,
+}
+
+impl Statement {
+    // changes whenever the body would, apart from `data_extrato`: every write
+    // moves the balance or adds a newer row, and a new limit shows up as is.
+    // weak, since the `data_extrato` of two responses with the same tag differ
+    pub fn etag(&self) -> String {
+        let newest = self
+            .transactions
+            .first()
+            .map_or(0, |row| row.inserted_at.unix_timestamp_nanos());
+
+        format!(
+            "W/\"{}.{}.{}.{}\"",
+            self.wallet.balance.unwrap_or_default().0,
+            self.wallet.credit_limit.unwrap_or_default().0,
+            newest,
+            self.transactions.len()
+        )
+    }
+}
+
+// body of `GET /clientes/:id/extrato`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct StatementResponse {
+    #[serde(rename = "saldo")]
+    pub balance: BalanceSummary,
+    #[serde(rename = "ultimas_transacoes")]
+    pub transactions: Vec<TransactionItem>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct BalanceSummary {
+    pub total: Option<Cents>,
+    #[serde(rename = "data_extrato", with = "rfc3339")]
+    pub statement_date: OffsetDateTime,
+    #[serde(rename = "limite")]
+    pub credit_limit: Option<Cents>,
+    #[serde(rename = "moeda")]
+    #[schema(example = "BRL")]
+    pub currency: String,
+}
+
+impl StatementResponse {
+    pub fn new(statement: Statement, statement_date: OffsetDateTime) -> Self {
+        StatementResponse {
+            balance: BalanceSummary {
+                total: statement.wallet.balance,
+                statement_date,
+                credit_limit: statement.wallet.credit_limit,
+                currency: statement.currency,
+            },
+            transactions: statement.transactions,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StatementFilter {
+    pub from: Option<OffsetDateTime>,
+    pub to: Option<OffsetDateTime>,
+    pub kind: Option<TransactionKind>,
+}
+
+// `time`'s own serde impls aren't RFC3339, which is what the rinha spec uses
+mod rfc3339 {
+    use serde::{de, ser, Deserialize, Deserializer, Serializer};
+    use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+    pub fn serialize<S>(value: &OffsetDateTime, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let formatted = value.format(&Rfc3339).map_err(ser::Error::custom)?;
+        serializer.serialize_str(&formatted)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<OffsetDateTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // owned, a flattened struct buffers its fields and can't lend them
+        let value = String::deserialize(deserializer)?;
+        OffsetDateTime::parse(&value, &Rfc3339).map_err(de::Error::custom)
+    }
+
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+        use time::OffsetDateTime;
+
+        pub fn serialize<S>(
+            value: &Option<OffsetDateTime>,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error>
+        where
+            S: Serializer,
+        {
+            match value {
+                Some(value) => super::serialize(value, serializer),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<OffsetDateTime>, D::Error>
+        where
+            D: Deserializer<'de>,
+        {
+            #[derive(Deserialize)]
+            struct Wrapper(#[serde(with = "super")] OffsetDateTime);
+
+            Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(value)| value))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{http::StatusCode, response::IntoResponse};
+    use serde_json::json;
+
+    use super::*;
+    use crate::errors::ApiError;
+
+    fn transaction(value: Value, kind: &str) -> PostTransaction {
+        PostTransaction::try_from(RawPostTransaction {
+            valor: Some(value),
+            tipo: Some(json!(kind)),
+            descricao: Some(json!("teste")),
+            moeda: None,
+            categoria: None,
+            tags: None,
+            metadata: None,
+        })
+        .unwrap()
+    }
+
+    // the fields in error and that they make a 422
+    fn rejected(
+        valor: Option<Value>,
+        tipo: Option<Value>,
+        descricao: Option<Value>,
+    ) -> Vec<String> {
+        let errors = PostTransaction::try_from(RawPostTransaction {
+            valor,
+            tipo,
+            descricao,
+            moeda: None,
+            categoria: None,
+            tags: None,
+            metadata: None,
+        })
+        .err()
+        .expect("the transaction should be invalid");
+
+        let fields = errors.iter().map(|error| error.field.to_string()).collect();
+        let response = ApiError::from(errors).into_response();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        fields
+    }
+
+    #[test]
+    fn valor_must_be_positive() {
+        let c = || Some(json!("c"));
+        let teste = || Some(json!("teste"));
+
+        assert_eq!(rejected(Some(json!(0)), c(), teste()), ["valor"]);
+        assert_eq!(rejected(Some(json!(-1)), c(), teste()), ["valor"]);
+    }
+
+    #[test]
+    fn valor_must_be_an_integer() {
+        let c = || Some(json!("c"));
+        let teste = || Some(json!("teste"));
+
+        assert_eq!(rejected(Some(json!(1.5)), c(), teste()), ["valor"]);
+        assert_eq!(rejected(Some(json!("um")), c(), teste()), ["valor"]);
+    }
+
+    #[test]
+    fn tipo_must_be_c_or_d() {
+        let teste = || Some(json!("teste"));
+
+        assert_eq!(
+            rejected(Some(json!(1)), Some(json!("x")), teste()),
+            ["tipo"]
+        );
+        assert_eq!(rejected(Some(json!(1)), Some(json!(1)), teste()), ["tipo"]);
+    }
+
+    #[test]
+    fn every_field_is_required() {
+        assert_eq!(
+            rejected(None, Some(json!("c")), Some(json!("teste"))),
+            ["valor"]
+        );
+        assert_eq!(
+            rejected(Some(json!(1)), Some(Value::Null), Some(json!("teste"))),
+            ["tipo"]
+        );
+        assert_eq!(
+            rejected(Some(json!(1)), Some(json!("c")), None),
+            ["descricao"]
+        );
+        // all the problems at once
+        assert_eq!(rejected(None, None, None), ["valor", "tipo", "descricao"]);
+    }
+
+    #[test]
+    fn descricao_must_have_1_to_10_characters() {
+        let valid = |descricao: &str| {
+            PostTransaction::try_from(RawPostTransaction {
+                valor: Some(json!(1)),
+                tipo: Some(json!("c")),
+                descricao: Some(json!(descricao)),
+                moeda: None,
+                categoria: None,
+                tags: None,
+                metadata: None,
+            })
+            .is_ok()
+        };
+
+        assert!(valid("a"));
+        assert!(valid("dezcaracte"));
+        // characters, not bytes
+        assert!(valid("çççççççççç"));
+        assert_eq!(
+            rejected(Some(json!(1)), Some(json!("c")), Some(json!(""))),
+            ["descricao"]
+        );
+        assert_eq!(
+            rejected(Some(json!(1)), Some(json!("c")), Some(json!("onzecaracte"))),
+            ["descricao"]
+        );
+    }
+
+    #[test]
+    fn checked_add_up_to_the_max() {
+        let below = Cents(Amount::MAX - Amount::from(1));
+
+        assert_eq!(below.checked_add(Cents::from(1)), Some(Cents(Amount::MAX)));
+        assert_eq!(Cents(Amount::MAX).checked_add(Cents::from(1)), None);
+        assert_eq!(Cents(Amount::MAX).checked_add(Cents(Amount::MAX)), None);
+    }
+
+    #[test]
+    fn checked_sub_down_to_the_min() {
+        let above = Cents(Amount::MIN + Amount::from(1));
+
+        assert_eq!(above.checked_sub(Cents::from(1)), Some(Cents(Amount::MIN)));
+        assert_eq!(Cents(Amount::MIN).checked_sub(Cents::from(1)), None);
+        assert_eq!(Cents(Amount::MIN).checked_sub(Cents(Amount::MAX)), None);
+    }
+
+    #[test]
+    fn saturating_add_stops_at_the_bounds() {
+        assert_eq!(
+            Cents(Amount::MAX).saturating_add(Cents::from(1)),
+            Cents(Amount::MAX)
+        );
+        assert_eq!(
+            Cents(Amount::MIN).saturating_add(Cents::from(-1)),
+            Cents(Amount::MIN)
+        );
+    }
+
+    #[test]
+    fn apply_is_none_when_the_balance_doesnt_fit() {
+        let credit = transaction(json!(1), "c");
+        let debit = transaction(json!(1), "d");
+
+        assert_eq!(credit.apply(Cents(Amount::MAX)), None);
+        assert_eq!(debit.apply(Cents(Amount::MIN)), None);
+        assert_eq!(credit.apply(Cents::from(-1)), Some(Cents::ZERO));
+        assert_eq!(debit.delta(), Cents::from(-1));
+    }
+}
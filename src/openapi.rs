@@ -0,0 +1,186 @@
+use axum::{response::Html, Json};
+use utoipa::{
+    openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme},
+    Modify, OpenApi,
+};
+
+use crate::{
+    errors::Problem,
+    events::TransactionEvent,
+    handlers,
+    models::{
+        Account, AuditEntry, AuditPage, BalanceSummary, CapturedHold, CategorySummary, Cents,
+        ChainBreak, ChainBreakReason, ChainVerification, CreatedWallet, CreatedWebhook, FeeRule,
+        FieldError, HistoryPage, Hold, HoldStatus, LogLevel, MonthlySummary, PatchCreditLimit,
+        PatchDailyDebitLimit, PatchInterestRate, PostFeeRule, PostHold, PostRecurrence,
+        PostTransaction, PostTransactionBatch, PostTransactionRequest, PostTransfer, PostWallet,
+        PostWebhook, Reconciliation, Recurrence, ScheduledStatus, ScheduledTransaction,
+        StatementResponse, TransactionBatchItem, TransactionBatchReceipt, TransactionDetails,
+        TransactionItem, TransactionKind, TransactionReceipt, TransferReceipt, Wallet,
+        WalletDetails, WalletStatus, Webhook,
+    },
+};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "rinha",
+        description = "Rinha de Backend 2024/Q1 API. the field names are portuguese, `?lang=en` or an `Accept-Language` preferring english names the response fields in english instead"
+    ),
+    // the same routes either way, `/v1` is where the next version's prefix
+    // will sit beside
+    servers(
+        (url = "/v1", description = "v1"),
+        (url = "/", description = "v1, unprefixed as the rinha test harness calls it")
+    ),
+    paths(
+        handlers::create_wallet,
+        handlers::wallet,
+        handlers::create_account,
+        handlers::accounts,
+        handlers::statement,
+        crate::export::export,
+        handlers::history,
+        handlers::summary,
+        handlers::insert_transaction,
+        handlers::scheduled_transactions,
+        handlers::cancel_scheduled_transaction,
+        handlers::insert_transactions,
+        handlers::transaction,
+        handlers::update_credit_limit,
+        handlers::update_daily_debit_limit,
+        handlers::update_interest_rate,
+        handlers::block_wallet,
+        handlers::unblock_wallet,
+        handlers::transfer,
+        handlers::transaction_stream,
+        handlers::create_webhook,
+        handlers::webhooks,
+        handlers::delete_webhook,
+        handlers::create_recurrence,
+        handlers::recurrences,
+        handlers::recurrence,
+        handlers::update_recurrence,
+        handlers::delete_recurrence,
+        handlers::create_hold,
+        handlers::hold,
+        handlers::capture_hold,
+        handlers::release_hold,
+        handlers::verify_chain,
+        handlers::reconcile,
+        handlers::audit_log,
+        handlers::create_fee_rule,
+        handlers::fee_rules,
+        handlers::update_fee_rule,
+        handlers::delete_fee_rule,
+        handlers::log_level,
+        handlers::update_log_level
+    ),
+    components(schemas(
+        Account,
+        AuditEntry,
+        AuditPage,
+        BalanceSummary,
+        CapturedHold,
+        Cents,
+        ChainBreak,
+        ChainBreakReason,
+        ChainVerification,
+        CreatedWallet,
+        CreatedWebhook,
+        FeeRule,
+        FieldError,
+        HistoryPage,
+        Hold,
+        HoldStatus,
+        LogLevel,
+        MonthlySummary,
+        CategorySummary,
+        PatchCreditLimit,
+        PatchDailyDebitLimit,
+        PatchInterestRate,
+        PostFeeRule,
+        PostHold,
+        PostRecurrence,
+        PostTransaction,
+        PostTransactionBatch,
+        PostTransactionRequest,
+        PostTransfer,
+        PostWallet,
+        PostWebhook,
+        Problem,
+        Recurrence,
+        Reconciliation,
+        ScheduledStatus,
+        ScheduledTransaction,
+        StatementResponse,
+        TransactionBatchItem,
+        TransactionBatchReceipt,
+        TransactionDetails,
+        TransactionEvent,
+        TransactionItem,
+        TransactionKind,
+        TransactionReceipt,
+        TransferReceipt,
+        Wallet,
+        WalletDetails,
+        WalletStatus,
+        Webhook,
+    )),
+    modifiers(&SecuritySchemes),
+    tags(
+        (name = "clientes", description = "balances and transactions of a client. each `/clientes/{id}/...` route is also there as `/clientes/{id}/contas/{conta_id}/...` for the client's other accounts, `/clientes/{id}` being the default one"),
+        (name = "holds", description = "amounts set aside on a client's balance until they're captured or released"),
+        (name = "admin", description = "looking after the whole api, needs an admin key or token")
+    )
+)]
+pub struct ApiDoc;
+
+// both are optional, see API_KEYS and JWT_SECRET/JWT_PUBLIC_KEY
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("X-Api-Key"))),
+        );
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .build(),
+            ),
+        );
+    }
+}
+
+pub async fn spec() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+// swagger ui is pulled from a cdn, bundling its assets would need them
+// downloaded at build time
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(
+        r##"<!doctype html>
+<html>
+  <head>
+    <meta charset="utf-8">
+    <title>rinha api</title>
+    <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+  </head>
+  <body>
+    <div id="swagger-ui"></div>
+    <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+    <script>
+      SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+    </script>
+  </body>
+</html>
+"##,
+    )
+}
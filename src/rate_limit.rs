@@ -0,0 +1,230 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{config::Config, errors::ApiError, extract::Path};
+
+const LIMIT_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-limit");
+const REMAINING_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-remaining");
+const RESET_HEADER: HeaderName = HeaderName::from_static("x-ratelimit-reset");
+
+// past this many tracked wallets the buckets that refilled completely are
+// dropped, they'd behave exactly like a fresh one anyway
+const MAX_TRACKED_WALLETS: usize = 10_000;
+
+// the rates every limiter goes by, so a reload changes them while running.
+// a zero `PER_SEC` lets every request through
+static PER_SEC: AtomicU32 = AtomicU32::new(0);
+static BURST: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_rates(per_sec: u32, burst: u32) {
+    PER_SEC.store(per_sec, Ordering::Relaxed);
+    BURST.store(burst, Ordering::Relaxed);
+}
+
+// a token bucket per wallet id, and tenant: `burst` requests can go through
+// at once and the bucket refills at `per_sec` tokens a second
+#[derive(Clone)]
+pub struct RateLimiter {
+    buckets: Arc<Mutex<HashMap<BucketKey, Bucket>>>,
+}
+
+#[derive(Clone, Copy)]
+struct Rates {
+    per_sec: f64,
+    burst: f64,
+}
+
+// the tenant and the wallet id
+type BucketKey = (Option<String>, i32);
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+enum Decision {
+    Allowed { remaining: u32 },
+    Limited { retry_after: Duration },
+}
+
+impl RateLimiter {
+    pub fn new(per_sec: u32, burst: u32) -> Self {
+        set_rates(per_sec, burst);
+        RateLimiter {
+            buckets: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    // `None` when RATE_LIMIT_PER_SEC is unset, the rinha load test expects
+    // every request to go through. setting it takes a restart after that
+    pub fn from_config(config: &Config) -> Option<Self> {
+        (config.rate_limit_per_sec > 0)
+            .then(|| Self::new(config.rate_limit_per_sec, config.rate_limit_burst))
+    }
+
+    fn check(&self, rates: Rates, key: BucketKey, now: Instant) -> Decision {
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_TRACKED_WALLETS {
+            buckets.retain(|_, bucket| rates.refilled(bucket, now) < rates.burst);
+        }
+
+        let bucket = buckets.entry(key).or_insert(Bucket {
+            tokens: rates.burst,
+            updated_at: now,
+        });
+
+        bucket.tokens = rates.refilled(bucket, now);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Decision::Allowed {
+                remaining: bucket.tokens as u32,
+            }
+        } else {
+            Decision::Limited {
+                retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / rates.per_sec),
+            }
+        }
+    }
+}
+
+impl Rates {
+    // `None` while the limit is off
+    fn current() -> Option<Self> {
+        let per_sec = PER_SEC.load(Ordering::Relaxed);
+        (per_sec > 0).then(|| Rates {
+            per_sec: f64::from(per_sec),
+            burst: f64::from(BURST.load(Ordering::Relaxed)),
+        })
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        (bucket.tokens + elapsed * self.per_sec).min(self.burst)
+    }
+}
+
+pub async fn enforce(
+    State(limiter): State<RateLimiter>,
+    Path(wallet_id): Path<i32>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let Some(rates) = Rates::current() else {
+        return next.run(req).await;
+    };
+    let limit = HeaderValue::from(rates.burst as u32);
+
+    let key = (crate::tenant::current(), wallet_id);
+    let mut response = match limiter.check(rates, key, Instant::now()) {
+        Decision::Allowed { remaining } => {
+            let mut response = next.run(req).await;
+            response
+                .headers_mut()
+                .insert(REMAINING_HEADER, HeaderValue::from(remaining));
+            response
+        }
+        Decision::Limited { retry_after } => {
+            let mut response = ApiError::RateLimited { retry_after }.into_response();
+            let headers = response.headers_mut();
+            headers.insert(REMAINING_HEADER, HeaderValue::from(0));
+            if let Some(retry_after) = headers.get(axum::http::header::RETRY_AFTER).cloned() {
+                headers.insert(RESET_HEADER, retry_after);
+            }
+            response
+        }
+    };
+
+    response.headers_mut().insert(LIMIT_HEADER, limit);
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATES: Rates = Rates {
+        per_sec: 2.0,
+        burst: 3.0,
+    };
+
+    // without `new`, which sets the rates every limiter goes by
+    fn limiter() -> RateLimiter {
+        RateLimiter {
+            buckets: Arc::default(),
+        }
+    }
+
+    fn allowed(decision: Decision) -> Option<u32> {
+        match decision {
+            Decision::Allowed { remaining } => Some(remaining),
+            Decision::Limited { .. } => None,
+        }
+    }
+
+    #[test]
+    fn a_burst_goes_through_and_then_waits_for_a_token() {
+        let limiter = limiter();
+        let start = Instant::now();
+
+        for remaining in [2, 1, 0] {
+            assert_eq!(
+                allowed(limiter.check(RATES, (None, 1), start)),
+                Some(remaining)
+            );
+        }
+        match limiter.check(RATES, (None, 1), start) {
+            Decision::Limited { retry_after } => {
+                assert_eq!(retry_after, Duration::from_millis(500))
+            }
+            Decision::Allowed { .. } => panic!("past the burst"),
+        }
+
+        // the other wallets have buckets of their own
+        assert_eq!(allowed(limiter.check(RATES, (None, 2), start)), Some(2));
+        assert_eq!(
+            allowed(limiter.check(RATES, (Some("acme".into()), 1), start)),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn a_bucket_refills_up_to_the_burst() {
+        let limiter = limiter();
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.check(RATES, (None, 1), start);
+        }
+
+        // a token every half second
+        let later = start + Duration::from_millis(500);
+        assert_eq!(allowed(limiter.check(RATES, (None, 1), later)), Some(0));
+        assert_eq!(allowed(limiter.check(RATES, (None, 1), later)), None);
+
+        // and never more than the burst
+        let much_later = later + Duration::from_secs(60);
+        for remaining in [2, 1, 0] {
+            assert_eq!(
+                allowed(limiter.check(RATES, (None, 1), much_later)),
+                Some(remaining)
+            );
+        }
+        assert_eq!(allowed(limiter.check(RATES, (None, 1), much_later)), None);
+    }
+}
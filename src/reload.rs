@@ -0,0 +1,187 @@
+use std::{path::PathBuf, time::SystemTime};
+
+use crate::config::{Config, ConfigError, Settings, DEFAULT_FILE};
+
+// the settings a running server picks up again, on a SIGHUP or once the file
+// changes with CONFIG_WATCH_MS set. the rest, like the pools', take a restart
+pub const HOT: [&str; 8] = [
+    "ACCESS_LOG",
+    "RATE_LIMIT_BURST",
+    "RATE_LIMIT_PER_SEC",
+    "RUST_LOG",
+    "SHARED_CACHE_TTL_MS",
+    "SLOW_QUERY_MS",
+    "STATEMENT_CACHE_TTL_MS",
+    "STRICT_PARSING",
+];
+
+// puts `config`'s hot settings into effect, once it's read and on every
+// reload
+pub fn apply(config: &Config) {
+    crate::log_level::configure(config.log_filter.as_deref());
+    crate::slow_queries::set_threshold(config.slow_query_threshold);
+    crate::rate_limit::set_rates(config.rate_limit_per_sec, config.rate_limit_burst);
+    crate::repository::set_cache_ttls(config.statement_cache_ttl, config.shared_cache_ttl);
+    crate::extract::set_strict_parsing(config.strict_parsing);
+    crate::access_log::set_enabled(config.access_log);
+}
+
+// reloads the configuration on every SIGHUP, which also puts back the log
+// filter `PUT /admin/log-level` changed, and whenever the file's modification
+// time changes. a SIGHUP reads the TLS certificate again too. `started` is
+// the configuration the server started with, a change to what only a
+// restart sets up is warned about and left alone
+pub async fn watch(mut settings: Settings, started: Config) {
+    #[cfg(unix)]
+    let mut hangups = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(hangups) => Some(hangups),
+        Err(err) => {
+            tracing::error!("can't install the SIGHUP handler: {}", err);
+            None
+        }
+    };
+
+    let mut modified = modified_of(&settings);
+    let mut interval = (!started.config_watch.is_zero()).then(|| {
+        let mut interval = tokio::time::interval(started.config_watch);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        interval
+    });
+
+    loop {
+        #[cfg(unix)]
+        let hangup = async {
+            match &mut hangups {
+                Some(hangups) => hangups.recv().await,
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let hangup = std::future::pending::<Option<()>>();
+
+        let tick = async {
+            match &mut interval {
+                Some(interval) => interval.tick().await,
+                None => std::future::pending().await,
+            }
+        };
+
+        tokio::select! {
+            Some(()) = hangup => {
+                if let Some(reloaded) = reload(&settings, &started) {
+                    settings = reloaded;
+                }
+                crate::log_level::reset();
+                #[cfg(feature = "tls")]
+                crate::tls::reload();
+                modified = modified_of(&settings);
+            }
+            _ = tick => {
+                let now = modified_of(&settings);
+                if now != modified {
+                    modified = now;
+                    if let Some(reloaded) = reload(&settings, &started) {
+                        settings = reloaded;
+                    }
+                }
+            }
+        }
+    }
+}
+
+// the new settings, once they're in effect. a configuration that doesn't
+// load keeps the current one
+fn reload(settings: &Settings, started: &Config) -> Option<Settings> {
+    let (reloaded, config, cold) = match read(settings, started) {
+        Ok(read) => read,
+        Err(err) => {
+            tracing::error!(
+                "can't reload the configuration, keeping the current one: {}",
+                err
+            );
+            return None;
+        }
+    };
+
+    for name in cold {
+        tracing::warn!(setting = %name, "changed, it takes a restart to pick it up");
+    }
+    apply(&config);
+    tracing::info!("configuration reloaded");
+
+    Some(reloaded)
+}
+
+// the settings read again, the configuration they make and the names of the
+// settings that changed but take a restart
+fn read(
+    settings: &Settings,
+    started: &Config,
+) -> Result<(Settings, Config, Vec<String>), ConfigError> {
+    let reloaded = settings.reload()?;
+    let (config, _) = Config::from_settings(&reloaded)?;
+
+    let mut cold: Vec<_> = settings
+        .changed(&reloaded)
+        .into_iter()
+        .filter(|name| !HOT.contains(&name.as_str()))
+        .collect();
+    // only a rate limit, or a statement cache, the server started with can
+    // be changed
+    if started.rate_limit_per_sec == 0 && config.rate_limit_per_sec > 0 {
+        cold.push("RATE_LIMIT_PER_SEC".to_string());
+    }
+    if started.statement_cache_ttl.is_zero() && !config.statement_cache_ttl.is_zero() {
+        cold.push("STATEMENT_CACHE_TTL_MS".to_string());
+    }
+
+    Ok((reloaded, config, cold))
+}
+
+// the file's, or DEFAULT_FILE's for a file that may show up yet
+fn modified_of(settings: &Settings) -> Option<SystemTime> {
+    let path = settings
+        .path()
+        .map_or_else(|| PathBuf::from(DEFAULT_FILE), |path| path.to_path_buf());
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    // the configuration coming out of `read` is the one applied, which is
+    // the whole process's, so it isn't applied here
+    #[test]
+    fn a_hot_setting_is_applied_and_a_cold_one_only_warned_about() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rinha.toml");
+        std::fs::write(&path, "slow_query_ms = 100\nport = 3000\n").unwrap();
+        let settings = Settings::load(Some(&path), &[]).unwrap();
+        let (started, _) = Config::from_settings(&settings).unwrap();
+
+        std::fs::write(&path, "slow_query_ms = 250\nport = 4000\n").unwrap();
+        let (_, config, cold) = read(&settings, &started).unwrap();
+
+        assert_eq!(config.slow_query_threshold, Duration::from_millis(250));
+        assert_eq!(cold, ["PORT"]);
+    }
+
+    #[test]
+    fn a_cache_off_at_startup_takes_a_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rinha.toml");
+        std::fs::write(&path, "").unwrap();
+        let settings = Settings::load(Some(&path), &[]).unwrap();
+        let (started, _) = Config::from_settings(&settings).unwrap();
+
+        std::fs::write(&path, "statement_cache_ttl_ms = 500\n").unwrap();
+        let (_, _, cold) = read(&settings, &started).unwrap();
+
+        assert_eq!(cold, ["STATEMENT_CACHE_TTL_MS"]);
+    }
+}
@@ -0,0 +1,67 @@
+use axum::{
+    extract::{MatchedPath, RawPathParams, Request},
+    middleware::Next,
+    response::Response,
+};
+use sentry::{integrations::tracing::EventFilter, ClientInitGuard, Hub, SentryFutureExt};
+use tracing::{Level, Metadata, Subscriber};
+use tracing_subscriber::{registry::LookupSpan, Layer};
+
+// nothing is reported unless there's somewhere to report it to. the client
+// reads the DSN, and SENTRY_ENVIRONMENT and SENTRY_RELEASE, itself
+const DSN_VAR: &str = "SENTRY_DSN";
+
+// flushes the events still queued when dropped
+pub fn init() -> Option<ClientInitGuard> {
+    std::env::var_os(DSN_VAR)?;
+
+    Some(sentry::init(sentry::ClientOptions {
+        release: sentry::release_name!(),
+        ..Default::default()
+    }))
+}
+
+// the error logs become sentry events, with the warnings and infos before them
+// as breadcrumbs. the panics are reported by the client's own hook, with their
+// backtrace, so their log is only a breadcrumb
+pub fn layer<S>() -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    sentry::integrations::tracing::layer().event_filter(|metadata: &Metadata| {
+        match *metadata.level() {
+            Level::ERROR if metadata.fields().field("panic").is_some() => EventFilter::Breadcrumb,
+            Level::ERROR => EventFilter::Exception,
+            Level::WARN | Level::INFO => EventFilter::Breadcrumb,
+            _ => EventFilter::Ignore,
+        }
+    })
+}
+
+// what went wrong during a request is reported with its route, wallet and id,
+// from a hub of its own so they don't end up on another request's events
+pub async fn scope(
+    path: Option<MatchedPath>,
+    params: Option<RawPathParams>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let hub = Hub::new_from_top(Hub::current());
+    hub.configure_scope(|scope| {
+        if let Some(path) = &path {
+            scope.set_tag("route", path.as_str());
+        }
+        if let Some((_, id)) = params
+            .iter()
+            .flat_map(|params| params.iter())
+            .find(|(name, _)| *name == "id")
+        {
+            scope.set_tag("wallet_id", id);
+        }
+        if let Some(request_id) = crate::request_id::current() {
+            scope.set_tag("request_id", request_id);
+        }
+    });
+
+    next.run(req).bind_hub(hub).await
+}
@@ -0,0 +1,719 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use metrics::{counter, gauge};
+use time::OffsetDateTime;
+use tokio::sync::{broadcast, mpsc, oneshot};
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    auth::Scope,
+    errors::ApiError,
+    events::TransactionEvent,
+    models::{
+        Account, AuditFilter, AuditPage, CapturedHold, Cents, ChainVerification, CreatedWallet,
+        CreatedWebhook, FeeRule, HistoryFilter, HistoryPage, Hold, LedgerViolation, MonthlySummary,
+        PostFeeRule, PostHold, PostRecurrence, PostTransaction, PostTransfer, PostWallet,
+        PostWebhook, Reconciliation, Recurrence, ScheduledTransaction, Statement, StatementFilter,
+        SummaryMonth, TransactionBatchReceipt, TransactionDetails, TransactionReceipt,
+        TransferReceipt, Wallet, WalletDetails, WalletStatus, Webhook,
+    },
+};
+
+use super::{running_balances, OnWalletChanged, PoolStatus, TransactionStream, WalletRepository};
+
+// commands a wallet's actor queues before the senders have to wait
+const MAILBOX: usize = 256;
+
+type Reply<T> = oneshot::Sender<Result<T, ApiError>>;
+
+enum Command {
+    // the writes carry the audit context of the request they came from,
+    // task-locals don't follow them here
+    Insert {
+        transaction: PostTransaction,
+        idempotency_key: Option<String>,
+        context: audit::Context,
+        reply: Reply<TransactionReceipt>,
+    },
+    InsertBatch {
+        transactions: Vec<PostTransaction>,
+        context: audit::Context,
+        reply: Reply<TransactionBatchReceipt>,
+    },
+}
+
+#[derive(Clone)]
+struct Mailbox {
+    sender: mpsc::Sender<Command>,
+    // set when the wallet changed behind the actor's back, it reads the
+    // balance again on its next write
+    stale: Arc<AtomicBool>,
+}
+
+type Mailboxes = Arc<Mutex<HashMap<i32, Mailbox>>>;
+
+// wraps another repository and hands the transactions of each wallet to a
+// task of its own, which writes them one at a time in the order they came
+// in. concurrent requests for a hot wallet queue up here instead of on its
+// row lock, each holding a pool connection.
+//
+// the task also owns the wallet's balance as of its last write, and turns
+// away the debits that would go past the limit without asking the database.
+// the other instance, the background workers and the fees write to the
+// wallet behind its back, so the balance is only kept while `watch_changes`
+// hears about those writes, and dropped on every change it reports. tasks
+// stop after `idle_after` without work
+#[derive(Clone)]
+pub struct Actors<R> {
+    inner: R,
+    enabled: bool,
+    idle_after: Duration,
+    mailboxes: Mailboxes,
+    // whether the inner repository reports the wallet changes
+    watched: Arc<AtomicBool>,
+}
+
+impl<R: WalletRepository> Actors<R> {
+    // a pass-through unless `enabled`
+    pub fn new(inner: R, enabled: bool, idle_after: Duration) -> Self {
+        Actors {
+            inner,
+            enabled,
+            idle_after,
+            mailboxes: Arc::default(),
+            watched: Arc::default(),
+        }
+    }
+
+    async fn send(&self, wallet_id: i32, mut command: Command) {
+        loop {
+            let mailbox = self
+                .mailboxes
+                .lock()
+                .unwrap()
+                .entry(wallet_id)
+                .or_insert_with(|| self.spawn(wallet_id))
+                .sender
+                .clone();
+
+            // the actor went idle after the mailbox was looked up and took it
+            // out of the map, the next round starts a new one
+            command = match mailbox.send(command).await {
+                Ok(()) => return,
+                Err(mpsc::error::SendError(command)) => command,
+            };
+        }
+    }
+
+    fn spawn(&self, wallet_id: i32) -> Mailbox {
+        let (sender, receiver) = mpsc::channel(MAILBOX);
+        let stale = Arc::new(AtomicBool::new(false));
+        let actor = Actor {
+            inner: self.inner.clone(),
+            wallet_id,
+            wallet: None,
+            stale: stale.clone(),
+            watched: self.watched.clone(),
+            closing: false,
+        };
+        tokio::spawn(actor.run(receiver, self.mailboxes.clone(), self.idle_after));
+        gauge!("wallet_actors").increment(1.0);
+        Mailbox { sender, stale }
+    }
+}
+
+// `None` for every wallet. one without an actor has nothing to forget, and
+// one that just went idle doesn't keep its balance anymore
+fn forget(mailboxes: &Mutex<HashMap<i32, Mailbox>>, wallet_id: Option<i32>) {
+    let mailboxes = mailboxes.lock().unwrap();
+    match wallet_id {
+        Some(wallet_id) => {
+            if let Some(mailbox) = mailboxes.get(&wallet_id) {
+                mailbox.stale.store(true, Ordering::SeqCst);
+            }
+        }
+        None => {
+            for mailbox in mailboxes.values() {
+                mailbox.stale.store(true, Ordering::SeqCst);
+            }
+        }
+    }
+}
+
+struct Actor<R> {
+    inner: R,
+    wallet_id: i32,
+    // `(balance, credit_limit)` after the last write, `None` until one went
+    // through or once it can't be trusted
+    wallet: Option<(Cents, Cents)>,
+    stale: Arc<AtomicBool>,
+    watched: Arc<AtomicBool>,
+    // out of the map, nothing tells it about changes anymore
+    closing: bool,
+}
+
+impl<R: WalletRepository> Actor<R> {
+    async fn run(
+        mut self,
+        mut receiver: mpsc::Receiver<Command>,
+        mailboxes: Mailboxes,
+        idle_after: Duration,
+    ) {
+        loop {
+            let command = if self.closing {
+                receiver.recv().await
+            } else {
+                match tokio::time::timeout(idle_after, receiver.recv()).await {
+                    Ok(command) => command,
+                    Err(_) => {
+                        // nobody finds the mailbox from here on, and a sender
+                        // that already has it gets it back closed. what was
+                        // queued before the close still gets written
+                        mailboxes.lock().unwrap().remove(&self.wallet_id);
+                        receiver.close();
+                        self.closing = true;
+                        self.wallet = None;
+                        continue;
+                    }
+                }
+            };
+            let Some(command) = command else {
+                break;
+            };
+
+            if self.stale.swap(false, Ordering::SeqCst) {
+                self.wallet = None;
+            }
+
+            match command {
+                Command::Insert {
+                    transaction,
+                    idempotency_key,
+                    context,
+                    reply,
+                } => {
+                    let result = context
+                        .scope(self.insert(&transaction, idempotency_key.as_deref()))
+                        .await;
+                    let _ = reply.send(result);
+                }
+                Command::InsertBatch {
+                    transactions,
+                    context,
+                    reply,
+                } => {
+                    let result = context.scope(self.insert_batch(&transactions)).await;
+                    let _ = reply.send(result);
+                }
+            }
+        }
+
+        gauge!("wallet_actors").decrement(1.0);
+    }
+
+    // `LimitExceeded` straight away when the known balance can't cover
+    // `transactions`
+    fn check(&self, transactions: &[PostTransaction]) -> Result<(), ApiError> {
+        let Some((balance, credit_limit)) = self.wallet else {
+            return Ok(());
+        };
+
+        running_balances(balance, credit_limit, transactions)
+            .map(drop)
+            .inspect_err(|_| counter!("wallet_actor_rejections_total").increment(1))
+    }
+
+    // a change reported during the write is still pending in `stale`, the
+    // next command drops what's kept here
+    fn keep(&mut self, wallet: Option<(Cents, Cents)>) {
+        let trusted = self.watched.load(Ordering::SeqCst) && !self.closing;
+        self.wallet = wallet.filter(|_| trusted);
+    }
+
+    async fn insert(
+        &mut self,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        // a replay gets the original response, whatever the balance is now
+        if idempotency_key.is_none() {
+            self.check(std::slice::from_ref(transaction))?;
+        }
+
+        let result = self
+            .inner
+            .insert_transaction(self.wallet_id, transaction, idempotency_key)
+            .await;
+
+        // the receipt of a replay has the balance of back then
+        self.keep(match &result {
+            Ok(receipt) if idempotency_key.is_none() => {
+                receipt.wallet.balance.zip(receipt.wallet.credit_limit)
+            }
+            _ => None,
+        });
+        result
+    }
+
+    async fn insert_batch(
+        &mut self,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        self.check(transactions)?;
+
+        let result = self
+            .inner
+            .insert_transactions(self.wallet_id, transactions)
+            .await;
+
+        self.keep(match &result {
+            Ok(receipt) => Some((receipt.balance, receipt.credit_limit)),
+            Err(_) => None,
+        });
+        result
+    }
+}
+
+// the actor dropping the reply means it's gone, nothing was written
+async fn answer<T>(receiver: oneshot::Receiver<Result<T, ApiError>>) -> Result<T, ApiError> {
+    receiver.await.unwrap_or(Err(ApiError::Internal))
+}
+
+#[async_trait]
+impl<R: WalletRepository> WalletRepository for Actors<R> {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError> {
+        self.inner.get_statement(wallet_id, filter).await
+    }
+
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError> {
+        self.inner.get_wallet(wallet_id).await
+    }
+
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        if !self.enabled {
+            return self
+                .inner
+                .insert_transaction(wallet_id, transaction, idempotency_key)
+                .await;
+        }
+
+        let (reply, receiver) = oneshot::channel();
+        let command = Command::Insert {
+            transaction: transaction.clone(),
+            idempotency_key: idempotency_key.map(str::to_string),
+            context: audit::Context::current(),
+            reply,
+        };
+        self.send(wallet_id, command).await;
+        answer(receiver).await
+    }
+
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        if !self.enabled {
+            return self
+                .inner
+                .insert_transactions(wallet_id, transactions)
+                .await;
+        }
+
+        let (reply, receiver) = oneshot::channel();
+        let command = Command::InsertBatch {
+            transactions: transactions.to_vec(),
+            context: audit::Context::current(),
+            reply,
+        };
+        self.send(wallet_id, command).await;
+        answer(receiver).await
+    }
+
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError> {
+        self.inner.get_history(wallet_id, filter).await
+    }
+
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError> {
+        self.inner.get_summary(wallet_id, month).await
+    }
+
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError> {
+        self.inner.export(wallet_id).await
+    }
+
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError> {
+        self.inner.get_transaction(wallet_id, transaction_id).await
+    }
+
+    // transfers and limit changes skip the actors, which then read the
+    // balance again on their next write. the notification would tell them
+    // too, only later
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError> {
+        let result = self.inner.transfer(transfer).await;
+        forget(&self.mailboxes, Some(transfer.from));
+        forget(&self.mailboxes, Some(transfer.to));
+        result
+    }
+
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError> {
+        self.inner.create_wallet(wallet).await
+    }
+
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError> {
+        let result = self
+            .inner
+            .update_credit_limit(wallet_id, credit_limit, actor)
+            .await;
+        forget(&self.mailboxes, Some(wallet_id));
+        result
+    }
+
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.inner.set_wallet_status(wallet_id, status, actor).await
+    }
+
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.inner
+            .set_daily_debit_limit(wallet_id, limit, actor)
+            .await
+    }
+
+    async fn create_account(
+        &self,
+        client_id: i32,
+        wallet: &PostWallet,
+    ) -> Result<CreatedWallet, ApiError> {
+        self.inner.create_account(client_id, wallet).await
+    }
+
+    async fn list_accounts(&self, client_id: i32) -> Result<Vec<Account>, ApiError> {
+        self.inner.list_accounts(client_id).await
+    }
+
+    async fn owns_account(&self, client_id: i32, account_id: i32) -> Result<bool, ApiError> {
+        self.inner.owns_account(client_id, account_id).await
+    }
+
+    async fn reconcile(
+        &self,
+        wallet_id: i32,
+        repair: bool,
+        actor: &str,
+    ) -> Result<Reconciliation, ApiError> {
+        let result = self.inner.reconcile(wallet_id, repair, actor).await;
+        forget(&self.mailboxes, Some(wallet_id));
+        result
+    }
+
+    async fn verify_chain(&self, wallet_id: i32) -> Result<ChainVerification, ApiError> {
+        self.inner.verify_chain(wallet_id).await
+    }
+
+    async fn create_webhook(
+        &self,
+        wallet_id: i32,
+        webhook: &PostWebhook,
+    ) -> Result<CreatedWebhook, ApiError> {
+        self.inner.create_webhook(wallet_id, webhook).await
+    }
+
+    async fn list_webhooks(&self, wallet_id: i32) -> Result<Vec<Webhook>, ApiError> {
+        self.inner.list_webhooks(wallet_id).await
+    }
+
+    async fn delete_webhook(&self, wallet_id: i32, webhook_id: i32) -> Result<(), ApiError> {
+        self.inner.delete_webhook(wallet_id, webhook_id).await
+    }
+
+    // the actors don't know what the holds keep aside, the database turns
+    // away the debits that would dig into it. a capture moves the balance
+    // under them
+    async fn create_hold(&self, wallet_id: i32, hold: &PostHold) -> Result<Hold, ApiError> {
+        self.inner.create_hold(wallet_id, hold).await
+    }
+
+    async fn get_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.inner.get_hold(hold_id).await
+    }
+
+    async fn capture_hold(&self, hold_id: Uuid) -> Result<CapturedHold, ApiError> {
+        let captured = self.inner.capture_hold(hold_id).await?;
+        forget(&self.mailboxes, Some(captured.hold.wallet_id));
+        Ok(captured)
+    }
+
+    async fn release_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.inner.release_hold(hold_id).await
+    }
+
+    async fn schedule_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        due_at: OffsetDateTime,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.inner
+            .schedule_transaction(wallet_id, transaction, due_at)
+            .await
+    }
+
+    async fn list_scheduled(&self, wallet_id: i32) -> Result<Vec<ScheduledTransaction>, ApiError> {
+        self.inner.list_scheduled(wallet_id).await
+    }
+
+    async fn cancel_scheduled(
+        &self,
+        wallet_id: i32,
+        scheduled_id: Uuid,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.inner.cancel_scheduled(wallet_id, scheduled_id).await
+    }
+
+    async fn create_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.inner.create_recurrence(wallet_id, recurrence).await
+    }
+
+    async fn list_recurrences(&self, wallet_id: i32) -> Result<Vec<Recurrence>, ApiError> {
+        self.inner.list_recurrences(wallet_id).await
+    }
+
+    async fn get_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+    ) -> Result<Recurrence, ApiError> {
+        self.inner.get_recurrence(wallet_id, recurrence_id).await
+    }
+
+    async fn update_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.inner
+            .update_recurrence(wallet_id, recurrence_id, recurrence)
+            .await
+    }
+
+    async fn delete_recurrence(&self, wallet_id: i32, recurrence_id: i32) -> Result<(), ApiError> {
+        self.inner.delete_recurrence(wallet_id, recurrence_id).await
+    }
+
+    async fn create_fee_rule(&self, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.inner.create_fee_rule(rule).await
+    }
+
+    async fn list_fee_rules(&self) -> Result<Vec<FeeRule>, ApiError> {
+        self.inner.list_fee_rules().await
+    }
+
+    async fn update_fee_rule(&self, rule_id: i32, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.inner.update_fee_rule(rule_id, rule).await
+    }
+
+    async fn delete_fee_rule(&self, rule_id: i32) -> Result<(), ApiError> {
+        self.inner.delete_fee_rule(rule_id).await
+    }
+
+    async fn set_interest_rate(
+        &self,
+        wallet_id: i32,
+        rate: Option<f64>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.inner.set_interest_rate(wallet_id, rate, actor).await
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<AuditPage, ApiError> {
+        self.inner.audit_log(filter).await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        self.inner.subscribe()
+    }
+
+    fn watch_changes(&self, on_change: OnWalletChanged) -> bool {
+        let mailboxes = self.mailboxes.clone();
+        let watched = self.inner.watch_changes(Arc::new(move |wallet_id| {
+            forget(&mailboxes, wallet_id);
+            on_change(wallet_id);
+        }));
+        self.watched.store(watched, Ordering::SeqCst);
+        watched
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.inner.ping().await
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        self.inner.pool_status()
+    }
+
+    async fn run_migrations(&self) -> Result<(), ApiError> {
+        self.inner.run_migrations().await
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<(i64, String)>, ApiError> {
+        self.inner.pending_migrations().await
+    }
+
+    async fn missing_ledger_guards(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_ledger_guards().await
+    }
+
+    async fn missing_indexes(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_indexes().await
+    }
+
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError> {
+        self.inner.check_ledger().await
+    }
+
+    async fn rebuild_projections(&self) -> Result<u64, ApiError> {
+        let result = self.inner.rebuild_projections().await;
+        // every balance may have changed
+        forget(&self.mailboxes, None);
+        result
+    }
+
+    async fn seed(&self, reset: bool) -> Result<(), ApiError> {
+        let result = self.inner.seed(reset).await;
+        // a reset rewrites every wallet
+        forget(&self.mailboxes, None);
+        result
+    }
+
+    async fn api_key_scope(&self, key_hash: &str) -> Result<Option<Scope>, ApiError> {
+        self.inner.api_key_scope(key_hash).await
+    }
+
+    async fn close(&self) {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{models::RawPostTransaction, repository::MemoryWalletRepository};
+
+    fn transaction(value: i64, kind: &str) -> PostTransaction {
+        let raw: RawPostTransaction =
+            serde_json::from_value(json!({"valor": value, "tipo": kind, "descricao": "teste"}))
+                .unwrap();
+        PostTransaction::try_from(raw).unwrap()
+    }
+
+    // the memory repository doesn't report changes, so nothing is kept
+    #[tokio::test]
+    async fn a_credit_behind_the_actors_back_covers_the_next_debit() {
+        let inner = MemoryWalletRepository::seeded();
+        let actors = Actors::new(inner.clone(), true, Duration::from_secs(60));
+
+        // client 1 down to its limit of 100000
+        let receipt = actors
+            .insert_transaction(1, &transaction(100000, "d"), None)
+            .await
+            .unwrap();
+        assert_eq!(receipt.wallet.balance, Some(Cents::from(-100000)));
+
+        // like the scheduler or the other instance would
+        inner
+            .insert_transaction(1, &transaction(500, "c"), None)
+            .await
+            .unwrap();
+
+        let receipt = actors
+            .insert_transaction(1, &transaction(500, "d"), None)
+            .await
+            .unwrap();
+        assert_eq!(receipt.wallet.balance, Some(Cents::from(-100000)));
+    }
+
+    // as if the inner repository reported the changes, a write behind the
+    // actor's back only counts once it's reported
+    #[tokio::test]
+    async fn the_actor_owns_the_balance_until_a_change_is_reported() {
+        let inner = MemoryWalletRepository::seeded();
+        let actors = Actors::new(inner.clone(), true, Duration::from_secs(60));
+        actors.watched.store(true, Ordering::SeqCst);
+
+        actors
+            .insert_transaction(1, &transaction(100000, "d"), None)
+            .await
+            .unwrap();
+        inner
+            .insert_transaction(1, &transaction(500, "c"), None)
+            .await
+            .unwrap();
+
+        // turned away without asking `inner`, which would take it
+        let err = actors
+            .insert_transaction(1, &transaction(500, "d"), None)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ApiError::LimitExceeded));
+
+        // what the `wallet_changed` listener calls
+        forget(&actors.mailboxes, Some(1));
+        let receipt = actors
+            .insert_transaction(1, &transaction(500, "d"), None)
+            .await
+            .unwrap();
+        assert_eq!(receipt.wallet.balance, Some(Cents::from(-100000)));
+    }
+}
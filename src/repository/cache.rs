@@ -0,0 +1,595 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use metrics::counter;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    auth::Scope,
+    errors::ApiError,
+    events::TransactionEvent,
+    models::{
+        Account, AuditFilter, AuditPage, CapturedHold, Cents, ChainVerification, CreatedWallet,
+        CreatedWebhook, FeeRule, HistoryFilter, HistoryPage, Hold, LedgerViolation, MonthlySummary,
+        PostFeeRule, PostHold, PostRecurrence, PostTransaction, PostTransfer, PostWallet,
+        PostWebhook, Reconciliation, Recurrence, ScheduledTransaction, Statement, StatementFilter,
+        SummaryMonth, TransactionBatchReceipt, TransactionDetails, TransactionReceipt,
+        TransferReceipt, Wallet, WalletDetails, WalletStatus, Webhook,
+    },
+};
+
+use super::{OnWalletChanged, PoolStatus, TransactionStream, WalletRepository};
+
+// wallets share these, a write to one only stops the others in its stripe
+// from caching a read that was in flight
+const GENERATION_STRIPES: usize = 64;
+
+// in milliseconds, what every cache keeps the entries stored from now on for
+static TTL_MS: AtomicU64 = AtomicU64::new(0);
+
+pub(super) fn set_ttl(ttl: Duration) {
+    TTL_MS.store(ttl.as_millis() as u64, Ordering::Relaxed);
+}
+
+// the ttl at the time the entry was stored, as `time_to_live` would but
+// without building the cache again when it changes
+struct Ttl;
+
+impl moka::Expiry<i32, Statement> for Ttl {
+    fn expire_after_create(&self, _: &i32, _: &Statement, _: Instant) -> Option<Duration> {
+        Some(Duration::from_millis(TTL_MS.load(Ordering::Relaxed)))
+    }
+
+    fn expire_after_update(
+        &self,
+        key: &i32,
+        statement: &Statement,
+        at: Instant,
+        _: Option<Duration>,
+    ) -> Option<Duration> {
+        self.expire_after_create(key, statement, at)
+    }
+}
+
+// the unfiltered statement of each wallet, kept for up to a ttl. clones
+// share the entries, so a listener for other instances' writes can drop them
+#[derive(Clone)]
+pub struct StatementCache {
+    entries: moka::sync::Cache<i32, Statement>,
+    // bumped by every write before the entry is dropped, so a read that
+    // started before the write can tell its result may be stale
+    generations: Arc<[AtomicU64; GENERATION_STRIPES]>,
+}
+
+impl StatementCache {
+    // `None` for a zero ttl, which turns caching off
+    pub fn new(ttl: Duration, max_wallets: u64) -> Option<Self> {
+        (!ttl.is_zero()).then(|| StatementCache {
+            entries: moka::sync::Cache::builder()
+                .expire_after(Ttl)
+                .max_capacity(max_wallets)
+                .build(),
+            generations: Arc::new(std::array::from_fn(|_| AtomicU64::new(0))),
+        })
+    }
+
+    pub fn invalidate(&self, wallet_id: i32) {
+        self.stripe(wallet_id).fetch_add(1, Ordering::SeqCst);
+        self.entries.invalidate(&wallet_id);
+    }
+
+    pub fn invalidate_all(&self) {
+        for generation in self.generations.iter() {
+            generation.fetch_add(1, Ordering::SeqCst);
+        }
+        self.entries.invalidate_all();
+    }
+
+    // for `WalletRepository::watch_changes`
+    pub fn on_change(&self) -> OnWalletChanged {
+        let cache = self.clone();
+        Arc::new(move |wallet_id| match wallet_id {
+            Some(wallet_id) => cache.invalidate(wallet_id),
+            None => cache.invalidate_all(),
+        })
+    }
+
+    fn stripe(&self, wallet_id: i32) -> &AtomicU64 {
+        &self.generations[wallet_id.unsigned_abs() as usize % GENERATION_STRIPES]
+    }
+
+    fn generation(&self, wallet_id: i32) -> u64 {
+        self.stripe(wallet_id).load(Ordering::SeqCst)
+    }
+
+    // the entry goes in first and comes back out if a write bumped the
+    // generation meanwhile. a write that bumps it after the check drops the
+    // entry itself, either way nothing stale outlives the write
+    fn store(&self, wallet_id: i32, generation: u64, statement: &Statement) {
+        if self.generation(wallet_id) != generation {
+            return;
+        }
+        self.entries.insert(wallet_id, statement.clone());
+        if self.generation(wallet_id) != generation {
+            self.entries.invalidate(&wallet_id);
+        }
+    }
+}
+
+// wraps another repository and answers unfiltered statements from a
+// `StatementCache`. writes through this instance drop the wallet's entry once
+// they commit; writes from other instances only show up after the ttl, unless
+// something listening for them invalidates the cache
+#[derive(Clone)]
+pub struct Cached<R> {
+    inner: R,
+    cache: Option<StatementCache>,
+}
+
+impl<R> Cached<R> {
+    pub fn new(inner: R, cache: Option<StatementCache>) -> Self {
+        Cached { inner, cache }
+    }
+
+    fn invalidate(&self, wallet_id: i32) {
+        if let Some(cache) = &self.cache {
+            cache.invalidate(wallet_id);
+        }
+    }
+}
+
+#[async_trait]
+impl<R: WalletRepository> WalletRepository for Cached<R> {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError> {
+        // a filtered statement is a different body, those always go through
+        let (
+            Some(cache),
+            StatementFilter {
+                from: None,
+                to: None,
+                kind: None,
+            },
+        ) = (&self.cache, filter)
+        else {
+            return self.inner.get_statement(wallet_id, filter).await;
+        };
+
+        if let Some(statement) = cache.entries.get(&wallet_id) {
+            counter!("statement_cache_total", "result" => "hit").increment(1);
+            return Ok(statement);
+        }
+        counter!("statement_cache_total", "result" => "miss").increment(1);
+
+        let generation = cache.generation(wallet_id);
+        let statement = self.inner.get_statement(wallet_id, filter).await?;
+        cache.store(wallet_id, generation, &statement);
+
+        Ok(statement)
+    }
+
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError> {
+        self.inner.get_wallet(wallet_id).await
+    }
+
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        let receipt = self
+            .inner
+            .insert_transaction(wallet_id, transaction, idempotency_key)
+            .await?;
+        self.invalidate(wallet_id);
+        Ok(receipt)
+    }
+
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        let receipt = self
+            .inner
+            .insert_transactions(wallet_id, transactions)
+            .await?;
+        self.invalidate(wallet_id);
+        Ok(receipt)
+    }
+
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError> {
+        self.inner.get_history(wallet_id, filter).await
+    }
+
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError> {
+        self.inner.get_summary(wallet_id, month).await
+    }
+
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError> {
+        self.inner.export(wallet_id).await
+    }
+
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError> {
+        self.inner.get_transaction(wallet_id, transaction_id).await
+    }
+
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError> {
+        let receipt = self.inner.transfer(transfer).await?;
+        self.invalidate(transfer.from);
+        self.invalidate(transfer.to);
+        Ok(receipt)
+    }
+
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError> {
+        self.inner.create_wallet(wallet).await
+    }
+
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError> {
+        let wallet = self
+            .inner
+            .update_credit_limit(wallet_id, credit_limit, actor)
+            .await?;
+        self.invalidate(wallet_id);
+        Ok(wallet)
+    }
+
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.inner.set_wallet_status(wallet_id, status, actor).await
+    }
+
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.inner
+            .set_daily_debit_limit(wallet_id, limit, actor)
+            .await
+    }
+
+    async fn create_account(
+        &self,
+        client_id: i32,
+        wallet: &PostWallet,
+    ) -> Result<CreatedWallet, ApiError> {
+        self.inner.create_account(client_id, wallet).await
+    }
+
+    async fn list_accounts(&self, client_id: i32) -> Result<Vec<Account>, ApiError> {
+        self.inner.list_accounts(client_id).await
+    }
+
+    async fn owns_account(&self, client_id: i32, account_id: i32) -> Result<bool, ApiError> {
+        self.inner.owns_account(client_id, account_id).await
+    }
+
+    async fn reconcile(
+        &self,
+        wallet_id: i32,
+        repair: bool,
+        actor: &str,
+    ) -> Result<Reconciliation, ApiError> {
+        let reconciliation = self.inner.reconcile(wallet_id, repair, actor).await?;
+        if reconciliation.repaired {
+            self.invalidate(wallet_id);
+        }
+        Ok(reconciliation)
+    }
+
+    async fn verify_chain(&self, wallet_id: i32) -> Result<ChainVerification, ApiError> {
+        self.inner.verify_chain(wallet_id).await
+    }
+
+    async fn create_webhook(
+        &self,
+        wallet_id: i32,
+        webhook: &PostWebhook,
+    ) -> Result<CreatedWebhook, ApiError> {
+        self.inner.create_webhook(wallet_id, webhook).await
+    }
+
+    async fn list_webhooks(&self, wallet_id: i32) -> Result<Vec<Webhook>, ApiError> {
+        self.inner.list_webhooks(wallet_id).await
+    }
+
+    async fn delete_webhook(&self, wallet_id: i32, webhook_id: i32) -> Result<(), ApiError> {
+        self.inner.delete_webhook(wallet_id, webhook_id).await
+    }
+
+    async fn create_hold(&self, wallet_id: i32, hold: &PostHold) -> Result<Hold, ApiError> {
+        self.inner.create_hold(wallet_id, hold).await
+    }
+
+    async fn get_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.inner.get_hold(hold_id).await
+    }
+
+    // the only one of them that moves the balance
+    async fn capture_hold(&self, hold_id: Uuid) -> Result<CapturedHold, ApiError> {
+        let captured = self.inner.capture_hold(hold_id).await?;
+        self.invalidate(captured.hold.wallet_id);
+        Ok(captured)
+    }
+
+    async fn release_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.inner.release_hold(hold_id).await
+    }
+
+    async fn schedule_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        due_at: OffsetDateTime,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.inner
+            .schedule_transaction(wallet_id, transaction, due_at)
+            .await
+    }
+
+    async fn list_scheduled(&self, wallet_id: i32) -> Result<Vec<ScheduledTransaction>, ApiError> {
+        self.inner.list_scheduled(wallet_id).await
+    }
+
+    async fn cancel_scheduled(
+        &self,
+        wallet_id: i32,
+        scheduled_id: Uuid,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.inner.cancel_scheduled(wallet_id, scheduled_id).await
+    }
+
+    async fn create_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.inner.create_recurrence(wallet_id, recurrence).await
+    }
+
+    async fn list_recurrences(&self, wallet_id: i32) -> Result<Vec<Recurrence>, ApiError> {
+        self.inner.list_recurrences(wallet_id).await
+    }
+
+    async fn get_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+    ) -> Result<Recurrence, ApiError> {
+        self.inner.get_recurrence(wallet_id, recurrence_id).await
+    }
+
+    async fn update_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.inner
+            .update_recurrence(wallet_id, recurrence_id, recurrence)
+            .await
+    }
+
+    async fn delete_recurrence(&self, wallet_id: i32, recurrence_id: i32) -> Result<(), ApiError> {
+        self.inner.delete_recurrence(wallet_id, recurrence_id).await
+    }
+
+    async fn create_fee_rule(&self, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.inner.create_fee_rule(rule).await
+    }
+
+    async fn list_fee_rules(&self) -> Result<Vec<FeeRule>, ApiError> {
+        self.inner.list_fee_rules().await
+    }
+
+    async fn update_fee_rule(&self, rule_id: i32, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.inner.update_fee_rule(rule_id, rule).await
+    }
+
+    async fn delete_fee_rule(&self, rule_id: i32) -> Result<(), ApiError> {
+        self.inner.delete_fee_rule(rule_id).await
+    }
+
+    async fn set_interest_rate(
+        &self,
+        wallet_id: i32,
+        rate: Option<f64>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.inner.set_interest_rate(wallet_id, rate, actor).await
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<AuditPage, ApiError> {
+        self.inner.audit_log(filter).await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        self.inner.subscribe()
+    }
+
+    fn watch_changes(&self, on_change: OnWalletChanged) -> bool {
+        self.inner.watch_changes(on_change)
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.inner.ping().await
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        self.inner.pool_status()
+    }
+
+    async fn run_migrations(&self) -> Result<(), ApiError> {
+        self.inner.run_migrations().await
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<(i64, String)>, ApiError> {
+        self.inner.pending_migrations().await
+    }
+
+    async fn missing_ledger_guards(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_ledger_guards().await
+    }
+
+    async fn missing_indexes(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_indexes().await
+    }
+
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError> {
+        self.inner.check_ledger().await
+    }
+
+    async fn rebuild_projections(&self) -> Result<u64, ApiError> {
+        let rebuilt = self.inner.rebuild_projections().await?;
+        // every balance may have changed
+        if let Some(cache) = &self.cache {
+            cache.invalidate_all();
+        }
+        Ok(rebuilt)
+    }
+
+    async fn seed(&self, reset: bool) -> Result<(), ApiError> {
+        self.inner.seed(reset).await?;
+        // a reset rewrites every wallet
+        if let Some(cache) = &self.cache {
+            cache.invalidate_all();
+        }
+        Ok(())
+    }
+
+    async fn api_key_scope(&self, key_hash: &str) -> Result<Option<Scope>, ApiError> {
+        self.inner.api_key_scope(key_hash).await
+    }
+
+    async fn close(&self) {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::{models::RawPostTransaction, repository::MemoryWalletRepository};
+
+    // every test here sets the same one, the ttl is shared by the caches
+    const TTL: Duration = Duration::from_millis(100);
+
+    fn credit(value: i64) -> PostTransaction {
+        let raw: RawPostTransaction =
+            serde_json::from_value(json!({"valor": value, "tipo": "c", "descricao": "teste"}))
+                .unwrap();
+        PostTransaction::try_from(raw).unwrap()
+    }
+
+    fn cached() -> (Cached<MemoryWalletRepository>, MemoryWalletRepository) {
+        set_ttl(TTL);
+        let inner = MemoryWalletRepository::seeded();
+        (
+            Cached::new(inner.clone(), StatementCache::new(TTL, 100)),
+            inner,
+        )
+    }
+
+    async fn balance(repo: &impl WalletRepository) -> Option<Cents> {
+        repo.get_statement(1, &StatementFilter::default())
+            .await
+            .unwrap()
+            .wallet
+            .balance
+    }
+
+    #[tokio::test]
+    async fn a_write_through_the_cache_drops_the_statement() {
+        let (cached, _) = cached();
+        assert_eq!(balance(&cached).await, Some(Cents::ZERO));
+
+        cached
+            .insert_transaction(1, &credit(100), None)
+            .await
+            .unwrap();
+        assert_eq!(balance(&cached).await, Some(Cents::from(100)));
+    }
+
+    #[tokio::test]
+    async fn a_write_behind_its_back_shows_up_once_invalidated_or_expired() {
+        let (cached, inner) = cached();
+        assert_eq!(balance(&cached).await, Some(Cents::ZERO));
+
+        // what another instance writing looks like
+        inner
+            .insert_transaction(1, &credit(100), None)
+            .await
+            .unwrap();
+        assert_eq!(balance(&cached).await, Some(Cents::ZERO));
+        cached.cache.as_ref().unwrap().invalidate(1);
+        assert_eq!(balance(&cached).await, Some(Cents::from(100)));
+
+        inner
+            .insert_transaction(1, &credit(100), None)
+            .await
+            .unwrap();
+        assert_eq!(balance(&cached).await, Some(Cents::from(100)));
+        tokio::time::sleep(TTL).await;
+        assert_eq!(balance(&cached).await, Some(Cents::from(200)));
+    }
+
+    // a read that started before a write doesn't store what it read
+    #[test]
+    fn a_read_from_before_a_write_isnt_stored() {
+        set_ttl(TTL);
+        let cache = StatementCache::new(TTL, 100).unwrap();
+        let statement = Statement {
+            wallet: Wallet {
+                balance: Some(Cents::ZERO),
+                credit_limit: Some(Cents::ZERO),
+            },
+            currency: "BRL".into(),
+            transactions: Vec::new(),
+        };
+
+        let generation = cache.generation(1);
+        cache.invalidate(1);
+        cache.store(1, generation, &statement);
+        assert!(cache.entries.get(&1).is_none());
+
+        cache.store(1, cache.generation(1), &statement);
+        assert!(cache.entries.get(&1).is_some());
+    }
+}
@@ -0,0 +1,516 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use metrics::gauge;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    auth::Scope,
+    errors::ApiError,
+    events::TransactionEvent,
+    models::{
+        Account, AuditFilter, AuditPage, CapturedHold, Cents, ChainVerification, CreatedWallet,
+        CreatedWebhook, FeeRule, HistoryFilter, HistoryPage, Hold, LedgerViolation, MonthlySummary,
+        PostFeeRule, PostHold, PostRecurrence, PostTransaction, PostTransfer, PostWallet,
+        PostWebhook, Reconciliation, Recurrence, ScheduledTransaction, Statement, StatementFilter,
+        SummaryMonth, TransactionBatchReceipt, TransactionDetails, TransactionReceipt,
+        TransferReceipt, Wallet, WalletDetails, WalletStatus, Webhook,
+    },
+};
+
+use super::{OnWalletChanged, PoolStatus, TransactionStream, WalletRepository};
+
+// wraps another repository and stops sending it requests after `threshold`
+// database errors in a row. while open every call fails with `Unavailable`
+// straight away; once `reset_after` has passed a single trial call is let
+// through, and its outcome closes the breaker or opens it for another round.
+// a threshold of 0 never opens
+#[derive(Clone)]
+pub struct CircuitBreaker<R> {
+    inner: R,
+    threshold: u32,
+    reset_after: Duration,
+    state: Arc<Mutex<BreakerState>>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    // a trial whose caller went away never reports back, so another one is
+    // allowed once this is older than `reset_after`
+    trial_started_at: Option<Instant>,
+}
+
+impl<R> CircuitBreaker<R> {
+    pub fn new(inner: R, threshold: u32, reset_after: Duration) -> Self {
+        CircuitBreaker {
+            inner,
+            threshold,
+            reset_after,
+            state: Arc::default(),
+        }
+    }
+
+    fn before_call(&self) -> Result<(), ApiError> {
+        let mut state = self.state.lock().unwrap();
+
+        let Some(opened_at) = state.opened_at else {
+            return Ok(());
+        };
+
+        let now = Instant::now();
+        let trial_running = state
+            .trial_started_at
+            .is_some_and(|started| now.duration_since(started) < self.reset_after);
+
+        if now.duration_since(opened_at) < self.reset_after || trial_running {
+            return Err(ApiError::Unavailable);
+        }
+
+        state.trial_started_at = Some(now);
+        Ok(())
+    }
+
+    fn after_call<T>(&self, result: &Result<T, ApiError>) {
+        let mut state = self.state.lock().unwrap();
+
+        // only the database failing counts, a missing wallet or a rejected
+        // debit is the database working as intended
+        if let Err(ApiError::Database(_) | ApiError::Timeout) = result {
+            state.consecutive_failures = state.consecutive_failures.saturating_add(1);
+
+            let was_trial = state.trial_started_at.take().is_some();
+            if self.threshold > 0 && (was_trial || state.consecutive_failures >= self.threshold) {
+                if state.opened_at.is_none() {
+                    tracing::warn!(
+                        "database failed {} times in a row, opening the circuit breaker",
+                        state.consecutive_failures
+                    );
+                    gauge!("db_circuit_breaker_open").set(1.0);
+                }
+                state.opened_at = Some(Instant::now());
+            }
+        } else {
+            if state.opened_at.is_some() {
+                tracing::info!("database is back, closing the circuit breaker");
+                gauge!("db_circuit_breaker_open").set(0.0);
+            }
+            *state = BreakerState::default();
+        }
+    }
+
+    async fn guard<T, F>(&self, call: F) -> Result<T, ApiError>
+    where
+        F: std::future::Future<Output = Result<T, ApiError>>,
+    {
+        self.before_call()?;
+        let result = call.await;
+        self.after_call(&result);
+        result
+    }
+}
+
+#[async_trait]
+impl<R: WalletRepository> WalletRepository for CircuitBreaker<R> {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError> {
+        self.guard(self.inner.get_statement(wallet_id, filter))
+            .await
+    }
+
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError> {
+        self.guard(self.inner.get_wallet(wallet_id)).await
+    }
+
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        self.guard(
+            self.inner
+                .insert_transaction(wallet_id, transaction, idempotency_key),
+        )
+        .await
+    }
+
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        self.guard(self.inner.insert_transactions(wallet_id, transactions))
+            .await
+    }
+
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError> {
+        self.guard(self.inner.get_history(wallet_id, filter)).await
+    }
+
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError> {
+        self.guard(self.inner.get_summary(wallet_id, month)).await
+    }
+
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError> {
+        self.guard(self.inner.export(wallet_id)).await
+    }
+
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError> {
+        self.guard(self.inner.get_transaction(wallet_id, transaction_id))
+            .await
+    }
+
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError> {
+        self.guard(self.inner.transfer(transfer)).await
+    }
+
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError> {
+        self.guard(self.inner.create_wallet(wallet)).await
+    }
+
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError> {
+        self.guard(
+            self.inner
+                .update_credit_limit(wallet_id, credit_limit, actor),
+        )
+        .await
+    }
+
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.guard(self.inner.set_wallet_status(wallet_id, status, actor))
+            .await
+    }
+
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.guard(self.inner.set_daily_debit_limit(wallet_id, limit, actor))
+            .await
+    }
+
+    async fn create_account(
+        &self,
+        client_id: i32,
+        wallet: &PostWallet,
+    ) -> Result<CreatedWallet, ApiError> {
+        self.guard(self.inner.create_account(client_id, wallet))
+            .await
+    }
+
+    async fn list_accounts(&self, client_id: i32) -> Result<Vec<Account>, ApiError> {
+        self.guard(self.inner.list_accounts(client_id)).await
+    }
+
+    async fn owns_account(&self, client_id: i32, account_id: i32) -> Result<bool, ApiError> {
+        self.guard(self.inner.owns_account(client_id, account_id))
+            .await
+    }
+
+    async fn reconcile(
+        &self,
+        wallet_id: i32,
+        repair: bool,
+        actor: &str,
+    ) -> Result<Reconciliation, ApiError> {
+        self.guard(self.inner.reconcile(wallet_id, repair, actor))
+            .await
+    }
+
+    async fn verify_chain(&self, wallet_id: i32) -> Result<ChainVerification, ApiError> {
+        self.guard(self.inner.verify_chain(wallet_id)).await
+    }
+
+    async fn create_webhook(
+        &self,
+        wallet_id: i32,
+        webhook: &PostWebhook,
+    ) -> Result<CreatedWebhook, ApiError> {
+        self.guard(self.inner.create_webhook(wallet_id, webhook))
+            .await
+    }
+
+    async fn list_webhooks(&self, wallet_id: i32) -> Result<Vec<Webhook>, ApiError> {
+        self.guard(self.inner.list_webhooks(wallet_id)).await
+    }
+
+    async fn delete_webhook(&self, wallet_id: i32, webhook_id: i32) -> Result<(), ApiError> {
+        self.guard(self.inner.delete_webhook(wallet_id, webhook_id))
+            .await
+    }
+
+    async fn create_hold(&self, wallet_id: i32, hold: &PostHold) -> Result<Hold, ApiError> {
+        self.guard(self.inner.create_hold(wallet_id, hold)).await
+    }
+
+    async fn get_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.guard(self.inner.get_hold(hold_id)).await
+    }
+
+    async fn capture_hold(&self, hold_id: Uuid) -> Result<CapturedHold, ApiError> {
+        self.guard(self.inner.capture_hold(hold_id)).await
+    }
+
+    async fn release_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.guard(self.inner.release_hold(hold_id)).await
+    }
+
+    async fn schedule_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        due_at: OffsetDateTime,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.guard(
+            self.inner
+                .schedule_transaction(wallet_id, transaction, due_at),
+        )
+        .await
+    }
+
+    async fn list_scheduled(&self, wallet_id: i32) -> Result<Vec<ScheduledTransaction>, ApiError> {
+        self.guard(self.inner.list_scheduled(wallet_id)).await
+    }
+
+    async fn cancel_scheduled(
+        &self,
+        wallet_id: i32,
+        scheduled_id: Uuid,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.guard(self.inner.cancel_scheduled(wallet_id, scheduled_id))
+            .await
+    }
+
+    async fn create_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.guard(self.inner.create_recurrence(wallet_id, recurrence))
+            .await
+    }
+
+    async fn list_recurrences(&self, wallet_id: i32) -> Result<Vec<Recurrence>, ApiError> {
+        self.guard(self.inner.list_recurrences(wallet_id)).await
+    }
+
+    async fn get_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+    ) -> Result<Recurrence, ApiError> {
+        self.guard(self.inner.get_recurrence(wallet_id, recurrence_id))
+            .await
+    }
+
+    async fn update_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.guard(
+            self.inner
+                .update_recurrence(wallet_id, recurrence_id, recurrence),
+        )
+        .await
+    }
+
+    async fn delete_recurrence(&self, wallet_id: i32, recurrence_id: i32) -> Result<(), ApiError> {
+        self.guard(self.inner.delete_recurrence(wallet_id, recurrence_id))
+            .await
+    }
+
+    async fn create_fee_rule(&self, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.guard(self.inner.create_fee_rule(rule)).await
+    }
+
+    async fn list_fee_rules(&self) -> Result<Vec<FeeRule>, ApiError> {
+        self.guard(self.inner.list_fee_rules()).await
+    }
+
+    async fn update_fee_rule(&self, rule_id: i32, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.guard(self.inner.update_fee_rule(rule_id, rule)).await
+    }
+
+    async fn delete_fee_rule(&self, rule_id: i32) -> Result<(), ApiError> {
+        self.guard(self.inner.delete_fee_rule(rule_id)).await
+    }
+
+    async fn set_interest_rate(
+        &self,
+        wallet_id: i32,
+        rate: Option<f64>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.guard(self.inner.set_interest_rate(wallet_id, rate, actor))
+            .await
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<AuditPage, ApiError> {
+        self.guard(self.inner.audit_log(filter)).await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        self.inner.subscribe()
+    }
+
+    // readiness probes always reach the database, so they keep reporting the
+    // real state while the breaker is open
+    fn watch_changes(&self, on_change: OnWalletChanged) -> bool {
+        self.inner.watch_changes(on_change)
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.inner.ping().await
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        self.inner.pool_status()
+    }
+
+    async fn run_migrations(&self) -> Result<(), ApiError> {
+        self.inner.run_migrations().await
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<(i64, String)>, ApiError> {
+        self.inner.pending_migrations().await
+    }
+
+    async fn missing_ledger_guards(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_ledger_guards().await
+    }
+
+    async fn missing_indexes(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_indexes().await
+    }
+
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError> {
+        self.inner.check_ledger().await
+    }
+
+    async fn rebuild_projections(&self) -> Result<u64, ApiError> {
+        self.inner.rebuild_projections().await
+    }
+
+    async fn seed(&self, reset: bool) -> Result<(), ApiError> {
+        self.inner.seed(reset).await
+    }
+
+    async fn api_key_scope(&self, key_hash: &str) -> Result<Option<Scope>, ApiError> {
+        self.guard(self.inner.api_key_scope(key_hash)).await
+    }
+
+    async fn close(&self) {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use super::*;
+    use crate::repository::MemoryWalletRepository;
+
+    const RESET_AFTER: Duration = Duration::from_millis(50);
+
+    fn breaker() -> CircuitBreaker<MemoryWalletRepository> {
+        CircuitBreaker::new(MemoryWalletRepository::new(), 2, RESET_AFTER)
+    }
+
+    // what the breaker answers for a call that would end in `result`, and
+    // whether the call was made at all
+    async fn call(
+        breaker: &CircuitBreaker<MemoryWalletRepository>,
+        result: Result<(), ApiError>,
+    ) -> (Result<(), ApiError>, bool) {
+        let called = AtomicBool::new(false);
+        let answer = breaker
+            .guard(async {
+                called.store(true, Ordering::Relaxed);
+                result
+            })
+            .await;
+        (answer, called.load(Ordering::Relaxed))
+    }
+
+    fn failure() -> Result<(), ApiError> {
+        Err(ApiError::Database(sqlx::Error::PoolTimedOut))
+    }
+
+    #[tokio::test]
+    async fn it_opens_lets_a_trial_through_and_closes() {
+        let breaker = breaker();
+
+        // a missing wallet is the database working
+        assert!(call(&breaker, Err(ApiError::NotFound)).await.1);
+        assert!(call(&breaker, failure()).await.1);
+        assert!(call(&breaker, failure()).await.1);
+
+        // open: nothing reaches the database
+        let (answer, called) = call(&breaker, Ok(())).await;
+        assert!(matches!(answer, Err(ApiError::Unavailable)));
+        assert!(!called);
+
+        // half open: a single trial, which failing opens it again right away
+        tokio::time::sleep(RESET_AFTER).await;
+        assert!(breaker.before_call().is_ok());
+        assert!(matches!(breaker.before_call(), Err(ApiError::Unavailable)));
+        breaker.after_call(&failure());
+        assert!(!call(&breaker, Ok(())).await.1);
+
+        // and succeeding closes it
+        tokio::time::sleep(RESET_AFTER).await;
+        let (answer, called) = call(&breaker, Ok(())).await;
+        assert!(answer.is_ok() && called);
+        assert!(call(&breaker, Ok(())).await.1);
+
+        // the count of failures starts over
+        assert!(call(&breaker, failure()).await.1);
+        assert!(call(&breaker, Ok(())).await.1);
+    }
+
+    #[tokio::test]
+    async fn a_threshold_of_0_never_opens() {
+        let breaker = CircuitBreaker::new(MemoryWalletRepository::new(), 0, RESET_AFTER);
+
+        for _ in 0..10 {
+            assert!(call(&breaker, failure()).await.1);
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use metrics::counter;
+use sqlx::PgPool;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::db;
+
+// holds one round expires, a backlog drains over the following rounds
+const MAX_HOLDS: i64 = 1000;
+
+// expires the pending holds past their time every `interval`, giving back
+// what they kept aside. a hold past its time can't be captured even before
+// it's swept, this only frees the amount. every instance runs one, the
+// holds one of them is expiring are skipped by the others
+#[derive(Clone)]
+pub struct HoldSweeper {
+    stop: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    task: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl HoldSweeper {
+    pub fn spawn(pool: PgPool, interval: Duration) -> Self {
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(sweep(pool, stopped, interval));
+
+        HoldSweeper {
+            stop: Arc::new(Mutex::new(Some(stop))),
+            task: Arc::new(tokio::sync::Mutex::new(Some(task))),
+        }
+    }
+
+    // finishes the round in flight and stops
+    pub async fn close(&self) {
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.lock().await.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+async fn sweep(pool: PgPool, mut stopped: oneshot::Receiver<()>, interval: Duration) {
+    // the first round after an `interval`, like the reconciler's
+    let start = tokio::time::Instant::now() + interval;
+    let mut ticks = tokio::time::interval_at(start, interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                match db::expire_holds(&pool, MAX_HOLDS).await {
+                    Ok(expired) => counter!("holds_expired_total").increment(expired),
+                    Err(err) => tracing::warn!(error = %err, "expiring the holds failed, retrying"),
+                }
+            }
+            _ = &mut stopped => return,
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use metrics::counter;
+use time::OffsetDateTime;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::audit;
+
+use super::postgres::PgWalletRepository;
+
+// wallets one round charges, a backlog drains over the following rounds
+const MAX_WALLETS: usize = 1000;
+
+#[derive(Clone, Copy)]
+pub struct InterestOptions {
+    pub check_interval: Duration,
+    // the (utc) hour a day's interest is charged from
+    pub accrual_hour: u8,
+    // the rate of the wallets without one of their own
+    pub default_rate: f64,
+}
+
+// charges each wallet with a negative balance its interest once a day, as a
+// debit in the `juros` category, one database transaction each. the day is
+// recorded along with the debit, so a restart or another instance never
+// charges it twice. every instance runs one, the wallets one of them is
+// charging are skipped by the others
+#[derive(Clone)]
+pub struct InterestAccruer {
+    stop: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    task: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl InterestAccruer {
+    pub fn spawn(repo: PgWalletRepository, options: InterestOptions) -> Self {
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(run(repo, stopped, options));
+
+        InterestAccruer {
+            stop: Arc::new(Mutex::new(Some(stop))),
+            task: Arc::new(tokio::sync::Mutex::new(Some(task))),
+        }
+    }
+
+    // finishes the round in flight and stops
+    pub async fn close(&self) {
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.lock().await.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+async fn run(
+    repo: PgWalletRepository,
+    mut stopped: oneshot::Receiver<()>,
+    options: InterestOptions,
+) {
+    // the first round after an `interval`, like the scheduler's
+    let interval = options.check_interval;
+    let start = tokio::time::Instant::now() + interval;
+    let mut ticks = tokio::time::interval_at(start, interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    let context = || audit::Context {
+        actor: Some("interest".into()),
+        request_id: None,
+    };
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                context().scope(round(&repo, options)).await;
+            }
+            _ = &mut stopped => return,
+        }
+    }
+}
+
+async fn round(repo: &PgWalletRepository, options: InterestOptions) {
+    let now = OffsetDateTime::now_utc();
+    if now.hour() < options.accrual_hour {
+        return;
+    }
+
+    for _ in 0..MAX_WALLETS {
+        match repo.accrue_interest(now.date(), options.default_rate).await {
+            Ok(Some(charged)) => {
+                let status = if charged { "charged" } else { "failed" };
+                counter!("interest_accruals_total", "status" => status).increment(1);
+            }
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!(error = %err, "accruing interest failed, retrying");
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,648 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use async_trait::async_trait;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    db::SEED_WALLETS,
+    errors::ApiError,
+    events::{Events, TransactionEvent},
+    models::{
+        Cents, CreatedWallet, HistoryCursor, HistoryFilter, HistoryPage, KindTotal,
+        LedgerViolation, MonthlySummary, PostTransaction, PostTransfer, PostWallet, Statement,
+        StatementFilter, SummaryMonth, TransactionBatchReceipt, TransactionDetails,
+        TransactionItem, TransactionKind, TransactionReceipt, TransferReceipt, Wallet,
+        WalletDetails, WalletStatus, DEFAULT_CURRENCY,
+    },
+};
+
+use super::{
+    batch_receipt, check_currency, check_daily_debits, check_status, debit_total, history_page,
+    limit_below_balance, running_balances, start_of_day, TransactionStream, WalletRepository,
+};
+
+struct WalletState {
+    balance: Cents,
+    credit_limit: Cents,
+    external_id: Option<String>,
+    currency: String,
+    status: WalletStatus,
+    daily_debit_limit: Option<Cents>,
+    inserted_at: OffsetDateTime,
+    // oldest first, the statement reads it backwards
+    transactions: Vec<TransactionDetails>,
+    // by key, along with the fingerprint of the transaction
+    idempotent_responses: HashMap<String, (String, TransactionReceipt)>,
+}
+
+impl Default for WalletState {
+    fn default() -> Self {
+        WalletState {
+            balance: Cents::ZERO,
+            credit_limit: Cents::ZERO,
+            external_id: None,
+            currency: DEFAULT_CURRENCY.to_string(),
+            status: WalletStatus::Active,
+            daily_debit_limit: None,
+            inserted_at: OffsetDateTime::now_utc(),
+            transactions: Vec::new(),
+            idempotent_responses: HashMap::new(),
+        }
+    }
+}
+
+impl WalletState {
+    // today's rows are the newest ones, the walk stops at the first older one
+    fn debited_today(&self) -> Cents {
+        let since = start_of_day();
+        self.transactions
+            .iter()
+            .rev()
+            .map(|row| &row.transaction)
+            .take_while(|row| row.inserted_at >= since)
+            .filter(|row| row.kind == TransactionKind::Debit)
+            .map(|row| row.value)
+            .fold(Cents::ZERO, Cents::saturating_add)
+    }
+}
+
+// keeps everything in process, nothing survives a restart. the map lock
+// is only taken for writing when wallets are added, writes to a wallet
+// serialize on that wallet's own mutex
+#[derive(Clone, Default)]
+pub struct MemoryWalletRepository {
+    wallets: Arc<RwLock<HashMap<i32, Arc<Mutex<WalletState>>>>>,
+    events: Events,
+}
+
+impl MemoryWalletRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // starts with the same five clients the migrations create
+    pub fn seeded() -> Self {
+        let repo = Self::new();
+        for (id, credit_limit) in SEED_WALLETS {
+            repo.add_wallet(id, credit_limit.into());
+        }
+        repo
+    }
+
+    pub fn add_wallet(&self, id: i32, credit_limit: Cents) {
+        let wallet = WalletState {
+            credit_limit,
+            ..WalletState::default()
+        };
+
+        self.wallets
+            .write()
+            .unwrap()
+            .insert(id, Arc::new(Mutex::new(wallet)));
+    }
+
+    fn wallet(&self, id: i32) -> Result<Arc<Mutex<WalletState>>, ApiError> {
+        self.wallets
+            .read()
+            .unwrap()
+            .get(&id)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+}
+
+#[async_trait]
+impl WalletRepository for MemoryWalletRepository {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError> {
+        let wallet = self.wallet(wallet_id)?;
+        let wallet = wallet.lock().unwrap();
+
+        let transactions = wallet
+            .transactions
+            .iter()
+            .rev()
+            .map(|row| &row.transaction)
+            .filter(|row| {
+                filter.from.is_none_or(|from| row.inserted_at >= from)
+                    && filter.to.is_none_or(|to| row.inserted_at < to)
+                    && filter.kind.is_none_or(|kind| row.kind == kind)
+            })
+            .take(10)
+            .cloned()
+            .collect();
+
+        Ok(Statement {
+            wallet: Wallet {
+                balance: Some(wallet.balance),
+                credit_limit: Some(wallet.credit_limit),
+            },
+            currency: wallet.currency.clone(),
+            transactions,
+        })
+    }
+
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError> {
+        let wallet = self.wallet(wallet_id)?;
+        let wallet = wallet.lock().unwrap();
+
+        Ok(WalletDetails {
+            id: wallet_id,
+            balance: wallet.balance,
+            credit_limit: wallet.credit_limit,
+            external_id: wallet.external_id.clone(),
+            currency: wallet.currency.clone(),
+            inserted_at: wallet.inserted_at,
+            transaction_count: wallet.transactions.len() as i64,
+            status: wallet.status,
+            daily_debit_limit: wallet.daily_debit_limit,
+            debited_today: wallet.debited_today(),
+            // holds and interest need postgres
+            held: Cents::ZERO,
+            interest_rate: None,
+        })
+    }
+
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        let wallet = self.wallet(wallet_id)?;
+        let mut wallet = wallet.lock().unwrap();
+
+        check_currency(&wallet.currency, transaction.currency.as_deref())?;
+
+        if let Some((fingerprint, stored)) =
+            idempotency_key.and_then(|key| wallet.idempotent_responses.get(key))
+        {
+            if *fingerprint != transaction.fingerprint() {
+                return Err(ApiError::IdempotencyKeyReused);
+            }
+            return Ok(stored.clone());
+        }
+
+        check_status(wallet.status)?;
+        if transaction.kind == TransactionKind::Debit {
+            check_daily_debits(
+                wallet.daily_debit_limit,
+                wallet.debited_today(),
+                transaction.value,
+            )?;
+        }
+
+        let balance = transaction
+            .apply(wallet.balance)
+            .ok_or(ApiError::LimitExceeded)?;
+
+        // same rule as the `positive_balance` constraint
+        if balance.saturating_add(wallet.credit_limit).is_negative() {
+            return Err(ApiError::LimitExceeded);
+        }
+
+        let item = TransactionItem {
+            value: transaction.value,
+            kind: transaction.kind,
+            description: transaction.description.clone(),
+            inserted_at: OffsetDateTime::now_utc(),
+            category: transaction.category.clone(),
+            tags: transaction.tags.clone(),
+            metadata: transaction.metadata.clone(),
+        };
+
+        let id = Uuid::new_v4();
+        wallet.balance = balance;
+        wallet.transactions.push(TransactionDetails {
+            id,
+            transaction: item.clone(),
+        });
+
+        let response = TransactionReceipt {
+            wallet: Wallet {
+                balance: Some(wallet.balance),
+                credit_limit: Some(wallet.credit_limit),
+            },
+            id: Some(id),
+            inserted_at: Some(item.inserted_at),
+        };
+
+        if let Some(key) = idempotency_key {
+            wallet.idempotent_responses.insert(
+                key.to_string(),
+                (transaction.fingerprint(), response.clone()),
+            );
+        }
+
+        // still under the wallet lock, so events come out in the order the
+        // transactions were applied
+        self.events.publish(TransactionEvent {
+            wallet_id,
+            balance: wallet.balance,
+            credit_limit: wallet.credit_limit,
+            transaction: item,
+        });
+
+        Ok(response)
+    }
+
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        let wallet = self.wallet(wallet_id)?;
+        let mut wallet = wallet.lock().unwrap();
+
+        check_status(wallet.status)?;
+        for transaction in transactions {
+            check_currency(&wallet.currency, transaction.currency.as_deref())?;
+        }
+        check_daily_debits(
+            wallet.daily_debit_limit,
+            wallet.debited_today(),
+            debit_total(transactions),
+        )?;
+
+        let balances = running_balances(wallet.balance, wallet.credit_limit, transactions)?;
+
+        let inserted_at = OffsetDateTime::now_utc();
+        let mut rows = Vec::with_capacity(transactions.len());
+        for (transaction, balance) in transactions.iter().zip(&balances) {
+            let id = Uuid::new_v4();
+            let item = TransactionItem {
+                value: transaction.value,
+                kind: transaction.kind,
+                description: transaction.description.clone(),
+                inserted_at,
+                category: transaction.category.clone(),
+                tags: transaction.tags.clone(),
+                metadata: transaction.metadata.clone(),
+            };
+
+            wallet.balance = *balance;
+            wallet.transactions.push(TransactionDetails {
+                id,
+                transaction: item.clone(),
+            });
+            rows.push((id, inserted_at));
+
+            self.events.publish(TransactionEvent {
+                wallet_id,
+                balance: *balance,
+                credit_limit: wallet.credit_limit,
+                transaction: item,
+            });
+        }
+
+        Ok(batch_receipt(wallet.credit_limit, balances, rows))
+    }
+
+    // a row's position in the list stands in for its id
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError> {
+        let wallet = self.wallet(wallet_id)?;
+        let wallet = wallet.lock().unwrap();
+
+        let search = filter.search.as_ref().map(|search| search.to_lowercase());
+
+        let rows = wallet
+            .transactions
+            .iter()
+            .enumerate()
+            .map(|(position, row)| {
+                let cursor = HistoryCursor {
+                    inserted_at: row.transaction.inserted_at,
+                    id: position as i64 + 1,
+                };
+                (cursor, row)
+            })
+            .filter(|(cursor, _)| {
+                filter.after.is_none_or(|after| {
+                    (cursor.inserted_at, cursor.id) > (after.inserted_at, after.id)
+                })
+            })
+            .filter(|(_, row)| {
+                search.as_ref().is_none_or(|search| {
+                    row.transaction.description.to_lowercase().contains(search)
+                })
+            })
+            .filter(|(_, row)| {
+                filter
+                    .category
+                    .as_ref()
+                    .is_none_or(|category| row.transaction.category.as_ref() == Some(category))
+            })
+            .filter(|(_, row)| {
+                filter
+                    .tag
+                    .as_ref()
+                    .is_none_or(|tag| row.transaction.tags.contains(tag))
+            })
+            .filter(|(_, row)| filter.matches_metadata(row.transaction.metadata.as_ref()))
+            .take(filter.limit as usize + 1)
+            .map(|(cursor, row)| (cursor, row.clone()))
+            .collect();
+
+        Ok(history_page(rows, filter))
+    }
+
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError> {
+        let wallet = self.wallet(wallet_id)?;
+        let wallet = wallet.lock().unwrap();
+        let (from, to) = month.range();
+
+        let totals = wallet
+            .transactions
+            .iter()
+            .map(|row| &row.transaction)
+            .filter(|row| row.inserted_at >= from && row.inserted_at < to)
+            .map(|row| KindTotal {
+                kind: row.kind,
+                category: row.category.clone(),
+                sum: row.value,
+                count: 1,
+            });
+
+        Ok(MonthlySummary::new(month, totals))
+    }
+
+    // a copy of the rows, there's nothing to stream them from
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError> {
+        let wallet = self.wallet(wallet_id)?;
+        let rows = wallet.lock().unwrap().transactions.clone();
+
+        Ok(Box::pin(tokio_stream::iter(rows.into_iter().map(Ok))))
+    }
+
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError> {
+        let wallet = self.wallet(wallet_id)?;
+        let wallet = wallet.lock().unwrap();
+
+        wallet
+            .transactions
+            .iter()
+            .find(|row| row.id == transaction_id)
+            .cloned()
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError> {
+        let from = self.wallet(transfer.from)?;
+        let to = self.wallet(transfer.to)?;
+
+        // locked in id order, like the database does
+        let (mut from, mut to) = if transfer.from < transfer.to {
+            let from = from.lock().unwrap();
+            (from, to.lock().unwrap())
+        } else {
+            let to = to.lock().unwrap();
+            (from.lock().unwrap(), to)
+        };
+
+        check_status(from.status)?;
+        check_status(to.status)?;
+        check_currency(&from.currency, Some(&to.currency))?;
+        check_currency(&from.currency, transfer.currency.as_deref())?;
+        check_daily_debits(from.daily_debit_limit, from.debited_today(), transfer.value)?;
+
+        let debited = from
+            .balance
+            .checked_sub(transfer.value)
+            .filter(|balance| !balance.saturating_add(from.credit_limit).is_negative())
+            .ok_or(ApiError::LimitExceeded)?;
+        let credited = to
+            .balance
+            .checked_add(transfer.value)
+            .ok_or(ApiError::LimitExceeded)?;
+
+        let inserted_at = OffsetDateTime::now_utc();
+        for (wallet_id, state, balance, kind) in [
+            (transfer.from, &mut from, debited, TransactionKind::Debit),
+            (transfer.to, &mut to, credited, TransactionKind::Credit),
+        ] {
+            let item = TransactionItem {
+                value: transfer.value,
+                kind,
+                description: transfer.description.clone(),
+                inserted_at,
+                category: None,
+                tags: Vec::new(),
+                metadata: None,
+            };
+
+            state.balance = balance;
+            state.transactions.push(TransactionDetails {
+                id: Uuid::new_v4(),
+                transaction: item.clone(),
+            });
+
+            self.events.publish(TransactionEvent {
+                wallet_id,
+                balance,
+                credit_limit: state.credit_limit,
+                transaction: item,
+            });
+        }
+
+        Ok(TransferReceipt {
+            id: Uuid::new_v4(),
+            balance: from.balance,
+            credit_limit: from.credit_limit,
+        })
+    }
+
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError> {
+        let mut wallets = self.wallets.write().unwrap();
+
+        if let Some(external_id) = &wallet.external_id {
+            let taken = wallets
+                .values()
+                .any(|state| state.lock().unwrap().external_id.as_ref() == Some(external_id));
+            if taken {
+                return Err(ApiError::Conflict);
+            }
+        }
+
+        let id = wallets.keys().max().map_or(1, |id| id + 1);
+        let state = WalletState {
+            credit_limit: wallet.credit_limit,
+            external_id: wallet.external_id.clone(),
+            currency: wallet.currency.clone(),
+            daily_debit_limit: wallet.daily_debit_limit,
+            ..WalletState::default()
+        };
+        wallets.insert(id, Arc::new(Mutex::new(state)));
+
+        Ok(CreatedWallet {
+            id,
+            balance: Cents::ZERO,
+            credit_limit: wallet.credit_limit,
+            external_id: wallet.external_id.clone(),
+            currency: wallet.currency.clone(),
+            daily_debit_limit: wallet.daily_debit_limit,
+        })
+    }
+
+    // nothing to audit into, the change is only logged
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError> {
+        let wallet = self.wallet(wallet_id)?;
+        let mut wallet = wallet.lock().unwrap();
+
+        if wallet.balance.saturating_add(credit_limit).is_negative() {
+            return Err(limit_below_balance());
+        }
+
+        tracing::info!(
+            wallet_id,
+            actor,
+            "credit limit changed from {} to {}",
+            wallet.credit_limit,
+            credit_limit
+        );
+        wallet.credit_limit = credit_limit;
+
+        Ok(Wallet {
+            balance: Some(wallet.balance),
+            credit_limit: Some(wallet.credit_limit),
+        })
+    }
+
+    // like `update_credit_limit`, only logged
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        {
+            let wallet = self.wallet(wallet_id)?;
+            let mut wallet = wallet.lock().unwrap();
+
+            if wallet.status != status {
+                tracing::info!(
+                    wallet_id,
+                    actor,
+                    "status changed from {} to {}",
+                    wallet.status,
+                    status
+                );
+                wallet.status = status;
+            }
+        }
+
+        self.get_wallet(wallet_id).await
+    }
+
+    // logged like the others
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        {
+            let wallet = self.wallet(wallet_id)?;
+            let mut wallet = wallet.lock().unwrap();
+
+            if wallet.daily_debit_limit != limit {
+                tracing::info!(
+                    wallet_id,
+                    actor,
+                    "daily debit limit changed from {:?} to {:?}",
+                    wallet.daily_debit_limit.map(|limit| limit.to_string()),
+                    limit.map(|limit| limit.to_string())
+                );
+                wallet.daily_debit_limit = limit;
+            }
+        }
+
+        self.get_wallet(wallet_id).await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        self.events.subscribe()
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    // each row stands for both of its entries here, so they can't be out of
+    // balance. what's left is a balance that isn't its rows' sum
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError> {
+        let wallets = self.wallets.read().unwrap();
+        let mut ids: Vec<i32> = wallets.keys().copied().collect();
+        ids.sort_unstable();
+
+        Ok(ids
+            .into_iter()
+            .filter_map(|wallet_id| {
+                let wallet = wallets[&wallet_id].lock().unwrap();
+                let entries = wallet
+                    .transactions
+                    .iter()
+                    .map(|row| match row.transaction.kind {
+                        TransactionKind::Credit => row.transaction.value,
+                        TransactionKind::Debit => Cents::ZERO.saturating_sub(row.transaction.value),
+                    })
+                    .fold(Cents::ZERO, Cents::saturating_add);
+
+                (entries != wallet.balance).then_some(LedgerViolation::BalanceMismatch {
+                    wallet_id,
+                    balance: wallet.balance,
+                    entries,
+                })
+            })
+            .collect())
+    }
+
+    async fn rebuild_projections(&self) -> Result<u64, ApiError> {
+        let wallets = self.wallets.read().unwrap();
+        for wallet in wallets.values() {
+            let mut wallet = wallet.lock().unwrap();
+            wallet.balance = wallet
+                .transactions
+                .iter()
+                .map(|row| match row.transaction.kind {
+                    TransactionKind::Credit => row.transaction.value,
+                    TransactionKind::Debit => Cents::ZERO.saturating_sub(row.transaction.value),
+                })
+                .fold(Cents::ZERO, Cents::saturating_add);
+        }
+        Ok(wallets.len() as u64)
+    }
+
+    async fn seed(&self, reset: bool) -> Result<(), ApiError> {
+        for (id, credit_limit) in SEED_WALLETS {
+            if reset || self.wallet(id).is_err() {
+                self.add_wallet(id, credit_limit.into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,557 @@
+use std::{pin::Pin, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use tokio_stream::Stream;
+use uuid::Uuid;
+
+use crate::{
+    auth::Scope,
+    errors::ApiError,
+    events::TransactionEvent,
+    models::{
+        Account, AuditFilter, AuditPage, CapturedHold, Cents, ChainVerification, CreatedWallet,
+        CreatedWebhook, FeeRule, FieldError, HistoryCursor, HistoryFilter, HistoryPage, Hold,
+        LedgerViolation, MonthlySummary, PostFeeRule, PostHold, PostRecurrence, PostTransaction,
+        PostTransfer, PostWallet, PostWebhook, Reconciliation, Recurrence, ScheduledTransaction,
+        Statement, StatementFilter, SummaryMonth, TransactionBatchItem, TransactionBatchReceipt,
+        TransactionDetails, TransactionKind, TransactionReceipt, TransferReceipt, Wallet,
+        WalletDetails, WalletStatus, Webhook,
+    },
+};
+
+mod actors;
+mod cache;
+mod circuit_breaker;
+mod hold_sweeper;
+mod interest;
+mod memory;
+mod postgres;
+mod projector;
+#[cfg(feature = "nats")]
+mod publisher;
+mod reconciler;
+mod replicas;
+mod retry;
+mod scheduler;
+#[cfg(feature = "redis")]
+mod shared_cache;
+#[cfg(all(feature = "sqlite", not(feature = "decimal")))]
+mod sqlite;
+mod tenants;
+mod webhooks;
+mod write_behind;
+
+pub use actors::Actors;
+pub use cache::{Cached, StatementCache};
+pub use circuit_breaker::CircuitBreaker;
+pub use memory::MemoryWalletRepository;
+pub use postgres::PgWalletRepository;
+pub use retry::Retry;
+#[cfg(feature = "redis")]
+pub use shared_cache::SharedCache;
+#[cfg(all(feature = "sqlite", not(feature = "decimal")))]
+pub use sqlite::SqliteWalletRepository;
+pub use tenants::Tenants;
+
+// what the caches keep the statements stored from now on for. the statement
+// cache stays off if it started with a zero ttl, and a zero one set while
+// running expires every entry right away
+pub fn set_cache_ttls(statement: Duration, shared: Duration) {
+    cache::set_ttl(statement);
+    #[cfg(feature = "redis")]
+    shared_cache::set_ttl(shared);
+    #[cfg(not(feature = "redis"))]
+    let _ = shared;
+}
+
+// `action` of the audit log rows written by `update_credit_limit`
+const CREDIT_LIMIT_CHANGED: &str = "credit_limit_changed";
+// and by `set_wallet_status`
+const STATUS_CHANGED: &str = "status_changed";
+// and by `set_daily_debit_limit`
+const DAILY_DEBIT_LIMIT_CHANGED: &str = "daily_debit_limit_changed";
+// and by `set_interest_rate`
+const INTEREST_RATE_CHANGED: &str = "interest_rate_changed";
+
+// the category, and description, of the debits interest is charged as
+const INTEREST_CATEGORY: &str = "juros";
+
+fn limit_below_balance() -> ApiError {
+    ApiError::Validation(vec![FieldError {
+        field: "limite".into(),
+        message: "must cover the current balance",
+    }])
+}
+
+// `CurrencyMismatch` when `requested` names another currency than `wallet`'s.
+// not naming one means the wallet's
+fn check_currency(wallet: &str, requested: Option<&str>) -> Result<(), ApiError> {
+    match requested {
+        Some(requested) if requested != wallet => Err(ApiError::CurrencyMismatch),
+        _ => Ok(()),
+    }
+}
+
+// `WalletBlocked` unless the wallet takes new transactions
+fn check_status(status: WalletStatus) -> Result<(), ApiError> {
+    match status {
+        WalletStatus::Active => Ok(()),
+        WalletStatus::Blocked => Err(ApiError::WalletBlocked),
+    }
+}
+
+// `DailyLimitExceeded` when a debit of `value` on top of the `debited` today
+// goes past the wallet's daily `limit`, if it has one
+fn check_daily_debits(limit: Option<Cents>, debited: Cents, value: Cents) -> Result<(), ApiError> {
+    match limit {
+        Some(limit) if debited.saturating_add(value) > limit => Err(ApiError::DailyLimitExceeded),
+        _ => Ok(()),
+    }
+}
+
+// what the debits among `transactions` add up to
+fn debit_total(transactions: &[PostTransaction]) -> Cents {
+    transactions
+        .iter()
+        .filter(|transaction| transaction.kind == TransactionKind::Debit)
+        .map(|transaction| transaction.value)
+        .fold(Cents::ZERO, Cents::saturating_add)
+}
+
+// the start of the current day, which the daily debit limit counts from
+fn start_of_day() -> OffsetDateTime {
+    OffsetDateTime::now_utc().replace_time(time::Time::MIDNIGHT)
+}
+
+// the balance right after each of `transactions`, applied in order on top
+// of `balance`. `LimitExceeded` as soon as one goes past the credit limit
+fn running_balances(
+    mut balance: Cents,
+    credit_limit: Cents,
+    transactions: &[PostTransaction],
+) -> Result<Vec<Cents>, ApiError> {
+    transactions
+        .iter()
+        .map(|transaction| {
+            balance = transaction
+                .apply(balance)
+                .filter(|balance| !balance.saturating_add(credit_limit).is_negative())
+                .ok_or(ApiError::LimitExceeded)?;
+            Ok(balance)
+        })
+        .collect()
+}
+
+// pairs every running balance with the `(id, inserted_at)` of its row
+fn batch_receipt(
+    credit_limit: Cents,
+    balances: Vec<Cents>,
+    rows: Vec<(Uuid, OffsetDateTime)>,
+) -> TransactionBatchReceipt {
+    TransactionBatchReceipt {
+        balance: balances.last().copied().unwrap_or_default(),
+        credit_limit,
+        transactions: balances
+            .into_iter()
+            .zip(rows)
+            .map(|(balance, (id, inserted_at))| TransactionBatchItem {
+                id,
+                balance,
+                inserted_at,
+            })
+            .collect(),
+    }
+}
+
+// cuts the rows fetched for a page, one more than `limit` so it's known
+// whether another page follows
+fn history_page(
+    mut rows: Vec<(HistoryCursor, TransactionDetails)>,
+    filter: &HistoryFilter,
+) -> HistoryPage {
+    let limit = filter.limit as usize;
+    let next = (rows.len() > limit).then(|| rows[limit - 1].0);
+    rows.truncate(limit);
+
+    let (cursors, transactions) = rows.into_iter().unzip();
+    HistoryPage {
+        transactions,
+        next,
+        cursors,
+    }
+}
+
+// called with the wallet another write changed, `None` when it can't tell
+// which ones did
+pub type OnWalletChanged = Arc<dyn Fn(Option<i32>) + Send + Sync>;
+
+// rows handed out one at a time, for results too big to collect first
+pub type TransactionStream =
+    Pin<Box<dyn Stream<Item = Result<TransactionDetails, ApiError>> + Send>>;
+
+// rows a streaming query reads ahead of the client. a slow client holds the
+// query back instead of rows piling up in memory
+const STREAM_BUFFER: usize = 64;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStatus {
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
+}
+
+// storage behind the handlers. `NotFound`, `LimitExceeded` and
+// `CurrencyMismatch` are reported through `ApiError` so every backend
+// surfaces them the same way
+#[async_trait]
+pub trait WalletRepository: Clone + Send + Sync + 'static {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError>;
+
+    // the wallet itself, without its transactions
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError>;
+
+    // applies the transaction and returns the updated balance along with the
+    // id of the new transaction. when an idempotency key is given and was
+    // already used on this wallet, the receipt recorded the first time is
+    // returned and nothing is applied
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError>;
+
+    // applies all of the transactions in order, or none of them when any
+    // would take the balance past the credit limit
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError>;
+
+    // every transaction of the wallet, oldest first, one page at a time
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError>;
+
+    // credits, debits and count of the transactions made during the month
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError>;
+
+    // the whole history like `get_history`, read as the stream is polled.
+    // `NotFound` is reported before any row
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError>;
+
+    // a single transaction of the wallet. `NotFound` when it belongs to
+    // another one
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError>;
+
+    // debits `from` and credits `to` in one go, recording a transaction on
+    // each. `LimitExceeded` when the source can't afford it, and
+    // `CurrencyMismatch` unless both wallets hold the same currency
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError>;
+
+    // a new wallet with a zero balance. `Conflict` when the external id is
+    // already taken
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError>;
+
+    // sets a new credit limit and records the change in the audit log under
+    // `actor`. a limit that no longer covers a negative balance is rejected
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError>;
+
+    // blocks or unblocks the wallet, recording the change in the audit log
+    // under `actor`. a blocked one turns every new transaction away with
+    // `WalletBlocked`, transfers to it included
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError>;
+
+    // sets or, with `None`, lifts the wallet's daily debit limit, recording
+    // the change in the audit log under `actor`. debits past it are turned
+    // away with `DailyLimitExceeded`
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError>;
+
+    // another wallet for the client, listed among its accounts. `NotFound`
+    // for an unknown client or one that's itself an account. `NotSupported`
+    // for backends that only keep the default accounts
+    async fn create_account(
+        &self,
+        _client_id: i32,
+        _wallet: &PostWallet,
+    ) -> Result<CreatedWallet, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // the client's accounts, the default one first
+    async fn list_accounts(&self, _client_id: i32) -> Result<Vec<Account>, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // whether the account is one the client created, the default account
+    // is the client itself and isn't asked about
+    async fn owns_account(&self, _client_id: i32, _account_id: i32) -> Result<bool, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // compares the balance with what the wallet's transactions add up to and,
+    // with `repair`, fixes it, recording the drift in the audit log under
+    // `actor`. `NotSupported` for backends that don't keep an audit log
+    async fn reconcile(
+        &self,
+        _wallet_id: i32,
+        _repair: bool,
+        _actor: &str,
+    ) -> Result<Reconciliation, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // walks the wallet's hash chain from its first transaction, stopping at
+    // the first link that doesn't hold. `NotSupported` for backends that
+    // don't chain their transactions
+    async fn verify_chain(&self, _wallet_id: i32) -> Result<ChainVerification, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // a url that gets each new transaction of the wallet posted to it from
+    // then on. `NotSupported` for backends without an outbox to deliver from
+    async fn create_webhook(
+        &self,
+        _wallet_id: i32,
+        _webhook: &PostWebhook,
+    ) -> Result<CreatedWebhook, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // the wallet's webhooks, oldest first and without their secrets
+    async fn list_webhooks(&self, _wallet_id: i32) -> Result<Vec<Webhook>, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // drops the webhook along with the deliveries it still has queued
+    async fn delete_webhook(&self, _wallet_id: i32, _webhook_id: i32) -> Result<(), ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // sets the amount aside until the hold is captured, released or expires.
+    // it's debited like a transaction would be, so `LimitExceeded`,
+    // `CurrencyMismatch` and `WalletBlocked` come back the same way.
+    // `NotSupported` for backends without a sweeper to expire the holds
+    async fn create_hold(&self, _wallet_id: i32, _hold: &PostHold) -> Result<Hold, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    async fn get_hold(&self, _hold_id: Uuid) -> Result<Hold, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // turns a pending hold into a debit of its amount. `HoldSettled` once
+    // it's been captured, released or has expired
+    async fn capture_hold(&self, _hold_id: Uuid) -> Result<CapturedHold, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // gives the amount of a pending hold back, `HoldSettled` like
+    // `capture_hold`
+    async fn release_hold(&self, _hold_id: Uuid) -> Result<Hold, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // stores the transaction to be applied at `due_at`, when the limits are
+    // checked. `NotFound`, `CurrencyMismatch` and `WalletBlocked` come back
+    // right away. `NotSupported` for backends without a scheduler
+    async fn schedule_transaction(
+        &self,
+        _wallet_id: i32,
+        _transaction: &PostTransaction,
+        _due_at: OffsetDateTime,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // the wallet's pending scheduled transactions, the next one due first
+    async fn list_scheduled(&self, _wallet_id: i32) -> Result<Vec<ScheduledTransaction>, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // `ScheduledSettled` once it's been applied, has failed or was cancelled
+    async fn cancel_scheduled(
+        &self,
+        _wallet_id: i32,
+        _scheduled_id: Uuid,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // a transaction the scheduler makes on every run of its schedule, checked
+    // like `schedule_transaction`'s. `NotSupported` for backends without a
+    // scheduler
+    async fn create_recurrence(
+        &self,
+        _wallet_id: i32,
+        _recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // the wallet's recurrences, oldest first
+    async fn list_recurrences(&self, _wallet_id: i32) -> Result<Vec<Recurrence>, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    async fn get_recurrence(
+        &self,
+        _wallet_id: i32,
+        _recurrence_id: i32,
+    ) -> Result<Recurrence, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // replaces the definition, the next run worked out again from now
+    async fn update_recurrence(
+        &self,
+        _wallet_id: i32,
+        _recurrence_id: i32,
+        _recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // the transactions it made stay
+    async fn delete_recurrence(
+        &self,
+        _wallet_id: i32,
+        _recurrence_id: i32,
+    ) -> Result<(), ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // a rule the fees charged alongside the transactions from now on are
+    // worked out with. `NotSupported` for backends that don't charge fees
+    async fn create_fee_rule(&self, _rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // every rule, the oldest first, which is the order the fees are charged
+    // in
+    async fn list_fee_rules(&self) -> Result<Vec<FeeRule>, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // the fees already charged stay like they were
+    async fn update_fee_rule(
+        &self,
+        _rule_id: i32,
+        _rule: &PostFeeRule,
+    ) -> Result<FeeRule, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    async fn delete_fee_rule(&self, _rule_id: i32) -> Result<(), ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // sets or, with `None`, clears the wallet's daily interest rate, which
+    // leaves it to the default one, recording the change in the audit log
+    // under `actor`. `NotSupported` for backends that don't accrue interest
+    async fn set_interest_rate(
+        &self,
+        _wallet_id: i32,
+        _rate: Option<f64>,
+        _actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // the audit log rows matching `filter`, newest first. `NotSupported` for
+    // backends that don't record every write
+    async fn audit_log(&self, _filter: &AuditFilter) -> Result<AuditPage, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // every transaction committed from now on, on any wallet. replays of an
+    // idempotency key aren't reported again
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent>;
+
+    // reports the writes of the other instances to `on_change`, for backends
+    // they share. `false` when there's nothing to report
+    fn watch_changes(&self, _on_change: OnWalletChanged) -> bool {
+        false
+    }
+
+    async fn ping(&self) -> Result<(), ApiError>;
+
+    // connection pool usage, for backends that have one
+    fn pool_status(&self) -> Option<PoolStatus> {
+        None
+    }
+
+    // schema management, a no-op for backends without a schema
+    async fn run_migrations(&self) -> Result<(), ApiError> {
+        Ok(())
+    }
+
+    // `(version, description)` of the migrations not applied yet
+    async fn pending_migrations(&self) -> Result<Vec<(i64, String)>, ApiError> {
+        Ok(Vec::new())
+    }
+
+    // the triggers keeping the transactions immutable the schema lacks.
+    // `NotSupported` for backends without them
+    async fn missing_ledger_guards(&self) -> Result<Vec<String>, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // the indexes the migrations create that the schema lacks, `NotSupported`
+    // for backends that don't check
+    async fn missing_indexes(&self) -> Result<Vec<String>, ApiError> {
+        Err(ApiError::NotSupported)
+    }
+
+    // whatever doesn't add up in the ledger: transactions without their two
+    // balanced entries, and wallets whose balance isn't the sum of their
+    // account's. reads everything, meant for the cli rather than requests
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError>;
+
+    // works every balance out again from the transactions, the event log
+    // they're a projection of. returns the number of wallets
+    async fn rebuild_projections(&self) -> Result<u64, ApiError>;
+
+    // creates the canonical rinha clients, see `db::seed`
+    async fn seed(&self, reset: bool) -> Result<(), ApiError>;
+
+    // the scope of the api key with this hash, for backends that keep an
+    // `api_keys` table
+    async fn api_key_scope(&self, _key_hash: &str) -> Result<Option<Scope>, ApiError> {
+        Ok(None)
+    }
+
+    async fn close(&self) {}
+}
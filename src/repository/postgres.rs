@@ -0,0 +1,1768 @@
+use std::{
+    convert::Infallible,
+    sync::{Arc, Once},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use serde_json::json;
+use sqlx::{
+    pool::PoolConnection, postgres::PgListener, Connection, PgConnection, PgPool, Postgres,
+};
+use time::{Date, OffsetDateTime};
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::{
+    audit,
+    auth::Scope,
+    config::Config,
+    cron::Schedule,
+    db,
+    errors::ApiError,
+    events::{Events, TransactionEvent},
+    models::{
+        Account, AuditFilter, AuditPage, CapturedHold, Cents, ChainVerification, CreatedWallet,
+        CreatedWebhook, FeeRule, HistoryFilter, HistoryPage, Hold, HoldStatus, LedgerViolation,
+        MonthlySummary, PostFeeRule, PostHold, PostRecurrence, PostTransaction, PostTransfer,
+        PostWallet, PostWebhook, Reconciliation, Recurrence, ScheduledStatus, ScheduledTransaction,
+        Statement, StatementFilter, SummaryMonth, TransactionBatchReceipt, TransactionDetails,
+        TransactionItem, TransactionKind, TransactionReceipt, TransferReceipt, Wallet,
+        WalletDetails, WalletStatus, Webhook, DEFAULT_HOLD_TTL_SECS,
+    },
+};
+
+use super::{
+    batch_receipt, check_currency, check_status, history_page,
+    hold_sweeper::HoldSweeper,
+    interest::{InterestAccruer, InterestOptions},
+    limit_below_balance,
+    projector::Projector,
+    reconciler::{self, ReconcileOptions, Reconciler},
+    replicas::Replicas,
+    running_balances,
+    scheduler::Scheduler,
+    webhooks::{self, Deliverer, DeliveryOptions},
+    write_behind::{Slot, WriteBehind},
+    OnWalletChanged, PoolStatus, TransactionStream, WalletRepository, CREDIT_LIMIT_CHANGED,
+    DAILY_DEBIT_LIMIT_CHANGED, INTEREST_CATEGORY, INTEREST_RATE_CHANGED, STATUS_CHANGED,
+    STREAM_BUFFER,
+};
+
+#[derive(Clone)]
+pub struct PgWalletRepository {
+    pool: PgPool,
+    // statements and the other reads, the same pool as `pool` unless
+    // DB_READ_MAX_CONNECTIONS gives them their own
+    read_pool: PgPool,
+    // statements and listings go to these first
+    replicas: Replicas,
+    events: Events,
+    // the listener holds on to a pool connection, so it's only started by
+    // the first subscriber
+    listener: Arc<Once>,
+    write_behind: Option<WriteBehind>,
+    // writes go through the `register_transaction` function
+    db_function: bool,
+    // fees are charged alongside the transactions
+    fees: bool,
+    // writes leave the wallet rows to `projector` and only append, to
+    // `transactions` and `pending_projections`
+    event_sourcing: bool,
+    projector: Option<Projector>,
+    deliverer: Option<Deliverer>,
+    reconciler: Option<Reconciler>,
+    hold_sweeper: Option<HoldSweeper>,
+    // of the holds that don't say
+    hold_ttl: Duration,
+    scheduler: Option<Scheduler>,
+    interest_accruer: Option<InterestAccruer>,
+    #[cfg(feature = "nats")]
+    publisher: Option<super::publisher::Publisher>,
+    statement_timeout: Duration,
+    // whose schema the pools are set up for, `None` for the default dataset
+    tenant: Option<String>,
+}
+
+impl PgWalletRepository {
+    pub fn new(pool: PgPool) -> Self {
+        PgWalletRepository {
+            read_pool: pool.clone(),
+            replicas: Replicas::default(),
+            pool,
+            events: Events::default(),
+            listener: Arc::new(Once::new()),
+            write_behind: None,
+            db_function: false,
+            fees: false,
+            event_sourcing: false,
+            projector: None,
+            deliverer: None,
+            reconciler: None,
+            hold_sweeper: None,
+            hold_ttl: Duration::from_secs(DEFAULT_HOLD_TTL_SECS),
+            scheduler: None,
+            interest_accruer: None,
+            #[cfg(feature = "nats")]
+            publisher: None,
+            statement_timeout: Duration::ZERO,
+            tenant: None,
+        }
+    }
+
+    pub async fn connect(config: &Config) -> Result<Self, sqlx::Error> {
+        let mut repo = Self::new(db::connect(config).await?);
+        if let Some(read_pool) = db::connect_read(config).await? {
+            repo.read_pool = read_pool;
+        }
+        repo.replicas = Replicas::new(db::connect_replicas(config)?);
+        repo.db_function = config.use_db_func;
+        repo.fees = config.fees;
+        repo.statement_timeout = config.db_statement_timeout;
+        repo.tenant = config.tenant.clone();
+        if !config.write_behind_flush.is_zero() {
+            repo.write_behind = Some(WriteBehind::spawn(
+                repo.pool.clone(),
+                config.write_behind_flush,
+                config.write_behind_max_batch,
+            ));
+        }
+        if config.event_sourcing {
+            repo.event_sourcing = true;
+            repo.projector = Some(Projector::spawn(
+                repo.pool.clone(),
+                config.projector_interval,
+            ));
+        }
+        if !config.webhook_poll.is_zero() {
+            let options = DeliveryOptions {
+                poll: config.webhook_poll,
+                timeout: config.webhook_timeout,
+                max_attempts: config.webhook_max_attempts,
+                retry_delay: config.webhook_retry_delay,
+            };
+            repo.deliverer = Some(Deliverer::spawn(repo.pool.clone(), options));
+        }
+        if !config.reconcile_interval.is_zero() {
+            let options = ReconcileOptions {
+                event_sourcing: repo.event_sourcing,
+                repair: config.reconcile_repair,
+            };
+            repo.reconciler = Some(Reconciler::spawn(
+                repo.pool.clone(),
+                config.reconcile_interval,
+                options,
+            ));
+        }
+        repo.hold_ttl = config.hold_ttl;
+        if !config.hold_sweep_interval.is_zero() {
+            repo.hold_sweeper = Some(HoldSweeper::spawn(
+                repo.pool.clone(),
+                config.hold_sweep_interval,
+            ));
+        }
+        #[cfg(feature = "nats")]
+        if let Some(url) = &config.nats_url {
+            let publisher = super::publisher::Publisher::spawn(
+                repo.pool.clone(),
+                url,
+                config.nats_subject.clone(),
+                config.tenant.clone(),
+                config.event_publish_poll,
+            )
+            .await
+            .map_err(|err| sqlx::Error::Configuration(err.into()))?;
+            repo.publisher = Some(publisher);
+        }
+        // the copy they run on is set up like this one, without either of
+        // them
+        let background = repo.clone();
+        if !config.scheduler_interval.is_zero() {
+            repo.scheduler = Some(Scheduler::spawn(
+                background.clone(),
+                config.scheduler_interval,
+            ));
+        }
+        if !config.interest_check_interval.is_zero() {
+            let options = InterestOptions {
+                check_interval: config.interest_check_interval,
+                accrual_hour: config.interest_accrual_hour,
+                default_rate: config.interest_rate,
+            };
+            repo.interest_accruer = Some(InterestAccruer::spawn(background, options));
+        }
+        Ok(repo)
+    }
+
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    // from a replica while one is reachable, otherwise from the primary
+    async fn acquire_replica(&self) -> Result<PoolConnection<Postgres>, sqlx::Error> {
+        match self.replicas.acquire().await {
+            Some(conn) => Ok(conn),
+            None => db::acquire(&self.read_pool).await,
+        }
+    }
+
+    // the wallet row, or in the event sourcing mode the advisory lock that
+    // stands in for it. false when the wallet doesn't exist
+    async fn lock_wallet(&self, conn: &mut PgConnection, wallet_id: i32) -> Result<bool, ApiError> {
+        if self.event_sourcing {
+            Ok(db::lock_wallet_log(conn, wallet_id).await?)
+        } else {
+            Ok(db::lock_wallet(conn, wallet_id).await?)
+        }
+    }
+
+    async fn lock_wallets(
+        &self,
+        conn: &mut PgConnection,
+        wallet_ids: &[i32],
+    ) -> Result<usize, ApiError> {
+        if self.event_sourcing {
+            Ok(db::lock_wallet_logs(conn, wallet_ids).await?)
+        } else {
+            Ok(db::lock_wallets(conn, wallet_ids).await?)
+        }
+    }
+
+    // the event sourcing mode takes several statements, the wallet must
+    // already be locked
+    async fn register(
+        &self,
+        conn: &mut PgConnection,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+    ) -> Result<TransactionReceipt, ApiError> {
+        if self.event_sourcing {
+            let wallet = db::fetch_wallet(&mut *conn, wallet_id)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            let balance = wallet.balance.unwrap_or_default();
+            let credit_limit = wallet.credit_limit.unwrap_or_default();
+            let held = db::fetch_held(&mut *conn, wallet_id)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+
+            let transactions = std::slice::from_ref(transaction);
+            let balances =
+                running_balances(balance, credit_limit.saturating_sub(held), transactions)?;
+            db::append_pending_projection(&mut *conn, wallet_id, transaction.delta()).await?;
+            let rows = db::append_transactions(&mut *conn, wallet_id, transactions).await?;
+
+            let mut receipt = batch_receipt(credit_limit, balances, rows);
+            let item = receipt.transactions.pop().ok_or(ApiError::Internal)?;
+            return Ok(TransactionReceipt {
+                wallet: Wallet {
+                    balance: Some(item.balance),
+                    credit_limit: Some(credit_limit),
+                },
+                id: Some(item.id),
+                inserted_at: Some(item.inserted_at),
+            });
+        }
+
+        let receipt = if self.db_function {
+            db::call_register_transaction(conn, wallet_id, transaction).await?
+        } else {
+            db::register_transaction(conn, wallet_id, transaction).await?
+        };
+        receipt.ok_or(ApiError::NotFound)
+    }
+
+    // `register`, and the fees the rules charge alongside the transaction,
+    // the receipt left with the balance after them
+    async fn register_with_fees(
+        &self,
+        conn: &mut PgConnection,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+    ) -> Result<TransactionReceipt, ApiError> {
+        let mut receipt = self.register(&mut *conn, wallet_id, transaction).await?;
+
+        if let Some(transaction_id) = receipt.id {
+            if let Some(balance) = self
+                .charge_fees(conn, wallet_id, transaction, transaction_id)
+                .await?
+            {
+                receipt.wallet.balance = Some(balance);
+            }
+        }
+
+        Ok(receipt)
+    }
+
+    // debits the fees for `transaction`, already registered as
+    // `transaction_id`, linked to it. the wallet is locked by then, by the
+    // write itself. returns the balance after them, none when there were
+    // none. `LimitExceeded` when they don't fit, which turns the
+    // transaction away with them. hold captures and interest aren't charged
+    async fn charge_fees(
+        &self,
+        conn: &mut PgConnection,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        transaction_id: Uuid,
+    ) -> Result<Option<Cents>, ApiError> {
+        if !self.fees {
+            return Ok(None);
+        }
+        let fees = db::fetch_fees(&mut *conn, transaction.kind, transaction.value).await?;
+        if fees.is_empty() {
+            return Ok(None);
+        }
+
+        let wallet = db::fetch_wallet(&mut *conn, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        let balance = wallet.balance.unwrap_or_default();
+        let credit_limit = wallet.credit_limit.unwrap_or_default();
+        let held = db::fetch_held(&mut *conn, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        let balances = running_balances(balance, credit_limit.saturating_sub(held), &fees)?;
+        let after = balances.last().copied().unwrap_or(balance);
+        let delta = after.checked_sub(balance).ok_or(ApiError::LimitExceeded)?;
+
+        db::set_fee_of(&mut *conn, Some(transaction_id)).await?;
+        if self.event_sourcing {
+            db::append_pending_projection(&mut *conn, wallet_id, delta).await?;
+            db::append_transactions(&mut *conn, wallet_id, &fees).await?;
+        } else {
+            db::register_transaction_batch(&mut *conn, wallet_id, delta, &fees).await?;
+        }
+        db::set_fee_of(&mut *conn, None).await?;
+
+        Ok(Some(after))
+    }
+
+    // applies the scheduled transaction due the earliest, like a transaction
+    // posted right now. one the limits or the wallet's status turn away is
+    // kept as failed with the code of the error. none when nothing is due
+    pub(super) async fn apply_scheduled(&self) -> Result<Option<ScheduledStatus>, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        // the scheduled row before the wallet, like a hold's capture
+        let Some(scheduled) = db::claim_scheduled(&mut *db_transaction).await? else {
+            return Ok(None);
+        };
+        if !self
+            .lock_wallet(&mut db_transaction, scheduled.wallet_id)
+            .await?
+        {
+            return Err(ApiError::NotFound);
+        }
+
+        let transaction = PostTransaction {
+            value: scheduled.value,
+            kind: scheduled.kind,
+            description: scheduled.description.clone(),
+            currency: None,
+            category: scheduled.category.clone(),
+            tags: scheduled.tags.clone(),
+            metadata: scheduled.metadata.clone(),
+        };
+        // a failed statement would abort the whole transaction, the
+        // savepoint keeps the row around to be settled
+        let mut savepoint = db_transaction.begin().await?;
+        let (status, transaction_id, failure) = match self
+            .register_with_fees(&mut savepoint, scheduled.wallet_id, &transaction)
+            .await
+        {
+            Ok(receipt) => {
+                savepoint.commit().await?;
+                (ScheduledStatus::Applied, receipt.id, None)
+            }
+            Err(
+                err @ (ApiError::LimitExceeded
+                | ApiError::WalletBlocked
+                | ApiError::DailyLimitExceeded
+                | ApiError::NotFound),
+            ) => {
+                savepoint.rollback().await?;
+                (ScheduledStatus::Failed, None, Some(err.code()))
+            }
+            Err(err) => return Err(err),
+        };
+
+        db::settle_scheduled(
+            &mut *db_transaction,
+            scheduled.wallet_id,
+            scheduled.id,
+            status,
+            transaction_id,
+            failure,
+        )
+        .await?
+        .ok_or(ApiError::Internal)?;
+
+        db_transaction.commit().await?;
+
+        Ok(Some(status))
+    }
+
+    // runs the recurrence due the earliest, like a transaction posted right
+    // now, and moves it on to its next run. true when the transaction went
+    // through, none when nothing is due
+    pub(super) async fn apply_recurrence(&self) -> Result<Option<bool>, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        let Some(recurrence) = db::claim_recurrence(&mut *db_transaction).await? else {
+            return Ok(None);
+        };
+        if !self
+            .lock_wallet(&mut db_transaction, recurrence.wallet_id)
+            .await?
+        {
+            return Err(ApiError::NotFound);
+        }
+
+        let transaction = PostTransaction {
+            value: recurrence.value,
+            kind: recurrence.kind,
+            description: recurrence.description.clone(),
+            currency: None,
+            category: recurrence.category.clone(),
+            tags: recurrence.tags.clone(),
+            metadata: recurrence.metadata.clone(),
+        };
+        let mut savepoint = db_transaction.begin().await?;
+        let failure = match self
+            .register_with_fees(&mut savepoint, recurrence.wallet_id, &transaction)
+            .await
+        {
+            Ok(_) => {
+                savepoint.commit().await?;
+                None
+            }
+            Err(
+                err @ (ApiError::LimitExceeded
+                | ApiError::WalletBlocked
+                | ApiError::DailyLimitExceeded
+                | ApiError::NotFound),
+            ) => {
+                savepoint.rollback().await?;
+                Some(err.code())
+            }
+            Err(err) => return Err(err),
+        };
+
+        // from now, the runs missed while no instance was up are skipped
+        let schedule: Schedule = recurrence
+            .schedule
+            .parse()
+            .map_err(|_| ApiError::Internal)?;
+        let next_run_at = schedule.next_after(OffsetDateTime::now_utc());
+        db::advance_recurrence(&mut *db_transaction, recurrence.id, next_run_at, failure).await?;
+
+        db_transaction.commit().await?;
+
+        Ok(Some(failure.is_none()))
+    }
+
+    // debits `day`'s interest from the next wallet with a negative balance
+    // that hasn't been charged it, at the wallet's rate or `default_rate`.
+    // true when that went through, or there was nothing to charge, none
+    // when no wallet is left
+    pub(super) async fn accrue_interest(
+        &self,
+        day: Date,
+        default_rate: f64,
+    ) -> Result<Option<bool>, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        let Some(wallet_id) =
+            db::claim_interest_wallet(&mut *db_transaction, day, default_rate).await?
+        else {
+            return Ok(None);
+        };
+        if !self.lock_wallet(&mut db_transaction, wallet_id).await? {
+            return Err(ApiError::NotFound);
+        }
+        let value = db::fetch_interest_due(&mut *db_transaction, wallet_id, default_rate)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        let (transaction_id, failure) = if value.is_positive() {
+            let transaction = PostTransaction {
+                value,
+                kind: TransactionKind::Debit,
+                description: INTEREST_CATEGORY.into(),
+                currency: None,
+                category: None,
+                tags: Vec::new(),
+                metadata: None,
+            };
+            db::set_category(&mut *db_transaction, INTEREST_CATEGORY).await?;
+            let mut savepoint = db_transaction.begin().await?;
+            match self.register(&mut savepoint, wallet_id, &transaction).await {
+                Ok(receipt) => {
+                    savepoint.commit().await?;
+                    (receipt.id, None)
+                }
+                Err(
+                    err @ (ApiError::LimitExceeded
+                    | ApiError::WalletBlocked
+                    | ApiError::DailyLimitExceeded
+                    | ApiError::NotFound),
+                ) => {
+                    savepoint.rollback().await?;
+                    (None, Some(err.code()))
+                }
+                Err(err) => return Err(err),
+            }
+        } else {
+            (None, None)
+        };
+
+        // the day is done even when the debit was turned away, it's not
+        // charged again on a later round
+        db::insert_interest_accrual(
+            &mut *db_transaction,
+            wallet_id,
+            day,
+            value,
+            transaction_id,
+            failure,
+        )
+        .await?;
+
+        db_transaction.commit().await?;
+
+        Ok(Some(failure.is_none()))
+    }
+}
+
+#[async_trait]
+impl WalletRepository for PgWalletRepository {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError> {
+        let mut conn = self.acquire_replica().await?;
+
+        // the unfiltered statement is a single row, no snapshot needed
+        if let StatementFilter {
+            from: None,
+            to: None,
+            kind: None,
+        } = filter
+        {
+            return db::fetch_statement(&mut *conn, wallet_id)
+                .await?
+                .ok_or(ApiError::NotFound);
+        }
+
+        // both reads come from the same snapshot so `saldo.total` always
+        // matches `ultimas_transacoes`, even with writes landing in between
+        let mut db_transaction = db::begin_snapshot(&mut conn, self.statement_timeout).await?;
+
+        let wallet = db::fetch_wallet(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        let currency = db::fetch_currency(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        let transactions = db::fetch_transactions(&mut *db_transaction, wallet_id, filter).await?;
+
+        db_transaction.commit().await?;
+
+        Ok(Statement {
+            wallet,
+            currency,
+            transactions,
+        })
+    }
+
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        db::fetch_wallet_details(&mut *conn, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        // the wallet's currency never changes, so it's checked ahead of the
+        // write. a transaction that doesn't name one skips the extra read
+        if let Some(currency) = &transaction.currency {
+            let mut conn = db::acquire(&self.read_pool).await?;
+            let wallet_currency = db::fetch_currency(&mut *conn, wallet_id)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            check_currency(&wallet_currency, Some(currency))?;
+        }
+
+        // the balance is checked and updated right away, the row itself goes
+        // through the queue. keyed writes store their response along with the
+        // row, and fees go in along with theirs, so they keep the path below
+        if let (None, Some(write_behind), false) = (idempotency_key, &self.write_behind, self.fees)
+        {
+            let slot = write_behind.reserve().await?;
+
+            // the update commits on its own, so it and the queueing of its row
+            // go to a task of their own. a request dropped in between, by its
+            // timeout or its client, would move the balance without the row
+            let pool = self.pool.clone();
+            let transaction = transaction.clone();
+            let applied = tokio::spawn(
+                async move { apply_behind(&pool, slot, wallet_id, &transaction).await }
+                    .in_current_span(),
+            )
+            .await
+            .map_err(|_| ApiError::Internal)??;
+
+            if let Some(receipt) = applied {
+                return Ok(receipt);
+            }
+        }
+
+        let mut conn = db::acquire(&self.pool).await?;
+        // even a single statement write needs one, for the audit context
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        // without a key the whole write is a single statement, the fees
+        // after it find the wallet locked by it. the event sourcing mode's
+        // never is
+        if let (None, false) = (idempotency_key, self.event_sourcing) {
+            let receipt = self
+                .register_with_fees(&mut db_transaction, wallet_id, transaction)
+                .await?;
+            db_transaction.commit().await?;
+            return Ok(receipt);
+        }
+
+        // this path takes several statements, so the wallet is locked up front
+        // for the whole transaction instead of only during the UPDATE
+        if !self.lock_wallet(&mut db_transaction, wallet_id).await? {
+            return Err(ApiError::NotFound);
+        }
+
+        // a retried request with the same key gets the original response back.
+        // the key row is claimed up front so a concurrent duplicate blocks on it
+        // until this transaction finishes, and it's released again on rollback
+        if let Some(key) = idempotency_key {
+            let fingerprint = transaction.fingerprint();
+            let claimed =
+                db::claim_idempotency_key(&mut *db_transaction, wallet_id, key, &fingerprint)
+                    .await?;
+
+            if !claimed {
+                let (stored, stored_fingerprint) =
+                    db::fetch_idempotent_response(&mut *db_transaction, wallet_id, key).await?;
+                if stored_fingerprint.is_some_and(|stored| stored != fingerprint) {
+                    return Err(ApiError::IdempotencyKeyReused);
+                }
+
+                return serde_json::from_value(stored)
+                    .map_err(|err| ApiError::Database(sqlx::Error::Decode(Box::new(err))));
+            }
+        }
+
+        let receipt = self
+            .register_with_fees(&mut db_transaction, wallet_id, transaction)
+            .await?;
+
+        if let Some(key) = idempotency_key {
+            let response = serde_json::to_value(&receipt).expect("receipt is always serializable");
+            db::store_idempotent_response(&mut *db_transaction, wallet_id, key, &response).await?;
+        }
+
+        db_transaction.commit().await?;
+
+        Ok(receipt)
+    }
+
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        // the running balances are worked out here, so nothing may write to
+        // the wallet between reading it and applying the batch
+        if !self.lock_wallet(&mut db_transaction, wallet_id).await? {
+            return Err(ApiError::NotFound);
+        }
+        let wallet = db::fetch_wallet(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        let balance = wallet.balance.unwrap_or_default();
+        let credit_limit = wallet.credit_limit.unwrap_or_default();
+        // what the holds keep aside can't be spent
+        let held = db::fetch_held(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        if transactions.iter().any(|t| t.currency.is_some()) {
+            let wallet_currency = db::fetch_currency(&mut *db_transaction, wallet_id)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            for transaction in transactions {
+                check_currency(&wallet_currency, transaction.currency.as_deref())?;
+            }
+        }
+
+        let balances = running_balances(balance, credit_limit.saturating_sub(held), transactions)?;
+        let delta = balances
+            .last()
+            .copied()
+            .unwrap_or(balance)
+            .checked_sub(balance)
+            .ok_or(ApiError::LimitExceeded)?;
+
+        let rows = if self.event_sourcing {
+            db::append_pending_projection(&mut *db_transaction, wallet_id, delta).await?;
+            db::append_transactions(&mut *db_transaction, wallet_id, transactions).await?
+        } else {
+            db::register_transaction_batch(&mut *db_transaction, wallet_id, delta, transactions)
+                .await?
+        };
+
+        // each transaction's fees come after the whole batch, its own
+        // balance in the receipt is from before them
+        let mut receipt = batch_receipt(credit_limit, balances, rows);
+        for (transaction, item) in transactions.iter().zip(&receipt.transactions) {
+            if let Some(balance) = self
+                .charge_fees(&mut db_transaction, wallet_id, transaction, item.id)
+                .await?
+            {
+                receipt.balance = balance;
+            }
+        }
+
+        db_transaction.commit().await?;
+
+        Ok(receipt)
+    }
+
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError> {
+        let mut conn = self.acquire_replica().await?;
+
+        let rows =
+            db::fetch_history(&mut *conn, wallet_id, filter, i64::from(filter.limit) + 1).await?;
+
+        // an empty page may as well be an unknown wallet
+        if rows.is_empty() && db::fetch_wallet(&mut *conn, wallet_id).await?.is_none() {
+            return Err(ApiError::NotFound);
+        }
+
+        Ok(history_page(rows, filter))
+    }
+
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError> {
+        let mut conn = self.acquire_replica().await?;
+
+        let totals = db::fetch_monthly_totals(&mut *conn, wallet_id, month)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        Ok(MonthlySummary::new(month, totals))
+    }
+
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError> {
+        let mut conn = self.acquire_replica().await?;
+        if db::fetch_wallet(&mut *conn, wallet_id).await?.is_none() {
+            return Err(ApiError::NotFound);
+        }
+
+        // the query runs on its own task holding the connection, the client
+        // pulls the rows from the other end of the channel
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            let mut rows = db::stream_transactions(&mut *conn, wallet_id);
+            while let Some(row) = rows.next().await {
+                // the client went away
+                if sender.send(row.map_err(ApiError::from)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Box::pin(ReceiverStream::new(receiver)))
+    }
+
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        db::fetch_transaction(&mut *conn, wallet_id, transaction_id)
+            .await?
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        if self
+            .lock_wallets(&mut db_transaction, &[transfer.from, transfer.to])
+            .await?
+            < 2
+        {
+            return Err(ApiError::NotFound);
+        }
+
+        let from_currency = db::fetch_currency(&mut *db_transaction, transfer.from)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        let to_currency = db::fetch_currency(&mut *db_transaction, transfer.to)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        check_currency(&from_currency, Some(&to_currency))?;
+        check_currency(&from_currency, transfer.currency.as_deref())?;
+
+        let id = Uuid::new_v4();
+        let wallet = if self.event_sourcing {
+            // nothing checks the balances further down, no constraint sees
+            // the pending rows
+            let from = db::fetch_wallet(&mut *db_transaction, transfer.from)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            let to = db::fetch_wallet(&mut *db_transaction, transfer.to)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            let credit_limit = from.credit_limit.unwrap_or_default();
+            let held = db::fetch_held(&mut *db_transaction, transfer.from)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            let debited = from
+                .balance
+                .unwrap_or_default()
+                .checked_sub(transfer.value)
+                .filter(|balance| {
+                    !balance
+                        .saturating_add(credit_limit)
+                        .saturating_sub(held)
+                        .is_negative()
+                })
+                .ok_or(ApiError::LimitExceeded)?;
+            to.balance
+                .unwrap_or_default()
+                .checked_add(transfer.value)
+                .ok_or(ApiError::LimitExceeded)?;
+
+            let debit = Cents::ZERO
+                .checked_sub(transfer.value)
+                .expect("a positive value always fits below zero");
+            db::append_pending_projection(&mut *db_transaction, transfer.from, debit).await?;
+            db::append_pending_projection(&mut *db_transaction, transfer.to, transfer.value)
+                .await?;
+            db::append_transfer(&mut *db_transaction, id, transfer).await?;
+
+            Wallet {
+                balance: Some(debited),
+                credit_limit: Some(credit_limit),
+            }
+        } else {
+            db::register_transfer(&mut *db_transaction, id, transfer).await?
+        };
+
+        db_transaction.commit().await?;
+
+        Ok(TransferReceipt {
+            id,
+            balance: wallet.balance.unwrap_or_default(),
+            credit_limit: wallet.credit_limit.unwrap_or_default(),
+        })
+    }
+
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+        let created = db::create_wallet(&mut *db_transaction, wallet).await?;
+        db_transaction.commit().await?;
+        Ok(created)
+    }
+
+    async fn create_account(
+        &self,
+        client_id: i32,
+        wallet: &PostWallet,
+    ) -> Result<CreatedWallet, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+        let created = db::create_account(&mut *db_transaction, client_id, wallet)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        db_transaction.commit().await?;
+        Ok(created)
+    }
+
+    async fn list_accounts(&self, client_id: i32) -> Result<Vec<Account>, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        // the default account is always there for a client
+        let accounts = db::fetch_accounts(&mut *conn, client_id).await?;
+        if accounts.is_empty() {
+            return Err(ApiError::NotFound);
+        }
+
+        Ok(accounts)
+    }
+
+    async fn owns_account(&self, client_id: i32, account_id: i32) -> Result<bool, ApiError> {
+        // the primary, an account is used right after it's created and a
+        // replica lagging behind would answer it doesn't exist
+        let mut conn = db::acquire(&self.pool).await?;
+        Ok(db::owns_account(&mut *conn, client_id, account_id).await?)
+    }
+
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        // locked so no transaction moves the balance between the check and
+        // the update
+        if !self.lock_wallet(&mut db_transaction, wallet_id).await? {
+            return Err(ApiError::NotFound);
+        }
+
+        let current = db::fetch_wallet(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        let balance = current.balance.unwrap_or_default();
+        let held = db::fetch_held(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        if balance
+            .saturating_add(credit_limit)
+            .saturating_sub(held)
+            .is_negative()
+        {
+            return Err(limit_below_balance());
+        }
+
+        let wallet = db::update_credit_limit(&mut *db_transaction, wallet_id, credit_limit)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        db::insert_audit_log(
+            &mut *db_transaction,
+            wallet_id,
+            CREDIT_LIMIT_CHANGED,
+            &json!({ "limite": current.credit_limit }),
+            &json!({ "limite": credit_limit }),
+            actor,
+            audit::Context::current().request_id.as_deref(),
+        )
+        .await?;
+
+        db_transaction.commit().await?;
+
+        Ok(wallet)
+    }
+
+    // only a change is audited, blocking a blocked wallet is a no-op
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        if !db::lock_wallet(&mut *db_transaction, wallet_id).await? {
+            return Err(ApiError::NotFound);
+        }
+        let current = db::fetch_status(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        if current != status {
+            db::update_status(&mut *db_transaction, wallet_id, status).await?;
+            db::insert_audit_log(
+                &mut *db_transaction,
+                wallet_id,
+                STATUS_CHANGED,
+                &json!({ "status": current }),
+                &json!({ "status": status }),
+                actor,
+                audit::Context::current().request_id.as_deref(),
+            )
+            .await?;
+        }
+
+        let wallet = db::fetch_wallet_details(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        db_transaction.commit().await?;
+
+        Ok(wallet)
+    }
+
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        if !db::lock_wallet(&mut *db_transaction, wallet_id).await? {
+            return Err(ApiError::NotFound);
+        }
+        let current = db::fetch_wallet_details(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        if current.daily_debit_limit != limit {
+            db::update_daily_debit_limit(&mut *db_transaction, wallet_id, limit).await?;
+            db::insert_audit_log(
+                &mut *db_transaction,
+                wallet_id,
+                DAILY_DEBIT_LIMIT_CHANGED,
+                &json!({ "limite_diario": current.daily_debit_limit }),
+                &json!({ "limite_diario": limit }),
+                actor,
+                audit::Context::current().request_id.as_deref(),
+            )
+            .await?;
+        }
+
+        let wallet = db::fetch_wallet_details(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        db_transaction.commit().await?;
+
+        Ok(wallet)
+    }
+
+    async fn set_interest_rate(
+        &self,
+        wallet_id: i32,
+        rate: Option<f64>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        if !db::lock_wallet(&mut *db_transaction, wallet_id).await? {
+            return Err(ApiError::NotFound);
+        }
+        let current = db::fetch_wallet_details(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        if current.interest_rate != rate {
+            db::update_interest_rate(&mut *db_transaction, wallet_id, rate).await?;
+            db::insert_audit_log(
+                &mut *db_transaction,
+                wallet_id,
+                INTEREST_RATE_CHANGED,
+                &json!({ "taxa_diaria": current.interest_rate }),
+                &json!({ "taxa_diaria": rate }),
+                actor,
+                audit::Context::current().request_id.as_deref(),
+            )
+            .await?;
+        }
+
+        let wallet = db::fetch_wallet_details(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        db_transaction.commit().await?;
+
+        Ok(wallet)
+    }
+
+    async fn reconcile(
+        &self,
+        wallet_id: i32,
+        repair: bool,
+        actor: &str,
+    ) -> Result<Reconciliation, ApiError> {
+        // the balances run ahead of the rows still queued, they'd look
+        // drifted by those
+        let options = ReconcileOptions {
+            event_sourcing: self.event_sourcing,
+            repair: repair && self.write_behind.is_none(),
+        };
+        let request_id = audit::Context::current().request_id;
+
+        reconciler::reconcile(&self.pool, wallet_id, options, actor, request_id.as_deref())
+            .await?
+            .ok_or(ApiError::NotFound)
+    }
+
+    // the head and the links come from the same snapshot, a transaction
+    // landing in between would look like the end of the chain went missing.
+    // reads every row of the wallet, so without the statement timeout
+    async fn verify_chain(&self, wallet_id: i32) -> Result<ChainVerification, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+        let mut db_transaction = db::begin_snapshot(&mut conn, Duration::ZERO).await?;
+
+        let (head, length) = db::fetch_chain_head(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        let mut verification = ChainVerification::new(wallet_id);
+        let mut links = db::stream_chain(&mut *db_transaction, wallet_id);
+        while let Some(link) = links.next().await {
+            if !verification.check(link?) {
+                break;
+            }
+        }
+        drop(links);
+
+        db_transaction.commit().await?;
+
+        Ok(verification.finish(head, length))
+    }
+
+    async fn create_webhook(
+        &self,
+        wallet_id: i32,
+        webhook: &PostWebhook,
+    ) -> Result<CreatedWebhook, ApiError> {
+        let secret = webhook.secret.clone().unwrap_or_else(webhooks::new_secret);
+
+        let mut conn = db::acquire(&self.pool).await?;
+        let created = db::insert_webhook(&mut *conn, wallet_id, &webhook.url, &secret).await?;
+
+        Ok(CreatedWebhook {
+            webhook: created,
+            secret,
+        })
+    }
+
+    async fn list_webhooks(&self, wallet_id: i32) -> Result<Vec<Webhook>, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        let webhooks = db::fetch_webhooks(&mut *conn, wallet_id).await?;
+
+        // an empty list may as well be an unknown wallet
+        if webhooks.is_empty() && db::fetch_wallet(&mut *conn, wallet_id).await?.is_none() {
+            return Err(ApiError::NotFound);
+        }
+
+        Ok(webhooks)
+    }
+
+    async fn delete_webhook(&self, wallet_id: i32, webhook_id: i32) -> Result<(), ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+
+        if !db::delete_webhook(&mut *conn, wallet_id, webhook_id).await? {
+            return Err(ApiError::NotFound);
+        }
+        Ok(())
+    }
+
+    async fn create_hold(&self, wallet_id: i32, hold: &PostHold) -> Result<Hold, ApiError> {
+        let ttl = hold.ttl_secs.map_or(self.hold_ttl, Duration::from_secs);
+        let expires_at = OffsetDateTime::now_utc() + ttl;
+
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        if !self.lock_wallet(&mut db_transaction, wallet_id).await? {
+            return Err(ApiError::NotFound);
+        }
+
+        // no trigger of `transactions` sees a hold, it's their checks until
+        // it's captured
+        let status = db::fetch_status(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        check_status(status)?;
+        if let Some(currency) = &hold.currency {
+            let wallet_currency = db::fetch_currency(&mut *db_transaction, wallet_id)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            check_currency(&wallet_currency, Some(currency))?;
+        }
+
+        // the constraint checks the balance of the row, which is behind by
+        // whatever is still pending
+        if self.event_sourcing {
+            db::project_wallet(&mut *db_transaction, wallet_id).await?;
+        }
+
+        let created = db::insert_hold(&mut *db_transaction, wallet_id, hold, expires_at)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        db_transaction.commit().await?;
+
+        Ok(created)
+    }
+
+    async fn get_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        db::fetch_hold(&mut *conn, hold_id, false)
+            .await?
+            .ok_or(ApiError::NotFound)
+    }
+
+    // the hold's amount comes back and is debited in the same transaction,
+    // which fails like any debit of a blocked wallet or one past its daily
+    // limit. the hold stays pending then
+    async fn capture_hold(&self, hold_id: Uuid) -> Result<CapturedHold, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        // the hold before the wallet, like the sweeper
+        let hold = db::fetch_hold(&mut *db_transaction, hold_id, true)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        if hold.status != HoldStatus::Pending || hold.expires_at <= OffsetDateTime::now_utc() {
+            return Err(ApiError::HoldSettled);
+        }
+        if !self
+            .lock_wallet(&mut db_transaction, hold.wallet_id)
+            .await?
+        {
+            return Err(ApiError::NotFound);
+        }
+
+        db::release_held(&mut *db_transaction, hold.wallet_id, hold.value).await?;
+        let debit = PostTransaction {
+            value: hold.value,
+            kind: TransactionKind::Debit,
+            description: hold.description.clone(),
+            currency: None,
+            category: None,
+            tags: Vec::new(),
+            metadata: None,
+        };
+        let receipt = self
+            .register(&mut db_transaction, hold.wallet_id, &debit)
+            .await?;
+        let hold = db::settle_hold(
+            &mut *db_transaction,
+            hold_id,
+            HoldStatus::Captured,
+            receipt.id,
+        )
+        .await?
+        .ok_or(ApiError::Internal)?;
+
+        db_transaction.commit().await?;
+
+        Ok(CapturedHold {
+            hold,
+            balance: receipt.wallet.balance.unwrap_or_default(),
+            credit_limit: receipt.wallet.credit_limit.unwrap_or_default(),
+        })
+    }
+
+    async fn release_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        let hold = db::fetch_hold(&mut *db_transaction, hold_id, true)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        if hold.status != HoldStatus::Pending {
+            return Err(ApiError::HoldSettled);
+        }
+
+        db::release_held(&mut *db_transaction, hold.wallet_id, hold.value).await?;
+        let hold = db::settle_hold(&mut *db_transaction, hold_id, HoldStatus::Released, None)
+            .await?
+            .ok_or(ApiError::Internal)?;
+
+        db_transaction.commit().await?;
+
+        Ok(hold)
+    }
+
+    async fn schedule_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        due_at: OffsetDateTime,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        // the limits are checked when it's applied, the rest can't change
+        // until then or is worth knowing right away
+        let status = db::fetch_status(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        check_status(status)?;
+        if let Some(currency) = &transaction.currency {
+            let wallet_currency = db::fetch_currency(&mut *db_transaction, wallet_id)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            check_currency(&wallet_currency, Some(currency))?;
+        }
+
+        let scheduled = db::insert_scheduled(&mut *db_transaction, wallet_id, transaction, due_at)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+
+        db_transaction.commit().await?;
+
+        Ok(scheduled)
+    }
+
+    async fn list_scheduled(&self, wallet_id: i32) -> Result<Vec<ScheduledTransaction>, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        let scheduled = db::fetch_scheduled(&mut *conn, wallet_id).await?;
+        if scheduled.is_empty() && db::fetch_wallet(&mut *conn, wallet_id).await?.is_none() {
+            return Err(ApiError::NotFound);
+        }
+
+        Ok(scheduled)
+    }
+
+    async fn cancel_scheduled(
+        &self,
+        wallet_id: i32,
+        scheduled_id: Uuid,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        // waits for the scheduler when it's applying this one right now
+        let cancelled = db::settle_scheduled(
+            &mut *db_transaction,
+            wallet_id,
+            scheduled_id,
+            ScheduledStatus::Cancelled,
+            None,
+            None,
+        )
+        .await?;
+        let Some(cancelled) = cancelled else {
+            return match db::scheduled_exists(&mut *db_transaction, wallet_id, scheduled_id).await?
+            {
+                true => Err(ApiError::ScheduledSettled),
+                false => Err(ApiError::NotFound),
+            };
+        };
+
+        db_transaction.commit().await?;
+
+        Ok(cancelled)
+    }
+
+    async fn create_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        // like a scheduled transaction's, the limits are checked on each run
+        let status = db::fetch_status(&mut *db_transaction, wallet_id)
+            .await?
+            .ok_or(ApiError::NotFound)?;
+        check_status(status)?;
+        if let Some(currency) = &recurrence.currency {
+            let wallet_currency = db::fetch_currency(&mut *db_transaction, wallet_id)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            check_currency(&wallet_currency, Some(currency))?;
+        }
+
+        let next_run_at = next_run(recurrence);
+        let created =
+            db::insert_recurrence(&mut *db_transaction, wallet_id, recurrence, next_run_at)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+
+        db_transaction.commit().await?;
+
+        Ok(created)
+    }
+
+    async fn list_recurrences(&self, wallet_id: i32) -> Result<Vec<Recurrence>, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        let recurrences = db::fetch_recurrences(&mut *conn, wallet_id).await?;
+        if recurrences.is_empty() && db::fetch_wallet(&mut *conn, wallet_id).await?.is_none() {
+            return Err(ApiError::NotFound);
+        }
+
+        Ok(recurrences)
+    }
+
+    async fn get_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+    ) -> Result<Recurrence, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        db::fetch_recurrence(&mut *conn, wallet_id, recurrence_id)
+            .await?
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn update_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        if let Some(currency) = &recurrence.currency {
+            let wallet_currency = db::fetch_currency(&mut *db_transaction, wallet_id)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+            check_currency(&wallet_currency, Some(currency))?;
+        }
+
+        // waits for the scheduler when it's running this one right now
+        let updated = db::update_recurrence(
+            &mut *db_transaction,
+            wallet_id,
+            recurrence_id,
+            recurrence,
+            next_run(recurrence),
+        )
+        .await?
+        .ok_or(ApiError::NotFound)?;
+
+        db_transaction.commit().await?;
+
+        Ok(updated)
+    }
+
+    async fn delete_recurrence(&self, wallet_id: i32, recurrence_id: i32) -> Result<(), ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin(&mut conn, self.statement_timeout).await?;
+
+        if !db::delete_recurrence(&mut *db_transaction, wallet_id, recurrence_id).await? {
+            return Err(ApiError::NotFound);
+        }
+
+        db_transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn create_fee_rule(&self, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+
+        Ok(db::insert_fee_rule(&mut *conn, rule).await?)
+    }
+
+    async fn list_fee_rules(&self) -> Result<Vec<FeeRule>, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        Ok(db::fetch_fee_rules(&mut *conn).await?)
+    }
+
+    async fn update_fee_rule(&self, rule_id: i32, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+
+        db::update_fee_rule(&mut *conn, rule_id, rule)
+            .await?
+            .ok_or(ApiError::NotFound)
+    }
+
+    async fn delete_fee_rule(&self, rule_id: i32) -> Result<(), ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+
+        if !db::delete_fee_rule(&mut *conn, rule_id).await? {
+            return Err(ApiError::NotFound);
+        }
+
+        Ok(())
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<AuditPage, ApiError> {
+        let mut conn = db::acquire(&self.read_pool).await?;
+
+        let limit = filter.limit as usize;
+        let mut entries = db::fetch_audit_log(&mut *conn, filter, limit as i64 + 1).await?;
+        let next = (entries.len() > limit).then(|| entries[limit - 1].id);
+        entries.truncate(limit);
+
+        Ok(AuditPage { entries, next })
+    }
+
+    // the events come from the `transactions_notify` trigger, so the writes
+    // of the other instances show up as well
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        let receiver = self.events.subscribe();
+
+        self.listener.call_once(|| {
+            let channel = db::channel(self.tenant.as_deref(), db::TRANSACTIONS_CHANNEL);
+            tokio::spawn(listen(self.pool.clone(), channel, self.events.clone()));
+        });
+
+        receiver
+    }
+
+    // the ids come from the `wallets_notify_changed` trigger, the writes of
+    // this instance included
+    fn watch_changes(&self, on_change: OnWalletChanged) -> bool {
+        let channel = db::channel(self.tenant.as_deref(), db::WALLET_CHANGED_CHANNEL);
+        tokio::spawn(watch(self.pool.clone(), channel, on_change));
+        true
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ping().await?;
+        Ok(())
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        Some(PoolStatus {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+            max: self.pool.options().get_max_connections(),
+        })
+    }
+
+    async fn run_migrations(&self) -> Result<(), ApiError> {
+        db::run_migrations(&self.pool, self.tenant.as_deref())
+            .await
+            .map_err(|err| ApiError::Database(err.into()))
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<(i64, String)>, ApiError> {
+        Ok(db::pending_migrations(&self.pool).await?)
+    }
+
+    async fn missing_ledger_guards(&self) -> Result<Vec<String>, ApiError> {
+        Ok(db::missing_ledger_guards(&self.pool).await?)
+    }
+
+    async fn missing_indexes(&self) -> Result<Vec<String>, ApiError> {
+        Ok(db::missing_indexes(&self.pool).await?)
+    }
+
+    // from the primary and a single snapshot, so a write landing halfway
+    // doesn't show up as a mismatch. rows still queued for the write-behind
+    // do, their balance is already in. no statement timeout, it reads every
+    // row
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+        let mut db_transaction = db::begin_snapshot(&mut conn, Duration::ZERO).await?;
+
+        let mut violations = db::fetch_unbalanced_transactions(&mut *db_transaction).await?;
+        violations.extend(db::fetch_balance_mismatches(&mut *db_transaction).await?);
+
+        db_transaction.commit().await?;
+
+        Ok(violations)
+    }
+
+    async fn rebuild_projections(&self) -> Result<u64, ApiError> {
+        Ok(db::rebuild_projections(&self.pool).await?)
+    }
+
+    async fn seed(&self, reset: bool) -> Result<(), ApiError> {
+        Ok(db::seed(&self.pool, reset).await?)
+    }
+
+    async fn api_key_scope(&self, key_hash: &str) -> Result<Option<Scope>, ApiError> {
+        let mut conn = db::acquire(&self.pool).await?;
+
+        db::fetch_api_key_scope(&mut *conn, key_hash)
+            .await?
+            .map(|scope| {
+                scope
+                    .parse()
+                    .map_err(|err: String| sqlx::Error::Decode(err.into()))
+            })
+            .transpose()
+            .map_err(ApiError::from)
+    }
+
+    async fn close(&self) {
+        // the queued rows go out before the pool closes
+        if let Some(scheduler) = &self.scheduler {
+            scheduler.close().await;
+        }
+        if let Some(interest_accruer) = &self.interest_accruer {
+            interest_accruer.close().await;
+        }
+        if let Some(write_behind) = &self.write_behind {
+            write_behind.close().await;
+        }
+        if let Some(projector) = &self.projector {
+            projector.close().await;
+        }
+        if let Some(deliverer) = &self.deliverer {
+            deliverer.close().await;
+        }
+        if let Some(reconciler) = &self.reconciler {
+            reconciler.close().await;
+        }
+        if let Some(hold_sweeper) = &self.hold_sweeper {
+            hold_sweeper.close().await;
+        }
+        #[cfg(feature = "nats")]
+        if let Some(publisher) = &self.publisher {
+            publisher.close().await;
+        }
+        self.pool.close().await;
+        self.read_pool.close().await;
+        self.replicas.close().await;
+    }
+}
+
+// none for a paused recurrence
+// the write-behind half of `insert_transaction`: the balance update, and the
+// row queued for the flusher once it went through. `None` for a debit of a
+// wallet with a daily limit, which has to be inserted right away
+async fn apply_behind(
+    pool: &PgPool,
+    slot: Slot,
+    wallet_id: i32,
+    transaction: &PostTransaction,
+) -> Result<Option<TransactionReceipt>, ApiError> {
+    let mut conn = db::acquire(pool).await?;
+
+    let wallet = match db::update_balance(&mut *conn, wallet_id, transaction).await? {
+        Some(wallet) => wallet,
+        None => match db::fetch_status(&mut *conn, wallet_id).await? {
+            None => return Err(ApiError::NotFound),
+            Some(WalletStatus::Blocked) => return Err(ApiError::WalletBlocked),
+            Some(WalletStatus::Active) => return Ok(None),
+        },
+    };
+
+    // postgres keeps microseconds, the receipt shouldn't promise more
+    let now = OffsetDateTime::now_utc();
+    let inserted_at = now
+        .replace_nanosecond(now.nanosecond() / 1000 * 1000)
+        .unwrap_or(now);
+    let row = TransactionDetails {
+        id: Uuid::new_v4(),
+        transaction: TransactionItem {
+            value: transaction.value,
+            kind: transaction.kind,
+            description: transaction.description.clone(),
+            inserted_at,
+            category: transaction.category.clone(),
+            tags: transaction.tags.clone(),
+            metadata: transaction.metadata.clone(),
+        },
+    };
+    let receipt = TransactionReceipt {
+        wallet,
+        id: Some(row.id),
+        inserted_at: Some(inserted_at),
+    };
+    slot.fill(wallet_id, row);
+
+    Ok(Some(receipt))
+}
+
+fn next_run(recurrence: &PostRecurrence) -> Option<OffsetDateTime> {
+    recurrence
+        .active
+        .then(|| recurrence.schedule.next_after(OffsetDateTime::now_utc()))
+        .flatten()
+}
+
+// forwards the notifications until the pool is closed. the listener
+// reconnects by itself, whatever was committed while it was away is lost
+async fn listen(pool: PgPool, channel: String, events: Events) {
+    loop {
+        let Err(err) = forward(&pool, &channel, &events).await;
+        if let sqlx::Error::PoolClosed = err {
+            return;
+        }
+
+        tracing::warn!(error = %err, "transactions listener failed, restarting");
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn forward(pool: &PgPool, channel: &str, events: &Events) -> Result<Infallible, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(channel).await?;
+
+    loop {
+        let notification = listener.recv().await?;
+
+        match serde_json::from_str(notification.payload()) {
+            Ok(event) => events.publish(event),
+            Err(err) => tracing::error!(error = %err, "malformed transaction notification"),
+        }
+    }
+}
+
+// like `listen`, for the `wallet_changed` channel. whatever changed while the
+// listener was away is unknown, so every wallet changed as far as `on_change`
+// knows once it's back
+async fn watch(pool: PgPool, channel: String, on_change: OnWalletChanged) {
+    loop {
+        let Err(err) = invalidate(&pool, &channel, &on_change).await;
+        if let sqlx::Error::PoolClosed = err {
+            return;
+        }
+
+        tracing::warn!(error = %err, "wallet changes listener failed, restarting");
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+async fn invalidate(
+    pool: &PgPool,
+    channel: &str,
+    on_change: &OnWalletChanged,
+) -> Result<Infallible, sqlx::Error> {
+    let mut listener = PgListener::connect_with(pool).await?;
+    listener.listen(channel).await?;
+    on_change(None);
+
+    loop {
+        // `None` when the connection dropped, the next call reconnects
+        let Some(notification) = listener.try_recv().await? else {
+            on_change(None);
+            continue;
+        };
+
+        match notification.payload() {
+            "*" => on_change(None),
+            payload => match payload.parse() {
+                Ok(wallet_id) => on_change(Some(wallet_id)),
+                Err(_) => tracing::error!(payload, "malformed wallet notification"),
+            },
+        }
+    }
+}
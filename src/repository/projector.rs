@@ -0,0 +1,77 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use metrics::counter;
+use sqlx::PgPool;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::db;
+
+// wallets whose pending rows one round folds in, the others wait for the
+// next round
+const MAX_WALLETS: i64 = 1000;
+
+// folds the rows the event sourcing mode appends to `pending_projections`
+// into the balances, every `interval`. reads don't wait for it, they add what
+// is still pending themselves, it only keeps that short. every instance runs
+// one, a row is only ever folded in by whichever gets to it first
+#[derive(Clone)]
+pub struct Projector {
+    stop: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    task: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl Projector {
+    pub fn spawn(pool: PgPool, interval: Duration) -> Self {
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(project(pool, stopped, interval));
+
+        Projector {
+            stop: Arc::new(Mutex::new(Some(stop))),
+            task: Arc::new(tokio::sync::Mutex::new(Some(task))),
+        }
+    }
+
+    // folds in what's left and stops
+    pub async fn close(&self) {
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.lock().await.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+async fn project(pool: PgPool, mut stopped: oneshot::Receiver<()>, interval: Duration) {
+    let mut ticks = tokio::time::interval(interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                round(&pool).await;
+            }
+            _ = &mut stopped => {
+                while round(&pool).await > 0 {}
+                return;
+            }
+        }
+    }
+}
+
+// the rows folded in, none when it failed
+async fn round(pool: &PgPool) -> u64 {
+    match db::project_pending(pool, MAX_WALLETS).await {
+        Ok(projected) => {
+            counter!("projected_rows_total").increment(projected);
+            projected
+        }
+        Err(err) => {
+            tracing::warn!(error = %err, "projecting the pending balances failed, retrying");
+            0
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_nats::{
+    jetstream::{self, context::Publish},
+    ConnectError, ConnectOptions,
+};
+use metrics::counter;
+use serde_json::json;
+use sqlx::PgPool;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::db;
+
+// events one round claims and publishes at once. a full round is followed
+// by another straight away
+const MAX_EVENTS: i64 = 500;
+
+const TENANT_HEADER: &str = "Rinha-Tenant";
+
+// publishes what the `transactions_enqueue_events` trigger puts in the
+// outbox to a jetstream stream taking `subject`, checking for it every
+// `poll`. an event leaves the outbox only once the stream acknowledged it,
+// so it's published at least once, and twice when the outbox row outlives
+// the ack. the outbox row's id goes in `Nats-Msg-Id`, for the stream to
+// drop those repeats within its duplicate window. a tenant's events share
+// the subject, with the tenant in `Rinha-Tenant` and ahead of the id
+#[derive(Clone)]
+pub struct Publisher {
+    stop: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    task: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl Publisher {
+    // only fails on a url that doesn't parse, the server is connected to
+    // and reconnected to in the background
+    pub async fn spawn(
+        pool: PgPool,
+        url: &str,
+        subject: String,
+        tenant: Option<String>,
+        poll: Duration,
+    ) -> Result<Self, ConnectError> {
+        let client = ConnectOptions::new()
+            .name("rinha")
+            .retry_on_initial_connect()
+            .connect(url)
+            .await?;
+        let jetstream = jetstream::new(client);
+
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(publish_all(pool, jetstream, subject, tenant, stopped, poll));
+
+        Ok(Publisher {
+            stop: Arc::new(Mutex::new(Some(stop))),
+            task: Arc::new(tokio::sync::Mutex::new(Some(task))),
+        })
+    }
+
+    // finishes the round in flight and stops, the rest waits for the next
+    // instance to start
+    pub async fn close(&self) {
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.lock().await.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+async fn publish_all(
+    pool: PgPool,
+    jetstream: jetstream::Context,
+    subject: String,
+    tenant: Option<String>,
+    mut stopped: oneshot::Receiver<()>,
+    poll: Duration,
+) {
+    // the first round after a `poll`, a cli command never gets to one
+    let start = tokio::time::Instant::now() + poll;
+    let mut ticks = tokio::time::interval_at(start, poll);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => loop {
+                match round(&pool, &jetstream, &subject, tenant.as_deref()).await {
+                    Ok(published) if published == MAX_EVENTS as usize => continue,
+                    Ok(_) => break,
+                    Err(err) => {
+                        tracing::warn!(error = %err, "can't read the event outbox, retrying");
+                        break;
+                    }
+                }
+            },
+            _ = &mut stopped => return,
+        }
+    }
+}
+
+// the events published and taken out of the outbox. they're all sent before
+// the acks are waited on, in outbox order, and the first one missing its ack
+// leaves it and the ones after it for the next round
+async fn round(
+    pool: &PgPool,
+    jetstream: &jetstream::Context,
+    subject: &str,
+    tenant: Option<&str>,
+) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let events = db::claim_outbox_events(&mut tx, MAX_EVENTS).await?;
+    if events.is_empty() {
+        return Ok(0);
+    }
+
+    let mut acks = Vec::with_capacity(events.len());
+    for event in &events {
+        let body = json!({
+            "evento": "transaction.created",
+            "cliente_id": event.wallet_id,
+            "transacao": event.transaction,
+        })
+        .to_string();
+        let publish = Publish::build().payload(body.into());
+        let publish = match tenant {
+            Some(tenant) => publish
+                .header(TENANT_HEADER, tenant)
+                .message_id(format!("{}:{}", tenant, event.id)),
+            None => publish.message_id(event.id.to_string()),
+        };
+
+        match jetstream.send_publish(subject.to_string(), publish).await {
+            Ok(ack) => acks.push((event.id, ack)),
+            Err(err) => {
+                tracing::warn!(error = %err, event_id = event.id, "can't publish the event, retrying");
+                break;
+            }
+        }
+    }
+
+    let mut published = Vec::with_capacity(acks.len());
+    for (event_id, ack) in acks {
+        match ack.await {
+            Ok(_) => published.push(event_id),
+            Err(err) => {
+                tracing::warn!(error = %err, event_id, "the event wasn't acknowledged, retrying");
+                break;
+            }
+        }
+    }
+
+    db::delete_outbox_events(&mut *tx, &published).await?;
+    tx.commit().await?;
+
+    counter!("events_published_total").increment(published.len() as u64);
+    Ok(published.len())
+}
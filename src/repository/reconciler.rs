@@ -0,0 +1,160 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use metrics::counter;
+use serde_json::json;
+use sqlx::PgPool;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::{db, models::Reconciliation};
+
+// `action` of the audit log rows a drifted balance leaves: repaired, or left
+// as it was because repairs are off or would go past the credit limit
+const BALANCE_REPAIRED: &str = "balance_repaired";
+const BALANCE_DRIFTED: &str = "balance_drifted";
+
+// the knobs `reconcile` needs from the repository it runs for
+#[derive(Debug, Clone, Copy)]
+pub struct ReconcileOptions {
+    pub event_sourcing: bool,
+    pub repair: bool,
+}
+
+// checks every `interval` that the balances are what their transactions add
+// up to, and goes through `reconcile` for each one that isn't. a drift left
+// unrepaired is reported again on every round until someone deals with it
+#[derive(Clone)]
+pub struct Reconciler {
+    stop: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    task: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl Reconciler {
+    pub fn spawn(pool: PgPool, interval: Duration, options: ReconcileOptions) -> Self {
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(reconcile_all(pool, stopped, interval, options));
+
+        Reconciler {
+            stop: Arc::new(Mutex::new(Some(stop))),
+            task: Arc::new(tokio::sync::Mutex::new(Some(task))),
+        }
+    }
+
+    // finishes the round in flight and stops
+    pub async fn close(&self) {
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.lock().await.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+async fn reconcile_all(
+    pool: PgPool,
+    mut stopped: oneshot::Receiver<()>,
+    interval: Duration,
+    options: ReconcileOptions,
+) {
+    // the first round after an `interval`, a cli command never gets to one
+    let start = tokio::time::Instant::now() + interval;
+    let mut ticks = tokio::time::interval_at(start, interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                if let Err(err) = round(&pool, options).await {
+                    tracing::warn!(error = %err, "reconciling the balances failed, retrying");
+                }
+            }
+            _ = &mut stopped => return,
+        }
+    }
+}
+
+async fn round(pool: &PgPool, options: ReconcileOptions) -> Result<(), sqlx::Error> {
+    let drifted = db::fetch_drifted_wallets(pool).await?;
+
+    for wallet_id in drifted {
+        reconcile(pool, wallet_id, options, "reconciler", None).await?;
+    }
+
+    Ok(())
+}
+
+// compares the wallet's balance with its transactions under its lock, and
+// with `options.repair` moves the balance to match. a drift is counted and
+// written to the audit log under `actor`. `None` for an unknown wallet
+pub async fn reconcile(
+    pool: &PgPool,
+    wallet_id: i32,
+    options: ReconcileOptions,
+    actor: &str,
+    request_id: Option<&str>,
+) -> Result<Option<Reconciliation>, sqlx::Error> {
+    let mut db_transaction = pool.begin().await?;
+
+    // the writers of the event sourcing mode only take the advisory lock,
+    // the projector only the row's
+    if options.event_sourcing && !db::lock_wallet_log(&mut *db_transaction, wallet_id).await? {
+        return Ok(None);
+    }
+    if !db::lock_wallet(&mut *db_transaction, wallet_id).await? {
+        return Ok(None);
+    }
+
+    let Some((balance, expected)) =
+        db::fetch_balance_drift(&mut *db_transaction, wallet_id).await?
+    else {
+        return Ok(None);
+    };
+
+    let mut reconciliation = Reconciliation {
+        wallet_id,
+        balance,
+        expected,
+        repaired: false,
+    };
+    if !reconciliation.drifted() {
+        return Ok(Some(reconciliation));
+    }
+
+    if options.repair {
+        let correction = expected.saturating_sub(balance);
+        reconciliation.repaired =
+            db::correct_balance(&mut *db_transaction, wallet_id, correction).await?;
+    }
+
+    let (action, result) = if reconciliation.repaired {
+        (BALANCE_REPAIRED, "repaired")
+    } else {
+        (BALANCE_DRIFTED, "reported")
+    };
+    db::insert_audit_log(
+        &mut *db_transaction,
+        wallet_id,
+        action,
+        &json!({ "saldo": balance }),
+        &json!({ "saldo": expected }),
+        actor,
+        request_id,
+    )
+    .await?;
+
+    db_transaction.commit().await?;
+
+    counter!("balance_drifts_total", "result" => result).increment(1);
+    tracing::warn!(
+        wallet_id,
+        balance = %balance,
+        expected = %expected,
+        repaired = reconciliation.repaired,
+        "balance drifted from the transactions"
+    );
+
+    Ok(Some(reconciliation))
+}
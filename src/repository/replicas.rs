@@ -0,0 +1,87 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use metrics::counter;
+use sqlx::{pool::PoolConnection, PgPool, Postgres};
+
+use crate::db;
+
+// a replica that couldn't hand out a connection is skipped for this long,
+// so reads don't all wait out its acquire timeout first
+const RETRY_AFTER: Duration = Duration::from_secs(5);
+
+struct Replica {
+    pool: PgPool,
+    down_until: Mutex<Option<Instant>>,
+}
+
+// the replicas the statements and listings are read from, taking turns.
+// they lag behind the primary, so a statement read right after a write may
+// not have it yet
+#[derive(Clone, Default)]
+pub struct Replicas {
+    replicas: Arc<[Replica]>,
+    next: Arc<AtomicUsize>,
+}
+
+impl Replicas {
+    pub fn new(pools: Vec<PgPool>) -> Self {
+        Replicas {
+            replicas: pools
+                .into_iter()
+                .map(|pool| Replica {
+                    pool,
+                    down_until: Mutex::new(None),
+                })
+                .collect(),
+            next: Arc::default(),
+        }
+    }
+
+    // a connection to the next replica that's up, `None` once none of them
+    // is and the read has to go to the primary
+    pub async fn acquire(&self) -> Option<PoolConnection<Postgres>> {
+        if self.replicas.is_empty() {
+            return None;
+        }
+
+        let first = self.next.fetch_add(1, Ordering::Relaxed);
+        for offset in 0..self.replicas.len() {
+            let replica = &self.replicas[(first + offset) % self.replicas.len()];
+            if replica.is_down() {
+                continue;
+            }
+
+            match db::acquire(&replica.pool).await {
+                Ok(conn) => return Some(conn),
+                Err(err) => {
+                    tracing::warn!(error = %err, "replica unreachable, skipping it for a while");
+                    *replica.down_until.lock().unwrap() = Some(Instant::now() + RETRY_AFTER);
+                }
+            }
+        }
+
+        counter!("replica_fallbacks_total").increment(1);
+        None
+    }
+
+    pub async fn close(&self) {
+        for replica in self.replicas.iter() {
+            replica.pool.close().await;
+        }
+    }
+}
+
+impl Replica {
+    fn is_down(&self) -> bool {
+        self.down_until
+            .lock()
+            .unwrap()
+            .is_some_and(|until| Instant::now() < until)
+    }
+}
@@ -0,0 +1,646 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use metrics::counter;
+use rand::Rng;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    auth::Scope,
+    errors::ApiError,
+    events::TransactionEvent,
+    models::{
+        Account, AuditFilter, AuditPage, CapturedHold, Cents, ChainVerification, CreatedWallet,
+        CreatedWebhook, FeeRule, HistoryFilter, HistoryPage, Hold, LedgerViolation, MonthlySummary,
+        PostFeeRule, PostHold, PostRecurrence, PostTransaction, PostTransfer, PostWallet,
+        PostWebhook, Reconciliation, Recurrence, ScheduledTransaction, Statement, StatementFilter,
+        SummaryMonth, TransactionBatchReceipt, TransactionDetails, TransactionReceipt,
+        TransferReceipt, Wallet, WalletDetails, WalletStatus, Webhook,
+    },
+};
+
+use super::{OnWalletChanged, PoolStatus, TransactionStream, WalletRepository};
+
+// wraps another repository and retries the calls that failed on a transient
+// database error, up to `max_attempts` in total, sleeping a jittered
+// exponential backoff in between
+#[derive(Clone)]
+pub struct Retry<R> {
+    inner: R,
+    max_attempts: u32,
+    base_delay: Duration,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Transient {
+    // the server rolled the transaction back, running it again is always safe
+    Conflict(&'static str),
+    // the connection went away. the statement may or may not have been
+    // applied, so writes are only retried when an idempotency key makes
+    // running them twice harmless
+    Connection,
+}
+
+impl Transient {
+    fn classify(err: &ApiError) -> Option<Self> {
+        let ApiError::Database(err) = err else {
+            return None;
+        };
+
+        match err {
+            sqlx::Error::Database(db_err) => match db_err.code().as_deref() {
+                Some("40001") => Some(Transient::Conflict("serialization_failure")),
+                Some("40P01") => Some(Transient::Conflict("deadlock")),
+                Some(code) if code.starts_with("08") => Some(Transient::Connection),
+                _ => None,
+            },
+            sqlx::Error::Io(_) => Some(Transient::Connection),
+            _ => None,
+        }
+    }
+
+    fn reason(self) -> &'static str {
+        match self {
+            Transient::Conflict(reason) => reason,
+            Transient::Connection => "connection",
+        }
+    }
+}
+
+impl<R> Retry<R> {
+    pub fn new(inner: R, max_attempts: u32, base_delay: Duration) -> Self {
+        Retry {
+            inner,
+            max_attempts: max_attempts.max(1),
+            base_delay,
+        }
+    }
+
+    // "full jitter": a random delay up to the exponential backoff, so the
+    // callers that failed together don't all come back at the same time
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self.base_delay.saturating_mul(1 << (attempt - 1).min(16));
+        ceiling.mul_f64(rand::thread_rng().gen::<f64>())
+    }
+
+    async fn run<T, F, Fut>(
+        &self,
+        operation: &'static str,
+        replay_safe: bool,
+        call: F,
+    ) -> Result<T, ApiError>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<T, ApiError>>,
+    {
+        let mut attempt = 1;
+        loop {
+            let err = match call().await {
+                Ok(value) => return Ok(value),
+                Err(err) => err,
+            };
+
+            let retryable = match Transient::classify(&err) {
+                Some(transient @ Transient::Conflict(_)) => Some(transient),
+                Some(Transient::Connection) if replay_safe => Some(Transient::Connection),
+                _ => None,
+            };
+
+            let Some(transient) = retryable.filter(|_| attempt < self.max_attempts) else {
+                return Err(err);
+            };
+
+            counter!("db_retries_total", "operation" => operation, "reason" => transient.reason())
+                .increment(1);
+            tracing::debug!(
+                "{} failed on a transient error ({}), retrying",
+                operation,
+                err.source_message()
+            );
+
+            tokio::time::sleep(self.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[async_trait]
+impl<R: WalletRepository> WalletRepository for Retry<R> {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError> {
+        self.run("get_statement", true, || {
+            self.inner.get_statement(wallet_id, filter)
+        })
+        .await
+    }
+
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError> {
+        self.run("get_wallet", true, || self.inner.get_wallet(wallet_id))
+            .await
+    }
+
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        self.run("insert_transaction", idempotency_key.is_some(), || {
+            self.inner
+                .insert_transaction(wallet_id, transaction, idempotency_key)
+        })
+        .await
+    }
+
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        // no idempotency key to make a replay safe
+        self.run("insert_transactions", false, || {
+            self.inner.insert_transactions(wallet_id, transactions)
+        })
+        .await
+    }
+
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError> {
+        self.run("get_history", true, || {
+            self.inner.get_history(wallet_id, filter)
+        })
+        .await
+    }
+
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError> {
+        self.run("get_summary", true, || {
+            self.inner.get_summary(wallet_id, month)
+        })
+        .await
+    }
+
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError> {
+        // only opening the stream is retried, not a failure halfway through
+        self.run("export", true, || self.inner.export(wallet_id))
+            .await
+    }
+
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError> {
+        self.run("get_transaction", true, || {
+            self.inner.get_transaction(wallet_id, transaction_id)
+        })
+        .await
+    }
+
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError> {
+        // without an idempotency key a replay could move the value twice
+        self.run("transfer", false, || self.inner.transfer(transfer))
+            .await
+    }
+
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError> {
+        // never replayed on a lost connection, it could create the wallet twice
+        self.run("create_wallet", false, || self.inner.create_wallet(wallet))
+            .await
+    }
+
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError> {
+        // a replay would log the change twice
+        self.run("update_credit_limit", false, || {
+            self.inner
+                .update_credit_limit(wallet_id, credit_limit, actor)
+        })
+        .await
+    }
+
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.run("set_wallet_status", true, || {
+            self.inner.set_wallet_status(wallet_id, status, actor)
+        })
+        .await
+    }
+
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.run("set_daily_debit_limit", true, || {
+            self.inner.set_daily_debit_limit(wallet_id, limit, actor)
+        })
+        .await
+    }
+
+    async fn create_account(
+        &self,
+        client_id: i32,
+        wallet: &PostWallet,
+    ) -> Result<CreatedWallet, ApiError> {
+        // like `create_wallet`, a replay could create it twice
+        self.run("create_account", false, || {
+            self.inner.create_account(client_id, wallet)
+        })
+        .await
+    }
+
+    async fn list_accounts(&self, client_id: i32) -> Result<Vec<Account>, ApiError> {
+        self.run("list_accounts", true, || {
+            self.inner.list_accounts(client_id)
+        })
+        .await
+    }
+
+    async fn owns_account(&self, client_id: i32, account_id: i32) -> Result<bool, ApiError> {
+        self.run("owns_account", true, || {
+            self.inner.owns_account(client_id, account_id)
+        })
+        .await
+    }
+
+    async fn reconcile(
+        &self,
+        wallet_id: i32,
+        repair: bool,
+        actor: &str,
+    ) -> Result<Reconciliation, ApiError> {
+        // a replay could log the drift twice
+        self.run("reconcile", false, || {
+            self.inner.reconcile(wallet_id, repair, actor)
+        })
+        .await
+    }
+
+    async fn verify_chain(&self, wallet_id: i32) -> Result<ChainVerification, ApiError> {
+        self.run("verify_chain", true, || self.inner.verify_chain(wallet_id))
+            .await
+    }
+
+    async fn create_webhook(
+        &self,
+        wallet_id: i32,
+        webhook: &PostWebhook,
+    ) -> Result<CreatedWebhook, ApiError> {
+        // like `create_wallet`, a replay could register it twice
+        self.run("create_webhook", false, || {
+            self.inner.create_webhook(wallet_id, webhook)
+        })
+        .await
+    }
+
+    async fn list_webhooks(&self, wallet_id: i32) -> Result<Vec<Webhook>, ApiError> {
+        self.run("list_webhooks", true, || {
+            self.inner.list_webhooks(wallet_id)
+        })
+        .await
+    }
+
+    async fn delete_webhook(&self, wallet_id: i32, webhook_id: i32) -> Result<(), ApiError> {
+        // a replay of a delete that went through would answer `NotFound`
+        self.run("delete_webhook", false, || {
+            self.inner.delete_webhook(wallet_id, webhook_id)
+        })
+        .await
+    }
+
+    async fn create_hold(&self, wallet_id: i32, hold: &PostHold) -> Result<Hold, ApiError> {
+        // a replay could set the amount aside twice
+        self.run("create_hold", false, || {
+            self.inner.create_hold(wallet_id, hold)
+        })
+        .await
+    }
+
+    async fn get_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.run("get_hold", true, || self.inner.get_hold(hold_id))
+            .await
+    }
+
+    async fn capture_hold(&self, hold_id: Uuid) -> Result<CapturedHold, ApiError> {
+        // a replay of a capture that went through would answer `HoldSettled`
+        self.run("capture_hold", false, || self.inner.capture_hold(hold_id))
+            .await
+    }
+
+    async fn release_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.run("release_hold", false, || self.inner.release_hold(hold_id))
+            .await
+    }
+
+    async fn schedule_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        due_at: OffsetDateTime,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        // a replay could schedule it twice
+        self.run("schedule_transaction", false, || {
+            self.inner
+                .schedule_transaction(wallet_id, transaction, due_at)
+        })
+        .await
+    }
+
+    async fn list_scheduled(&self, wallet_id: i32) -> Result<Vec<ScheduledTransaction>, ApiError> {
+        self.run("list_scheduled", true, || {
+            self.inner.list_scheduled(wallet_id)
+        })
+        .await
+    }
+
+    async fn cancel_scheduled(
+        &self,
+        wallet_id: i32,
+        scheduled_id: Uuid,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        // a replay of a cancel that went through would answer `ScheduledSettled`
+        self.run("cancel_scheduled", false, || {
+            self.inner.cancel_scheduled(wallet_id, scheduled_id)
+        })
+        .await
+    }
+
+    async fn create_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        // like `create_webhook`, a replay could create it twice
+        self.run("create_recurrence", false, || {
+            self.inner.create_recurrence(wallet_id, recurrence)
+        })
+        .await
+    }
+
+    async fn list_recurrences(&self, wallet_id: i32) -> Result<Vec<Recurrence>, ApiError> {
+        self.run("list_recurrences", true, || {
+            self.inner.list_recurrences(wallet_id)
+        })
+        .await
+    }
+
+    async fn get_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+    ) -> Result<Recurrence, ApiError> {
+        self.run("get_recurrence", true, || {
+            self.inner.get_recurrence(wallet_id, recurrence_id)
+        })
+        .await
+    }
+
+    async fn update_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        // a replay puts the same definition back
+        self.run("update_recurrence", true, || {
+            self.inner
+                .update_recurrence(wallet_id, recurrence_id, recurrence)
+        })
+        .await
+    }
+
+    async fn delete_recurrence(&self, wallet_id: i32, recurrence_id: i32) -> Result<(), ApiError> {
+        // like `delete_webhook`'s, a replay would answer `NotFound`
+        self.run("delete_recurrence", false, || {
+            self.inner.delete_recurrence(wallet_id, recurrence_id)
+        })
+        .await
+    }
+
+    async fn create_fee_rule(&self, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        // a replay would add it twice
+        self.run("create_fee_rule", false, || {
+            self.inner.create_fee_rule(rule)
+        })
+        .await
+    }
+
+    async fn list_fee_rules(&self) -> Result<Vec<FeeRule>, ApiError> {
+        self.run("list_fee_rules", true, || self.inner.list_fee_rules())
+            .await
+    }
+
+    async fn update_fee_rule(&self, rule_id: i32, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.run("update_fee_rule", true, || {
+            self.inner.update_fee_rule(rule_id, rule)
+        })
+        .await
+    }
+
+    async fn delete_fee_rule(&self, rule_id: i32) -> Result<(), ApiError> {
+        // like `delete_recurrence`'s, a replay would answer `NotFound`
+        self.run("delete_fee_rule", false, || {
+            self.inner.delete_fee_rule(rule_id)
+        })
+        .await
+    }
+
+    async fn set_interest_rate(
+        &self,
+        wallet_id: i32,
+        rate: Option<f64>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.run("set_interest_rate", true, || {
+            self.inner.set_interest_rate(wallet_id, rate, actor)
+        })
+        .await
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<AuditPage, ApiError> {
+        self.run("audit_log", true, || self.inner.audit_log(filter))
+            .await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        self.inner.subscribe()
+    }
+
+    fn watch_changes(&self, on_change: OnWalletChanged) -> bool {
+        self.inner.watch_changes(on_change)
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.inner.ping().await
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        self.inner.pool_status()
+    }
+
+    async fn run_migrations(&self) -> Result<(), ApiError> {
+        self.inner.run_migrations().await
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<(i64, String)>, ApiError> {
+        self.inner.pending_migrations().await
+    }
+
+    async fn missing_ledger_guards(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_ledger_guards().await
+    }
+
+    async fn missing_indexes(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_indexes().await
+    }
+
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError> {
+        self.inner.check_ledger().await
+    }
+
+    async fn rebuild_projections(&self) -> Result<u64, ApiError> {
+        self.inner.rebuild_projections().await
+    }
+
+    async fn seed(&self, reset: bool) -> Result<(), ApiError> {
+        self.inner.seed(reset).await
+    }
+
+    async fn api_key_scope(&self, key_hash: &str) -> Result<Option<Scope>, ApiError> {
+        self.run("api_key_scope", true, || self.inner.api_key_scope(key_hash))
+            .await
+    }
+
+    async fn close(&self) {
+        self.inner.close().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        borrow::Cow,
+        fmt,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+
+    use super::*;
+    use crate::repository::MemoryWalletRepository;
+
+    // what postgres answers a transaction that lost a serializable conflict
+    #[derive(Debug)]
+    struct SerializationFailure;
+
+    impl fmt::Display for SerializationFailure {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("could not serialize access")
+        }
+    }
+
+    impl std::error::Error for SerializationFailure {}
+
+    impl sqlx::error::DatabaseError for SerializationFailure {
+        fn message(&self) -> &str {
+            "could not serialize access"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some("40001".into())
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> sqlx::error::ErrorKind {
+            sqlx::error::ErrorKind::Other
+        }
+    }
+
+    fn conflict() -> ApiError {
+        ApiError::Database(sqlx::Error::Database(Box::new(SerializationFailure)))
+    }
+
+    fn connection() -> ApiError {
+        ApiError::Database(sqlx::Error::Io(std::io::ErrorKind::ConnectionReset.into()))
+    }
+
+    // how many times `run` made a call that always fails with `err`
+    async fn attempts(replay_safe: bool, err: fn() -> ApiError) -> u32 {
+        let retry = Retry::new(MemoryWalletRepository::new(), 3, Duration::from_millis(1));
+        let calls = AtomicU32::new(0);
+
+        let result: Result<(), ApiError> = retry
+            .run("test", replay_safe, || async {
+                calls.fetch_add(1, Ordering::Relaxed);
+                Err(err())
+            })
+            .await;
+
+        assert!(result.is_err());
+        calls.load(Ordering::Relaxed)
+    }
+
+    #[tokio::test]
+    async fn a_transient_error_is_retried_up_to_the_attempt_limit() {
+        assert_eq!(attempts(false, conflict).await, 3);
+        assert_eq!(attempts(true, connection).await, 3);
+    }
+
+    #[tokio::test]
+    async fn the_other_errors_arent_retried() {
+        assert_eq!(attempts(true, || ApiError::LimitExceeded).await, 1);
+        assert_eq!(
+            attempts(true, || ApiError::Database(sqlx::Error::RowNotFound)).await,
+            1
+        );
+        // a write the lost connection may have applied already
+        assert_eq!(attempts(false, connection).await, 1);
+    }
+
+    #[tokio::test]
+    async fn a_retry_that_succeeds_answers_with_its_result() {
+        let retry = Retry::new(MemoryWalletRepository::new(), 3, Duration::from_millis(1));
+        let calls = AtomicU32::new(0);
+
+        let result = retry
+            .run("test", false, || async {
+                match calls.fetch_add(1, Ordering::Relaxed) {
+                    0 => Err(conflict()),
+                    attempt => Ok(attempt),
+                }
+            })
+            .await;
+
+        assert_eq!(result.unwrap(), 1);
+    }
+}
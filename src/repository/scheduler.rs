@@ -0,0 +1,102 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use metrics::counter;
+use tokio::{sync::oneshot, task::JoinHandle};
+
+use crate::{audit, models::ScheduledStatus};
+
+use super::postgres::PgWalletRepository;
+
+// scheduled transactions, and separately recurrences, one round applies.
+// a backlog drains over the following rounds
+const MAX_SCHEDULED: usize = 1000;
+
+// applies the scheduled transactions and the runs of the recurrences that
+// are due every `interval`, one database transaction each, so a slow one doesn't hold back the others'
+// locks. every instance runs one, the rows one of them is applying are
+// skipped by the others
+#[derive(Clone)]
+pub struct Scheduler {
+    stop: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    task: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl Scheduler {
+    pub fn spawn(repo: PgWalletRepository, interval: Duration) -> Self {
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(run(repo, stopped, interval));
+
+        Scheduler {
+            stop: Arc::new(Mutex::new(Some(stop))),
+            task: Arc::new(tokio::sync::Mutex::new(Some(task))),
+        }
+    }
+
+    // finishes the round in flight and stops
+    pub async fn close(&self) {
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.lock().await.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+async fn run(repo: PgWalletRepository, mut stopped: oneshot::Receiver<()>, interval: Duration) {
+    // the first round after an `interval`, like the hold sweeper's
+    let start = tokio::time::Instant::now() + interval;
+    let mut ticks = tokio::time::interval_at(start, interval);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    // the audit log tells what it applied from what a client posted
+    let context = || audit::Context {
+        actor: Some("scheduler".into()),
+        request_id: None,
+    };
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                context().scope(round(&repo)).await;
+            }
+            _ = &mut stopped => return,
+        }
+    }
+}
+
+async fn round(repo: &PgWalletRepository) {
+    for _ in 0..MAX_SCHEDULED {
+        match repo.apply_scheduled().await {
+            Ok(Some(status)) => {
+                let status = match status {
+                    ScheduledStatus::Applied => "applied",
+                    _ => "failed",
+                };
+                counter!("scheduled_transactions_settled_total", "status" => status).increment(1);
+            }
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!(error = %err, "applying a scheduled transaction failed, retrying");
+                break;
+            }
+        }
+    }
+
+    for _ in 0..MAX_SCHEDULED {
+        match repo.apply_recurrence().await {
+            Ok(Some(applied)) => {
+                let status = if applied { "applied" } else { "failed" };
+                counter!("recurring_transactions_runs_total", "status" => status).increment(1);
+            }
+            Ok(None) => break,
+            Err(err) => {
+                tracing::warn!(error = %err, "running a recurrence failed, retrying");
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,600 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use metrics::counter;
+use redis::{aio::ConnectionManager, AsyncCommands, Client, RedisError, Script};
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use tokio_stream::StreamExt;
+use uuid::Uuid;
+
+use crate::{
+    auth::Scope,
+    errors::ApiError,
+    events::TransactionEvent,
+    models::{
+        Account, AuditFilter, AuditPage, CapturedHold, Cents, ChainVerification, CreatedWallet,
+        CreatedWebhook, FeeRule, HistoryFilter, HistoryPage, Hold, LedgerViolation, MonthlySummary,
+        PostFeeRule, PostHold, PostRecurrence, PostTransaction, PostTransfer, PostWallet,
+        PostWebhook, Reconciliation, Recurrence, ScheduledTransaction, Statement, StatementFilter,
+        SummaryMonth, TransactionBatchReceipt, TransactionDetails, TransactionReceipt,
+        TransferReceipt, Wallet, WalletDetails, WalletStatus, Webhook,
+    },
+};
+
+use super::{OnWalletChanged, PoolStatus, TransactionStream, WalletRepository};
+
+// every write publishes the wallet id here, `*` after a reseed. a tenant's
+// go to `<tenant>:wallet_changed`
+const WALLET_CHANGED: &str = "wallet_changed";
+
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+// in milliseconds, what the entries stored from now on are kept for
+static TTL_MS: AtomicU64 = AtomicU64::new(5000);
+
+pub(super) fn set_ttl(ttl: Duration) {
+    // redis turns a zero `PX` away, and zero turns nothing off here
+    TTL_MS.store((ttl.as_millis() as u64).max(1), Ordering::Relaxed);
+}
+
+// the statement only goes in if no write bumped the generation since the
+// read started, checked inside redis so it can't race the bump
+const STORE: &str = r"
+if (redis.call('GET', KEYS[2]) or '0') == ARGV[1] then
+    redis.call('SET', KEYS[1], ARGV[2], 'PX', ARGV[3])
+end
+";
+
+#[derive(Clone)]
+struct Shared {
+    client: Client,
+    conn: ConnectionManager,
+    store: Script,
+    // `rinha`, and `rinha:<tenant>` for a tenant's keys
+    prefix: String,
+    channel: String,
+}
+
+// wraps another repository and keeps the unfiltered statement of each wallet
+// in redis for up to SHARED_CACHE_TTL_MS, so every instance behind the load balancer reads
+// the same entries. writes drop the entry and announce the wallet on
+// `WALLET_CHANGED`, where `watch_changes` tells each instance, which drops
+// its own `StatementCache` entry. redis failing only costs the cache, never the
+// request
+#[derive(Clone)]
+pub struct SharedCache<R> {
+    inner: R,
+    shared: Option<Shared>,
+}
+
+impl<R> SharedCache<R> {
+    // a pass-through without a `url`
+    pub async fn connect(
+        inner: R,
+        url: Option<&str>,
+        tenant: Option<&str>,
+    ) -> Result<Self, RedisError> {
+        let Some(url) = url else {
+            return Ok(SharedCache {
+                inner,
+                shared: None,
+            });
+        };
+
+        let client = Client::open(url)?;
+        let conn = client.get_connection_manager().await?;
+
+        Ok(SharedCache {
+            inner,
+            shared: Some(Shared {
+                client,
+                conn,
+                store: Script::new(STORE),
+                prefix: match tenant {
+                    Some(tenant) => format!("rinha:{}", tenant),
+                    None => "rinha".to_string(),
+                },
+                channel: match tenant {
+                    Some(tenant) => format!("{}:{}", tenant, WALLET_CHANGED),
+                    None => WALLET_CHANGED.to_string(),
+                },
+            }),
+        })
+    }
+}
+
+impl Shared {
+    fn statement_key(&self, wallet_id: i32) -> String {
+        format!("{}:statement:{}", self.prefix, wallet_id)
+    }
+
+    // never expire, a reset generation could match one captured before it
+    fn generation_key(&self, wallet_id: i32) -> String {
+        format!("{}:generation:{}", self.prefix, wallet_id)
+    }
+
+    // `(statement, generation)`, the generation to hand back to `store`
+    async fn load(&self, wallet_id: i32) -> Result<(Option<Statement>, u64), RedisError> {
+        let (statement, generation): (Option<String>, Option<u64>) = redis::pipe()
+            .get(self.statement_key(wallet_id))
+            .get(self.generation_key(wallet_id))
+            .query_async(&mut self.conn.clone())
+            .await?;
+
+        // an entry this version can't read is just a miss
+        let statement = statement.and_then(|body| serde_json::from_str(&body).ok());
+        Ok((statement, generation.unwrap_or_default()))
+    }
+
+    async fn store(
+        &self,
+        wallet_id: i32,
+        generation: u64,
+        statement: &Statement,
+    ) -> Result<(), RedisError> {
+        let body = serde_json::to_string(statement).expect("statements are always serializable");
+        self.store
+            .key(self.statement_key(wallet_id))
+            .key(self.generation_key(wallet_id))
+            .arg(generation)
+            .arg(body)
+            .arg(TTL_MS.load(Ordering::Relaxed))
+            .invoke_async(&mut self.conn.clone())
+            .await
+    }
+
+    async fn invalidate(&self, wallet_id: i32) {
+        let result: Result<(), RedisError> = redis::pipe()
+            .atomic()
+            .incr(self.generation_key(wallet_id), 1)
+            .ignore()
+            .del(self.statement_key(wallet_id))
+            .ignore()
+            .publish(&self.channel, wallet_id)
+            .ignore()
+            .query_async(&mut self.conn.clone())
+            .await;
+
+        // the entry stays stale until its ttl runs out
+        if let Err(err) = result {
+            counter!("shared_cache_total", "result" => "error").increment(1);
+            tracing::error!(error = %err, wallet_id, "can't invalidate the shared cache");
+        }
+    }
+
+    async fn invalidate_all(&self) -> Result<(), RedisError> {
+        let mut conn = self.conn.clone();
+        let keys: Vec<String> = conn
+            .scan_match::<_, String>(format!("{}:statement:*", self.prefix))
+            .await?
+            .collect::<Result<_, _>>()
+            .await?;
+        if !keys.is_empty() {
+            conn.del::<_, ()>(keys).await?;
+        }
+        conn.publish(&self.channel, "*").await
+    }
+}
+
+// messages published while the subscription was down are lost, so every
+// wallet changed as far as `on_change` knows after every reconnect
+async fn listen(client: Client, channel: String, on_change: OnWalletChanged) {
+    loop {
+        match client.get_async_pubsub().await {
+            Ok(mut pubsub) => match pubsub.subscribe(&channel).await {
+                Ok(()) => {
+                    on_change(None);
+                    let mut messages = pubsub.into_on_message();
+                    while let Some(message) = messages.next().await {
+                        match message.get_payload::<String>().as_deref() {
+                            Ok("*") => on_change(None),
+                            Ok(id) => match id.parse() {
+                                Ok(wallet_id) => on_change(Some(wallet_id)),
+                                Err(_) => tracing::warn!(payload = id, "unexpected cache message"),
+                            },
+                            Err(err) => tracing::warn!(error = %err, "unexpected cache message"),
+                        }
+                    }
+                    tracing::warn!("lost the cache invalidation subscription");
+                }
+                Err(err) => tracing::warn!(error = %err, "can't subscribe to cache invalidations"),
+            },
+            Err(err) => tracing::warn!(error = %err, "can't subscribe to cache invalidations"),
+        }
+
+        tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+    }
+}
+
+#[async_trait]
+impl<R: WalletRepository> WalletRepository for SharedCache<R> {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError> {
+        // a filtered statement is a different body, those always go through
+        let (
+            Some(shared),
+            StatementFilter {
+                from: None,
+                to: None,
+                kind: None,
+            },
+        ) = (&self.shared, filter)
+        else {
+            return self.inner.get_statement(wallet_id, filter).await;
+        };
+
+        let generation = match shared.load(wallet_id).await {
+            Ok((Some(statement), _)) => {
+                counter!("shared_cache_total", "result" => "hit").increment(1);
+                return Ok(statement);
+            }
+            Ok((None, generation)) => {
+                counter!("shared_cache_total", "result" => "miss").increment(1);
+                Some(generation)
+            }
+            Err(err) => {
+                counter!("shared_cache_total", "result" => "error").increment(1);
+                tracing::warn!(error = %err, wallet_id, "can't read the shared cache");
+                None
+            }
+        };
+
+        let statement = self.inner.get_statement(wallet_id, filter).await?;
+        if let Some(generation) = generation {
+            if let Err(err) = shared.store(wallet_id, generation, &statement).await {
+                counter!("shared_cache_total", "result" => "error").increment(1);
+                tracing::warn!(error = %err, wallet_id, "can't write the shared cache");
+            }
+        }
+
+        Ok(statement)
+    }
+
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError> {
+        self.inner.get_wallet(wallet_id).await
+    }
+
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        let receipt = self
+            .inner
+            .insert_transaction(wallet_id, transaction, idempotency_key)
+            .await?;
+        if let Some(shared) = &self.shared {
+            shared.invalidate(wallet_id).await;
+        }
+        Ok(receipt)
+    }
+
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        let receipt = self
+            .inner
+            .insert_transactions(wallet_id, transactions)
+            .await?;
+        if let Some(shared) = &self.shared {
+            shared.invalidate(wallet_id).await;
+        }
+        Ok(receipt)
+    }
+
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError> {
+        self.inner.get_history(wallet_id, filter).await
+    }
+
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError> {
+        self.inner.get_summary(wallet_id, month).await
+    }
+
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError> {
+        self.inner.export(wallet_id).await
+    }
+
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError> {
+        self.inner.get_transaction(wallet_id, transaction_id).await
+    }
+
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError> {
+        let receipt = self.inner.transfer(transfer).await?;
+        if let Some(shared) = &self.shared {
+            shared.invalidate(transfer.from).await;
+            shared.invalidate(transfer.to).await;
+        }
+        Ok(receipt)
+    }
+
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError> {
+        self.inner.create_wallet(wallet).await
+    }
+
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError> {
+        let wallet = self
+            .inner
+            .update_credit_limit(wallet_id, credit_limit, actor)
+            .await?;
+        if let Some(shared) = &self.shared {
+            shared.invalidate(wallet_id).await;
+        }
+        Ok(wallet)
+    }
+
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.inner.set_wallet_status(wallet_id, status, actor).await
+    }
+
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.inner
+            .set_daily_debit_limit(wallet_id, limit, actor)
+            .await
+    }
+
+    async fn create_account(
+        &self,
+        client_id: i32,
+        wallet: &PostWallet,
+    ) -> Result<CreatedWallet, ApiError> {
+        self.inner.create_account(client_id, wallet).await
+    }
+
+    async fn list_accounts(&self, client_id: i32) -> Result<Vec<Account>, ApiError> {
+        self.inner.list_accounts(client_id).await
+    }
+
+    async fn owns_account(&self, client_id: i32, account_id: i32) -> Result<bool, ApiError> {
+        self.inner.owns_account(client_id, account_id).await
+    }
+
+    async fn reconcile(
+        &self,
+        wallet_id: i32,
+        repair: bool,
+        actor: &str,
+    ) -> Result<Reconciliation, ApiError> {
+        let reconciliation = self.inner.reconcile(wallet_id, repair, actor).await?;
+        if let (true, Some(shared)) = (reconciliation.repaired, &self.shared) {
+            shared.invalidate(wallet_id).await;
+        }
+        Ok(reconciliation)
+    }
+
+    async fn verify_chain(&self, wallet_id: i32) -> Result<ChainVerification, ApiError> {
+        self.inner.verify_chain(wallet_id).await
+    }
+
+    async fn create_webhook(
+        &self,
+        wallet_id: i32,
+        webhook: &PostWebhook,
+    ) -> Result<CreatedWebhook, ApiError> {
+        self.inner.create_webhook(wallet_id, webhook).await
+    }
+
+    async fn list_webhooks(&self, wallet_id: i32) -> Result<Vec<Webhook>, ApiError> {
+        self.inner.list_webhooks(wallet_id).await
+    }
+
+    async fn delete_webhook(&self, wallet_id: i32, webhook_id: i32) -> Result<(), ApiError> {
+        self.inner.delete_webhook(wallet_id, webhook_id).await
+    }
+
+    async fn create_hold(&self, wallet_id: i32, hold: &PostHold) -> Result<Hold, ApiError> {
+        self.inner.create_hold(wallet_id, hold).await
+    }
+
+    async fn get_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.inner.get_hold(hold_id).await
+    }
+
+    async fn capture_hold(&self, hold_id: Uuid) -> Result<CapturedHold, ApiError> {
+        let captured = self.inner.capture_hold(hold_id).await?;
+        if let Some(shared) = &self.shared {
+            shared.invalidate(captured.hold.wallet_id).await;
+        }
+        Ok(captured)
+    }
+
+    async fn release_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.inner.release_hold(hold_id).await
+    }
+
+    async fn schedule_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        due_at: OffsetDateTime,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.inner
+            .schedule_transaction(wallet_id, transaction, due_at)
+            .await
+    }
+
+    async fn list_scheduled(&self, wallet_id: i32) -> Result<Vec<ScheduledTransaction>, ApiError> {
+        self.inner.list_scheduled(wallet_id).await
+    }
+
+    async fn cancel_scheduled(
+        &self,
+        wallet_id: i32,
+        scheduled_id: Uuid,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.inner.cancel_scheduled(wallet_id, scheduled_id).await
+    }
+
+    async fn create_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.inner.create_recurrence(wallet_id, recurrence).await
+    }
+
+    async fn list_recurrences(&self, wallet_id: i32) -> Result<Vec<Recurrence>, ApiError> {
+        self.inner.list_recurrences(wallet_id).await
+    }
+
+    async fn get_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+    ) -> Result<Recurrence, ApiError> {
+        self.inner.get_recurrence(wallet_id, recurrence_id).await
+    }
+
+    async fn update_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.inner
+            .update_recurrence(wallet_id, recurrence_id, recurrence)
+            .await
+    }
+
+    async fn delete_recurrence(&self, wallet_id: i32, recurrence_id: i32) -> Result<(), ApiError> {
+        self.inner.delete_recurrence(wallet_id, recurrence_id).await
+    }
+
+    async fn create_fee_rule(&self, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.inner.create_fee_rule(rule).await
+    }
+
+    async fn list_fee_rules(&self) -> Result<Vec<FeeRule>, ApiError> {
+        self.inner.list_fee_rules().await
+    }
+
+    async fn update_fee_rule(&self, rule_id: i32, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.inner.update_fee_rule(rule_id, rule).await
+    }
+
+    async fn delete_fee_rule(&self, rule_id: i32) -> Result<(), ApiError> {
+        self.inner.delete_fee_rule(rule_id).await
+    }
+
+    async fn set_interest_rate(
+        &self,
+        wallet_id: i32,
+        rate: Option<f64>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.inner.set_interest_rate(wallet_id, rate, actor).await
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<AuditPage, ApiError> {
+        self.inner.audit_log(filter).await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        self.inner.subscribe()
+    }
+
+    fn watch_changes(&self, on_change: OnWalletChanged) -> bool {
+        if let Some(shared) = &self.shared {
+            tokio::spawn(listen(
+                shared.client.clone(),
+                shared.channel.clone(),
+                on_change.clone(),
+            ));
+        }
+        self.inner.watch_changes(on_change) || self.shared.is_some()
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.inner.ping().await
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        self.inner.pool_status()
+    }
+
+    async fn run_migrations(&self) -> Result<(), ApiError> {
+        self.inner.run_migrations().await
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<(i64, String)>, ApiError> {
+        self.inner.pending_migrations().await
+    }
+
+    async fn missing_ledger_guards(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_ledger_guards().await
+    }
+
+    async fn missing_indexes(&self) -> Result<Vec<String>, ApiError> {
+        self.inner.missing_indexes().await
+    }
+
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError> {
+        self.inner.check_ledger().await
+    }
+
+    async fn rebuild_projections(&self) -> Result<u64, ApiError> {
+        let rebuilt = self.inner.rebuild_projections().await?;
+        // every balance may have changed
+        if let Some(shared) = &self.shared {
+            if let Err(err) = shared.invalidate_all().await {
+                tracing::error!(error = %err, "can't invalidate the shared cache");
+            }
+        }
+        Ok(rebuilt)
+    }
+
+    async fn seed(&self, reset: bool) -> Result<(), ApiError> {
+        self.inner.seed(reset).await?;
+        // a reset rewrites every wallet
+        if let Some(shared) = &self.shared {
+            if let Err(err) = shared.invalidate_all().await {
+                tracing::error!(error = %err, "can't invalidate the shared cache");
+            }
+        }
+        Ok(())
+    }
+
+    async fn api_key_scope(&self, key_hash: &str) -> Result<Option<Scope>, ApiError> {
+        self.inner.api_key_scope(key_hash).await
+    }
+
+    async fn close(&self) {
+        self.inner.close().await
+    }
+}
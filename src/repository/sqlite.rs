@@ -0,0 +1,1240 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use sqlx::{
+    migrate::Migrator,
+    sqlite::{SqliteConnectOptions, SqliteJournalMode, SqlitePoolOptions},
+    Connection, QueryBuilder, SqliteConnection, SqlitePool,
+};
+use time::OffsetDateTime;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use uuid::Uuid;
+
+use crate::{
+    auth::Scope,
+    config::Config,
+    db::SEED_WALLETS,
+    errors::ApiError,
+    events::{Events, TransactionEvent},
+    models::{
+        Cents, CreatedWallet, HistoryCursor, HistoryFilter, HistoryPage, KindTotal,
+        LedgerViolation, MonthlySummary, PostTransaction, PostTransfer, PostWallet, Statement,
+        StatementFilter, SummaryMonth, TransactionBatchReceipt, TransactionDetails,
+        TransactionItem, TransactionKind, TransactionReceipt, TransferReceipt, Wallet,
+        WalletDetails, WalletStatus,
+    },
+};
+
+use super::{
+    batch_receipt, check_currency, check_daily_debits, debit_total, history_page,
+    limit_below_balance, running_balances, start_of_day, PoolStatus, TransactionStream,
+    WalletRepository, CREDIT_LIMIT_CHANGED, DAILY_DEBIT_LIMIT_CHANGED, STATUS_CHANGED,
+    STREAM_BUFFER,
+};
+
+static MIGRATOR: Migrator = sqlx::migrate!("migrations/sqlite");
+
+// the query macros are checked against postgres, so this backend sticks to
+// runtime-checked queries. the database file belongs to a single process,
+// so transactions are reported from memory
+#[derive(Clone)]
+pub struct SqliteWalletRepository {
+    pool: SqlitePool,
+    events: Events,
+}
+
+impl SqliteWalletRepository {
+    pub fn new(pool: SqlitePool) -> Self {
+        SqliteWalletRepository {
+            pool,
+            events: Events::default(),
+        }
+    }
+
+    pub async fn connect(config: &Config) -> Result<Self, sqlx::Error> {
+        let options = SqliteConnectOptions::from_str(&config.database_url)?
+            .create_if_missing(true)
+            .foreign_keys(true)
+            .journal_mode(SqliteJournalMode::Wal)
+            .busy_timeout(config.db_acquire_timeout);
+
+        let pool = SqlitePoolOptions::new()
+            .max_connections(config.db_max_connections)
+            .min_connections(config.db_min_connections)
+            .acquire_timeout(config.db_acquire_timeout)
+            .connect_with(options)
+            .await?;
+
+        Ok(Self::new(pool))
+    }
+}
+
+fn to_unix_nanos(timestamp: OffsetDateTime) -> i64 {
+    timestamp.unix_timestamp_nanos() as i64
+}
+
+fn from_unix_nanos(nanos: i64) -> Result<OffsetDateTime, sqlx::Error> {
+    OffsetDateTime::from_unix_timestamp_nanos(nanos.into())
+        .map_err(|err| sqlx::Error::Decode(err.into()))
+}
+
+// the tags are a json array, the metadata a json object
+fn decode_row(
+    (value, kind, description, inserted_at, category, tags, metadata): (
+        Cents,
+        String,
+        String,
+        i64,
+        Option<String>,
+        String,
+        Option<String>,
+    ),
+) -> Result<TransactionItem, sqlx::Error> {
+    Ok(TransactionItem {
+        value,
+        kind: TransactionKind::from_str(&kind).map_err(|err| sqlx::Error::Decode(err.into()))?,
+        description,
+        inserted_at: from_unix_nanos(inserted_at)?,
+        category,
+        tags: serde_json::from_str(&tags).map_err(|err| sqlx::Error::Decode(err.into()))?,
+        metadata: metadata
+            .map(|metadata| serde_json::from_str(&metadata))
+            .transpose()
+            .map_err(|err| sqlx::Error::Decode(err.into()))?,
+    })
+}
+
+fn encode_tags(tags: &[String]) -> String {
+    serde_json::to_string(tags).expect("strings are always serializable")
+}
+
+fn decode_status(status: &str) -> Result<WalletStatus, sqlx::Error> {
+    WalletStatus::from_str(status).map_err(|err| sqlx::Error::Decode(err.into()))
+}
+
+// why a balance update guarded by the limit and the status matched no row
+async fn rejection(conn: &mut SqliteConnection, wallet_id: i32) -> ApiError {
+    let status: Result<Option<(String,)>, sqlx::Error> =
+        sqlx::query_as("SELECT status FROM wallets WHERE id = ?1")
+            .bind(wallet_id)
+            .fetch_optional(conn)
+            .await;
+
+    match status.and_then(|status| status.map(|(status,)| decode_status(&status)).transpose()) {
+        Ok(Some(WalletStatus::Blocked)) => ApiError::WalletBlocked,
+        Ok(Some(WalletStatus::Active)) => ApiError::LimitExceeded,
+        Ok(None) => ApiError::NotFound,
+        Err(err) => err.into(),
+    }
+}
+
+// `DailyLimitExceeded` when `debits` go past the wallet's daily limit on top
+// of what it debited today. called after the balance update took the write
+// lock, so no other debit lands in between
+async fn limit_daily_debits(
+    conn: &mut SqliteConnection,
+    wallet_id: i32,
+    debits: Cents,
+) -> Result<(), ApiError> {
+    if !debits.is_positive() {
+        return Ok(());
+    }
+
+    let (limit, debited): (Option<Cents>, Cents) = sqlx::query_as(
+        r#"
+        SELECT daily_debit_limit, (
+            SELECT COALESCE(SUM(value), 0) FROM transactions
+            WHERE wallet_id = wallets.id AND kind = 'd' AND inserted_at >= ?2
+        )
+        FROM wallets
+        WHERE id = ?1
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(to_unix_nanos(start_of_day()))
+    .fetch_one(conn)
+    .await?;
+
+    check_daily_debits(limit, debited, debits)
+}
+
+#[async_trait]
+impl WalletRepository for SqliteWalletRepository {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError> {
+        let mut conn = self.pool.acquire().await?;
+
+        // in WAL mode a transaction reads from one snapshot, taken at its first
+        // read, so the balance and the list below always agree
+        let mut db_transaction = conn.begin().await?;
+
+        let (balance, credit_limit, currency): (Cents, Cents, String) =
+            sqlx::query_as("SELECT balance, credit_limit, currency FROM wallets WHERE id = ?1")
+                .bind(wallet_id)
+                .fetch_optional(&mut *db_transaction)
+                .await?
+                .ok_or(ApiError::NotFound)?;
+
+        let rows: Vec<(
+            Cents,
+            String,
+            String,
+            i64,
+            Option<String>,
+            String,
+            Option<String>,
+        )> = sqlx::query_as(
+            r#"
+            SELECT value, kind, description, inserted_at, category, tags, metadata
+            FROM transactions
+            WHERE wallet_id = ?1
+              AND (?2 IS NULL OR inserted_at >= ?2)
+              AND (?3 IS NULL OR inserted_at < ?3)
+              AND (?4 IS NULL OR kind = ?4)
+            -- the rows of a batch share their timestamp
+            ORDER BY inserted_at DESC, id DESC
+            LIMIT 10
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(filter.from.map(to_unix_nanos))
+        .bind(filter.to.map(to_unix_nanos))
+        .bind(filter.kind.map(|kind| kind.to_string()))
+        .fetch_all(&mut *db_transaction)
+        .await?;
+
+        db_transaction.commit().await?;
+
+        let transactions = rows.into_iter().map(decode_row).collect::<Result<_, _>>()?;
+
+        Ok(Statement {
+            wallet: Wallet {
+                balance: Some(balance),
+                credit_limit: Some(credit_limit),
+            },
+            currency,
+            transactions,
+        })
+    }
+
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError> {
+        let row: Option<(
+            i32,
+            Cents,
+            Cents,
+            Option<String>,
+            String,
+            i64,
+            i64,
+            String,
+            Option<Cents>,
+            Cents,
+        )> = sqlx::query_as(
+            r#"
+            SELECT
+                id,
+                balance,
+                credit_limit,
+                external_id,
+                currency,
+                COALESCE(inserted_at, 0),
+                (SELECT COUNT(*) FROM transactions WHERE wallet_id = wallets.id),
+                status,
+                daily_debit_limit,
+                (
+                    SELECT COALESCE(SUM(value), 0) FROM transactions
+                    WHERE wallet_id = wallets.id AND kind = 'd' AND inserted_at >= ?2
+                )
+            FROM wallets
+            WHERE id = ?1
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(to_unix_nanos(start_of_day()))
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let (
+            id,
+            balance,
+            credit_limit,
+            external_id,
+            currency,
+            inserted_at,
+            transaction_count,
+            status,
+            daily_debit_limit,
+            debited_today,
+        ) = row.ok_or(ApiError::NotFound)?;
+
+        Ok(WalletDetails {
+            id,
+            balance,
+            credit_limit,
+            external_id,
+            currency,
+            inserted_at: from_unix_nanos(inserted_at)?,
+            transaction_count,
+            status: decode_status(&status)?,
+            daily_debit_limit,
+            debited_today,
+            // holds and interest need postgres
+            held: Cents::ZERO,
+            interest_rate: None,
+        })
+    }
+
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        let mut db_transaction = self.pool.begin().await?;
+
+        // every path starts with a write so the transaction takes the write
+        // lock right away; a deferred read upgraded later could fail with
+        // SQLITE_BUSY instead of waiting
+        if let Some(key) = idempotency_key {
+            let fingerprint = transaction.fingerprint();
+            let claimed = sqlx::query(
+                r#"
+                INSERT INTO idempotency_keys (wallet_id, key, fingerprint)
+                SELECT id, ?2, ?3 FROM wallets WHERE id = ?1
+                ON CONFLICT DO NOTHING
+                "#,
+            )
+            .bind(wallet_id)
+            .bind(key)
+            .bind(&fingerprint)
+            .execute(&mut *db_transaction)
+            .await?;
+
+            if claimed.rows_affected() == 0 {
+                // writers are serialized, so a claimed key always has its
+                // response and a missing row means a missing wallet
+                let stored: Option<(String, Option<String>)> = sqlx::query_as(
+                    "SELECT response, fingerprint FROM idempotency_keys WHERE wallet_id = ?1 AND key = ?2",
+                )
+                .bind(wallet_id)
+                .bind(key)
+                .fetch_optional(&mut *db_transaction)
+                .await?;
+
+                let (stored, stored_fingerprint) = stored.ok_or(ApiError::NotFound)?;
+                if stored_fingerprint.is_some_and(|stored| stored != fingerprint) {
+                    return Err(ApiError::IdempotencyKeyReused);
+                }
+                return serde_json::from_str(&stored)
+                    .map_err(|err| ApiError::Database(sqlx::Error::Decode(Box::new(err))));
+            }
+        }
+
+        // sqlite turns an INTEGER sum that overflows into a REAL, which
+        // the `typeof` check turns away like any other debit past the limit
+        let updated: Option<(Cents, Cents, String)> = sqlx::query_as(
+            r#"
+            UPDATE wallets SET balance = balance + ?2
+            WHERE id = ?1 AND balance + ?2 + credit_limit >= 0
+                AND typeof(balance + ?2) = 'integer' AND status = 'ativo'
+            RETURNING balance, credit_limit, currency
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(transaction.delta())
+        .fetch_optional(&mut *db_transaction)
+        .await?;
+
+        let Some((balance, credit_limit, currency)) = updated else {
+            return Err(rejection(&mut db_transaction, wallet_id).await);
+        };
+
+        // dropping the transaction on an error rolls the update back
+        check_currency(&currency, transaction.currency.as_deref())?;
+        if transaction.kind == TransactionKind::Debit {
+            limit_daily_debits(&mut db_transaction, wallet_id, transaction.value).await?;
+        }
+
+        let id = Uuid::new_v4();
+        let inserted_at = OffsetDateTime::now_utc();
+
+        sqlx::query(
+            r#"
+            INSERT INTO transactions
+                (wallet_id, value, kind, description, inserted_at, public_id, currency, category, tags, metadata)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(transaction.value)
+        .bind(transaction.kind.to_string())
+        .bind(&transaction.description)
+        .bind(to_unix_nanos(inserted_at))
+        .bind(id.to_string())
+        .bind(&currency)
+        .bind(&transaction.category)
+        .bind(encode_tags(&transaction.tags))
+        .bind(transaction.metadata.as_ref().map(Value::to_string))
+        .execute(&mut *db_transaction)
+        .await?;
+
+        let receipt = TransactionReceipt {
+            wallet: Wallet {
+                balance: Some(balance),
+                credit_limit: Some(credit_limit),
+            },
+            id: Some(id),
+            inserted_at: Some(inserted_at),
+        };
+
+        if let Some(key) = idempotency_key {
+            let response = serde_json::to_string(&receipt).expect("receipt is always serializable");
+
+            sqlx::query(
+                "UPDATE idempotency_keys SET response = ?3 WHERE wallet_id = ?1 AND key = ?2",
+            )
+            .bind(wallet_id)
+            .bind(key)
+            .bind(response)
+            .execute(&mut *db_transaction)
+            .await?;
+        }
+
+        db_transaction.commit().await?;
+
+        self.events.publish(TransactionEvent {
+            wallet_id,
+            balance,
+            credit_limit,
+            transaction: TransactionItem {
+                value: transaction.value,
+                kind: transaction.kind,
+                description: transaction.description.clone(),
+                inserted_at,
+                category: transaction.category.clone(),
+                tags: transaction.tags.clone(),
+                metadata: transaction.metadata.clone(),
+            },
+        });
+
+        Ok(receipt)
+    }
+
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        let delta = transactions
+            .iter()
+            .try_fold(Cents::ZERO, |delta, transaction| transaction.apply(delta))
+            .ok_or(ApiError::LimitExceeded)?;
+
+        let mut db_transaction = self.pool.begin().await?;
+
+        // writing first takes the write lock, see `insert_transaction`. the
+        // balance before the batch is worked back from the one after it
+        let updated: Option<(Cents, Cents, String)> = sqlx::query_as(
+            r#"
+            UPDATE wallets SET balance = balance + ?2
+            WHERE id = ?1 AND balance + ?2 + credit_limit >= 0
+                AND typeof(balance + ?2) = 'integer' AND status = 'ativo'
+            RETURNING balance, credit_limit, currency
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(delta)
+        .fetch_optional(&mut *db_transaction)
+        .await?;
+
+        let Some((balance, credit_limit, currency)) = updated else {
+            return Err(rejection(&mut db_transaction, wallet_id).await);
+        };
+
+        // dropping the transaction on an error rolls the update back
+        for transaction in transactions {
+            check_currency(&currency, transaction.currency.as_deref())?;
+        }
+        limit_daily_debits(&mut db_transaction, wallet_id, debit_total(transactions)).await?;
+        let balances = running_balances(
+            balance.checked_sub(delta).ok_or(ApiError::LimitExceeded)?,
+            credit_limit,
+            transactions,
+        )?;
+
+        let inserted_at = OffsetDateTime::now_utc();
+        let rows: Vec<(Uuid, OffsetDateTime)> = transactions
+            .iter()
+            .map(|_| (Uuid::new_v4(), inserted_at))
+            .collect();
+
+        let mut insert = QueryBuilder::new(
+            "INSERT INTO transactions (wallet_id, value, kind, description, inserted_at, public_id, currency, category, tags, metadata) ",
+        );
+        insert.push_values(
+            transactions.iter().zip(&rows),
+            |mut row, (transaction, (id, _))| {
+                row.push_bind(wallet_id)
+                    .push_bind(transaction.value)
+                    .push_bind(transaction.kind.to_string())
+                    .push_bind(&transaction.description)
+                    .push_bind(to_unix_nanos(inserted_at))
+                    .push_bind(id.to_string())
+                    .push_bind(&currency)
+                    .push_bind(&transaction.category)
+                    .push_bind(encode_tags(&transaction.tags))
+                    .push_bind(transaction.metadata.as_ref().map(Value::to_string));
+            },
+        );
+        insert.build().execute(&mut *db_transaction).await?;
+
+        db_transaction.commit().await?;
+
+        for (transaction, balance) in transactions.iter().zip(&balances) {
+            self.events.publish(TransactionEvent {
+                wallet_id,
+                balance: *balance,
+                credit_limit,
+                transaction: TransactionItem {
+                    value: transaction.value,
+                    kind: transaction.kind,
+                    description: transaction.description.clone(),
+                    inserted_at,
+                    category: transaction.category.clone(),
+                    tags: transaction.tags.clone(),
+                    metadata: transaction.metadata.clone(),
+                },
+            });
+        }
+
+        Ok(batch_receipt(credit_limit, balances, rows))
+    }
+
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        let mut db_transaction = conn.begin().await?;
+
+        let exists: Option<(i32,)> = sqlx::query_as("SELECT id FROM wallets WHERE id = ?1")
+            .bind(wallet_id)
+            .fetch_optional(&mut *db_transaction)
+            .await?;
+        if exists.is_none() {
+            return Err(ApiError::NotFound);
+        }
+
+        let (after_nanos, after_id) = match filter.after {
+            Some(cursor) => (to_unix_nanos(cursor.inserted_at), cursor.id),
+            None => (i64::MIN, 0),
+        };
+
+        // sqlite's LIKE only ignores the case of ascii letters
+        let rows: Vec<(
+            i64,
+            String,
+            Cents,
+            String,
+            String,
+            i64,
+            Option<String>,
+            String,
+            Option<String>,
+        )> = sqlx::query_as(
+            r#"
+            SELECT id, public_id, value, kind, description, inserted_at, category, tags, metadata
+            FROM transactions
+            WHERE wallet_id = ?1 AND (inserted_at, id) > (?2, ?3)
+              AND (?5 IS NULL OR description LIKE ?5 ESCAPE '\')
+              AND (?6 IS NULL OR category = ?6)
+              AND (?7 IS NULL OR EXISTS (SELECT 1 FROM json_each(tags) WHERE value = ?7))
+              AND NOT EXISTS (
+                  SELECT 1 FROM json_each(?8) AS wanted
+                  WHERE (
+                      -- the values as postgres' `->>` gives them
+                      SELECT CASE found.type
+                          WHEN 'true' THEN 'true'
+                          WHEN 'false' THEN 'false'
+                          WHEN 'object' THEN found.value
+                          WHEN 'array' THEN found.value
+                          ELSE CAST(found.atom AS TEXT)
+                      END
+                      FROM json_each(transactions.metadata) AS found
+                      WHERE found.key = wanted.key
+                  ) IS NOT wanted.value
+              )
+            ORDER BY inserted_at, id
+            LIMIT ?4
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(after_nanos)
+        .bind(after_id)
+        .bind(i64::from(filter.limit) + 1)
+        .bind(filter.search_pattern())
+        .bind(&filter.category)
+        .bind(&filter.tag)
+        .bind(Value::from_iter(filter.metadata.clone()).to_string())
+        .fetch_all(&mut *db_transaction)
+        .await?;
+
+        let rows = rows
+            .into_iter()
+            .map(
+                |(
+                    id,
+                    public_id,
+                    value,
+                    kind,
+                    description,
+                    inserted_at,
+                    category,
+                    tags,
+                    metadata,
+                )| {
+                    let transaction = decode_row((
+                        value,
+                        kind,
+                        description,
+                        inserted_at,
+                        category,
+                        tags,
+                        metadata,
+                    ))?;
+                    let cursor = HistoryCursor {
+                        inserted_at: transaction.inserted_at,
+                        id,
+                    };
+                    let id = Uuid::parse_str(&public_id)
+                        .map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+                    Ok((cursor, TransactionDetails { id, transaction }))
+                },
+            )
+            .collect::<Result<_, sqlx::Error>>()?;
+
+        Ok(history_page(rows, filter))
+    }
+
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError> {
+        let (from, to) = month.range();
+
+        // same left join as postgres, so an unknown wallet has no rows at all
+        let rows: Vec<(Option<String>, Option<String>, Cents, i64)> = sqlx::query_as(
+            r#"
+            SELECT transactions.kind, transactions.category, COALESCE(SUM(transactions.value), 0), COUNT(transactions.id)
+            FROM wallets
+            LEFT JOIN transactions
+                ON transactions.wallet_id = wallets.id
+                AND transactions.inserted_at >= ?2
+                AND transactions.inserted_at < ?3
+            WHERE wallets.id = ?1
+            GROUP BY wallets.id, transactions.kind, transactions.category
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(to_unix_nanos(from))
+        .bind(to_unix_nanos(to))
+        .fetch_all(&self.pool)
+        .await?;
+
+        if rows.is_empty() {
+            return Err(ApiError::NotFound);
+        }
+
+        let totals = rows
+            .into_iter()
+            .filter_map(|(kind, category, sum, count)| Some((kind?, category, sum, count)))
+            .map(|(kind, category, sum, count)| {
+                let kind = TransactionKind::from_str(&kind)
+                    .map_err(|err| sqlx::Error::Decode(err.into()))?;
+                Ok(KindTotal {
+                    kind,
+                    category,
+                    sum,
+                    count,
+                })
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+        Ok(MonthlySummary::new(month, totals))
+    }
+
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError> {
+        let mut conn = self.pool.acquire().await?;
+
+        let exists: Option<(i32,)> = sqlx::query_as("SELECT id FROM wallets WHERE id = ?1")
+            .bind(wallet_id)
+            .fetch_optional(&mut *conn)
+            .await?;
+        if exists.is_none() {
+            return Err(ApiError::NotFound);
+        }
+
+        // same as postgres, the rows are read on a task of their own
+        let (sender, receiver) = mpsc::channel(STREAM_BUFFER);
+        tokio::spawn(async move {
+            let mut rows = sqlx::query_as::<
+                _,
+                (
+                    String,
+                    Cents,
+                    String,
+                    String,
+                    i64,
+                    Option<String>,
+                    String,
+                    Option<String>,
+                ),
+            >(
+                r#"
+                SELECT public_id, value, kind, description, inserted_at, category, tags, metadata
+                FROM transactions
+                WHERE wallet_id = ?1
+                ORDER BY inserted_at, id
+                "#,
+            )
+            .bind(wallet_id)
+            .fetch(&mut *conn);
+
+            while let Some(row) = rows.next().await {
+                let row = row.and_then(
+                    |(
+                        public_id,
+                        value,
+                        kind,
+                        description,
+                        inserted_at,
+                        category,
+                        tags,
+                        metadata,
+                    )| {
+                        Ok(TransactionDetails {
+                            id: Uuid::parse_str(&public_id)
+                                .map_err(|err| sqlx::Error::Decode(Box::new(err)))?,
+                            transaction: decode_row((
+                                value,
+                                kind,
+                                description,
+                                inserted_at,
+                                category,
+                                tags,
+                                metadata,
+                            ))?,
+                        })
+                    },
+                );
+                if sender.send(row.map_err(ApiError::from)).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Ok(Box::pin(ReceiverStream::new(receiver)))
+    }
+
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError> {
+        let row: Option<(
+            Cents,
+            String,
+            String,
+            i64,
+            Option<String>,
+            String,
+            Option<String>,
+        )> = sqlx::query_as(
+            r#"
+            SELECT value, kind, description, inserted_at, category, tags, metadata
+            FROM transactions
+            WHERE wallet_id = ?1 AND public_id = ?2
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(transaction_id.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(TransactionDetails {
+            id: transaction_id,
+            transaction: decode_row(row.ok_or(ApiError::NotFound)?)?,
+        })
+    }
+
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError> {
+        let mut db_transaction = self.pool.begin().await?;
+
+        let debited: Option<(Cents, Cents, String)> = sqlx::query_as(
+            r#"
+            UPDATE wallets SET balance = balance - ?2
+            WHERE id = ?1 AND balance - ?2 + credit_limit >= 0
+                AND typeof(balance - ?2) = 'integer' AND status = 'ativo'
+            RETURNING balance, credit_limit, currency
+            "#,
+        )
+        .bind(transfer.from)
+        .bind(transfer.value)
+        .fetch_optional(&mut *db_transaction)
+        .await?;
+
+        let Some((balance, credit_limit, currency)) = debited else {
+            return Err(rejection(&mut db_transaction, transfer.from).await);
+        };
+
+        let credited: Option<(Cents, Cents, String)> = sqlx::query_as(
+            r#"
+            UPDATE wallets SET balance = balance + ?2 WHERE id = ?1 AND status = 'ativo'
+            RETURNING balance, credit_limit, currency
+            "#,
+        )
+        .bind(transfer.to)
+        .bind(transfer.value)
+        .fetch_optional(&mut *db_transaction)
+        .await?;
+
+        let Some((to_balance, to_credit_limit, to_currency)) = credited else {
+            return Err(rejection(&mut db_transaction, transfer.to).await);
+        };
+
+        // both updates roll back along with the dropped transaction
+        check_currency(&currency, Some(&to_currency))?;
+        check_currency(&currency, transfer.currency.as_deref())?;
+        limit_daily_debits(&mut db_transaction, transfer.from, transfer.value).await?;
+
+        let id = Uuid::new_v4();
+        let inserted_at = OffsetDateTime::now_utc();
+
+        sqlx::query(
+            r#"
+            INSERT INTO transactions
+                (wallet_id, value, kind, description, inserted_at, transfer_id, public_id, currency)
+            VALUES (?1, ?3, 'd', ?4, ?5, ?6, ?7, ?9), (?2, ?3, 'c', ?4, ?5, ?6, ?8, ?9)
+            "#,
+        )
+        .bind(transfer.from)
+        .bind(transfer.to)
+        .bind(transfer.value)
+        .bind(&transfer.description)
+        .bind(to_unix_nanos(inserted_at))
+        .bind(id.to_string())
+        .bind(Uuid::new_v4().to_string())
+        .bind(Uuid::new_v4().to_string())
+        .bind(&currency)
+        .execute(&mut *db_transaction)
+        .await?;
+
+        db_transaction.commit().await?;
+
+        for (wallet_id, balance, credit_limit, kind) in [
+            (transfer.from, balance, credit_limit, TransactionKind::Debit),
+            (
+                transfer.to,
+                to_balance,
+                to_credit_limit,
+                TransactionKind::Credit,
+            ),
+        ] {
+            self.events.publish(TransactionEvent {
+                wallet_id,
+                balance,
+                credit_limit,
+                transaction: TransactionItem {
+                    value: transfer.value,
+                    kind,
+                    description: transfer.description.clone(),
+                    inserted_at,
+                    category: None,
+                    tags: Vec::new(),
+                    metadata: None,
+                },
+            });
+        }
+
+        Ok(TransferReceipt {
+            id,
+            balance,
+            credit_limit,
+        })
+    }
+
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError> {
+        let (id, balance, credit_limit, external_id, currency, daily_debit_limit) =
+            sqlx::query_as(
+                r#"
+            INSERT INTO wallets (credit_limit, external_id, inserted_at, currency, daily_debit_limit)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            RETURNING id, balance, credit_limit, external_id, currency, daily_debit_limit
+            "#,
+            )
+            .bind(wallet.credit_limit)
+            .bind(&wallet.external_id)
+            .bind(to_unix_nanos(OffsetDateTime::now_utc()))
+            .bind(&wallet.currency)
+            .bind(wallet.daily_debit_limit)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(CreatedWallet {
+            id,
+            balance,
+            credit_limit,
+            external_id,
+            currency,
+            daily_debit_limit,
+        })
+    }
+
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError> {
+        let mut db_transaction = self.pool.begin().await?;
+
+        // a no-op write first, to take the write lock before reading
+        let touched = sqlx::query("UPDATE wallets SET credit_limit = credit_limit WHERE id = ?1")
+            .bind(wallet_id)
+            .execute(&mut *db_transaction)
+            .await?;
+        if touched.rows_affected() == 0 {
+            return Err(ApiError::NotFound);
+        }
+
+        let (balance, old_limit): (Cents, Cents) =
+            sqlx::query_as("SELECT balance, credit_limit FROM wallets WHERE id = ?1")
+                .bind(wallet_id)
+                .fetch_one(&mut *db_transaction)
+                .await?;
+        if balance.saturating_add(credit_limit).is_negative() {
+            return Err(limit_below_balance());
+        }
+
+        sqlx::query("UPDATE wallets SET credit_limit = ?2 WHERE id = ?1")
+            .bind(wallet_id)
+            .bind(credit_limit)
+            .execute(&mut *db_transaction)
+            .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, inserted_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(CREDIT_LIMIT_CHANGED)
+        .bind(json!({ "limite": old_limit }).to_string())
+        .bind(json!({ "limite": credit_limit }).to_string())
+        .bind(actor)
+        .bind(to_unix_nanos(OffsetDateTime::now_utc()))
+        .execute(&mut *db_transaction)
+        .await?;
+
+        db_transaction.commit().await?;
+
+        Ok(Wallet {
+            balance: Some(balance),
+            credit_limit: Some(credit_limit),
+        })
+    }
+
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        let mut db_transaction = self.pool.begin().await?;
+
+        // a no-op write first, to take the write lock before reading
+        let touched = sqlx::query("UPDATE wallets SET status = status WHERE id = ?1")
+            .bind(wallet_id)
+            .execute(&mut *db_transaction)
+            .await?;
+        if touched.rows_affected() == 0 {
+            return Err(ApiError::NotFound);
+        }
+
+        let (current,): (String,) = sqlx::query_as("SELECT status FROM wallets WHERE id = ?1")
+            .bind(wallet_id)
+            .fetch_one(&mut *db_transaction)
+            .await?;
+        let current = decode_status(&current)?;
+
+        if current != status {
+            sqlx::query("UPDATE wallets SET status = ?2 WHERE id = ?1")
+                .bind(wallet_id)
+                .bind(status.to_string())
+                .execute(&mut *db_transaction)
+                .await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, inserted_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+            )
+            .bind(wallet_id)
+            .bind(STATUS_CHANGED)
+            .bind(json!({ "status": current }).to_string())
+            .bind(json!({ "status": status }).to_string())
+            .bind(actor)
+            .bind(to_unix_nanos(OffsetDateTime::now_utc()))
+            .execute(&mut *db_transaction)
+            .await?;
+        }
+
+        db_transaction.commit().await?;
+
+        self.get_wallet(wallet_id).await
+    }
+
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        let mut db_transaction = self.pool.begin().await?;
+
+        // a no-op write first, to take the write lock before reading
+        let touched =
+            sqlx::query("UPDATE wallets SET daily_debit_limit = daily_debit_limit WHERE id = ?1")
+                .bind(wallet_id)
+                .execute(&mut *db_transaction)
+                .await?;
+        if touched.rows_affected() == 0 {
+            return Err(ApiError::NotFound);
+        }
+
+        let (current,): (Option<Cents>,) =
+            sqlx::query_as("SELECT daily_debit_limit FROM wallets WHERE id = ?1")
+                .bind(wallet_id)
+                .fetch_one(&mut *db_transaction)
+                .await?;
+
+        if current != limit {
+            sqlx::query("UPDATE wallets SET daily_debit_limit = ?2 WHERE id = ?1")
+                .bind(wallet_id)
+                .bind(limit)
+                .execute(&mut *db_transaction)
+                .await?;
+
+            sqlx::query(
+                r#"
+                INSERT INTO audit_log (wallet_id, action, old_value, new_value, actor, inserted_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+            )
+            .bind(wallet_id)
+            .bind(DAILY_DEBIT_LIMIT_CHANGED)
+            .bind(json!({ "limite_diario": current }).to_string())
+            .bind(json!({ "limite_diario": limit }).to_string())
+            .bind(actor)
+            .bind(to_unix_nanos(OffsetDateTime::now_utc()))
+            .execute(&mut *db_transaction)
+            .await?;
+        }
+
+        db_transaction.commit().await?;
+
+        self.get_wallet(wallet_id).await
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        self.events.subscribe()
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ping().await?;
+        Ok(())
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        Some(PoolStatus {
+            size: self.pool.size(),
+            idle: self.pool.num_idle(),
+            max: self.pool.options().get_max_connections(),
+        })
+    }
+
+    async fn run_migrations(&self) -> Result<(), ApiError> {
+        MIGRATOR
+            .run(&self.pool)
+            .await
+            .map_err(|err| ApiError::Database(err.into()))
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<(i64, String)>, ApiError> {
+        let tracked: Option<(String,)> = sqlx::query_as(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let applied: Vec<i64> = if tracked.is_some() {
+            sqlx::query_scalar("SELECT version FROM _sqlx_migrations WHERE success")
+                .fetch_all(&self.pool)
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        Ok(MIGRATOR
+            .iter()
+            .filter(|migration| !applied.contains(&migration.version))
+            .map(|migration| (migration.version, migration.description.to_string()))
+            .collect())
+    }
+
+    // in one read transaction, which sees a single snapshot in WAL mode
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError> {
+        let mut db_transaction = self.pool.begin().await?;
+
+        let unbalanced: Vec<(String, i64, Cents)> = sqlx::query_as(
+            r#"
+            SELECT
+                transactions.public_id,
+                COUNT(ledger_entries.id),
+                COALESCE(SUM(ledger_entries.amount), 0)
+            FROM transactions
+            LEFT JOIN ledger_entries ON ledger_entries.transaction_id = transactions.id
+            GROUP BY transactions.id
+            HAVING COUNT(ledger_entries.id) <> 2 OR COALESCE(SUM(ledger_entries.amount), 0) <> 0
+            ORDER BY transactions.id
+            "#,
+        )
+        .fetch_all(&mut *db_transaction)
+        .await?;
+
+        let mismatches: Vec<(i32, Cents, Cents)> = sqlx::query_as(
+            r#"
+            SELECT wallets.id, wallets.balance, COALESCE(SUM(ledger_entries.amount), 0)
+            FROM wallets
+            LEFT JOIN ledger_entries
+                ON ledger_entries.wallet_id = wallets.id AND ledger_entries.account = 'wallet'
+            GROUP BY wallets.id
+            HAVING wallets.balance <> COALESCE(SUM(ledger_entries.amount), 0)
+            ORDER BY wallets.id
+            "#,
+        )
+        .fetch_all(&mut *db_transaction)
+        .await?;
+
+        db_transaction.commit().await?;
+
+        let mut violations = unbalanced
+            .into_iter()
+            .map(|(public_id, entries, sum)| {
+                let transaction_id = Uuid::parse_str(&public_id)
+                    .map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+                Ok(LedgerViolation::Unbalanced {
+                    transaction_id,
+                    entries,
+                    sum,
+                })
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()?;
+        violations.extend(mismatches.into_iter().map(|(wallet_id, balance, entries)| {
+            LedgerViolation::BalanceMismatch {
+                wallet_id,
+                balance,
+                entries,
+            }
+        }));
+
+        Ok(violations)
+    }
+
+    async fn rebuild_projections(&self) -> Result<u64, ApiError> {
+        let rebuilt = sqlx::query(
+            r#"
+            UPDATE wallets
+            SET balance = COALESCE((
+                SELECT SUM(CASE kind WHEN 'c' THEN value ELSE -value END)
+                FROM transactions
+                WHERE transactions.wallet_id = wallets.id
+            ), 0)
+            "#,
+        )
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+
+        Ok(rebuilt)
+    }
+
+    async fn seed(&self, reset: bool) -> Result<(), ApiError> {
+        let mut db_transaction = self.pool.begin().await?;
+
+        for (id, credit_limit) in SEED_WALLETS {
+            if reset {
+                sqlx::query("DELETE FROM idempotency_keys WHERE wallet_id = ?1")
+                    .bind(id)
+                    .execute(&mut *db_transaction)
+                    .await?;
+
+                sqlx::query("DELETE FROM transactions WHERE wallet_id = ?1")
+                    .bind(id)
+                    .execute(&mut *db_transaction)
+                    .await?;
+            }
+
+            sqlx::query(
+                r#"
+                INSERT INTO wallets (id, balance, credit_limit, inserted_at) VALUES (?1, 0, ?2, ?4)
+                ON CONFLICT (id) DO UPDATE
+                SET balance = 0, credit_limit = excluded.credit_limit, status = 'ativo',
+                    daily_debit_limit = NULL
+                WHERE ?3
+                "#,
+            )
+            .bind(id)
+            .bind(credit_limit)
+            .bind(reset)
+            .bind(to_unix_nanos(OffsetDateTime::now_utc()))
+            .execute(&mut *db_transaction)
+            .await?;
+        }
+
+        db_transaction.commit().await?;
+
+        Ok(())
+    }
+
+    async fn api_key_scope(&self, key_hash: &str) -> Result<Option<Scope>, ApiError> {
+        let scope: Option<String> =
+            sqlx::query_scalar("SELECT scope FROM api_keys WHERE key_hash = ?1")
+                .bind(key_hash)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        scope
+            .map(|scope| {
+                scope
+                    .parse()
+                    .map_err(|err: String| sqlx::Error::Decode(err.into()))
+            })
+            .transpose()
+            .map_err(ApiError::from)
+    }
+
+    async fn close(&self) {
+        self.pool.close().await;
+    }
+}
@@ -0,0 +1,404 @@
+use std::{collections::HashMap, future::Future, sync::Arc};
+
+use async_trait::async_trait;
+use time::OffsetDateTime;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::{
+    auth::Scope,
+    errors::ApiError,
+    events::TransactionEvent,
+    models::{
+        Account, AuditFilter, AuditPage, CapturedHold, Cents, ChainVerification, CreatedWallet,
+        CreatedWebhook, FeeRule, HistoryFilter, HistoryPage, Hold, LedgerViolation, MonthlySummary,
+        PostFeeRule, PostHold, PostRecurrence, PostTransaction, PostTransfer, PostWallet,
+        PostWebhook, Reconciliation, Recurrence, ScheduledTransaction, Statement, StatementFilter,
+        SummaryMonth, TransactionBatchReceipt, TransactionDetails, TransactionReceipt,
+        TransferReceipt, Wallet, WalletDetails, WalletStatus, Webhook,
+    },
+    tenant,
+};
+
+use super::{PoolStatus, TransactionStream, WalletRepository};
+
+// a repository for the default dataset and one for each tenant's, every call
+// going to the one of the tenant the current task works on (see
+// `tenant::scope`). each is a stack of its own, so no cache, actor or
+// background worker ever sees another tenant's wallets. a tenant that isn't
+// served is turned away with `UnknownTenant`
+#[derive(Clone)]
+pub struct Tenants<R> {
+    default: R,
+    tenants: Arc<HashMap<String, R>>,
+}
+
+impl<R> Tenants<R> {
+    pub fn new(default: R, tenants: HashMap<String, R>) -> Self {
+        Tenants {
+            default,
+            tenants: Arc::new(tenants),
+        }
+    }
+
+    // just the default dataset
+    pub fn single(default: R) -> Self {
+        Self::new(default, HashMap::new())
+    }
+
+    // `None` for the default dataset and then the tenants, for `tenant::scope`
+    pub fn names(&self) -> Vec<Option<String>> {
+        let mut names: Vec<String> = self.tenants.keys().cloned().collect();
+        names.sort();
+
+        std::iter::once(None)
+            .chain(names.into_iter().map(Some))
+            .collect()
+    }
+
+    // the same tenants, each repository passed through `f` along with the
+    // tenant's name
+    pub async fn try_map<S, E, F, Fut>(self, mut f: F) -> Result<Tenants<S>, E>
+    where
+        R: Clone,
+        F: FnMut(Option<String>, R) -> Fut,
+        Fut: Future<Output = Result<S, E>>,
+    {
+        let default = f(None, self.default).await?;
+
+        let mut tenants = HashMap::with_capacity(self.tenants.len());
+        for (name, repo) in self.tenants.iter() {
+            tenants.insert(name.clone(), f(Some(name.clone()), repo.clone()).await?);
+        }
+
+        Ok(Tenants::new(default, tenants))
+    }
+
+    fn current(&self) -> Result<&R, ApiError> {
+        match tenant::current() {
+            None => Ok(&self.default),
+            Some(name) => self.tenants.get(&name).ok_or(ApiError::UnknownTenant),
+        }
+    }
+}
+
+#[async_trait]
+impl<R: WalletRepository> WalletRepository for Tenants<R> {
+    async fn get_statement(
+        &self,
+        wallet_id: i32,
+        filter: &StatementFilter,
+    ) -> Result<Statement, ApiError> {
+        self.current()?.get_statement(wallet_id, filter).await
+    }
+
+    async fn get_wallet(&self, wallet_id: i32) -> Result<WalletDetails, ApiError> {
+        self.current()?.get_wallet(wallet_id).await
+    }
+
+    async fn insert_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        idempotency_key: Option<&str>,
+    ) -> Result<TransactionReceipt, ApiError> {
+        self.current()?
+            .insert_transaction(wallet_id, transaction, idempotency_key)
+            .await
+    }
+
+    async fn insert_transactions(
+        &self,
+        wallet_id: i32,
+        transactions: &[PostTransaction],
+    ) -> Result<TransactionBatchReceipt, ApiError> {
+        self.current()?
+            .insert_transactions(wallet_id, transactions)
+            .await
+    }
+
+    async fn get_history(
+        &self,
+        wallet_id: i32,
+        filter: &HistoryFilter,
+    ) -> Result<HistoryPage, ApiError> {
+        self.current()?.get_history(wallet_id, filter).await
+    }
+
+    async fn get_summary(
+        &self,
+        wallet_id: i32,
+        month: SummaryMonth,
+    ) -> Result<MonthlySummary, ApiError> {
+        self.current()?.get_summary(wallet_id, month).await
+    }
+
+    async fn export(&self, wallet_id: i32) -> Result<TransactionStream, ApiError> {
+        self.current()?.export(wallet_id).await
+    }
+
+    async fn get_transaction(
+        &self,
+        wallet_id: i32,
+        transaction_id: Uuid,
+    ) -> Result<TransactionDetails, ApiError> {
+        self.current()?
+            .get_transaction(wallet_id, transaction_id)
+            .await
+    }
+
+    async fn transfer(&self, transfer: &PostTransfer) -> Result<TransferReceipt, ApiError> {
+        self.current()?.transfer(transfer).await
+    }
+
+    async fn create_wallet(&self, wallet: &PostWallet) -> Result<CreatedWallet, ApiError> {
+        self.current()?.create_wallet(wallet).await
+    }
+
+    async fn update_credit_limit(
+        &self,
+        wallet_id: i32,
+        credit_limit: Cents,
+        actor: &str,
+    ) -> Result<Wallet, ApiError> {
+        self.current()?
+            .update_credit_limit(wallet_id, credit_limit, actor)
+            .await
+    }
+
+    async fn set_wallet_status(
+        &self,
+        wallet_id: i32,
+        status: WalletStatus,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.current()?
+            .set_wallet_status(wallet_id, status, actor)
+            .await
+    }
+
+    async fn set_daily_debit_limit(
+        &self,
+        wallet_id: i32,
+        limit: Option<Cents>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.current()?
+            .set_daily_debit_limit(wallet_id, limit, actor)
+            .await
+    }
+
+    async fn create_account(
+        &self,
+        client_id: i32,
+        wallet: &PostWallet,
+    ) -> Result<CreatedWallet, ApiError> {
+        self.current()?.create_account(client_id, wallet).await
+    }
+
+    async fn list_accounts(&self, client_id: i32) -> Result<Vec<Account>, ApiError> {
+        self.current()?.list_accounts(client_id).await
+    }
+
+    async fn owns_account(&self, client_id: i32, account_id: i32) -> Result<bool, ApiError> {
+        self.current()?.owns_account(client_id, account_id).await
+    }
+
+    async fn reconcile(
+        &self,
+        wallet_id: i32,
+        repair: bool,
+        actor: &str,
+    ) -> Result<Reconciliation, ApiError> {
+        self.current()?.reconcile(wallet_id, repair, actor).await
+    }
+
+    async fn verify_chain(&self, wallet_id: i32) -> Result<ChainVerification, ApiError> {
+        self.current()?.verify_chain(wallet_id).await
+    }
+
+    async fn create_webhook(
+        &self,
+        wallet_id: i32,
+        webhook: &PostWebhook,
+    ) -> Result<CreatedWebhook, ApiError> {
+        self.current()?.create_webhook(wallet_id, webhook).await
+    }
+
+    async fn list_webhooks(&self, wallet_id: i32) -> Result<Vec<Webhook>, ApiError> {
+        self.current()?.list_webhooks(wallet_id).await
+    }
+
+    async fn delete_webhook(&self, wallet_id: i32, webhook_id: i32) -> Result<(), ApiError> {
+        self.current()?.delete_webhook(wallet_id, webhook_id).await
+    }
+
+    async fn create_hold(&self, wallet_id: i32, hold: &PostHold) -> Result<Hold, ApiError> {
+        self.current()?.create_hold(wallet_id, hold).await
+    }
+
+    async fn get_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.current()?.get_hold(hold_id).await
+    }
+
+    async fn capture_hold(&self, hold_id: Uuid) -> Result<CapturedHold, ApiError> {
+        self.current()?.capture_hold(hold_id).await
+    }
+
+    async fn release_hold(&self, hold_id: Uuid) -> Result<Hold, ApiError> {
+        self.current()?.release_hold(hold_id).await
+    }
+
+    async fn schedule_transaction(
+        &self,
+        wallet_id: i32,
+        transaction: &PostTransaction,
+        due_at: OffsetDateTime,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.current()?
+            .schedule_transaction(wallet_id, transaction, due_at)
+            .await
+    }
+
+    async fn list_scheduled(&self, wallet_id: i32) -> Result<Vec<ScheduledTransaction>, ApiError> {
+        self.current()?.list_scheduled(wallet_id).await
+    }
+
+    async fn cancel_scheduled(
+        &self,
+        wallet_id: i32,
+        scheduled_id: Uuid,
+    ) -> Result<ScheduledTransaction, ApiError> {
+        self.current()?
+            .cancel_scheduled(wallet_id, scheduled_id)
+            .await
+    }
+
+    async fn create_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.current()?
+            .create_recurrence(wallet_id, recurrence)
+            .await
+    }
+
+    async fn list_recurrences(&self, wallet_id: i32) -> Result<Vec<Recurrence>, ApiError> {
+        self.current()?.list_recurrences(wallet_id).await
+    }
+
+    async fn get_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+    ) -> Result<Recurrence, ApiError> {
+        self.current()?
+            .get_recurrence(wallet_id, recurrence_id)
+            .await
+    }
+
+    async fn update_recurrence(
+        &self,
+        wallet_id: i32,
+        recurrence_id: i32,
+        recurrence: &PostRecurrence,
+    ) -> Result<Recurrence, ApiError> {
+        self.current()?
+            .update_recurrence(wallet_id, recurrence_id, recurrence)
+            .await
+    }
+
+    async fn delete_recurrence(&self, wallet_id: i32, recurrence_id: i32) -> Result<(), ApiError> {
+        self.current()?
+            .delete_recurrence(wallet_id, recurrence_id)
+            .await
+    }
+
+    async fn create_fee_rule(&self, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.current()?.create_fee_rule(rule).await
+    }
+
+    async fn list_fee_rules(&self) -> Result<Vec<FeeRule>, ApiError> {
+        self.current()?.list_fee_rules().await
+    }
+
+    async fn update_fee_rule(&self, rule_id: i32, rule: &PostFeeRule) -> Result<FeeRule, ApiError> {
+        self.current()?.update_fee_rule(rule_id, rule).await
+    }
+
+    async fn delete_fee_rule(&self, rule_id: i32) -> Result<(), ApiError> {
+        self.current()?.delete_fee_rule(rule_id).await
+    }
+
+    async fn set_interest_rate(
+        &self,
+        wallet_id: i32,
+        rate: Option<f64>,
+        actor: &str,
+    ) -> Result<WalletDetails, ApiError> {
+        self.current()?
+            .set_interest_rate(wallet_id, rate, actor)
+            .await
+    }
+
+    async fn audit_log(&self, filter: &AuditFilter) -> Result<AuditPage, ApiError> {
+        self.current()?.audit_log(filter).await
+    }
+
+    // a tenant that isn't served gets a channel that's already closed, the
+    // statement read alongside turns the request away
+    fn subscribe(&self) -> broadcast::Receiver<TransactionEvent> {
+        match self.current() {
+            Ok(repo) => repo.subscribe(),
+            Err(_) => broadcast::channel(1).1,
+        }
+    }
+
+    async fn ping(&self) -> Result<(), ApiError> {
+        self.current()?.ping().await
+    }
+
+    fn pool_status(&self) -> Option<PoolStatus> {
+        self.current().ok()?.pool_status()
+    }
+
+    async fn run_migrations(&self) -> Result<(), ApiError> {
+        self.current()?.run_migrations().await
+    }
+
+    async fn pending_migrations(&self) -> Result<Vec<(i64, String)>, ApiError> {
+        self.current()?.pending_migrations().await
+    }
+
+    async fn missing_ledger_guards(&self) -> Result<Vec<String>, ApiError> {
+        self.current()?.missing_ledger_guards().await
+    }
+
+    async fn missing_indexes(&self) -> Result<Vec<String>, ApiError> {
+        self.current()?.missing_indexes().await
+    }
+
+    async fn check_ledger(&self) -> Result<Vec<LedgerViolation>, ApiError> {
+        self.current()?.check_ledger().await
+    }
+
+    async fn rebuild_projections(&self) -> Result<u64, ApiError> {
+        self.current()?.rebuild_projections().await
+    }
+
+    async fn seed(&self, reset: bool) -> Result<(), ApiError> {
+        self.current()?.seed(reset).await
+    }
+
+    async fn api_key_scope(&self, key_hash: &str) -> Result<Option<Scope>, ApiError> {
+        self.current()?.api_key_scope(key_hash).await
+    }
+
+    async fn close(&self) {
+        self.default.close().await;
+        for repo in self.tenants.values() {
+            repo.close().await;
+        }
+    }
+}
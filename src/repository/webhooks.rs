@@ -0,0 +1,227 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use hmac::{Hmac, Mac};
+use metrics::counter;
+use rand::{distributions::Alphanumeric, Rng};
+use reqwest::{header, Client};
+use serde_json::json;
+use sha2::Sha256;
+use sqlx::PgPool;
+use tokio::{
+    sync::oneshot,
+    task::{JoinHandle, JoinSet},
+};
+
+use crate::{db, models::WebhookDelivery};
+
+// deliveries one round claims and sends at once. a full round is followed
+// by another straight away
+const MAX_DELIVERIES: i64 = 100;
+
+// the delay before a retry doubles with every failed attempt up to this
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+
+// the outbox row's id, the same on every attempt so receivers can drop the
+// repeats
+const DELIVERY_HEADER: &str = "x-rinha-delivery";
+// unix seconds, signed along with the body so an old delivery can't be
+// replayed later
+const TIMESTAMP_HEADER: &str = "x-rinha-timestamp";
+// `sha256=` and the hex HMAC-SHA256 of `{timestamp}.{body}` keyed with the
+// webhook's secret
+const SIGNATURE_HEADER: &str = "x-rinha-signature";
+
+#[derive(Debug, Clone, Copy)]
+pub struct DeliveryOptions {
+    pub poll: Duration,
+    pub timeout: Duration,
+    pub max_attempts: u32,
+    pub retry_delay: Duration,
+}
+
+// posts what the `transactions_enqueue_webhooks` trigger puts in the outbox,
+// checking for it every `poll`. a delivery is retried with a growing delay
+// until the url answers 2xx or `max_attempts` ran out, and may arrive more
+// than once, or out of order after a retry. every instance runs one, the
+// lease from `db::claim_webhook_deliveries` keeps them from sending the
+// same delivery at the same time
+#[derive(Clone)]
+pub struct Deliverer {
+    stop: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    task: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
+}
+
+impl Deliverer {
+    pub fn spawn(pool: PgPool, options: DeliveryOptions) -> Self {
+        // a redirect would post the signed body somewhere nobody registered
+        let client = Client::builder()
+            .timeout(options.timeout)
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent("rinha-webhooks")
+            .build()
+            .expect("the tls backend always initializes");
+
+        let (stop, stopped) = oneshot::channel();
+        let task = tokio::spawn(deliver_all(pool, client, stopped, options));
+
+        Deliverer {
+            stop: Arc::new(Mutex::new(Some(stop))),
+            task: Arc::new(tokio::sync::Mutex::new(Some(task))),
+        }
+    }
+
+    // finishes the round in flight and stops, the rest waits for the next
+    // instance to start
+    pub async fn close(&self) {
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+        if let Some(task) = self.task.lock().await.take() {
+            let _ = task.await;
+        }
+    }
+}
+
+// what a webhook signs with when it wasn't given a secret
+pub fn new_secret() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect()
+}
+
+async fn deliver_all(
+    pool: PgPool,
+    client: Client,
+    mut stopped: oneshot::Receiver<()>,
+    options: DeliveryOptions,
+) {
+    // the first round after a `poll`, a cli command never gets to one
+    let start = tokio::time::Instant::now() + options.poll;
+    let mut ticks = tokio::time::interval_at(start, options.poll);
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => {
+                while round(&pool, &client, options).await == MAX_DELIVERIES as usize {}
+            }
+            _ = &mut stopped => return,
+        }
+    }
+}
+
+// the deliveries claimed, none when the outbox couldn't be read
+async fn round(pool: &PgPool, client: &Client, options: DeliveryOptions) -> usize {
+    // long enough for the send to time out and the outcome to be written
+    let lease = options.timeout * 2;
+    let deliveries = match db::claim_webhook_deliveries(pool, MAX_DELIVERIES, lease).await {
+        Ok(deliveries) => deliveries,
+        Err(err) => {
+            tracing::warn!(error = %err, "can't read the webhook outbox, retrying");
+            return 0;
+        }
+    };
+
+    let claimed = deliveries.len();
+    let mut sends = JoinSet::new();
+    for delivery in deliveries {
+        sends.spawn(deliver(pool.clone(), client.clone(), delivery, options));
+    }
+    while sends.join_next().await.is_some() {}
+
+    claimed
+}
+
+async fn deliver(
+    pool: PgPool,
+    client: Client,
+    delivery: WebhookDelivery,
+    options: DeliveryOptions,
+) {
+    let body = json!({
+        "evento": "transacao",
+        "cliente_id": delivery.wallet_id,
+        "transacao": delivery.transaction,
+    })
+    .to_string();
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    let response = client
+        .post(&delivery.url)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(DELIVERY_HEADER, delivery.id)
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(
+            SIGNATURE_HEADER,
+            format!("sha256={}", sign(&delivery.secret, timestamp, &body)),
+        )
+        .body(body)
+        .send()
+        .await;
+
+    let error = match response {
+        Ok(response) if response.status().is_success() => None,
+        Ok(response) => Some(format!("answered {}", response.status())),
+        Err(err) => Some(err.to_string()),
+    };
+
+    // the lease runs out if this fails, and the delivery goes out again
+    let recorded = match error {
+        None => {
+            counter!("webhook_deliveries_total", "result" => "delivered").increment(1);
+            db::complete_webhook_delivery(&pool, delivery.id).await
+        }
+        Some(error) => {
+            let attempt = delivery.attempts.unsigned_abs() + 1;
+            let retry_in = (attempt < options.max_attempts).then(|| {
+                options
+                    .retry_delay
+                    .saturating_mul(2u32.saturating_pow(attempt - 1))
+                    .min(MAX_RETRY_DELAY)
+            });
+
+            if retry_in.is_some() {
+                counter!("webhook_deliveries_total", "result" => "retried").increment(1);
+                tracing::warn!(delivery_id = delivery.id, attempt, error = %error, "webhook delivery failed");
+            } else {
+                counter!("webhook_deliveries_total", "result" => "failed").increment(1);
+                tracing::error!(delivery_id = delivery.id, attempt, error = %error, "webhook delivery failed, giving up");
+            }
+
+            db::fail_webhook_delivery(&pool, delivery.id, &error, retry_in).await
+        }
+    };
+
+    if let Err(err) = recorded {
+        tracing::warn!(error = %err, delivery_id = delivery.id, "can't record the webhook delivery");
+    }
+}
+
+fn sign(secret: &str, timestamp: u64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac takes keys of any size");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("{:x}", mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // what a receiver works out with any hmac-sha256 over `timestamp.body`
+    #[test]
+    fn the_signature_is_the_hmac_of_the_timestamp_and_the_body() {
+        assert_eq!(
+            sign("segredo", 1700000000, r#"{"evento":"transacao"}"#),
+            "573d44ffb0354141ec393ca8014e90b84b5328f649f98b8df583520c00c2eaaa"
+        );
+    }
+}
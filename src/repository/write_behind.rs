@@ -0,0 +1,144 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use metrics::counter;
+use sqlx::PgPool;
+use tokio::{
+    sync::{mpsc, oneshot},
+    task::JoinHandle,
+};
+
+use crate::{db, errors::ApiError, models::TransactionDetails};
+
+// attempts at writing a batch before its rows are given up on
+const FLUSH_ATTEMPTS: u32 = 5;
+const FLUSH_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+type Row = (i32, TransactionDetails);
+
+// transaction rows whose balance update already committed, inserted in
+// batches of up to `max_batch` by a task of their own. a row is in the
+// database at most `flush_every` plus one INSERT after its request returned,
+// until then it's missing from the statements, and its audit log row is the
+// `system`'s. rows still queued when the process dies without `close` are
+// lost, while their balances aren't
+#[derive(Clone)]
+pub struct WriteBehind {
+    sender: mpsc::Sender<Row>,
+    stop: Arc<Mutex<Option<oneshot::Sender<()>>>>,
+    flusher: Arc<tokio::sync::Mutex<Option<JoinHandle<()>>>>,
+}
+
+// the place a queued row is promised before its balance update runs, so a
+// full or closed queue turns the request away without touching the wallet.
+// it's owned, so it can go along with the update to a task of its own
+pub struct Slot(mpsc::OwnedPermit<Row>);
+
+impl WriteBehind {
+    pub fn spawn(pool: PgPool, flush_every: Duration, max_batch: usize) -> Self {
+        // a few batches worth, past that requests wait for the flusher
+        let (sender, receiver) = mpsc::channel(max_batch * 4);
+        let (stop, stopped) = oneshot::channel();
+
+        let flusher = tokio::spawn(flush(pool, receiver, stopped, flush_every, max_batch));
+
+        WriteBehind {
+            sender,
+            stop: Arc::new(Mutex::new(Some(stop))),
+            flusher: Arc::new(tokio::sync::Mutex::new(Some(flusher))),
+        }
+    }
+
+    pub async fn reserve(&self) -> Result<Slot, ApiError> {
+        self.sender
+            .clone()
+            .reserve_owned()
+            .await
+            .map(Slot)
+            .map_err(|_| ApiError::Unavailable)
+    }
+
+    // writes out whatever is queued and stops taking rows
+    pub async fn close(&self) {
+        if let Some(stop) = self.stop.lock().unwrap().take() {
+            let _ = stop.send(());
+        }
+        if let Some(flusher) = self.flusher.lock().await.take() {
+            let _ = flusher.await;
+        }
+    }
+}
+
+impl Slot {
+    pub fn fill(self, wallet_id: i32, row: TransactionDetails) {
+        self.0.send((wallet_id, row));
+    }
+}
+
+async fn flush(
+    pool: PgPool,
+    mut receiver: mpsc::Receiver<Row>,
+    mut stopped: oneshot::Receiver<()>,
+    flush_every: Duration,
+    max_batch: usize,
+) {
+    let mut batch = Vec::with_capacity(max_batch);
+    let mut stopping = false;
+
+    loop {
+        tokio::select! {
+            received = receiver.recv_many(&mut batch, max_batch) => {
+                // only once closed and drained
+                if received == 0 {
+                    return;
+                }
+            }
+            _ = &mut stopped, if !stopping => {
+                stopping = true;
+                receiver.close();
+                continue;
+            }
+        }
+
+        // a short wait lets the rows of concurrent requests join the batch
+        if !stopping && batch.len() < max_batch {
+            tokio::time::sleep(flush_every).await;
+            while batch.len() < max_batch {
+                match receiver.try_recv() {
+                    Ok(row) => batch.push(row),
+                    Err(_) => break,
+                }
+            }
+        }
+
+        insert(&pool, &batch).await;
+        batch.clear();
+    }
+}
+
+async fn insert(pool: &PgPool, batch: &[Row]) {
+    for attempt in 1..=FLUSH_ATTEMPTS {
+        match db::insert_transaction_rows(pool, batch).await {
+            Ok(()) => {
+                counter!("write_behind_rows_total", "result" => "written")
+                    .increment(batch.len() as u64);
+                return;
+            }
+            Err(err) if attempt < FLUSH_ATTEMPTS => {
+                tracing::warn!(error = %err, rows = batch.len(), "write-behind flush failed, retrying");
+                tokio::time::sleep(FLUSH_RETRY_DELAY * attempt).await;
+            }
+            Err(err) => {
+                counter!("write_behind_rows_total", "result" => "lost")
+                    .increment(batch.len() as u64);
+                tracing::error!(
+                    error = %err,
+                    rows = batch.len(),
+                    "write-behind flush failed for good, the balances no longer match the transactions"
+                );
+            }
+        }
+    }
+}
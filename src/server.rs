@@ -0,0 +1,209 @@
+use std::{future::Future, io, time::Duration};
+
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+use axum::Router;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{
+        conn::auto::Builder,
+        graceful::{GracefulShutdown, Watcher},
+    },
+    service::TowerToHyperService,
+};
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+};
+
+// what `axum::serve` waits for after failing to accept, usually for running
+// out of file descriptors
+const ACCEPT_ERROR_DELAY: Duration = Duration::from_secs(1);
+
+// where the connections come from, along with who they're from for the logs
+#[async_trait]
+pub trait Accept: Send {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    async fn accept(&self) -> io::Result<(Self::Stream, String)>;
+}
+
+#[async_trait]
+impl Accept for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&self) -> io::Result<(TcpStream, String)> {
+        let (stream, peer) = TcpListener::accept(self).await?;
+        Ok((stream, peer.to_string()))
+    }
+}
+
+// LISTEN's socket, whose file goes away along with it
+#[cfg(unix)]
+pub struct UnixSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl UnixSocket {
+    // a socket left behind by a server that didn't get to remove it is
+    // replaced, anything else at `path` is an error
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+        match std::fs::symlink_metadata(path) {
+            Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    format!("{} exists and isn't a socket", path.display()),
+                ))
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+
+        let listener = UnixListener::bind(path)?;
+        // the proxy in front usually runs as a user of its own
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o666))?;
+
+        Ok(UnixSocket {
+            listener,
+            path: path.to_path_buf(),
+        })
+    }
+}
+
+#[cfg(unix)]
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+#[async_trait]
+impl Accept for UnixSocket {
+    type Stream = UnixStream;
+
+    // the peers are unnamed, the proxy's connections all look the same
+    async fn accept(&self) -> io::Result<(UnixStream, String)> {
+        let (stream, _) = self.listener.accept().await?;
+        Ok((stream, "unix".to_string()))
+    }
+}
+
+// serves `app` on every connection `listener` accepts until `shutdown`
+// completes, then waits for the connections being served to finish
+pub async fn serve<L, F>(listener: L, app: Router, shutdown: F) -> io::Result<()>
+where
+    L: Accept,
+    F: Future<Output = ()>,
+{
+    accept(listener, shutdown, move |stream, peer, watcher| {
+        serve_connection(stream, app.clone(), peer, watcher)
+    })
+    .await
+}
+
+// hands each connection `listener` accepts to `handle`, in a task of its own,
+// until `shutdown` completes. the connections `handle` serves with the
+// watcher are waited for after that
+pub async fn accept<L, F, H, Fut>(listener: L, shutdown: F, handle: H) -> io::Result<()>
+where
+    L: Accept,
+    F: Future<Output = ()>,
+    H: Fn(L::Stream, String, Watcher) -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+    loop {
+        let (stream, peer) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    tracing::error!("can't accept a connection: {}", err);
+                    tokio::time::sleep(ACCEPT_ERROR_DELAY).await;
+                    continue;
+                }
+            },
+            () = &mut shutdown => break,
+        };
+
+        tokio::spawn(handle(stream, peer, graceful.watcher()));
+    }
+
+    // no new connections while the ones being served finish
+    drop(listener);
+    graceful.shutdown().await;
+
+    Ok(())
+}
+
+// http/1, or http/2 for the clients that start with its preface or pick it
+// with alpn, with upgrades for the websockets
+pub async fn serve_connection<I>(io: I, app: Router, peer: String, watcher: Watcher)
+where
+    I: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let builder = Builder::new(TokioExecutor::new());
+    let service = TowerToHyperService::new(app);
+    let connection = builder.serve_connection_with_upgrades(TokioIo::new(io), service);
+    if let Err(err) = watcher.watch(connection.into_owned()).await {
+        tracing::debug!(%peer, "connection failed: {}", err);
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    use super::*;
+
+    fn is_socket(path: &Path) -> bool {
+        std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket())
+    }
+
+    #[tokio::test]
+    async fn a_socket_left_behind_is_replaced() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rinha.sock");
+        // a listener dropped without removing its file, like a crashed server
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(is_socket(&path));
+
+        let socket = UnixSocket::bind(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o666);
+        UnixStream::connect(&path).await.unwrap();
+        socket.accept().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn anything_but_a_socket_is_left_alone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rinha.sock");
+        std::fs::write(&path, "not a socket").unwrap();
+
+        let err = UnixSocket::bind(&path).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "not a socket");
+    }
+
+    #[tokio::test]
+    async fn the_file_goes_away_with_the_socket() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rinha.sock");
+
+        let socket = UnixSocket::bind(&path).unwrap();
+        assert!(is_socket(&path));
+        drop(socket);
+        assert!(!path.exists());
+    }
+}
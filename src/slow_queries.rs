@@ -0,0 +1,176 @@
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+use metrics::counter;
+use tracing::{
+    field::{Field, Visit},
+    level_filters::LevelFilter,
+    span::{Attributes, Id, Record},
+    subscriber::Interest,
+    Metadata, Subscriber,
+};
+use tracing_subscriber::{
+    layer::{Context, Filter},
+    registry::LookupSpan,
+    Layer,
+};
+
+// in milliseconds, zero turns the logging off. the layer is set up before
+// the configuration is read, so it's handed the threshold afterwards
+static THRESHOLD_MS: AtomicU64 = AtomicU64::new(0);
+
+pub fn set_threshold(threshold: Duration) {
+    THRESHOLD_MS.store(threshold.as_millis() as u64, Ordering::Relaxed);
+}
+
+// times the spans of the queries in `db`, each named after its function,
+// and warns about the ones that took longer than the threshold. they're
+// debug spans, which only get here whatever RUST_LOG says when the log
+// filter is wrapped in `Queries`. the sqlite repository's queries aren't
+// instrumented
+pub struct SlowQueries;
+
+// the spans `SlowQueries` times. waiting for a connection isn't a query,
+// the pool has metrics of its own for it
+pub fn is_query(metadata: &Metadata<'_>) -> bool {
+    metadata.is_span() && metadata.target() == "rinha_rust::db" && metadata.name() != "acquire"
+}
+
+// the log filter, letting the queries' spans through while there's a
+// threshold, whatever the filter says about them. the layers that log and
+// export spans go behind `Logged`, for the ones it wouldn't have let
+// through to stay out of them
+pub struct Queries<F>(pub F);
+
+impl<S, F> Layer<S> for Queries<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    F: Layer<S>,
+{
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        let interest = self.0.register_callsite(metadata);
+        // asked again for each span, for the threshold to turn them off
+        if is_query(metadata) && !interest.is_always() {
+            Interest::sometimes()
+        } else {
+            interest
+        }
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, S>) -> bool {
+        (is_query(metadata) && THRESHOLD_MS.load(Ordering::Relaxed) > 0)
+            || self.0.enabled(metadata, ctx)
+    }
+
+    // the queries' spans are at debug
+    fn max_level_hint(&self) -> Option<LevelFilter> {
+        self.0
+            .max_level_hint()
+            .map(|level| level.max(LevelFilter::DEBUG))
+    }
+
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        self.0.on_new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        self.0.on_record(id, values, ctx);
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        self.0.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        self.0.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        self.0.on_close(id, ctx);
+    }
+}
+
+struct Started {
+    at: Instant,
+    wallet_id: Option<i64>,
+}
+
+#[derive(Default)]
+struct WalletId(Option<i64>);
+
+impl Visit for WalletId {
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        if field.name() == "wallet_id" {
+            self.0 = Some(value);
+        }
+    }
+
+    fn record_debug(&mut self, _: &Field, _: &dyn std::fmt::Debug) {}
+}
+
+// the filter of the layers other than `SlowQueries`
+pub struct Logged;
+
+impl<S> Filter<S> for Logged {
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: &Context<'_, S>) -> bool {
+        !is_query(metadata) || crate::log_level::enabled(metadata, ctx)
+    }
+
+    fn callsite_enabled(&self, metadata: &'static Metadata<'static>) -> Interest {
+        if is_query(metadata) {
+            Interest::sometimes()
+        } else {
+            Interest::always()
+        }
+    }
+}
+
+impl<S> Layer<S> for SlowQueries
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if !is_query(attrs.metadata()) {
+            return;
+        }
+        if THRESHOLD_MS.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let mut wallet_id = WalletId::default();
+        attrs.record(&mut wallet_id);
+        span.extensions_mut().insert(Started {
+            at: Instant::now(),
+            wallet_id: wallet_id.0,
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(started) = span.extensions_mut().remove::<Started>() else {
+            return;
+        };
+
+        let elapsed = started.at.elapsed();
+        if elapsed.as_millis() < u128::from(THRESHOLD_MS.load(Ordering::Relaxed)) {
+            return;
+        }
+
+        let route = crate::metrics::current_route().unwrap_or_else(|| "none".to_string());
+        tracing::warn!(
+            query = span.name(),
+            wallet_id = started.wallet_id,
+            elapsed_ms = elapsed.as_secs_f64() * 1000.0,
+            route,
+            "slow query",
+        );
+        counter!("slow_queries_total", "route" => route).increment(1);
+    }
+}
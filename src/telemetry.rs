@@ -0,0 +1,79 @@
+use axum::http::HeaderMap;
+use opentelemetry::{
+    global,
+    propagation::Extractor,
+    trace::{TraceError, TracerProvider as _},
+    KeyValue,
+};
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator, runtime, trace::TracerProvider, Resource,
+};
+use tracing::Subscriber;
+use tracing_opentelemetry::{OpenTelemetryLayer, OpenTelemetrySpanExt};
+use tracing_subscriber::registry::LookupSpan;
+
+// spans are only exported when an OTLP collector is configured, the exporter
+// reads the endpoint (and the rest of the standard OTEL_* variables) itself
+const ENDPOINT_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+// flushes the spans still buffered by the batch exporter when dropped
+pub struct Telemetry {
+    provider: TracerProvider,
+}
+
+pub fn init() -> Result<Option<Telemetry>, TraceError> {
+    if std::env::var_os(ENDPOINT_VAR).is_none() {
+        return Ok(None);
+    }
+
+    let exporter = SpanExporter::builder().with_tonic().build()?;
+
+    let service_name = std::env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| "rinha".into());
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new([KeyValue::new("service.name", service_name)]))
+        .build();
+
+    global::set_text_map_propagator(TraceContextPropagator::new());
+    global::set_tracer_provider(provider.clone());
+
+    Ok(Some(Telemetry { provider }))
+}
+
+impl Telemetry {
+    pub fn layer<S>(&self) -> OpenTelemetryLayer<S, opentelemetry_sdk::trace::Tracer>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.provider.tracer("rinha"))
+    }
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        if let Err(err) = self.provider.shutdown() {
+            tracing::warn!("can't flush pending spans: {}", err);
+        }
+    }
+}
+
+// continues the trace nginx or the caller started, if the request carries a
+// `traceparent` header
+pub fn set_parent(span: &tracing::Span, headers: &HeaderMap) {
+    let context =
+        global::get_text_map_propagator(|propagator| propagator.extract(&HeaderExtractor(headers)));
+    span.set_parent(context);
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|key| key.as_str()).collect()
+    }
+}
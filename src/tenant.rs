@@ -0,0 +1,66 @@
+use std::future::Future;
+
+use axum::{
+    extract::Request,
+    http::HeaderName,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::errors::ApiError;
+
+pub const TENANT_HEADER: HeaderName = HeaderName::from_static("x-tenant-id");
+
+// what a schema name, and the notification channels named after it, have
+// room for
+pub const MAX_TENANT_CHARS: usize = 32;
+
+tokio::task_local! {
+    static TENANT: String;
+}
+
+// the tenant whose dataset the current task works on, `None` for the
+// default one. `repository::Tenants` picks the repository by it
+pub fn current() -> Option<String> {
+    TENANT.try_with(|tenant| tenant.clone()).ok()
+}
+
+// runs `f` against `tenant`'s dataset, the default one for `None`
+pub async fn scope<F: Future>(tenant: Option<String>, f: F) -> F::Output {
+    match tenant {
+        Some(tenant) => TENANT.scope(tenant, f).await,
+        None => f.await,
+    }
+}
+
+// the schema the tenant's tables live in
+pub fn schema(tenant: &str) -> String {
+    format!("tenant_{}", tenant)
+}
+
+// lowercase letters, digits and underscores, so the schema name never needs
+// quoting
+pub fn is_valid(tenant: &str) -> bool {
+    (1..=MAX_TENANT_CHARS).contains(&tenant.len())
+        && tenant
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'_')
+}
+
+// the request works on the dataset of the tenant in `X-Tenant-Id`, or on the
+// default one without the header. whether the tenant exists is up to the
+// repository, which turns away the ones it doesn't serve, and whether the
+// credential is the tenant's is up to the auth layers
+pub async fn select(req: Request, next: Next) -> Response {
+    let Some(value) = req.headers().get(&TENANT_HEADER) else {
+        return next.run(req).await;
+    };
+
+    match value.to_str() {
+        Ok(tenant) if is_valid(tenant) => {
+            let tenant = tenant.to_string();
+            TENANT.scope(tenant, next.run(req)).await
+        }
+        _ => ApiError::UnknownTenant.into_response(),
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    future::Future,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock, RwLock},
+    time::{Duration, SystemTime},
+};
+
+use axum::Router;
+use rustls::{
+    crypto::ring::{default_provider, sign::any_supported_type},
+    server::{ClientHello, ResolvesServerCert},
+    sign::CertifiedKey,
+    ServerConfig,
+};
+use tokio_rustls::TlsAcceptor;
+
+use crate::{
+    config::Config,
+    server::{self, Accept},
+};
+
+// a client that never finishes its handshake only holds on to its own task
+// for this long
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// the certificate being served, for `reload` to swap
+static CERTIFICATES: OnceLock<Arc<Certificates>> = OnceLock::new();
+
+#[derive(Debug, thiserror::Error)]
+#[error("can't load the certificate from {path}: {reason}")]
+pub struct CertificateError {
+    path: String,
+    reason: String,
+}
+
+// TLS_CERT_PATH's chain and TLS_KEY_PATH's key, which every handshake gets
+// the latest of
+#[derive(Debug)]
+pub struct Certificates {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    current: RwLock<Arc<CertifiedKey>>,
+    // of both files, when they were last read
+    modified: RwLock<(Option<SystemTime>, Option<SystemTime>)>,
+}
+
+impl Certificates {
+    // `None` unless TLS_CERT_PATH and TLS_KEY_PATH are set. there's only one,
+    // the first call's, which `reload` reads again
+    pub fn from_config(config: &Config) -> Result<Option<Arc<Self>>, CertificateError> {
+        let (Some(cert_path), Some(key_path)) = (&config.tls_cert_path, &config.tls_key_path)
+        else {
+            return Ok(None);
+        };
+
+        let cert_path = PathBuf::from(cert_path);
+        let key_path = PathBuf::from(key_path);
+        let modified = (modified(&cert_path), modified(&key_path));
+        let certificates = Arc::new(Certificates {
+            current: RwLock::new(load(&cert_path, &key_path)?),
+            cert_path,
+            key_path,
+            modified: RwLock::new(modified),
+        });
+
+        Ok(Some(CERTIFICATES.get_or_init(|| certificates).clone()))
+    }
+
+    // a pair that doesn't load, like a certificate renewed before its key,
+    // keeps the one being served
+    fn reload(&self) {
+        *self.modified.write().unwrap() = (modified(&self.cert_path), modified(&self.key_path));
+
+        match load(&self.cert_path, &self.key_path) {
+            Ok(certified) => {
+                *self.current.write().unwrap() = certified;
+                tracing::info!(path = %self.cert_path.display(), "certificate reloaded");
+            }
+            Err(err) => tracing::error!("{}, keeping the current one", err),
+        }
+    }
+
+    fn changed(&self) -> bool {
+        *self.modified.read().unwrap() != (modified(&self.cert_path), modified(&self.key_path))
+    }
+}
+
+impl ResolvesServerCert for Certificates {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().unwrap().clone())
+    }
+}
+
+// reads the certificate again, for a SIGHUP. a no-op without TLS
+pub fn reload() {
+    if let Some(certificates) = CERTIFICATES.get() {
+        certificates.reload();
+    }
+}
+
+// serves `app` over https on every connection `listener` accepts until
+// `shutdown` completes, like `server::serve`, speaking http/2 to the
+// clients that offer it
+pub async fn serve<L, F>(
+    listener: L,
+    app: Router,
+    certificates: Arc<Certificates>,
+    watch: Duration,
+    shutdown: F,
+) -> io::Result<()>
+where
+    L: Accept,
+    F: Future<Output = ()>,
+{
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(io::Error::other)?
+        .with_no_client_auth()
+        .with_cert_resolver(certificates.clone());
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    let acceptor = TlsAcceptor::from(Arc::new(config));
+
+    // zero leaves the reloads to SIGHUP
+    let watcher = (!watch.is_zero()).then(|| {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(watch);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                if certificates.changed() {
+                    certificates.reload();
+                }
+            }
+        })
+    });
+
+    let served = server::accept(listener, shutdown, move |stream, peer, watcher| {
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        async move {
+            let stream =
+                match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(err)) => {
+                        tracing::debug!(%peer, "tls handshake failed: {}", err);
+                        return;
+                    }
+                    Err(_) => {
+                        tracing::debug!(%peer, "tls handshake timed out");
+                        return;
+                    }
+                };
+
+            server::serve_connection(stream, app, peer, watcher).await;
+        }
+    })
+    .await;
+
+    if let Some(watcher) = watcher {
+        watcher.abort();
+    }
+
+    served
+}
+
+fn load(cert_path: &Path, key_path: &Path) -> Result<Arc<CertifiedKey>, CertificateError> {
+    let failed = |path: &Path, reason: String| CertificateError {
+        path: path.display().to_string(),
+        reason,
+    };
+
+    let pem = std::fs::read(cert_path).map_err(|err| failed(cert_path, err.to_string()))?;
+    let chain = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| failed(cert_path, err.to_string()))?;
+    if chain.is_empty() {
+        return Err(failed(cert_path, "no certificate in it".to_string()));
+    }
+
+    let pem = std::fs::read(key_path).map_err(|err| failed(key_path, err.to_string()))?;
+    let key = rustls_pemfile::private_key(&mut pem.as_slice())
+        .map_err(|err| failed(key_path, err.to_string()))?
+        .ok_or_else(|| failed(key_path, "no private key in it".to_string()))?;
+    let key = any_supported_type(&key).map_err(|err| failed(key_path, err.to_string()))?;
+
+    let certified = CertifiedKey::new(chain, key);
+    // a key that isn't the certificate's would only fail the handshakes
+    certified
+        .keys_match()
+        .map_err(|err| failed(key_path, err.to_string()))?;
+
+    Ok(Arc::new(certified))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // `a.crt` and `b.crt`, self-signed, each with its own key
+    fn fixture(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/tls")
+            .join(name)
+    }
+
+    fn install(dir: &Path, pair: &str) {
+        std::fs::copy(fixture(&format!("{}.crt", pair)), dir.join("tls.crt")).unwrap();
+        std::fs::copy(fixture(&format!("{}.key", pair)), dir.join("tls.key")).unwrap();
+    }
+
+    // like `from_config`, without taking the only `CERTIFICATES` there is
+    fn certificates(dir: &Path) -> Certificates {
+        let (cert_path, key_path) = (dir.join("tls.crt"), dir.join("tls.key"));
+        Certificates {
+            current: RwLock::new(load(&cert_path, &key_path).unwrap()),
+            modified: RwLock::new((modified(&cert_path), modified(&key_path))),
+            cert_path,
+            key_path,
+        }
+    }
+
+    fn serving(certificates: &Certificates) -> Vec<u8> {
+        certificates.current.read().unwrap().cert[0].to_vec()
+    }
+
+    fn der(name: &str) -> Vec<u8> {
+        let pem = std::fs::read(fixture(name)).unwrap();
+        let cert = rustls_pemfile::certs(&mut pem.as_slice())
+            .next()
+            .unwrap()
+            .unwrap();
+        cert.to_vec()
+    }
+
+    #[test]
+    fn a_renewed_certificate_is_served_once_reloaded() {
+        let dir = tempfile::tempdir().unwrap();
+        install(dir.path(), "a");
+        let certificates = certificates(dir.path());
+        assert!(!certificates.changed());
+
+        install(dir.path(), "b");
+        assert!(certificates.changed());
+        assert_eq!(serving(&certificates), der("a.crt"));
+
+        certificates.reload();
+        assert!(!certificates.changed());
+        assert_eq!(serving(&certificates), der("b.crt"));
+    }
+
+    #[test]
+    fn a_certificate_renewed_before_its_key_keeps_the_current_one() {
+        let dir = tempfile::tempdir().unwrap();
+        install(dir.path(), "a");
+        let certificates = certificates(dir.path());
+
+        std::fs::copy(fixture("b.crt"), dir.path().join("tls.crt")).unwrap();
+        certificates.reload();
+        assert_eq!(serving(&certificates), der("a.crt"));
+    }
+}
@@ -0,0 +1,161 @@
+use axum::{
+    middleware,
+    routing::{delete, get, patch, post, put},
+    Router,
+};
+use tower_http::compression::CompressionLayer;
+
+use crate::{config::Config, handlers, rate_limit::RateLimiter, repository::WalletRepository};
+
+// the prefixes the api versions are served under. the paths without one are
+// v1's too, so a response only changes in a breaking way under a new prefix
+// with a builder of its own, and the rinha test harness never notices
+pub const PREFIXES: [&str; 1] = ["/v1"];
+
+// the version prefix `path` starts with, empty for none, and the rest of it
+pub fn split_prefix(path: &str) -> (&str, &str) {
+    for prefix in PREFIXES {
+        if let Some(rest) = path.strip_prefix(prefix) {
+            if rest.is_empty() || rest.starts_with('/') {
+                return (prefix, rest);
+            }
+        }
+    }
+
+    ("", path)
+}
+
+// the routes of the first version, the one the rinha test harness calls
+pub fn v1<R: WalletRepository>(repo: &R, config: &Config) -> Router<R> {
+    // a batch counts as a single request against the rate limit
+    let mut insert_transaction = post(handlers::insert_transaction::<R>);
+    let mut insert_transactions = post(handlers::insert_transactions::<R>);
+    if let Some(limiter) = RateLimiter::from_config(config) {
+        insert_transaction = insert_transaction.route_layer(middleware::from_fn_with_state(
+            limiter.clone(),
+            crate::rate_limit::enforce,
+        ));
+        insert_transactions = insert_transactions.route_layer(middleware::from_fn_with_state(
+            limiter,
+            crate::rate_limit::enforce,
+        ));
+    }
+
+    let api = Router::new()
+        .route("/", get(handlers::hello_world))
+        .route("/clientes", post(handlers::create_wallet::<R>))
+        .route("/clientes/:id", get(handlers::wallet::<R>))
+        .route(
+            "/clientes/:id/contas",
+            get(handlers::accounts::<R>).post(handlers::create_account::<R>),
+        )
+        // statements are the only bodies big enough to be worth compressing
+        .route(
+            "/clientes/:id/extrato",
+            get(handlers::statement::<R>).layer(CompressionLayer::new()),
+        )
+        .route(
+            "/clientes/:id/extrato/export",
+            get(crate::export::export::<R>).layer(CompressionLayer::new()),
+        )
+        .route("/clientes/:id/resumo", get(handlers::summary::<R>))
+        .route(
+            "/clientes/:id/limite",
+            patch(handlers::update_credit_limit::<R>),
+        )
+        .route(
+            "/clientes/:id/limite_diario",
+            patch(handlers::update_daily_debit_limit::<R>),
+        )
+        .route(
+            "/clientes/:id/juros",
+            patch(handlers::update_interest_rate::<R>),
+        )
+        .route("/clientes/:id/bloquear", post(handlers::block_wallet::<R>))
+        .route(
+            "/clientes/:id/desbloquear",
+            post(handlers::unblock_wallet::<R>),
+        )
+        // the rate limit only covers the writes, the history is compressed
+        // like the statement
+        .route(
+            "/clientes/:id/transacoes",
+            insert_transaction.merge(get(handlers::history::<R>).layer(CompressionLayer::new())),
+        )
+        .route("/clientes/:id/transacoes/lote", insert_transactions)
+        .route(
+            "/clientes/:id/transacoes/stream",
+            get(handlers::transaction_stream::<R>),
+        )
+        .route(
+            "/clientes/:id/transacoes/verify",
+            get(handlers::verify_chain::<R>),
+        )
+        .route(
+            "/clientes/:id/transacoes/agendadas",
+            get(handlers::scheduled_transactions::<R>),
+        )
+        .route(
+            "/clientes/:id/transacoes/agendadas/:agendada_id",
+            delete(handlers::cancel_scheduled_transaction::<R>),
+        )
+        .route(
+            "/clientes/:id/transacoes/:transacao_id",
+            get(handlers::transaction::<R>),
+        )
+        .route(
+            "/clientes/:id/webhooks",
+            post(handlers::create_webhook::<R>).get(handlers::webhooks::<R>),
+        )
+        .route(
+            "/clientes/:id/webhooks/:webhook_id",
+            delete(handlers::delete_webhook::<R>),
+        )
+        .route(
+            "/clientes/:id/recorrencias",
+            post(handlers::create_recurrence::<R>).get(handlers::recurrences::<R>),
+        )
+        .route(
+            "/clientes/:id/recorrencias/:recorrencia_id",
+            get(handlers::recurrence::<R>)
+                .put(handlers::update_recurrence::<R>)
+                .delete(handlers::delete_recurrence::<R>),
+        )
+        .route("/clientes/:id/holds", post(handlers::create_hold::<R>))
+        .route(
+            "/holds/:hold_id",
+            get(handlers::hold::<R>).delete(handlers::release_hold::<R>),
+        )
+        .route("/holds/:hold_id/capture", post(handlers::capture_hold::<R>))
+        .route("/transferencias", post(handlers::transfer::<R>))
+        .route(
+            "/admin/clientes/:id/reconciliar",
+            post(handlers::reconcile::<R>),
+        )
+        .route("/admin/audit", get(handlers::audit_log::<R>))
+        .route(
+            "/admin/tarifas",
+            get(handlers::fee_rules::<R>).post(handlers::create_fee_rule::<R>),
+        )
+        .route(
+            "/admin/log-level",
+            get(handlers::log_level).put(handlers::update_log_level),
+        )
+        .route(
+            "/admin/tarifas/:tarifa_id",
+            put(handlers::update_fee_rule::<R>).delete(handlers::delete_fee_rule::<R>),
+        )
+        .route("/ws/clientes/:id", get(crate::ws::wallet_socket::<R>));
+
+    #[cfg(feature = "graphql")]
+    let api = api.route(
+        "/graphql",
+        get(crate::graphql::graphiql)
+            .post(crate::graphql::execute::<R>)
+            .layer(axum::Extension(crate::graphql::schema(repo.clone()))),
+    );
+    #[cfg(not(feature = "graphql"))]
+    let _ = repo;
+
+    api
+}
@@ -0,0 +1,128 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    response::Response,
+};
+use serde::Serialize;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::{
+    errors::ApiError,
+    events::TransactionEvent,
+    extract::Path,
+    models::{Cents, StatementFilter, Wallet},
+    repository::WalletRepository,
+};
+
+// a peer that hasn't answered the previous ping by the next one is gone
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+// a client that stops reading stalls its writes on a full socket buffer.
+// past this it's disconnected instead of holding on to the connection
+const SEND_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Serialize)]
+#[serde(tag = "evento")]
+enum LiveMessage {
+    // sent on connect, and again after dropping events for a slow client
+    #[serde(rename = "saldo")]
+    Balance { saldo: Cents, limite: Cents },
+    #[serde(rename = "transacao")]
+    Transaction(TransactionEvent),
+}
+
+impl From<Wallet> for LiveMessage {
+    fn from(wallet: Wallet) -> Self {
+        LiveMessage::Balance {
+            saldo: wallet.balance.unwrap_or_default(),
+            limite: wallet.credit_limit.unwrap_or_default(),
+        }
+    }
+}
+
+pub async fn wallet_socket<R: WalletRepository>(
+    State(repo): State<R>,
+    Path(wallet_id): Path<i32>,
+    upgrade: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    // subscribed before the balance is read, so nothing committed in between
+    // goes missing
+    let events = repo.subscribe();
+    let statement = repo
+        .get_statement(wallet_id, &StatementFilter::default())
+        .await?;
+
+    // the socket is served by a task of its own, which keeps working on the
+    // request's tenant
+    let tenant = crate::tenant::current();
+    Ok(upgrade.on_upgrade(move |socket| {
+        crate::tenant::scope(tenant, async move {
+            if let Err(err) = run(socket, repo, wallet_id, statement.wallet, events).await {
+                tracing::debug!(error = %err, wallet_id, "websocket closed");
+            }
+        })
+    }))
+}
+
+async fn run<R: WalletRepository>(
+    mut socket: WebSocket,
+    repo: R,
+    wallet_id: i32,
+    wallet: Wallet,
+    mut events: broadcast::Receiver<TransactionEvent>,
+) -> Result<(), axum::Error> {
+    send(&mut socket, wallet.into()).await?;
+
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    ping.tick().await;
+    let mut awaiting_pong = false;
+
+    loop {
+        tokio::select! {
+            _ = ping.tick() => {
+                if awaiting_pong {
+                    return Err(axum::Error::new("no pong from the client"));
+                }
+                send_frame(&mut socket, Message::Ping(Vec::new())).await?;
+                awaiting_pong = true;
+            }
+            message = socket.recv() => match message {
+                None | Some(Ok(Message::Close(_))) => return Ok(()),
+                Some(Err(err)) => return Err(err),
+                // pings from the client are answered by axum, anything it
+                // sends shows it's still there
+                Some(Ok(_)) => awaiting_pong = false,
+            },
+            event = events.recv() => match event {
+                Ok(event) if event.wallet_id == wallet_id => {
+                    send(&mut socket, LiveMessage::Transaction(event)).await?;
+                }
+                Ok(_) => {}
+                // the client fell behind and some events were dropped, the
+                // current balance brings it back in sync
+                Err(RecvError::Lagged(_)) => {
+                    let statement = repo
+                        .get_statement(wallet_id, &StatementFilter::default())
+                        .await
+                        .map_err(axum::Error::new)?;
+                    send(&mut socket, statement.wallet.into()).await?;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, message: LiveMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(&message).expect("live messages are always serializable");
+    send_frame(socket, Message::Text(text)).await
+}
+
+async fn send_frame(socket: &mut WebSocket, frame: Message) -> Result<(), axum::Error> {
+    tokio::time::timeout(SEND_TIMEOUT, socket.send(frame))
+        .await
+        .map_err(|_| axum::Error::new("client stopped reading"))?
+}
@@ -0,0 +1,600 @@
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use rinha_rust::{config::Config, repository::MemoryWalletRepository};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+
+// the api on the five seeded clients, client 1's limit being 100000
+fn app() -> Router {
+    app_with(&[])
+}
+
+// the same, with the settings in `vars` on top of the defaults
+fn app_with(vars: &[(&str, &str)]) -> Router {
+    let config = Config::from_lookup(|name| {
+        vars.iter()
+            .find(|(var, _)| *var == name)
+            .map(|(_, value)| value.to_string())
+    })
+    .unwrap();
+    rinha_rust::app(MemoryWalletRepository::seeded(), &config)
+}
+
+async fn send(app: &Router, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    send_with(app, method, uri, &[], body).await
+}
+
+async fn send_with(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+// an amount in a body, a string with the `decimal` feature
+fn amount(value: &Value) -> i64 {
+    match value {
+        Value::String(amount) => amount.parse().unwrap(),
+        amount => amount.as_i64().unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn credit_updates_the_balance() {
+    let app = app();
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        Some(json!({"valor": 1000, "tipo": "c", "descricao": "deposito"})),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(amount(&body["saldo"]), 1000);
+    assert_eq!(amount(&body["limite"]), 100000);
+}
+
+#[tokio::test]
+async fn debit_past_the_limit_is_unprocessable() {
+    let app = app();
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        Some(json!({"valor": 100001, "tipo": "d", "descricao": "saque"})),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["type"], "urn:rinha:problem:limit_exceeded");
+
+    // and it left the balance alone
+    let (_, body) = send(&app, Method::GET, "/clientes/1/extrato", None).await;
+    assert_eq!(amount(&body["saldo"]["total"]), 0);
+}
+
+#[tokio::test]
+async fn statement_has_the_newest_transactions_first() {
+    let app = app();
+
+    for (valor, tipo, descricao) in [(1000, "c", "primeira"), (300, "d", "segunda")] {
+        let (status, _) = send(
+            &app,
+            Method::POST,
+            "/clientes/1/transacoes",
+            Some(json!({"valor": valor, "tipo": tipo, "descricao": descricao})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, body) = send(&app, Method::GET, "/clientes/1/extrato", None).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(amount(&body["saldo"]["total"]), 700);
+    assert_eq!(amount(&body["saldo"]["limite"]), 100000);
+    let transactions = body["ultimas_transacoes"].as_array().unwrap();
+    assert_eq!(transactions.len(), 2);
+    assert_eq!(transactions[0]["descricao"], "segunda");
+    assert_eq!(transactions[0]["tipo"], "d");
+    assert_eq!(transactions[1]["descricao"], "primeira");
+}
+
+#[tokio::test]
+async fn unknown_client_is_not_found() {
+    let app = app();
+
+    let (status, _) = send(&app, Method::GET, "/clientes/6/extrato", None).await;
+
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// a decimal balance has room for far more than an i64
+#[cfg(not(feature = "decimal"))]
+#[tokio::test]
+async fn credit_past_the_largest_balance_is_unprocessable() {
+    let app = app();
+
+    let credit = |valor: i64| Some(json!({"valor": valor, "tipo": "c", "descricao": "deposito"}));
+    let (status, _) = send(&app, Method::POST, "/clientes/1/transacoes", credit(1)).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        credit(i64::MAX),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["type"], "urn:rinha:problem:limit_exceeded");
+
+    let (_, body) = send(&app, Method::GET, "/clientes/1/extrato", None).await;
+    assert_eq!(amount(&body["saldo"]["total"]), 1);
+}
+
+#[cfg(not(feature = "decimal"))]
+#[tokio::test]
+async fn transfer_past_the_largest_balance_is_unprocessable() {
+    let app = app();
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        Some(json!({"valor": i64::MAX - 10, "tipo": "c", "descricao": "deposito"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/transferencias",
+        Some(json!({"de": 2, "para": 1, "valor": 100, "descricao": "pix"})),
+    )
+    .await;
+
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["type"], "urn:rinha:problem:limit_exceeded");
+
+    // neither side moved
+    let (_, body) = send(&app, Method::GET, "/clientes/1/extrato", None).await;
+    assert_eq!(amount(&body["saldo"]["total"]), i64::MAX - 10);
+    let (_, body) = send(&app, Method::GET, "/clientes/2/extrato", None).await;
+    assert_eq!(amount(&body["saldo"]["total"]), 0);
+}
+
+#[tokio::test]
+async fn a_write_key_cant_create_an_account() {
+    let app = app_with(&[("API_KEYS", "escrita:write,gerente:admin")]);
+    let account = || Some(json!({"limite": 1000000000}));
+
+    let (status, _) = send_with(
+        &app,
+        Method::POST,
+        "/clientes/1/contas",
+        &[("x-api-key", "escrita")],
+        account(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = send_with(
+        &app,
+        Method::POST,
+        "/v1/clientes/1/contas",
+        &[("x-api-key", "escrita")],
+        account(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // reading them is still the client's own business
+    let (status, _) = send_with(
+        &app,
+        Method::GET,
+        "/clientes/1/contas",
+        &[("x-api-key", "escrita")],
+        None,
+    )
+    .await;
+    assert_ne!(status, StatusCode::FORBIDDEN);
+}
+
+// the rows of a batch share their timestamp, a page ending among them still
+// goes on with the rest
+#[cfg(feature = "graphql")]
+#[tokio::test]
+async fn graphql_pages_through_the_rows_of_a_batch() {
+    let app = app();
+
+    let item = |descricao: &str| json!({"valor": 100, "tipo": "c", "descricao": descricao});
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes/lote",
+        Some(json!({"transacoes": [item("a"), item("b"), item("c")]})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let page = |after: Option<&str>| {
+        let after = after.map_or("null".to_string(), |after| format!("{:?}", after));
+        let query = format!(
+            "{{ wallet(id: 1) {{ transactions(first: 2, after: {}) {{ \
+             edges {{ cursor node {{ description }} }} pageInfo {{ hasNextPage }} }} }} }}",
+            after
+        );
+        Some(json!({ "query": query }))
+    };
+
+    let (_, body) = send(&app, Method::POST, "/graphql", page(None)).await;
+    let transactions = &body["data"]["wallet"]["transactions"];
+    let edges = transactions["edges"].as_array().unwrap();
+    assert_eq!(edges.len(), 2);
+    assert_eq!(edges[0]["node"]["description"], "a");
+    assert_eq!(transactions["pageInfo"]["hasNextPage"], true);
+
+    let cursor = edges[1]["cursor"].as_str().unwrap();
+    let (_, body) = send(&app, Method::POST, "/graphql", page(Some(cursor))).await;
+    let transactions = &body["data"]["wallet"]["transactions"];
+    let edges = transactions["edges"].as_array().unwrap();
+    assert_eq!(edges.len(), 1);
+    assert_eq!(edges[0]["node"]["description"], "c");
+    assert_eq!(transactions["pageInfo"]["hasNextPage"], false);
+}
+
+// the history narrows down to a category or a tag, the summary adds each
+// category up on its own
+#[tokio::test]
+async fn transactions_are_filtered_and_added_up_by_category() {
+    let app = app();
+    for (valor, descricao, label) in [
+        (
+            100,
+            "feira",
+            json!({"categoria": "mercado", "tags": ["pix"]}),
+        ),
+        (200, "cinema", json!({"categoria": "lazer"})),
+        (300, "padaria", json!({"tags": ["pix"]})),
+    ] {
+        let mut body = json!({"valor": valor, "tipo": "d", "descricao": descricao});
+        body.as_object_mut()
+            .unwrap()
+            .extend(label.as_object().unwrap().clone());
+        let (status, _) = send(&app, Method::POST, "/clientes/1/transacoes", Some(body)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let descriptions = |uri: &'static str| {
+        let app = app.clone();
+        async move {
+            let (status, page) = send(&app, Method::GET, uri, None).await;
+            assert_eq!(status, StatusCode::OK);
+            page["transacoes"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|transaction| transaction["descricao"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+    assert_eq!(
+        descriptions("/clientes/1/transacoes?categoria=mercado").await,
+        ["feira"]
+    );
+    assert_eq!(
+        descriptions("/clientes/1/transacoes?tag=pix").await,
+        ["feira", "padaria"]
+    );
+
+    let (status, summary) = send(&app, Method::GET, "/clientes/1/resumo", None).await;
+    assert_eq!(status, StatusCode::OK);
+    let categories: Vec<(Value, i64)> = summary["categorias"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|category| {
+            (
+                category["categoria"].clone(),
+                amount(&category["total_debitos"]),
+            )
+        })
+        .collect();
+    assert_eq!(
+        categories,
+        [
+            (json!("lazer"), 200),
+            (json!("mercado"), 100),
+            (Value::Null, 300),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_category_is_at_most_32_characters() {
+    let app = app();
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        Some(json!({"valor": 100, "tipo": "d", "descricao": "feira", "categoria": "m".repeat(33)})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"][0]["field"], "categoria");
+
+    let uri = format!("/clientes/1/transacoes?categoria={}", "m".repeat(33));
+    let (status, _) = send(&app, Method::GET, &uri, None).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+async fn a_schedule_in_the_past_is_unprocessable() {
+    let (status, body) = send(
+        &app(),
+        Method::POST,
+        "/clientes/1/transacoes",
+        Some(json!({
+            "valor": 1000,
+            "tipo": "c",
+            "descricao": "agendada",
+            "agendada_para": "2000-01-01T00:00:00Z",
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"][0]["field"], "agendada_para");
+}
+
+// a retry under the same key gets the first response back without the
+// transaction being applied twice, another transaction under it is turned
+// away
+#[tokio::test]
+async fn an_idempotency_key_replays_its_transaction_only() {
+    let app = app();
+    let post = |body: Value| {
+        let app = app.clone();
+        async move {
+            send_with(
+                &app,
+                Method::POST,
+                "/clientes/1/transacoes",
+                &[("idempotency-key", "chave")],
+                Some(body),
+            )
+            .await
+        }
+    };
+
+    let (status, first) = post(json!({"valor": 1000, "tipo": "c", "descricao": "credito"})).await;
+    assert_eq!(status, StatusCode::OK);
+    // the same transaction, in english
+    let (status, retried) =
+        post(json!({"value": 1000, "type": "c", "description": "credito"})).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(retried, first);
+
+    let (status, body) = post(json!({"valor": 2000, "tipo": "c", "descricao": "credito"})).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["type"], "urn:rinha:problem:idempotency_key_reused");
+
+    let (_, statement) = send(&app, Method::GET, "/clientes/1/extrato", None).await;
+    assert_eq!(amount(&statement["saldo"]["total"]), 1000);
+}
+
+// the key's response would be stored along with a transaction that doesn't
+// exist yet
+#[tokio::test]
+async fn an_idempotency_key_cant_schedule() {
+    let app = app();
+
+    let (status, body) = send_with(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        &[("idempotency-key", "chave")],
+        Some(json!({
+            "valor": 1000,
+            "tipo": "c",
+            "descricao": "agendada",
+            "agendada_para": "2100-01-01T00:00:00Z",
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"][0]["field"], "Idempotency-Key");
+}
+
+// a credential only works for the dataset it was given for
+#[tokio::test]
+async fn credentials_dont_cross_tenants() {
+    let app = app_with(&[("JWT_SECRET", "segredo")]);
+    let token = |tenant: Option<&str>| {
+        let claims = json!({"sub": "1", "exp": 4102444800u64, "tenant": tenant});
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(b"segredo"),
+        )
+        .unwrap();
+        format!("Bearer {}", token)
+    };
+    let extrato = |authorization: &str, tenant: Option<&str>| {
+        let mut headers = vec![("authorization", authorization.to_string())];
+        headers.extend(tenant.map(|tenant| ("x-tenant-id", tenant.to_string())));
+        let app = app.clone();
+        async move {
+            let headers: Vec<(&str, &str)> = headers
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect();
+            send_with(&app, Method::GET, "/clientes/1/extrato", &headers, None)
+                .await
+                .0
+        }
+    };
+    let (default, acme) = (token(None), token(Some("acme")));
+
+    assert_eq!(extrato(&default, None).await, StatusCode::OK);
+    assert_eq!(extrato(&default, Some("acme")).await, StatusCode::FORBIDDEN);
+    assert_eq!(extrato(&acme, None).await, StatusCode::FORBIDDEN);
+    assert_eq!(extrato(&acme, Some("outra")).await, StatusCode::FORBIDDEN);
+
+    // the same goes for the keys in API_KEYS, which are the default
+    // dataset's unless they name a tenant
+    let app = app_with(&[("API_KEYS", "chave:read")]);
+    let (status, _) = send_with(
+        &app,
+        Method::GET,
+        "/clientes/1/extrato",
+        &[("x-api-key", "chave"), ("x-tenant-id", "acme")],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+// a read key gets to query, only a write one to register transactions
+#[cfg(feature = "graphql")]
+#[tokio::test]
+async fn graphql_mutations_need_a_write_key() {
+    let app = app_with(&[("API_KEYS", "leitura:read,escrita:write")]);
+
+    let graphql = |key: &'static str, query: &'static str| {
+        let app = app.clone();
+        async move {
+            send_with(
+                &app,
+                Method::POST,
+                "/graphql",
+                &[("x-api-key", key)],
+                Some(json!({ "query": query })),
+            )
+            .await
+        }
+    };
+    let query = "{ wallet(id: 1) { balance } }";
+    let mutation = "mutation { registerTransaction(clientId: 1, value: 100, kind: CREDIT, \
+                    description: \"pix\") { balance } }";
+
+    let (status, body) = graphql("leitura", query).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["wallet"]["balance"], 0);
+
+    let (status, body) = graphql("leitura", mutation).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["errors"][0]["extensions"]["code"], "forbidden");
+
+    let (_, body) = graphql("escrita", mutation).await;
+    assert_eq!(body["data"]["registerTransaction"]["balance"], 100);
+}
+
+// the fields are taken in english too, and `?lang=en` answers in english
+// with an etag of its own, which still gets a 304
+#[tokio::test]
+async fn english_fields_are_taken_and_given_back() {
+    let app = app();
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        Some(json!({"value": 1000, "type": "c", "description": "deposit"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(amount(&body["saldo"]), 1000);
+
+    let get = |etag: Option<&str>| {
+        let mut request = Request::get("/clientes/1/extrato?lang=en");
+        if let Some(etag) = etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap())
+    };
+
+    let response = get(None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[header::ETAG]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(etag.ends_with("-en\""), "{}", etag);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(amount(&body["balance"]["total"]), 1000);
+    assert_eq!(body["latest_transactions"][0]["description"], "deposit");
+    assert!(body.get("saldo").is_none());
+
+    let response = get(Some(&etag)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+}
+
+// what `Json` and `Path` turn away comes back as a problem like any other
+#[tokio::test]
+async fn a_malformed_request_gets_a_problem() {
+    let app = app();
+    let post = |content_type: &str, body: &str| {
+        let request = Request::post("/clientes/1/transacoes")
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let app = app.clone();
+        async move {
+            let response = app.oneshot(request).await.unwrap();
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            (status, serde_json::from_slice::<Value>(&body).unwrap())
+        }
+    };
+    let (status, body) = post("application/json", r#"{"valor": 100,"#).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["type"], "urn:rinha:problem:malformed_json");
+
+    let (status, body) = post(
+        "text/plain",
+        r#"{"valor": 100, "tipo": "c", "descricao": "pix"}"#,
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(body["type"], "urn:rinha:problem:unsupported_media_type");
+
+    let (status, body) = send(&app, Method::GET, "/clientes/abc/extrato", None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["type"], "urn:rinha:problem:invalid_parameter");
+}
@@ -0,0 +1,902 @@
+// the features only the postgres storage has, against the server in
+// DATABASE_URL. ignored by default, run them with `cargo test -- --ignored`
+use axum::{
+    body::Body,
+    http::{header, Method, Request, StatusCode},
+    Router,
+};
+use rinha_rust::{
+    config::Config,
+    models::{Cents, LedgerViolation, PostTransaction, StatementFilter},
+    repository::{
+        set_cache_ttls, Actors, Cached, PgWalletRepository, StatementCache, WalletRepository,
+    },
+};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use std::{future::Future, sync::Arc, time::Duration};
+use tower::ServiceExt;
+
+// the api on a database of its own named after the test, dropped and
+// created again on the server in DATABASE_URL, with the settings in `vars`
+// on top of the defaults. the five clients are seeded, client 1's limit
+// being 100000
+async fn app_with(name: &str, vars: &[(&str, &str)]) -> (Router, PgWalletRepository) {
+    let server = std::env::var("DATABASE_URL").expect("DATABASE_URL is set");
+    let admin = PgPool::connect(&server).await.unwrap();
+    let database = format!("rinha_test_{}", name);
+    sqlx::query(&format!(
+        "DROP DATABASE IF EXISTS {} WITH (FORCE)",
+        database
+    ))
+    .execute(&admin)
+    .await
+    .unwrap();
+    sqlx::query(&format!("CREATE DATABASE {}", database))
+        .execute(&admin)
+        .await
+        .unwrap();
+    admin.close().await;
+
+    let (base, _) = server.rsplit_once('/').unwrap();
+    let url = format!("{}/{}", base, database);
+    let config = Config::from_lookup(|var| match var {
+        "DATABASE_URL" => Some(url.clone()),
+        _ => vars
+            .iter()
+            .find(|(name, _)| *name == var)
+            .map(|(_, value)| value.to_string()),
+    })
+    .unwrap();
+
+    let repo = PgWalletRepository::connect(&config).await.unwrap();
+    repo.run_migrations().await.unwrap();
+    repo.seed(false).await.unwrap();
+
+    (rinha_rust::app(repo.clone(), &config), repo)
+}
+
+async fn send(app: &Router, method: Method, uri: &str, body: Option<Value>) -> (StatusCode, Value) {
+    send_with(app, method, uri, &[], body).await
+}
+
+async fn send_with(
+    app: &Router,
+    method: Method,
+    uri: &str,
+    headers: &[(&str, &str)],
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        request = request.header(*name, *value);
+    }
+    let request = match body {
+        Some(body) => request
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string())),
+        None => request.body(Body::empty()),
+    }
+    .unwrap();
+
+    let response = app.clone().oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+// an amount in a body, a string with the `decimal` feature
+fn amount(value: &Value) -> i64 {
+    match value {
+        Value::String(amount) => amount.parse().unwrap(),
+        amount => amount.as_i64().unwrap(),
+    }
+}
+
+// client `id`'s balance
+async fn balance(app: &Router, id: i32) -> i64 {
+    let (status, body) = send(app, Method::GET, &format!("/clientes/{}/extrato", id), None).await;
+    assert_eq!(status, StatusCode::OK);
+    amount(&body["saldo"]["total"])
+}
+
+// waits for `done`, what a background worker does shows up over its next
+// rounds
+async fn eventually<F, Fut>(mut done: F)
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = bool>,
+{
+    for _ in 0..250 {
+        if done().await {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("still not done after 5s");
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_hold_past_what_the_client_can_spend_is_unprocessable() {
+    let (app, _) = app_with("hold_past_the_limit", &[]).await;
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/holds",
+        Some(json!({"valor": 100001, "descricao": "reserva"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_hold_is_captured_or_released_once() {
+    let (app, _) = app_with("hold_captured_or_released", &[]).await;
+    let hold = |valor: i64| {
+        let app = app.clone();
+        async move {
+            let (status, hold) = send(
+                &app,
+                Method::POST,
+                "/clientes/1/holds",
+                Some(json!({"valor": valor, "descricao": "reserva"})),
+            )
+            .await;
+            assert_eq!(status, StatusCode::CREATED);
+            hold["id"].as_str().unwrap().to_string()
+        }
+    };
+
+    let captured = hold(300).await;
+    let uri = format!("/holds/{}/capture", captured);
+    let (status, body) = send(&app, Method::POST, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(amount(&body["saldo"]), -300);
+    assert_eq!(body["reserva"]["status"], "capturada");
+    let (status, _) = send(&app, Method::POST, &uri, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let released = hold(500).await;
+    let uri = format!("/holds/{}", released);
+    let (status, body) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["status"], "liberada");
+    let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    // only the captured one ever left the balance
+    assert_eq!(balance(&app, 1).await, -300);
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_held_amount_cant_be_debited() {
+    let (app, _) = app_with("held_amount_cant_be_debited", &[]).await;
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/holds",
+        Some(json!({"valor": 80000, "descricao": "reserva"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    // within the limit, not within what the hold left of it
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        Some(json!({"valor": 30000, "tipo": "d", "descricao": "debito"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(balance(&app, 1).await, 0);
+}
+
+// another client's hold looks like one that doesn't exist
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn another_clients_hold_is_not_found() {
+    let (app, _) = app_with("another_clients_hold", &[("JWT_SECRET", "segredo")]).await;
+    let token = |sub: &str| {
+        let claims = json!({"sub": sub, "exp": 4102444800u64});
+        let token = jsonwebtoken::encode(
+            &jsonwebtoken::Header::default(),
+            &claims,
+            &jsonwebtoken::EncodingKey::from_secret(b"segredo"),
+        )
+        .unwrap();
+        format!("Bearer {}", token)
+    };
+    let (owner, other) = (token("1"), token("2"));
+
+    let (status, hold) = send_with(
+        &app,
+        Method::POST,
+        "/clientes/1/holds",
+        &[("authorization", &owner)],
+        Some(json!({"valor": 100, "descricao": "reserva"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    let uri = format!("/holds/{}", hold["id"].as_str().unwrap());
+    let unknown = "/holds/00000000-0000-0000-0000-000000000000";
+
+    let get = |uri: String, authorization: String| {
+        let app = app.clone();
+        async move {
+            send_with(
+                &app,
+                Method::GET,
+                &uri,
+                &[("authorization", &authorization)],
+                None,
+            )
+            .await
+            .0
+        }
+    };
+    assert_eq!(get(uri.clone(), owner.clone()).await, StatusCode::OK);
+    assert_eq!(get(uri.clone(), other.clone()).await, StatusCode::NOT_FOUND);
+    assert_eq!(
+        get(unknown.into(), other.clone()).await,
+        StatusCode::NOT_FOUND
+    );
+
+    let capture = format!("{}/capture", uri);
+    let (status, _) = send_with(
+        &app,
+        Method::POST,
+        &capture,
+        &[("authorization", &other)],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (status, _) = send_with(
+        &app,
+        Method::DELETE,
+        &uri,
+        &[("authorization", &other)],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+// schedules a transaction of client 1 for an hour from now and moves it
+// into the past, where the scheduler picks it up
+async fn schedule_due(app: &Router, repo: &PgWalletRepository, transaction: Value) -> String {
+    let due_at = time::OffsetDateTime::now_utc() + time::Duration::hours(1);
+    let mut body = transaction;
+    body["agendada_para"] = due_at
+        .format(&time::format_description::well_known::Rfc3339)
+        .unwrap()
+        .into();
+
+    let (status, scheduled) = send(app, Method::POST, "/clientes/1/transacoes", Some(body)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(scheduled["status"], "pendente");
+
+    sqlx::query("UPDATE scheduled_transactions SET due_at = now() - interval '1 second'")
+        .execute(repo.pool())
+        .await
+        .unwrap();
+
+    scheduled["id"].as_str().unwrap().to_string()
+}
+
+// the status of the scheduled transaction `id` and the reason it failed.
+// the listing only has the pending ones
+async fn settled(repo: &PgWalletRepository, id: &str) -> (String, Option<String>) {
+    sqlx::query_as(
+        "SELECT status::text, failure FROM scheduled_transactions WHERE public_id = $1::uuid",
+    )
+    .bind(id)
+    .fetch_one(repo.pool())
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_due_transaction_is_applied() {
+    let (app, repo) = app_with("due_transaction", &[("SCHEDULER_INTERVAL_MS", "50")]).await;
+
+    let id = schedule_due(
+        &app,
+        &repo,
+        json!({"valor": 1000, "tipo": "c", "descricao": "agendada"}),
+    )
+    .await;
+
+    eventually(|| async { settled(&repo, &id).await.0 != "pending" }).await;
+    assert_eq!(settled(&repo, &id).await, ("applied".to_string(), None));
+    assert_eq!(balance(&app, 1).await, 1000);
+}
+
+// the balance is checked when it's due, a debit past the limit by then is
+// kept as failed with the reason
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_due_debit_past_the_limit_fails() {
+    let (app, repo) = app_with(
+        "due_debit_past_the_limit",
+        &[("SCHEDULER_INTERVAL_MS", "50")],
+    )
+    .await;
+
+    let id = schedule_due(
+        &app,
+        &repo,
+        json!({"valor": 100001, "tipo": "d", "descricao": "agendada"}),
+    )
+    .await;
+
+    eventually(|| async { settled(&repo, &id).await.0 != "pending" }).await;
+    assert_eq!(
+        settled(&repo, &id).await,
+        ("failed".to_string(), Some("limit_exceeded".to_string()))
+    );
+    assert_eq!(balance(&app, 1).await, 0);
+}
+
+// a run is made at `next_run_at`, and the next one worked out after it
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_due_recurrence_runs() {
+    let (app, repo) = app_with("due_recurrence", &[("SCHEDULER_INTERVAL_MS", "50")]).await;
+
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/recorrencias",
+        Some(json!({"valor": 250, "tipo": "c", "descricao": "mesada", "cron": "0 0 1 * *"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    sqlx::query("UPDATE recurring_transactions SET next_run_at = now() - interval '1 second'")
+        .execute(repo.pool())
+        .await
+        .unwrap();
+
+    eventually(|| async { balance(&app, 1).await == 250 }).await;
+    let (next_run_at,): (time::OffsetDateTime,) =
+        sqlx::query_as("SELECT next_run_at FROM recurring_transactions")
+            .fetch_one(repo.pool())
+            .await
+            .unwrap();
+    assert!(next_run_at > time::OffsetDateTime::now_utc());
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_webhook_is_registered_listed_and_deleted() {
+    let (app, _) = app_with("webhook_registered", &[]).await;
+
+    let (status, created) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/webhooks",
+        Some(json!({"url": "https://example.com/rinha"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    assert!(created["segredo"].is_string());
+
+    // without its secret
+    let (status, webhooks) = send(&app, Method::GET, "/clientes/1/webhooks", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        webhooks,
+        json!([{
+            "id": created["id"],
+            "url": "https://example.com/rinha",
+            "criado_em": created["criado_em"],
+        }])
+    );
+
+    let uri = format!("/clientes/1/webhooks/{}", created["id"]);
+    let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_webhook_needs_an_http_url() {
+    let (app, _) = app_with("webhook_needs_a_url", &[]).await;
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/webhooks",
+        Some(json!({"url": "ftp://example.com/rinha"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"][0]["field"], "url");
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_scheduled_transaction_is_listed_until_cancelled() {
+    let (app, _) = app_with("scheduled_cancelled", &[]).await;
+
+    let (status, scheduled) = send(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        Some(json!({
+            "valor": 1000,
+            "tipo": "c",
+            "descricao": "agendada",
+            "agendada_para": "2100-01-01T00:00:00Z",
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    let (status, pending) = send(&app, Method::GET, "/clientes/1/transacoes/agendadas", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(pending, json!([scheduled]));
+
+    let uri = format!(
+        "/clientes/1/transacoes/agendadas/{}",
+        scheduled["id"].as_str().unwrap()
+    );
+    let (status, cancelled) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(cancelled["status"], "cancelada");
+    let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let (_, pending) = send(&app, Method::GET, "/clientes/1/transacoes/agendadas", None).await;
+    assert_eq!(pending, json!([]));
+    assert_eq!(balance(&app, 1).await, 0);
+}
+
+// a fee rule of 10 plus 1.5% of the debits of at least 1000
+async fn fee_rule(app: &Router) -> Value {
+    let (status, rule) = send(
+        app,
+        Method::POST,
+        "/admin/tarifas",
+        Some(json!({
+            "tipo": "d",
+            "valor_minimo": 1000,
+            "fixa": 10,
+            "percentual": 0.015,
+            "descricao": "tarifa",
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED);
+    rule
+}
+
+async fn debit(app: &Router, value: i64) -> StatusCode {
+    let transaction = json!({"valor": value, "tipo": "d", "descricao": "debito"});
+    send(
+        app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        Some(transaction),
+    )
+    .await
+    .0
+}
+
+// the percentage is rounded to the cent, half of one up
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_fee_is_rounded_to_the_cent() {
+    let (app, _) = app_with("fee_rounded", &[("FEES", "true")]).await;
+    fee_rule(&app).await;
+
+    // 10 + 15.45
+    assert_eq!(debit(&app, 1030).await, StatusCode::OK);
+    assert_eq!(balance(&app, 1).await, -1030 - 25);
+
+    // 10 + 16.5
+    assert_eq!(debit(&app, 1100).await, StatusCode::OK);
+    assert_eq!(balance(&app, 1).await, -1030 - 25 - 1100 - 27);
+
+    // under the rule's minimum
+    assert_eq!(debit(&app, 999).await, StatusCode::OK);
+    assert_eq!(balance(&app, 1).await, -1030 - 25 - 1100 - 27 - 999);
+}
+
+// a debit within the limit whose fee isn't is turned away whole
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_fee_past_the_limit_turns_the_debit_away() {
+    let (app, _) = app_with("fee_past_the_limit", &[("FEES", "true")]).await;
+    fee_rule(&app).await;
+
+    assert_eq!(debit(&app, 100000).await, StatusCode::UNPROCESSABLE_ENTITY);
+    let (_, statement) = send(&app, Method::GET, "/clientes/1/extrato", None).await;
+    assert_eq!(amount(&statement["saldo"]["total"]), 0);
+    assert_eq!(statement["ultimas_transacoes"], json!([]));
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_fee_rule_is_listed_updated_and_deleted() {
+    let (app, _) = app_with("fee_rule_endpoints", &[("FEES", "true")]).await;
+    let rule = fee_rule(&app).await;
+
+    let (status, rules) = send(&app, Method::GET, "/admin/tarifas", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(rules, json!([rule]));
+
+    let uri = format!("/admin/tarifas/{}", rule["id"]);
+    let (status, updated) = send(
+        &app,
+        Method::PUT,
+        &uri,
+        Some(json!({"tipo": "d", "fixa": 50, "descricao": "tarifa"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(amount(&updated["fixa"]), 50);
+    assert_eq!(debit(&app, 100).await, StatusCode::OK);
+    assert_eq!(balance(&app, 1).await, -150);
+
+    let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, Method::DELETE, &uri, None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_fee_rule_needs_a_percentage_between_0_and_1() {
+    let (app, _) = app_with("fee_rule_percentage", &[("FEES", "true")]).await;
+
+    let (status, body) = send(
+        &app,
+        Method::POST,
+        "/admin/tarifas",
+        Some(json!({"tipo": "d", "percentual": 1.5, "descricao": "tarifa"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"][0]["field"], "percentual");
+}
+
+// the filters and the totals are worked out in sql here
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn transactions_are_filtered_and_added_up_by_category() {
+    let (app, _) = app_with("filtered_by_category", &[]).await;
+    for body in [
+        json!({"valor": 100, "tipo": "d", "descricao": "feira", "categoria": "mercado", "tags": ["pix"]}),
+        json!({"valor": 300, "tipo": "d", "descricao": "padaria", "tags": ["pix"]}),
+    ] {
+        let (status, _) = send(&app, Method::POST, "/clientes/1/transacoes", Some(body)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let (status, page) = send(
+        &app,
+        Method::GET,
+        "/clientes/1/transacoes?categoria=mercado",
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["transacoes"][0]["descricao"], "feira");
+    assert_eq!(page["transacoes"].as_array().unwrap().len(), 1);
+    let (_, page) = send(&app, Method::GET, "/clientes/1/transacoes?tag=pix", None).await;
+    assert_eq!(page["transacoes"].as_array().unwrap().len(), 2);
+
+    let (status, summary) = send(&app, Method::GET, "/clientes/1/resumo", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["categorias"][0]["categoria"], "mercado");
+    assert_eq!(amount(&summary["categorias"][0]["total_debitos"]), 100);
+    assert_eq!(summary["categorias"][1]["categoria"], Value::Null);
+    assert_eq!(amount(&summary["categorias"][1]["total_debitos"]), 300);
+}
+
+// a day's interest is the rate on the negative balance, rounded to the cent,
+// charged once
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn interest_is_rounded_to_the_cent_and_charged_once_a_day() {
+    let (app, _) = app_with(
+        "interest_rounded",
+        &[
+            ("INTEREST_DAILY_RATE", "0.001"),
+            ("INTEREST_CHECK_INTERVAL_SECS", "1"),
+        ],
+    )
+    .await;
+
+    let (status, _) = send(
+        &app,
+        Method::PATCH,
+        "/clientes/2/juros",
+        Some(json!({"taxa_diaria": 0.0333})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    for id in [1, 2] {
+        let transaction = json!({"valor": 1500, "tipo": "d", "descricao": "debito"});
+        let uri = format!("/clientes/{}/transacoes", id);
+        let (status, _) = send(&app, Method::POST, &uri, Some(transaction)).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    // 1.5 at the default rate, 49.95 at client 2's own
+    eventually(|| async { balance(&app, 1).await != -1500 && balance(&app, 2).await != -1500 })
+        .await;
+    assert_eq!(balance(&app, 1).await, -1502);
+    assert_eq!(balance(&app, 2).await, -1550);
+    let (_, statement) = send(&app, Method::GET, "/clientes/1/extrato", None).await;
+    assert_eq!(statement["ultimas_transacoes"][0]["descricao"], "juros");
+
+    // the next rounds find the day done
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert_eq!(balance(&app, 1).await, -1502);
+    assert_eq!(balance(&app, 2).await, -1550);
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn an_interest_rate_is_between_0_and_1() {
+    let (app, _) = app_with("interest_rate_range", &[]).await;
+
+    let (status, body) = send(
+        &app,
+        Method::PATCH,
+        "/clientes/1/juros",
+        Some(json!({"taxa_diaria": 2})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["errors"][0]["field"], "taxa_diaria");
+}
+
+// three credits of client 1
+async fn credits(app: &Router) {
+    for valor in [100, 200, 300] {
+        let transaction = json!({"valor": valor, "tipo": "c", "descricao": "credito"});
+        let (status, _) = send(
+            app,
+            Method::POST,
+            "/clientes/1/transacoes",
+            Some(transaction),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
+
+// editing a stored transaction behind the api's back, past the trigger that
+// forbids it, is what the hashes give away
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_tampered_transaction_breaks_the_chain() {
+    let (app, repo) = app_with("tampered_chain", &[]).await;
+    credits(&app).await;
+
+    let (status, verification) =
+        send(&app, Method::GET, "/clientes/1/transacoes/verify", None).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(verification["valido"], true);
+    assert_eq!(verification["transacoes"], 3);
+
+    let mut transaction = repo.pool().begin().await.unwrap();
+    for statement in [
+        "ALTER TABLE transactions DISABLE TRIGGER transactions_immutable",
+        "UPDATE transactions SET value = 2000 WHERE wallet_id = 1 AND chain_position = 2",
+        "ALTER TABLE transactions ENABLE TRIGGER transactions_immutable",
+    ] {
+        sqlx::query(statement)
+            .execute(&mut *transaction)
+            .await
+            .unwrap();
+    }
+    transaction.commit().await.unwrap();
+
+    let (_, verification) = send(&app, Method::GET, "/clientes/1/transacoes/verify", None).await;
+    assert_eq!(verification["valido"], false);
+    assert_eq!(verification["transacoes"], 1);
+    assert_eq!(verification["quebra"]["posicao"], 2);
+    assert_eq!(verification["quebra"]["motivo"], "adulterada");
+}
+
+// a balance edited behind the api's back no longer matches its entries
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_balance_that_doesnt_match_its_entries_is_reported() {
+    let (app, repo) = app_with("balance_mismatch", &[]).await;
+    credits(&app).await;
+    assert!(repo.check_ledger().await.unwrap().is_empty());
+
+    sqlx::query("UPDATE wallets SET balance = balance + 1 WHERE id = 1")
+        .execute(repo.pool())
+        .await
+        .unwrap();
+
+    let violations = repo.check_ledger().await.unwrap();
+    assert_eq!(violations.len(), 1);
+    assert!(matches!(
+        violations[0],
+        LedgerViolation::BalanceMismatch { wallet_id: 1, .. }
+    ));
+    assert_eq!(
+        violations[0].to_string(),
+        LedgerViolation::BalanceMismatch {
+            wallet_id: 1,
+            balance: 601.into(),
+            entries: 600.into(),
+        }
+        .to_string()
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn an_idempotency_key_replays_its_transaction_only() {
+    let (app, _) = app_with("idempotency_key", &[]).await;
+    let post = |valor: i64| {
+        let app = app.clone();
+        async move {
+            send_with(
+                &app,
+                Method::POST,
+                "/clientes/1/transacoes",
+                &[("idempotency-key", "chave")],
+                Some(json!({"valor": valor, "tipo": "c", "descricao": "credito"})),
+            )
+            .await
+        }
+    };
+
+    let (status, first) = post(1000).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(post(1000).await, (StatusCode::OK, first));
+
+    let (status, body) = post(2000).await;
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body["type"], "urn:rinha:problem:idempotency_key_reused");
+    assert_eq!(balance(&app, 1).await, 1000);
+}
+
+// another instance's write comes back as a notification, which drops this
+// one's cached statement long before its ttl
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_write_elsewhere_drops_the_cached_statement() {
+    let (_, repo) = app_with("cache_invalidated_elsewhere", &[]).await;
+    let ttl = Duration::from_secs(60);
+    set_cache_ttls(ttl, ttl);
+    let cache = StatementCache::new(ttl, 100).unwrap();
+    repo.watch_changes(cache.on_change());
+    // through the cache, unlike `repo` writing, which is the other instance
+    let cached = Cached::new(repo.clone(), Some(cache));
+    let balance = || async {
+        cached
+            .get_statement(1, &StatementFilter::default())
+            .await
+            .unwrap()
+            .wallet
+            .balance
+    };
+
+    assert_eq!(balance().await, Some(Cents::ZERO));
+    // time for the listener to connect, a notification before that is lost
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let credit: PostTransaction =
+        serde_json::from_value(json!({"valor": 100, "tipo": "c", "descricao": "credito"})).unwrap();
+    repo.insert_transaction(1, &credit, None).await.unwrap();
+    eventually(|| async { balance().await == Some(Cents::from(100)) }).await;
+}
+
+// the actor turns away the debit its balance can't cover until the
+// notification of another instance's credit drops that balance
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_credit_elsewhere_reaches_the_wallet_actor() {
+    let (_, repo) = app_with("actor_notified_elsewhere", &[]).await;
+    let actors = Actors::new(repo.clone(), true, Duration::from_secs(60));
+    assert!(actors.watch_changes(Arc::new(|_| {})));
+    // time for the listener to connect, a notification before that is lost
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let transaction = |value: i64, kind: &str| -> PostTransaction {
+        serde_json::from_value(json!({"valor": value, "tipo": kind, "descricao": "teste"})).unwrap()
+    };
+
+    actors
+        .insert_transaction(1, &transaction(100000, "d"), None)
+        .await
+        .unwrap();
+    repo.insert_transaction(1, &transaction(500, "c"), None)
+        .await
+        .unwrap();
+
+    eventually(|| async {
+        actors
+            .insert_transaction(1, &transaction(500, "d"), None)
+            .await
+            .is_ok()
+    })
+    .await;
+}
+
+// the rows of the audit log, newest first, as the admin key reads them
+async fn audit(app: &Router, action: &str) -> Vec<Value> {
+    let (status, body) = send_with(
+        app,
+        Method::GET,
+        &format!("/admin/audit?cliente_id=1&acao={}", action),
+        &[("x-api-key", "auditor")],
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    body["entradas"].as_array().unwrap().clone()
+}
+
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_write_is_audited_with_its_actor() {
+    let (app, _) = app_with("audit_actor", &[("API_KEYS", "caixa:write,auditor:admin")]).await;
+
+    let (status, _) = send_with(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        &[("x-api-key", "caixa"), ("x-request-id", "auditada")],
+        Some(json!({"valor": 100, "tipo": "c", "descricao": "pix"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let entries = audit(&app, "transaction_created").await;
+    assert_eq!(entries.len(), 1);
+    let actor = format!("api_key:{}", &rinha_rust::auth::hash_key("caixa")[..12]);
+    assert_eq!(entries[0]["ator"], actor);
+    assert_eq!(entries[0]["requisicao_id"], "auditada");
+    assert_eq!(entries[0]["depois"]["valor"], 100);
+}
+
+// the flush writes outside of any request, with nobody to attribute it to
+#[tokio::test]
+#[ignore = "needs a postgres server in DATABASE_URL"]
+async fn a_write_behind_row_is_the_systems() {
+    let (app, _) = app_with(
+        "audit_write_behind",
+        &[
+            ("API_KEYS", "caixa:write,auditor:admin"),
+            ("WRITE_BEHIND_FLUSH_MS", "20"),
+        ],
+    )
+    .await;
+
+    let (status, _) = send_with(
+        &app,
+        Method::POST,
+        "/clientes/1/transacoes",
+        &[("x-api-key", "caixa")],
+        Some(json!({"valor": 100, "tipo": "c", "descricao": "pix"})),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+
+    eventually(|| async { !audit(&app, "transaction_created").await.is_empty() }).await;
+    let entries = audit(&app, "transaction_created").await;
+    assert_eq!(entries[0]["ator"], "system");
+}